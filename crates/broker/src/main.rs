@@ -0,0 +1,107 @@
+/// The broker: one address for a cluster of hosts. Hosts started with
+/// `--broker` register with it as workers and heartbeat their load, and
+/// clients talk to it exactly as they would to a single host.
+mod pool;
+mod replay;
+mod service;
+
+use clap::Parser;
+use common::compute::cuda_executor_server::CudaExecutorServer;
+use common::compute::worker_registry_server::WorkerRegistryServer;
+use pool::Pool;
+use service::Broker;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Routes jobs across Ferris-Compute-Cuda hosts")]
+struct Args {
+    /// Address clients and hosts reach the broker at
+    #[arg(long, env = "FERRIS_BROKER_LISTEN", default_value = "[::1]:50050")]
+    listen: SocketAddr,
+
+    /// Token hosts must present to register (their --broker-token)
+    #[arg(long, env = "FERRIS_REGISTRATION_TOKEN", hide_env_values = true)]
+    registration_token: Option<String>,
+
+    /// Seconds without a heartbeat after which a worker is declared dead
+    /// and its jobs run elsewhere
+    #[arg(long, default_value_t = 30)]
+    worker_timeout_secs: u64,
+
+    /// Largest submission held while choosing a host, in MiB
+    #[arg(long, default_value_t = 512)]
+    max_buffer_mb: u64,
+
+    /// Largest gRPC message accepted or forwarded, in bytes
+    #[arg(long, default_value_t = 4 * 1024 * 1024)]
+    max_message_bytes: usize,
+
+    /// PEM certificate to serve TLS with (plaintext if unset)
+    #[arg(long, env = "FERRIS_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key matching --tls-cert
+    #[arg(long, env = "FERRIS_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// Require client certificates signed by this PEM CA
+    #[arg(long, alias = "client-ca", env = "FERRIS_TLS_CLIENT_CA")]
+    tls_client_ca: Option<PathBuf>,
+
+    #[arg(long, env = "FERRIS_LOG", default_value = "info")]
+    log_level: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_new(&args.log_level)?)
+        .init();
+
+    let pool = Pool::new(Duration::from_secs(args.worker_timeout_secs.max(1)), args.max_message_bytes);
+    if args.registration_token.is_none() {
+        warn!("⚠️ No registration token: any host that can reach {} may join", args.listen);
+    }
+    let broker = Broker {
+        pool,
+        max_buffered_bytes: args.max_buffer_mb * 1024 * 1024,
+        registration_token: args.registration_token,
+    };
+    broker.spawn_reaper();
+    info!("🛰 Ferris-Compute-Cuda broker listening on {}", args.listen);
+
+    // Both services share the one broker: hosts register with it, clients submit through it
+    let broker = std::sync::Arc::new(broker);
+    let mut server = Server::builder();
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        let identity = Identity::from_pem(std::fs::read(cert)?, std::fs::read(key)?);
+        let mut tls = ServerTlsConfig::new().identity(identity);
+        if let Some(ca) = &args.tls_client_ca {
+            tls = tls.client_ca_root(Certificate::from_pem(std::fs::read(ca)?));
+            info!("🪪 Requiring client certificates signed by {}", ca.display());
+        }
+        server = server.tls_config(tls)?;
+        info!("🔒 TLS enabled with {}", cert.display());
+    }
+    server
+        .add_service(
+            CudaExecutorServer::from_arc(broker.clone())
+                .accept_compressed(CompressionEncoding::Gzip)
+                .send_compressed(CompressionEncoding::Gzip)
+                .max_decoding_message_size(args.max_message_bytes),
+        )
+        .add_service(WorkerRegistryServer::from_arc(broker))
+        .serve_with_shutdown(args.listen, async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    info!("👋 Broker stopped");
+    Ok(())
+}
@@ -0,0 +1,461 @@
+/// The hosts a broker routes to, called workers here. Each registers under an
+/// id of its own choosing with its address and what it can run, then
+/// heartbeats its load; one whose heartbeats stop for the timeout is
+/// declared dead and left out.
+///
+/// Jobs and input uploads stay on the worker that took them, so the pool
+/// remembers which worker holds each job id and upload id it has seen. A job
+/// the broker runs again elsewhere keeps its original id towards clients,
+/// and its route leads to the id the new worker gave it.
+///
+/// Jobs no client is streaming, whether from SubmitJob or because their
+/// client hung up, are kept here until their worker's heartbeats show them
+/// finished, so that they can be submitted again if it dies.
+use crate::replay::Replay;
+use common::compute::cuda_executor_client::CudaExecutorClient;
+use common::compute::{
+    Accelerator, ComputeRequest, HeartbeatRequest, HostInfo, LaunchKernelRequest, Profiler,
+};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::Status;
+use tonic::metadata::MetadataMap;
+use tonic::transport::{Channel, Endpoint};
+use tracing::warn;
+
+pub type Client = CudaExecutorClient<Channel>;
+
+/// Job and upload ids remembered; the oldest are forgotten first.
+const MAX_ROUTES: usize = 100_000;
+
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    workers: Mutex<BTreeMap<String, Worker>>,
+    jobs: Mutex<Routes>,
+    uploads: Mutex<Routes>,
+    /// Detached jobs by the id clients know them by.
+    detached: Mutex<HashMap<String, Detached>>,
+    timeout: Duration,
+    max_message_bytes: usize,
+}
+
+struct Worker {
+    address: String,
+    client: Client,
+    /// As registered, with the load from the last heartbeat.
+    info: HostInfo,
+    last_seen: Instant,
+    alive: bool,
+    /// Jobs sent since the last heartbeat, which its load doesn't count yet.
+    dispatched: u32,
+    /// Jobs run elsewhere since it was declared dead, which it should cancel
+    /// if it comes back.
+    superseded: Vec<String>,
+}
+
+/// Where a job or upload is: the worker, and the id it has there.
+#[derive(Clone)]
+pub struct Route {
+    pub worker: String,
+    pub id: String,
+    /// Entries its client was shown that aren't in the worker's output: the
+    /// broker's notices that the job was run again. Offsets are moved by this.
+    pub shift: u64,
+}
+
+/// A job without a client, which the pool can submit again.
+pub struct Detached {
+    /// As clients know it.
+    pub job_id: String,
+    pub route: Route,
+    pub replay: Replay,
+    /// The client's, forwarded again with the request.
+    pub metadata: MetadataMap,
+    /// Whether a heartbeat has listed the job yet.
+    seen: bool,
+    submitted: Instant,
+}
+
+/// What a job needs of the host it runs on.
+pub struct Needs<'a> {
+    accelerator: Accelerator,
+    toolchain: &'a str,
+    libraries: &'a [String],
+    profiler: Profiler,
+    device_index: Option<u32>,
+    gpus: u32,
+    mig: &'a str,
+    shared: bool,
+    /// Reported to the host's notifiers.
+    notify: bool,
+}
+
+#[derive(Default)]
+struct Routes {
+    routes: HashMap<String, Route>,
+    /// Ids on a worker that differ from the one clients know, and that one.
+    originals: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+impl Pool {
+    pub fn new(timeout: Duration, max_message_bytes: usize) -> Self {
+        Pool {
+            inner: Arc::new(Inner {
+                workers: Mutex::new(BTreeMap::new()),
+                jobs: Mutex::new(Routes::default()),
+                uploads: Mutex::new(Routes::default()),
+                detached: Mutex::new(HashMap::new()),
+                timeout,
+                max_message_bytes,
+            }),
+        }
+    }
+
+    /// Adds the worker `id`, or brings it back; false if it was already
+    /// there and alive. Another worker at the same address has restarted
+    /// under this id, and is declared dead.
+    pub fn register(&self, id: &str, address: &str, info: HostInfo) -> Result<bool, String> {
+        let channel = Endpoint::from_shared(address.to_string())
+            .map_err(|e| format!("Invalid host address {:?}: {}", address, e))?
+            .connect_lazy();
+        let mut workers = self.inner.workers.lock().unwrap();
+        for (other, worker) in workers.iter_mut() {
+            if other != id && worker.address == address && worker.alive {
+                warn!("💀 Worker {} at {} was replaced by {}", other, address, id);
+                worker.alive = false;
+            }
+        }
+        if let Some(worker) = workers.get_mut(id)
+            && worker.address == address
+        {
+            let new = !worker.alive;
+            worker.info = info;
+            worker.last_seen = Instant::now();
+            worker.alive = true;
+            return Ok(new);
+        }
+        let client = CudaExecutorClient::new(channel)
+            .max_decoding_message_size(self.inner.max_message_bytes)
+            .max_encoding_message_size(self.inner.max_message_bytes);
+        let superseded = workers.remove(id).map(|w| w.superseded).unwrap_or_default();
+        let worker = Worker {
+            address: address.to_string(),
+            client,
+            info,
+            last_seen: Instant::now(),
+            alive: true,
+            dispatched: 0,
+            superseded,
+        };
+        workers.insert(id.to_string(), worker);
+        Ok(true)
+    }
+
+    /// Records a heartbeat and returns the jobs the worker should cancel, or
+    /// `None` if it must register (again) first.
+    pub fn heartbeat(&self, beat: &HeartbeatRequest) -> Option<Vec<String>> {
+        let mut workers = self.inner.workers.lock().unwrap();
+        let worker = workers.get_mut(&beat.worker_id).filter(|w| w.alive)?;
+        worker.last_seen = Instant::now();
+        worker.info.running_jobs = beat.running_jobs;
+        worker.info.queued_jobs = beat.queued_jobs;
+        worker.dispatched = 0;
+        let cancel = std::mem::take(&mut worker.superseded);
+        drop(workers);
+
+        // Detached jobs it no longer lists have finished; ones it hasn't
+        // listed yet may have finished between heartbeats
+        let active: HashSet<&str> = beat.active_jobs.iter().map(String::as_str).collect();
+        let grace = self.inner.timeout * 2;
+        self.inner.detached.lock().unwrap().retain(|_, job| {
+            if job.route.worker != beat.worker_id {
+                return true;
+            }
+            let listed = active.contains(job.route.id.as_str());
+            job.seen |= listed;
+            listed || (!job.seen && job.submitted.elapsed() < grace)
+        });
+        Some(cancel)
+    }
+
+    /// Declares dead the workers whose heartbeats stopped, every second, and
+    /// passes each of their detached jobs to `requeue`.
+    pub fn spawn_reaper<F, Fut>(&self, requeue: F)
+    where
+        F: Fn(Detached) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                for job in pool.reap() {
+                    requeue(job).await;
+                }
+            }
+        });
+    }
+
+    fn reap(&self) -> Vec<Detached> {
+        let mut dead = HashSet::new();
+        for (id, worker) in self.inner.workers.lock().unwrap().iter_mut() {
+            if worker.alive && worker.last_seen.elapsed() > self.inner.timeout {
+                warn!("💀 Worker {} at {} missed its heartbeats; declaring it dead", id, worker.address);
+                worker.alive = false;
+            }
+            if !worker.alive {
+                dead.insert(id.clone());
+            }
+        }
+        let mut detached = self.inner.detached.lock().unwrap();
+        let orphans: Vec<String> = detached
+            .iter()
+            .filter(|(_, job)| dead.contains(&job.route.worker))
+            .map(|(id, _)| id.clone())
+            .collect();
+        orphans.into_iter().filter_map(|id| detached.remove(&id)).collect()
+    }
+
+    pub fn is_alive(&self, worker: &str) -> bool {
+        self.inner.workers.lock().unwrap().get(worker).is_some_and(|w| w.alive)
+    }
+
+    pub fn address(&self, worker: &str) -> String {
+        let workers = self.inner.workers.lock().unwrap();
+        workers.get(worker).map_or_else(|| worker.to_string(), |w| w.address.clone())
+    }
+
+    /// How long a worker may go without heartbeats.
+    pub fn timeout(&self) -> Duration {
+        self.inner.timeout
+    }
+
+    /// The least loaded live worker that meets `needs`.
+    #[allow(clippy::result_large_err)]
+    pub fn pick(&self, needs: &Needs) -> Result<(String, Client), Status> {
+        let mut workers = self.inner.workers.lock().unwrap();
+        let candidates: Vec<(&String, &Worker)> = workers.iter().filter(|(_, worker)| worker.alive).collect();
+        if candidates.is_empty() {
+            return Err(Status::unavailable("No host is available"));
+        }
+        let id = candidates
+            .iter()
+            .filter(|(_, worker)| needs.met_by(&worker.info))
+            .min_by(|(_, a), (_, b)| a.load().total_cmp(&b.load()))
+            .map(|(id, _)| id.to_string())
+            .ok_or_else(|| Status::failed_precondition("No available host can run this job"))?;
+        let worker = workers.get_mut(&id).expect("just found");
+        worker.dispatched += 1;
+        Ok((id, worker.client.clone()))
+    }
+
+    /// Every live worker.
+    pub fn alive(&self) -> Vec<(String, Client)> {
+        self.inner
+            .workers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, worker)| worker.alive)
+            .map(|(id, worker)| (id.clone(), worker.client.clone()))
+            .collect()
+    }
+
+    /// Notes that the job clients know as `job_id` is `route`.
+    pub fn remember_job(&self, job_id: &str, route: Route) {
+        self.inner.jobs.lock().unwrap().insert(job_id, route);
+    }
+
+    /// Like `remember_job`, but leaves a route already known alone: a job
+    /// run elsewhere is still listed by a worker that came back.
+    pub fn learn_job(&self, job_id: &str, route: Route) {
+        let mut jobs = self.inner.jobs.lock().unwrap();
+        if jobs.get(job_id).is_none() {
+            jobs.insert(job_id, route);
+        }
+    }
+
+    /// Keeps a detached job to submit again if its worker dies.
+    pub fn keep_detached(&self, job_id: &str, route: Route, replay: Replay, metadata: MetadataMap) {
+        let job = Detached {
+            job_id: job_id.to_string(),
+            route,
+            replay,
+            metadata,
+            seen: false,
+            submitted: Instant::now(),
+        };
+        self.inner.detached.lock().unwrap().insert(job_id.to_string(), job);
+    }
+
+    /// Notes that the worker `worker` should cancel `job_id`, its copy of a
+    /// job run elsewhere.
+    pub fn supersede(&self, worker: &str, job_id: &str) {
+        if let Some(worker) = self.inner.workers.lock().unwrap().get_mut(worker) {
+            worker.superseded.push(job_id.to_string());
+        }
+    }
+
+    /// Where the job clients know as `job_id` is, if known.
+    pub fn route(&self, job_id: &str) -> Option<Route> {
+        self.inner.jobs.lock().unwrap().get(job_id)
+    }
+
+    /// Where the job clients know as `job_id` is.
+    #[allow(clippy::result_large_err)]
+    pub fn job_host(&self, job_id: &str) -> Result<(Route, Client), Status> {
+        let route = self.route(job_id).ok_or_else(|| Status::not_found(format!("Unknown job {}", job_id)))?;
+        let client = self.live_client(&route.worker, job_id)?;
+        Ok((route, client))
+    }
+
+    /// The id clients know the job `id` on a worker by.
+    pub fn original_id(&self, id: &str) -> String {
+        self.inner.jobs.lock().unwrap().original(id)
+    }
+
+    pub fn remember_upload(&self, upload_id: &str, worker: &str) {
+        let route = Route {
+            worker: worker.to_string(),
+            id: upload_id.to_string(),
+            shift: 0,
+        };
+        self.inner.uploads.lock().unwrap().insert(upload_id, route);
+    }
+
+    /// The worker holding the inputs staged as `upload_id`.
+    #[allow(clippy::result_large_err)]
+    pub fn upload_host(&self, upload_id: &str) -> Result<(String, Client), Status> {
+        let route = self.inner.uploads.lock().unwrap().get(upload_id);
+        let route = route.ok_or_else(|| Status::not_found(format!("Unknown upload {}", upload_id)))?;
+        let client = self.live_client(&route.worker, upload_id)?;
+        Ok((route.worker, client))
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn live_client(&self, worker: &str, id: &str) -> Result<Client, Status> {
+        let workers = self.inner.workers.lock().unwrap();
+        match workers.get(worker) {
+            Some(worker) if worker.alive => Ok(worker.client.clone()),
+            _ => Err(Status::unavailable(format!("The host holding {} is down", id))),
+        }
+    }
+}
+
+impl Worker {
+    /// Jobs per slot, counting those queued.
+    fn load(&self) -> f64 {
+        let jobs = self.info.running_jobs + self.info.queued_jobs + self.dispatched;
+        jobs as f64 / self.info.max_concurrent_jobs.max(1) as f64
+    }
+}
+
+impl<'a> Needs<'a> {
+    pub fn job(req: &'a ComputeRequest) -> Self {
+        Needs {
+            accelerator: req.accelerator(),
+            toolchain: &req.toolchain,
+            libraries: &req.libraries,
+            profiler: req.profiler(),
+            device_index: req.device_index,
+            gpus: req.gpu_count.max(1),
+            mig: &req.mig,
+            shared: req.shared,
+            notify: req.notify,
+        }
+    }
+
+    pub fn kernel(req: &LaunchKernelRequest) -> Self {
+        Needs {
+            accelerator: Accelerator::Cuda,
+            device_index: req.device_index,
+            ..Needs::anything()
+        }
+    }
+
+    /// Any host will do, e.g. for staging inputs.
+    pub fn anything() -> Self {
+        Needs {
+            accelerator: Accelerator::Any,
+            toolchain: "",
+            libraries: &[],
+            profiler: Profiler::None,
+            device_index: None,
+            gpus: 1,
+            mig: "",
+            shared: false,
+            notify: false,
+        }
+    }
+
+    /// Hosts too old to list their backends only have CUDA, and ones whose
+    /// GPUs nvidia-smi can't list run on whichever they have.
+    fn met_by(&self, info: &HostInfo) -> bool {
+        let backend = match self.accelerator {
+            Accelerator::Any => true,
+            Accelerator::Cuda if info.backends.is_empty() => true,
+            accelerator => info.backends.iter().any(|b| b.accelerator() == accelerator),
+        };
+        let profiler = match self.profiler {
+            Profiler::None => true,
+            profiler => info.profilers.iter().any(|p| p.eq_ignore_ascii_case(profiler.as_str_name())),
+        };
+        let gpus = self.accelerator == Accelerator::Cpu
+            || match self.mig {
+                "" if self.shared => info.mps_clients > 0,
+                "" => {
+                    info.gpus.is_empty()
+                        || match self.device_index {
+                            Some(index) => info.gpus.iter().any(|g| g.index == index),
+                            None => info.gpus.len() >= self.gpus as usize,
+                        }
+                }
+                "any" => !info.mig_slices.is_empty(),
+                name => info
+                    .mig_slices
+                    .iter()
+                    .any(|s| s.uuid == name || format!("{}/{}", s.gpu, s.device) == name),
+            };
+        backend
+            && profiler
+            && gpus
+            && (!self.notify || !info.notifiers.is_empty())
+            && (self.toolchain.is_empty() || info.toolchains.iter().any(|t| t == self.toolchain))
+            && self.libraries.iter().all(|l| info.libraries.iter().any(|i| &i.name == l))
+    }
+}
+
+impl Routes {
+    fn insert(&mut self, id: &str, route: Route) {
+        if route.id != id {
+            self.originals.insert(route.id.clone(), id.to_string());
+        }
+        if self.routes.insert(id.to_string(), route).is_none() {
+            self.order.push_back(id.to_string());
+            if self.order.len() > MAX_ROUTES
+                && let Some(oldest) = self.order.pop_front()
+                && let Some(route) = self.routes.remove(&oldest)
+            {
+                self.originals.remove(&route.id);
+            }
+        }
+    }
+
+    /// The route for `id`, which may also be the id a job was rerun under:
+    /// its output names that one too.
+    fn get(&self, id: &str) -> Option<Route> {
+        let original = self.originals.get(id).map_or(id, String::as_str);
+        self.routes.get(original).or_else(|| self.routes.get(id)).cloned()
+    }
+
+    fn original(&self, id: &str) -> String {
+        self.originals.get(id).cloned().unwrap_or_else(|| id.to_string())
+    }
+}
+
@@ -0,0 +1,59 @@
+/// What a job was submitted with, kept so the broker can submit it again on
+/// another worker when its own dies.
+use crate::pool::Client;
+use crate::service::forward;
+use common::compute::submit_message::Kind;
+use common::compute::{ComputeRequest, ComputeResponse, SubmitMessage};
+use tonic::metadata::MetadataMap;
+use tonic::{Status, Streaming};
+
+pub enum Replay {
+    Submit(Vec<SubmitMessage>),
+    RunBinary(Vec<SubmitMessage>),
+    Execute(Box<ComputeRequest>),
+    SubmitJob(Box<ComputeRequest>),
+}
+
+impl Replay {
+    /// The job's settings, which say where it can run.
+    pub fn start(&self) -> Option<&ComputeRequest> {
+        match self {
+            Replay::Submit(messages) | Replay::RunBinary(messages) => match &messages.last()?.kind {
+                Some(Kind::Start(start)) => Some(start),
+                _ => None,
+            },
+            Replay::Execute(request) | Replay::SubmitJob(request) => Some(request),
+        }
+    }
+
+    /// Starts the job on `client` and returns its output stream.
+    pub async fn send(&self, client: &mut Client, metadata: &MetadataMap) -> Result<Streaming<ComputeResponse>, Status> {
+        let response = match self {
+            Replay::Submit(messages) => client.submit(forward(metadata, tokio_stream::iter(messages.clone()))).await,
+            Replay::RunBinary(messages) => {
+                client.run_binary(forward(metadata, tokio_stream::iter(messages.clone()))).await
+            }
+            Replay::Execute(request) => client.execute_code(forward(metadata, (**request).clone())).await,
+            Replay::SubmitJob(_) => return Err(Status::internal("SubmitJob has no output stream")),
+        };
+        Ok(response?.into_inner())
+    }
+
+    /// Starts the job on `client`, left to run there without a client, and
+    /// returns the id it was given.
+    pub async fn resubmit(&self, client: &mut Client, metadata: &MetadataMap) -> Result<String, Status> {
+        if let Replay::SubmitJob(request) = self {
+            let response = client.submit_job(forward(metadata, (**request).clone())).await?;
+            return Ok(response.into_inner().job_id);
+        }
+        // Every message names the job, and the worker carries on once the
+        // stream is dropped
+        let mut stream = self.send(client, metadata).await?;
+        while let Some(response) = stream.message().await? {
+            if !response.job_id.is_empty() {
+                return Ok(response.job_id);
+            }
+        }
+        Err(Status::unavailable("The worker ended the job's stream before naming it"))
+    }
+}
@@ -0,0 +1,690 @@
+/// The broker's side of both services: CUDAExecutor, with each call forwarded
+/// to a worker, and the WorkerRegistry workers register and heartbeat
+/// through.
+///
+/// A call carries its client's `authorization`, file encoding and trace
+/// context on to the worker, which authenticates it as if the client had
+/// called it directly. A job goes to the least loaded worker that can run
+/// it, so a Submit stream is held until its start marker says what the job
+/// needs; calls about an existing job go to the worker that has it. Calls
+/// about every job (ListJobs, or PurgeWorkspaces without a job id) go to all
+/// workers, and GetHostInfo adds up what they report.
+///
+/// When a worker is declared dead, the jobs it had are started again on
+/// another: streamed ones by the stream still open to their client, which
+/// carries on with the new run's output, and ones no client is streaming by
+/// the reaper.
+/// Jobs that used staged inputs went down with them. Clients keep using the
+/// id the job was first given. A stream carried on elsewhere skips as many
+/// of the new run's entries as its client already had, so the offsets it
+/// resumes from with AttachJob still line up; the broker's notice in
+/// between is taken off them before they reach the worker.
+use crate::pool::{Detached, Needs, Pool, Route};
+use crate::replay::Replay;
+use common::auth::constant_time_eq;
+use common::compute::cuda_executor_server::CudaExecutor;
+use common::compute::submit_message::Kind;
+use common::compute::worker_registry_server::WorkerRegistry;
+use common::compute::{
+    AttachJobRequest, CancelJobRequest, CancelJobResponse, ComputeRequest, ComputeResponse, DebugData,
+    FileChunk, GetJobRequest, HeartbeatRequest, HeartbeatResponse, HostInfo, HostInfoRequest, JobOutputRequest,
+    JobOutputResponse, JobRecord, JobStatusRequest, JobStatusResponse, LaunchKernelRequest, LaunchKernelResponse,
+    ListJobsRequest, ListJobsResponse, OutputKind, PurgeWorkspacesRequest, PurgeWorkspacesResponse,
+    RegisterWorkerRequest, RegisterWorkerResponse, StdinChunk, SubmitJobResponse, SubmitMessage,
+    UploadInputsResponse, WriteStdinResponse,
+};
+use common::encoding::FILE_ENCODING_HEADER;
+use prost::Message;
+use std::collections::BTreeSet;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{info, warn};
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// Metadata passed on to workers.
+const FORWARDED: [&str; 4] = ["authorization", FILE_ENCODING_HEADER, "traceparent", "tracestate"];
+
+/// Responses buffered per relayed job stream.
+const RELAY_BUFFER: usize = 64;
+
+pub struct Broker {
+    pub pool: Pool,
+    /// Largest Submit stream held while waiting for its start marker.
+    pub max_buffered_bytes: u64,
+    /// What workers must present to register; `None` lets any host in.
+    pub registration_token: Option<String>,
+}
+
+/// `message` as a request carrying `from`'s forwarded metadata.
+pub fn forward<T>(from: &MetadataMap, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    for key in FORWARDED {
+        if let Some(value) = from.get(key) {
+            request.metadata_mut().insert(key, value.clone());
+        }
+    }
+    request
+}
+
+/// Gives a worker's response the job id its client knows.
+fn restore(response: &mut ComputeResponse, job_id: &str) {
+    response.job_id = job_id.to_string();
+    if let Some(result) = &mut response.result {
+        result.job_id = job_id.to_string();
+    }
+}
+
+/// A job stream from a worker on its way to the client.
+struct Relay {
+    pool: Pool,
+    worker: String,
+    /// The id the client knows, once there is one.
+    job_id: Option<String>,
+    /// The id the current worker has for the job.
+    remote_id: Option<String>,
+    metadata: MetadataMap,
+    /// `None` when the job can't be started again.
+    replay: Option<Replay>,
+    /// Entries from workers passed on so far, artifacts aside.
+    delivered: u64,
+    /// Entries of the current run still to drop, as the client has them.
+    skip: u64,
+    /// Notices sent, as in `Route::shift`.
+    shift: u64,
+    tx: mpsc::Sender<Result<ComputeResponse, Status>>,
+}
+
+impl Relay {
+    async fn run(mut self, mut stream: Streaming<ComputeResponse>) {
+        loop {
+            let mut failure = None;
+            let mut finished = false;
+            while let Some(item) = stream.next().await {
+                let mut response = match item {
+                    Ok(response) => response,
+                    Err(status) => {
+                        failure = Some(status);
+                        break;
+                    }
+                };
+                finished |= response.result.is_some();
+                if !response.job_id.is_empty() {
+                    self.route(&response.job_id);
+                    restore(&mut response, self.job_id.as_deref().unwrap_or_default());
+                }
+                if self.skip > 0 && response.result.is_none() {
+                    if response.artifact.is_none() {
+                        self.skip -= 1;
+                    }
+                    continue;
+                }
+                if response.artifact.is_none() {
+                    self.delivered += 1;
+                }
+                if self.tx.send(Ok(response)).await.is_err() {
+                    // The worker carries on without the client
+                    self.detach();
+                    return;
+                }
+            }
+            if finished {
+                return;
+            }
+            // A stream cut off by its worker dying is started again
+            // elsewhere; any other failure is the client's to see
+            if self.replay.is_none() || !self.declared_dead().await {
+                if let Some(status) = failure {
+                    let _ = self.tx.send(Err(status)).await;
+                }
+                return;
+            }
+            match self.rerun().await {
+                Ok(next) => stream = next,
+                Err(status) => {
+                    let _ = self.tx.send(Err(status)).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Notes where the job is, the first time the current worker names it.
+    fn route(&mut self, remote_id: &str) {
+        if self.remote_id.as_deref() == Some(remote_id) {
+            return;
+        }
+        self.remote_id = Some(remote_id.to_string());
+        let job_id = self.job_id.get_or_insert_with(|| remote_id.to_string());
+        let route = Route {
+            worker: self.worker.clone(),
+            id: remote_id.to_string(),
+            shift: self.shift,
+        };
+        self.pool.remember_job(job_id, route);
+    }
+
+    /// Leaves the job, if it can be run again, to the reaper.
+    fn detach(self) {
+        if let (Some(job_id), Some(remote_id), Some(replay)) = (self.job_id, self.remote_id, self.replay) {
+            let route = Route {
+                worker: self.worker,
+                id: remote_id,
+                shift: self.shift,
+            };
+            self.pool.keep_detached(&job_id, route, replay, self.metadata);
+        }
+    }
+
+    /// Waits for the pool to decide about the worker: true if it is declared
+    /// dead within a heartbeat timeout.
+    async fn declared_dead(&self) -> bool {
+        let deadline = tokio::time::Instant::now() + self.pool.timeout() + Duration::from_secs(2);
+        while tokio::time::Instant::now() < deadline {
+            if !self.pool.is_alive(&self.worker) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        false
+    }
+
+    async fn rerun(&mut self) -> Result<Streaming<ComputeResponse>, Status> {
+        let replay = self.replay.as_ref().expect("only called with a replay");
+        let start = replay.start().ok_or_else(|| Status::internal("Submission without a start marker"))?;
+        let (next, mut client) = self.pool.pick(&Needs::job(start))?;
+        let lost = self.pool.address(&self.worker);
+        let notice = format!(
+            "♻️ The host running this job ({}) was lost; running it again on {}",
+            lost,
+            self.pool.address(&next)
+        );
+        let mut response = ComputeResponse {
+            output: notice.into_bytes(),
+            is_error: true,
+            kind: OutputKind::System.into(),
+            ..Default::default()
+        };
+        restore(&mut response, self.job_id.as_deref().unwrap_or_default());
+        let _ = self.tx.send(Ok(response)).await;
+        self.shift += 1;
+        self.skip = self.delivered;
+        if let Some(remote_id) = self.remote_id.take() {
+            self.pool.supersede(&self.worker, &remote_id);
+        }
+        info!(from = %lost, to = %self.pool.address(&next), job = ?self.job_id, "♻️ Job requeued");
+        let stream = replay.send(&mut client, &self.metadata).await?;
+        self.worker = next;
+        Ok(stream)
+    }
+}
+
+impl Broker {
+    /// Relays a worker's job stream to the client in its own task.
+    fn relay(
+        &self,
+        worker: String,
+        job_id: Option<String>,
+        metadata: MetadataMap,
+        replay: Option<Replay>,
+        shift: u64,
+        stream: Streaming<ComputeResponse>,
+    ) -> ResponseStream<ComputeResponse> {
+        let (tx, rx) = mpsc::channel(RELAY_BUFFER);
+        let relay = Relay {
+            pool: self.pool.clone(),
+            worker,
+            job_id,
+            remote_id: None,
+            metadata,
+            replay,
+            delivered: 0,
+            skip: 0,
+            shift,
+            tx,
+        };
+        tokio::spawn(relay.run(stream));
+        Box::pin(ReceiverStream::new(rx))
+    }
+
+    /// Reads a Submit stream up to its start marker, then hands it all to
+    /// the worker picked for the job.
+    async fn submission(
+        &self,
+        request: Request<Streaming<SubmitMessage>>,
+        binary: bool,
+    ) -> Result<Response<ResponseStream<ComputeResponse>>, Status> {
+        let metadata = request.metadata().clone();
+        let mut incoming = request.into_inner();
+        let mut held = Vec::new();
+        let mut bytes = 0u64;
+        let start = loop {
+            let Some(message) = incoming.message().await? else {
+                return Err(Status::invalid_argument("Submission ended before its start marker"));
+            };
+            bytes += message.encoded_len() as u64;
+            if bytes > self.max_buffered_bytes {
+                return Err(Status::resource_exhausted(format!(
+                    "Submissions through this broker may be at most {} bytes",
+                    self.max_buffered_bytes
+                )));
+            }
+            let start = match &message.kind {
+                Some(Kind::Start(start)) => Some(start.clone()),
+                _ => None,
+            };
+            held.push(message);
+            if let Some(start) = start {
+                break start;
+            }
+        };
+        let (worker, mut client) = self.pool.pick(&Needs::job(&start))?;
+        info!(host = %self.pool.address(&worker), "📨 Job forwarded");
+        let replay = match binary {
+            true => Replay::RunBinary(held),
+            false => Replay::Submit(held),
+        };
+        let stream = replay.send(&mut client, &metadata).await?;
+        Ok(Response::new(self.relay(worker, None, metadata, Some(replay), 0, stream)))
+    }
+
+    /// Submits a detached job again after its worker died.
+    async fn requeue(pool: Pool, job: Detached) {
+        let lost = pool.address(&job.route.worker);
+        let Some(start) = job.replay.start() else {
+            return;
+        };
+        let (worker, mut client) = match pool.pick(&Needs::job(start)) {
+            Ok(picked) => picked,
+            Err(status) => {
+                warn!("⚠️ Job {} was lost with {}: {}", job.job_id, lost, status.message());
+                return;
+            }
+        };
+        let remote_id = match job.replay.resubmit(&mut client, &job.metadata).await {
+            Ok(remote_id) => remote_id,
+            Err(status) => {
+                warn!("⚠️ Job {} was lost with {}: {}", job.job_id, lost, status.message());
+                return;
+            }
+        };
+        info!(from = %lost, to = %pool.address(&worker), job = %job.job_id, "♻️ Job requeued");
+        pool.supersede(&job.route.worker, &job.route.id);
+        let route = Route {
+            worker,
+            id: remote_id,
+            shift: job.route.shift,
+        };
+        pool.remember_job(&job.job_id, route.clone());
+        pool.keep_detached(&job.job_id, route, job.replay, job.metadata);
+    }
+
+    /// Starts requeueing the detached jobs of workers declared dead.
+    pub fn spawn_reaper(&self) {
+        let pool = self.pool.clone();
+        self.pool.spawn_reaper(move |job| Broker::requeue(pool.clone(), job));
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn check_registration<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let Some(token) = &self.registration_token else {
+            return Ok(());
+        };
+        let presented = request.metadata().get("authorization").and_then(|v| v.to_str().ok());
+        match presented.and_then(|v| v.strip_prefix("Bearer ")) {
+            Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => Ok(()),
+            _ => Err(Status::unauthenticated("Workers need the broker's registration token")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl CudaExecutor for Broker {
+    type SubmitStream = ResponseStream<ComputeResponse>;
+
+    async fn submit(
+        &self,
+        request: Request<Streaming<SubmitMessage>>,
+    ) -> Result<Response<Self::SubmitStream>, Status> {
+        self.submission(request, false).await
+    }
+
+    type RunBinaryStream = ResponseStream<ComputeResponse>;
+
+    async fn run_binary(
+        &self,
+        request: Request<Streaming<SubmitMessage>>,
+    ) -> Result<Response<Self::RunBinaryStream>, Status> {
+        self.submission(request, true).await
+    }
+
+    type ExecuteCodeStream = ResponseStream<ComputeResponse>;
+
+    async fn execute_code(
+        &self,
+        request: Request<ComputeRequest>,
+    ) -> Result<Response<Self::ExecuteCodeStream>, Status> {
+        let (metadata, _, req) = request.into_parts();
+        // Staged inputs only exist on the worker they went to
+        let ((worker, mut client), replay) = match req.input_upload_id.as_str() {
+            "" => (self.pool.pick(&Needs::job(&req))?, Some(Replay::Execute(Box::new(req.clone())))),
+            upload_id => (self.pool.upload_host(upload_id)?, None),
+        };
+        info!(host = %self.pool.address(&worker), "📨 Job forwarded");
+        let response = client.execute_code(forward(&metadata, req)).await?;
+        Ok(Response::new(self.relay(worker, None, metadata, replay, 0, response.into_inner())))
+    }
+
+    async fn cancel_job(&self, request: Request<CancelJobRequest>) -> Result<Response<CancelJobResponse>, Status> {
+        let (route, mut client) = self.pool.job_host(&request.get_ref().job_id)?;
+        let req = CancelJobRequest { job_id: route.id };
+        client.cancel_job(forward(request.metadata(), req)).await
+    }
+
+    async fn write_stdin(
+        &self,
+        request: Request<Streaming<StdinChunk>>,
+    ) -> Result<Response<WriteStdinResponse>, Status> {
+        let metadata = request.metadata().clone();
+        let mut incoming = request.into_inner();
+        let Some(mut first) = incoming.message().await? else {
+            return Err(Status::invalid_argument("WriteStdin needs the job id first"));
+        };
+        let (route, mut client) = self.pool.job_host(&first.job_id)?;
+        first.job_id = route.id;
+        let chunks = tokio_stream::once(first).chain(incoming.map_while(Result::ok));
+        client.write_stdin(forward(&metadata, chunks)).await
+    }
+
+    async fn get_host_info(&self, request: Request<HostInfoRequest>) -> Result<Response<HostInfo>, Status> {
+        let mut total = HostInfo::default();
+        let mut versions = [BTreeSet::new(), BTreeSet::new(), BTreeSet::new()];
+        let mut last_error = None;
+        for (_, mut client) in self.pool.alive() {
+            let info = match client.get_host_info(forward(request.metadata(), HostInfoRequest {})).await {
+                Ok(info) => info.into_inner(),
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            for (seen, version) in versions.iter_mut().zip([info.driver_version, info.cuda_version, info.nvcc_version]) {
+                seen.insert(version);
+            }
+            total.gpus.extend(info.gpus);
+            total.mig_slices.extend(info.mig_slices);
+            total.mps_clients = total.mps_clients.max(info.mps_clients);
+            // Any host may get the job, so the smallest limit holds
+            if total.max_chunk_bytes == 0 || (info.max_chunk_bytes > 0 && info.max_chunk_bytes < total.max_chunk_bytes) {
+                total.max_chunk_bytes = info.max_chunk_bytes;
+            }
+            total.max_concurrent_jobs += info.max_concurrent_jobs;
+            total.max_timeout_secs = total.max_timeout_secs.max(info.max_timeout_secs);
+            total.running_jobs += info.running_jobs;
+            total.queued_jobs += info.queued_jobs;
+            for toolchain in info.toolchains {
+                if !total.toolchains.contains(&toolchain) {
+                    total.toolchains.push(toolchain);
+                }
+            }
+            for profiler in info.profilers {
+                if !total.profilers.contains(&profiler) {
+                    total.profilers.push(profiler);
+                }
+            }
+            for notifier in info.notifiers {
+                if !total.notifiers.contains(&notifier) {
+                    total.notifiers.push(notifier);
+                }
+            }
+            for library in info.libraries {
+                if !total.libraries.iter().any(|l| l.name == library.name) {
+                    total.libraries.push(library);
+                }
+            }
+            for backend in info.backends {
+                if !total.backends.iter().any(|b| b.accelerator == backend.accelerator) {
+                    total.backends.push(backend);
+                }
+            }
+        }
+        if total.max_concurrent_jobs == 0 {
+            return Err(last_error.unwrap_or_else(|| Status::unavailable("No host is available")));
+        }
+        // Workers that differ list every version they have
+        let [driver, cuda, nvcc] = versions.map(|seen| seen.into_iter().collect::<Vec<_>>().join(" / "));
+        total.driver_version = driver;
+        total.cuda_version = cuda;
+        total.nvcc_version = nvcc;
+        Ok(Response::new(total))
+    }
+
+    async fn upload_inputs(
+        &self,
+        request: Request<Streaming<FileChunk>>,
+    ) -> Result<Response<UploadInputsResponse>, Status> {
+        // The job that uses them will follow the inputs to this worker
+        let (worker, mut client) = self.pool.pick(&Needs::anything())?;
+        let metadata = request.metadata().clone();
+        let chunks = request.into_inner().map_while(Result::ok);
+        let response = client.upload_inputs(forward(&metadata, chunks)).await?;
+        self.pool.remember_upload(&response.get_ref().upload_id, &worker);
+        Ok(response)
+    }
+
+    async fn submit_job(&self, request: Request<ComputeRequest>) -> Result<Response<SubmitJobResponse>, Status> {
+        let (metadata, _, req) = request.into_parts();
+        let (worker, mut client) = match req.input_upload_id.as_str() {
+            "" => self.pool.pick(&Needs::job(&req))?,
+            upload_id => self.pool.upload_host(upload_id)?,
+        };
+        let response = client.submit_job(forward(&metadata, req.clone())).await?;
+        let job_id = response.get_ref().job_id.clone();
+        info!(host = %self.pool.address(&worker), job = %job_id, "📨 Job forwarded");
+        let route = Route {
+            worker,
+            id: job_id.clone(),
+            shift: 0,
+        };
+        self.pool.remember_job(&job_id, route.clone());
+        if req.input_upload_id.is_empty() {
+            self.pool.keep_detached(&job_id, route, Replay::SubmitJob(Box::new(req)), metadata);
+        }
+        Ok(response)
+    }
+
+    async fn get_job_status(&self, request: Request<JobStatusRequest>) -> Result<Response<JobStatusResponse>, Status> {
+        let job_id = &request.get_ref().job_id;
+        let (route, mut client) = self.pool.job_host(job_id)?;
+        let req = JobStatusRequest { job_id: route.id };
+        let mut response = client.get_job_status(forward(request.metadata(), req)).await?;
+        let status = response.get_mut();
+        status.job_id = job_id.clone();
+        if let Some(result) = &mut status.result {
+            result.job_id = job_id.clone();
+        }
+        Ok(response)
+    }
+
+    async fn get_job_output(&self, request: Request<JobOutputRequest>) -> Result<Response<JobOutputResponse>, Status> {
+        let job_id = &request.get_ref().job_id;
+        let (route, mut client) = self.pool.job_host(job_id)?;
+        let req = JobOutputRequest {
+            job_id: route.id,
+            from_offset: request.get_ref().from_offset.saturating_sub(route.shift),
+            ..request.get_ref().clone()
+        };
+        let mut response = client.get_job_output(forward(request.metadata(), req)).await?;
+        let page = response.get_mut();
+        for entry in &mut page.entries {
+            restore(entry, job_id);
+        }
+        page.next_offset += route.shift;
+        if page.first_offset > 0 {
+            page.first_offset += route.shift;
+        }
+        Ok(response)
+    }
+
+    type AttachJobStream = ResponseStream<ComputeResponse>;
+
+    async fn attach_job(
+        &self,
+        request: Request<AttachJobRequest>,
+    ) -> Result<Response<Self::AttachJobStream>, Status> {
+        let (metadata, _, req) = request.into_parts();
+        let (route, mut client) = self.pool.job_host(&req.job_id)?;
+        let job_id = req.job_id.clone();
+        let req = AttachJobRequest {
+            job_id: route.id,
+            from_offset: req.from_offset.saturating_sub(route.shift),
+        };
+        let response = client.attach_job(forward(&metadata, req)).await?;
+        let stream = response.into_inner();
+        Ok(Response::new(self.relay(route.worker, Some(job_id), metadata, None, route.shift, stream)))
+    }
+
+    async fn list_jobs(&self, request: Request<ListJobsRequest>) -> Result<Response<ListJobsResponse>, Status> {
+        let mut jobs: Vec<JobRecord> = Vec::new();
+        for (worker, mut client) in self.pool.alive() {
+            let Ok(response) = client.list_jobs(forward(request.metadata(), request.get_ref().clone())).await else {
+                continue;
+            };
+            for mut job in response.into_inner().jobs {
+                let job_id = self.pool.original_id(&job.job_id);
+                // A worker that came back still lists the jobs run elsewhere
+                if self.pool.route(&job_id).is_some_and(|route| route.worker != worker) {
+                    continue;
+                }
+                // Listed jobs can be looked at further
+                let route = Route {
+                    worker: worker.clone(),
+                    id: job.job_id.clone(),
+                    shift: 0,
+                };
+                self.pool.learn_job(&job_id, route);
+                job.job_id = job_id;
+                jobs.push(job);
+            }
+        }
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.submitted_at_ms));
+        if request.get_ref().limit > 0 {
+            jobs.truncate(request.get_ref().limit as usize);
+        }
+        Ok(Response::new(ListJobsResponse { jobs }))
+    }
+
+    async fn get_job(&self, request: Request<GetJobRequest>) -> Result<Response<JobRecord>, Status> {
+        let job_id = &request.get_ref().job_id;
+        if let Ok((route, mut client)) = self.pool.job_host(job_id) {
+            let req = GetJobRequest { job_id: route.id };
+            let mut response = client.get_job(forward(request.metadata(), req)).await?;
+            response.get_mut().job_id = job_id.clone();
+            return Ok(response);
+        }
+        // History outlives the broker's memory of where jobs went
+        for (worker, mut client) in self.pool.alive() {
+            match client.get_job(forward(request.metadata(), request.get_ref().clone())).await {
+                Ok(response) => {
+                    let route = Route {
+                        worker,
+                        id: job_id.clone(),
+                        shift: 0,
+                    };
+                    self.pool.learn_job(job_id, route);
+                    return Ok(response);
+                }
+                Err(status) if status.code() == tonic::Code::NotFound => continue,
+                Err(status) => return Err(status),
+            }
+        }
+        Err(Status::not_found(format!("No host knows job {}", job_id)))
+    }
+
+    async fn purge_workspaces(
+        &self,
+        request: Request<PurgeWorkspacesRequest>,
+    ) -> Result<Response<PurgeWorkspacesResponse>, Status> {
+        if !request.get_ref().job_id.is_empty() {
+            let (route, mut client) = self.pool.job_host(&request.get_ref().job_id)?;
+            let req = PurgeWorkspacesRequest { job_id: route.id };
+            return client.purge_workspaces(forward(request.metadata(), req)).await;
+        }
+        // One host failing doesn't stop the others from purging
+        let mut response = PurgeWorkspacesResponse::default();
+        for (worker, mut client) in self.pool.alive() {
+            match client.purge_workspaces(forward(request.metadata(), request.get_ref().clone())).await {
+                Ok(purged) => response.purged += purged.into_inner().purged,
+                Err(status) => {
+                    let failure = format!("{}: {}", self.pool.address(&worker), status.message());
+                    warn!("⚠️ Purge failed on {}", failure);
+                    response.failures.push(failure);
+                }
+            }
+        }
+        Ok(Response::new(response))
+    }
+
+    type DebugTunnelStream = ResponseStream<DebugData>;
+
+    async fn debug_tunnel(
+        &self,
+        request: Request<Streaming<DebugData>>,
+    ) -> Result<Response<Self::DebugTunnelStream>, Status> {
+        let metadata = request.metadata().clone();
+        let mut incoming = request.into_inner();
+        let Some(mut first) = incoming.message().await? else {
+            return Err(Status::invalid_argument("DebugTunnel needs the job id first"));
+        };
+        let (route, mut client) = self.pool.job_host(&first.job_id)?;
+        first.job_id = route.id;
+        let data = tokio_stream::once(first).chain(incoming.map_while(Result::ok));
+        let response = client.debug_tunnel(forward(&metadata, data)).await?;
+        Ok(Response::new(Box::pin(response.into_inner())))
+    }
+
+    async fn launch_kernel(
+        &self,
+        request: Request<LaunchKernelRequest>,
+    ) -> Result<Response<LaunchKernelResponse>, Status> {
+        let (worker, mut client) = self.pool.pick(&Needs::kernel(request.get_ref()))?;
+        info!(host = %self.pool.address(&worker), kernel = %request.get_ref().kernel, "📨 Kernel launch forwarded");
+        client.launch_kernel(forward(request.metadata(), request.get_ref().clone())).await
+    }
+}
+
+#[tonic::async_trait]
+impl WorkerRegistry for Broker {
+    async fn register(
+        &self,
+        request: Request<RegisterWorkerRequest>,
+    ) -> Result<Response<RegisterWorkerResponse>, Status> {
+        self.check_registration(&request)?;
+        let req = request.into_inner();
+        let Some(info) = req.info.filter(|_| !req.worker_id.is_empty()) else {
+            return Err(Status::invalid_argument("Workers register with an id and their host info"));
+        };
+        let (gpus, slots) = (info.gpus.len(), info.max_concurrent_jobs);
+        if self.pool.register(&req.worker_id, &req.address, info).map_err(Status::invalid_argument)? {
+            info!("🟢 Worker {} at {} joined: {} GPU(s), {} job slot(s)", req.worker_id, req.address, gpus, slots);
+        }
+        Ok(Response::new(RegisterWorkerResponse {}))
+    }
+
+    async fn heartbeat(&self, request: Request<HeartbeatRequest>) -> Result<Response<HeartbeatResponse>, Status> {
+        self.check_registration(&request)?;
+        let response = match self.pool.heartbeat(request.get_ref()) {
+            Some(cancel_jobs) => HeartbeatResponse {
+                registered: true,
+                cancel_jobs,
+            },
+            None => HeartbeatResponse::default(),
+        };
+        Ok(Response::new(response))
+    }
+}
@@ -0,0 +1,109 @@
+/// The `admin` subcommands: operational control of a host through its Admin
+/// service. Only the users the host names in `admin_users` may use them.
+use crate::connection::Connection;
+use crate::jobs::ago;
+use crate::messages::Messages;
+use crate::table;
+use clap::Subcommand;
+use colored::*;
+use common::compute::{
+    DrainQueueRequest, GetConfigRequest, JobPhase, KillJobRequest, ListActiveJobsRequest, ListOrphansRequest,
+    PurgeRequest, SetAcceptingRequest,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum AdminCommand {
+    /// List every queued and running job, whoever submitted it
+    Jobs,
+    /// Cancel any user's job
+    Kill { job_id: String },
+    /// Cancel every job still waiting in the queue; running jobs finish
+    Drain,
+    /// Stop accepting new jobs; queued and running ones carry on
+    Pause,
+    /// Accept new jobs again
+    Resume,
+    /// Empty the compile cache and/or delete every kept workspace
+    Purge {
+        #[arg(long)]
+        cache: bool,
+        #[arg(long)]
+        workspaces: bool,
+    },
+    /// Print the host's settings in effect, secrets redacted
+    Config,
+    /// List the directories the host failed to delete and gave up on
+    Orphans,
+}
+
+pub async fn run(connection: &Connection, msg: &Messages, command: AdminCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let mut admin = connection.host(msg).await?.admin();
+    match command {
+        AdminCommand::Jobs => {
+            let jobs = admin.list_active_jobs(ListActiveJobsRequest {}).await?.into_inner().jobs;
+            if jobs.is_empty() {
+                println!("{}", msg.get("admin_no_jobs", &[]));
+                return Ok(());
+            }
+            let mut rows = vec![table::header(&msg.get("admin_jobs_columns", &[]))];
+            for job in &jobs {
+                let phase = match job.phase() {
+                    JobPhase::Queued => format!("QUEUED #{}", job.queue_position),
+                    phase => phase.as_str_name().to_string(),
+                };
+                rows.push(vec![job.job_id.clone(), job.submitter.clone(), phase, ago(msg, job.submitted_at_ms)]);
+            }
+            table::print(&rows);
+        }
+        AdminCommand::Kill { job_id } => {
+            let killed = admin
+                .kill_job(KillJobRequest { job_id: job_id.clone() })
+                .await?
+                .into_inner()
+                .killed;
+            match killed {
+                true => println!("{}", msg.get("admin_killed", &[("job", &job_id.cyan())])),
+                false => return Err(msg.get("admin_not_active", &[("job", &job_id)]).into()),
+            }
+        }
+        AdminCommand::Drain => {
+            let cancelled = admin.drain_queue(DrainQueueRequest {}).await?.into_inner().cancelled;
+            println!("{}", msg.get("admin_drained", &[("count", &cancelled)]));
+        }
+        AdminCommand::Pause | AdminCommand::Resume => {
+            let accepting = matches!(command, AdminCommand::Resume);
+            admin.set_accepting(SetAcceptingRequest { accepting }).await?;
+            let key = if accepting { "admin_resumed" } else { "admin_paused" };
+            println!("{}", msg.get(key, &[]));
+        }
+        AdminCommand::Purge { cache, workspaces } => {
+            if !cache && !workspaces {
+                return Err(msg.get("admin_purge_what", &[]).into());
+            }
+            let purged = admin.purge(PurgeRequest { cache, workspaces }).await?.into_inner();
+            println!(
+                "{}",
+                msg.get(
+                    "admin_purged",
+                    &[("cache", &purged.cache_entries), ("workspaces", &purged.workspaces)],
+                )
+            );
+        }
+        AdminCommand::Config => {
+            print!("{}", admin.get_config(GetConfigRequest {}).await?.into_inner().toml);
+        }
+        AdminCommand::Orphans => {
+            let orphans = admin.list_orphans(ListOrphansRequest {}).await?.into_inner().orphans;
+            if orphans.is_empty() {
+                println!("{}", msg.get("admin_no_orphans", &[]));
+                return Ok(());
+            }
+            let mut rows = vec![table::header(&msg.get("admin_orphans_columns", &[]))];
+            for orphan in &orphans {
+                rows.push(vec![orphan.path.clone(), ago(msg, orphan.since_ms), orphan.error.clone()]);
+            }
+            table::print(&rows);
+        }
+    }
+    Ok(())
+}
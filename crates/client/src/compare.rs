@@ -0,0 +1,331 @@
+/// `compare`: the same job submitted to every `--server` at once (say an
+/// sm_70 box and an sm_90 box), then the results side by side and a diff of
+/// each host's stdout against the first one's, to catch behavior that
+/// depends on the architecture.
+///
+/// Output isn't streamed, as the hosts' would interleave; each host reports
+/// when its job is accepted and when it ends. Ctrl-C cancels every job.
+use crate::connection::Connection;
+use crate::messages::Messages;
+use crate::{JobArgs, events, locate, request, summary, table};
+use colored::*;
+use common::compute::{CancelJobRequest, HostInfo, HostInfoRequest, JobResult, JobStatus, OutputKind};
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+/// Unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+/// Largest table the line diff builds; past it, the differing stretch is
+/// shown as all removed then all added.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+struct Outcome {
+    server: String,
+    /// The GPUs the job ran on, e.g. "Tesla V100-SXM2-16GB (sm_70)".
+    gpu: String,
+    result: Option<JobResult>,
+    stdout: Vec<u8>,
+    error: Option<String>,
+}
+
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+    /// Unchanged lines left out, this many.
+    Skipped(usize),
+}
+
+pub async fn run(args: JobArgs, connection: &Connection, msg: &Messages) -> Result<(), Box<dyn std::error::Error>> {
+    if args.detach || args.stdin || args.debug || args.prebuilt || args.fetch_binary || !args.fetch.is_empty() {
+        return Err(msg.get("compare_unsupported", &[]).into());
+    }
+    let servers = connection.each_server();
+    if servers.len() < 2 {
+        return Err(msg.get("compare_servers", &[]).into());
+    }
+    let json = args.json || args.output == events::OutputFormat::Json;
+    let sources = locate(&args.files, msg)?;
+    let inputs = locate(&args.inputs, msg)?;
+    let start = request(&args);
+
+    let jobs: Vec<_> = servers
+        .into_iter()
+        .map(|connection| {
+            let (sources, inputs, start, msg) = (sources.clone(), inputs.clone(), start.clone(), msg.clone());
+            tokio::spawn(async move { submit(json, connection, sources, inputs, start, msg).await })
+        })
+        .collect();
+    let mut outcomes = Vec::new();
+    for job in jobs {
+        outcomes.push(job.await?);
+    }
+
+    // Hosts are compared with the first that finished its job
+    let reference = outcomes.iter().position(|o| o.result.is_some());
+    let matches: Vec<Option<bool>> = outcomes
+        .iter()
+        .enumerate()
+        .map(|(i, outcome)| match reference {
+            Some(r) if r != i && outcome.result.is_some() => Some(outcome.stdout == outcomes[r].stdout),
+            _ => None,
+        })
+        .collect();
+    let texts: Vec<Cow<str>> = outcomes.iter().map(|o| String::from_utf8_lossy(&o.stdout)).collect();
+    let diffs: Vec<Option<Vec<Line>>> = matches
+        .iter()
+        .enumerate()
+        .map(|(i, matched)| match (reference, matched) {
+            (Some(r), Some(false)) => Some(diff(&texts[r], &texts[i])),
+            _ => None,
+        })
+        .collect();
+
+    if json {
+        events::compare(outcomes.iter().zip(&matches).zip(&diffs).map(|((outcome, matched), diff)| {
+            events::Compared {
+                server: &outcome.server,
+                gpu: &outcome.gpu,
+                result: outcome.result.as_ref(),
+                error: outcome.error.as_deref(),
+                stdout_matches: *matched,
+                stdout_diff: diff.as_deref().map(|diff| render_plain(msg, diff)),
+            }
+        }));
+    } else {
+        println!("\n{}", msg.get("compare_title", &[]).bold());
+        print_table(msg, &outcomes, reference, &matches);
+        for (i, diff) in diffs.iter().enumerate() {
+            let (Some(r), Some(diff)) = (reference, diff) else { continue };
+            let args: [(&str, &dyn std::fmt::Display); 4] = [
+                ("index", &(i + 1)),
+                ("server", &outcomes[i].server),
+                ("reference_index", &(r + 1)),
+                ("reference", &outcomes[r].server),
+            ];
+            println!("\n{}", msg.get("compare_diff", &args).bold());
+            print_diff(msg, diff);
+        }
+        if reference.is_some() && matches.iter().all(|m| *m != Some(false)) {
+            println!("\n{}", msg.get("compare_same", &[]).green());
+        }
+    }
+
+    let all_agree = matches.iter().all(|m| *m != Some(false))
+        && outcomes
+            .iter()
+            .all(|o| o.result.as_ref().is_some_and(|r| r.status() == JobStatus::Succeeded));
+    if !all_agree {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Runs the job on one host, collecting what the comparison needs.
+async fn submit(
+    json: bool,
+    connection: Connection,
+    sources: Vec<(String, PathBuf)>,
+    inputs: Vec<(String, PathBuf)>,
+    start: common::compute::ComputeRequest,
+    msg: Messages,
+) -> Outcome {
+    let mut outcome = Outcome {
+        server: connection.server().to_string(),
+        gpu: "-".into(),
+        result: None,
+        stdout: Vec::new(),
+        error: None,
+    };
+    if let Err(e) = collect(json, &connection, sources, inputs, start, &msg, &mut outcome).await {
+        let error = e.to_string();
+        say!(json, "{}", msg.get("compare_failed", &[("server", &outcome.server.cyan()), ("error", &error)]).red());
+        outcome.error = Some(error);
+    }
+    outcome
+}
+
+async fn collect(
+    json: bool,
+    connection: &Connection,
+    sources: Vec<(String, PathBuf)>,
+    inputs: Vec<(String, PathBuf)>,
+    start: common::compute::ComputeRequest,
+    msg: &Messages,
+    outcome: &mut Outcome,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let host = connection.host(msg).await.map_err(|e| e.to_string())?;
+    // Only for naming the GPUs; a host that can't say still gets compared
+    let info = host.grpc().get_host_info(HostInfoRequest {}).await.ok().map(|r| r.into_inner());
+    let mut job = host.submit(sources, inputs, start).await?;
+    say!(json, "{}", msg.get("compare_accepted", &[("server", &outcome.server.cyan()), ("job", &job.id())]));
+
+    let canceller = tokio::spawn({
+        let (msg, job_id) = (msg.clone(), job.id().to_string());
+        let client = host.grpc();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                eprintln!("{}", msg.get("cancelling", &[("job", &job_id)]).yellow());
+                let request = CancelJobRequest { job_id };
+                let _ = client.clone().cancel_job(request).await;
+            }
+        }
+    });
+    let streamed = async {
+        while let Some(response) = job.next_message().await? {
+            if response.kind() == OutputKind::Stdout {
+                outcome.stdout.extend_from_slice(&response.output);
+            }
+            if let Some(result) = response.result {
+                outcome.gpu = gpus(info.as_ref(), &result.devices);
+                say!(json, "{}", msg.get("compare_finished", &[("server", &outcome.server.cyan()), ("summary", &summary(msg, &result))]));
+                outcome.result = Some(result);
+            }
+        }
+        Ok::<_, ferris_compute_client::Error>(())
+    }
+    .await;
+    canceller.abort();
+    Ok(streamed?)
+}
+
+/// The names and architectures of the GPUs a job ran on.
+fn gpus(info: Option<&HostInfo>, devices: &[u32]) -> String {
+    let Some(info) = info else { return "-".into() };
+    let mut names: Vec<String> = Vec::new();
+    for gpu in info.gpus.iter().filter(|g| devices.contains(&g.index)) {
+        let name = format!("{} (sm_{})", gpu.name, gpu.compute_capability.replace('.', ""));
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if names.is_empty() { "-".into() } else { names.join(", ") }
+}
+
+fn print_table(msg: &Messages, outcomes: &[Outcome], reference: Option<usize>, matches: &[Option<bool>]) {
+    let mut table = vec![table::header(&msg.get("compare_columns", &[]))];
+    for (i, (outcome, matched)) in outcomes.iter().zip(matches).enumerate() {
+        let (status, exit, compile, run) = match &outcome.result {
+            Some(r) => (
+                r.status().as_str_name().to_string(),
+                match (r.exit_code, r.signal) {
+                    (Some(code), _) => code.to_string(),
+                    (None, Some(signal)) => msg.get("exit_signal", &[("signal", &signal)]),
+                    (None, None) => "-".into(),
+                },
+                format!("{:.2}s", r.compile_ms as f64 / 1000.0),
+                format!("{:.2}s", r.run_ms as f64 / 1000.0),
+            ),
+            None => ("-".into(), "-".into(), "-".into(), "-".into()),
+        };
+        let stdout = match (matched, reference) {
+            (Some(true), Some(r)) => msg.get("compare_stdout_same", &[("reference_index", &(r + 1))]),
+            (Some(false), Some(r)) => msg.get("compare_stdout_differs", &[("reference_index", &(r + 1))]),
+            _ if reference == Some(i) => msg.get("compare_stdout_reference", &[]),
+            _ => "-".into(),
+        };
+        table.push(vec![(i + 1).to_string(), outcome.server.clone(), outcome.gpu.clone(), status, exit, compile, run, stdout]);
+    }
+    table::print(&table);
+}
+
+fn print_diff(msg: &Messages, lines: &[Line]) {
+    for line in lines {
+        match line {
+            Line::Same(text) => println!("  {}", text.dimmed()),
+            Line::Removed(text) => println!("{}", format!("- {}", text).red()),
+            Line::Added(text) => println!("{}", format!("+ {}", text).green()),
+            Line::Skipped(count) => println!("{}", msg.get("compare_skipped", &[("count", count)]).cyan()),
+        }
+    }
+}
+
+/// The diff as text, for `--output json`.
+fn render_plain(msg: &Messages, lines: &[Line]) -> String {
+    lines
+        .iter()
+        .map(|line| match line {
+            Line::Same(text) => format!("  {}\n", text),
+            Line::Removed(text) => format!("- {}\n", text),
+            Line::Added(text) => format!("+ {}\n", text),
+            Line::Skipped(count) => format!("{}\n", msg.get("compare_skipped", &[("count", count)])),
+        })
+        .collect()
+}
+
+/// A line diff turning `a` into `b`, with only the unchanged lines near a
+/// change kept.
+fn diff<'a>(a: &'a str, b: &'a str) -> Vec<Line<'a>> {
+    let (a, b): (Vec<&str>, Vec<&str>) = (a.lines().collect(), b.lines().collect());
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let mut lines: Vec<Line> = a[..prefix].iter().map(|l| Line::Same(l)).collect();
+    lines.extend(changes(&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]));
+    lines.extend(a[a.len() - suffix..].iter().map(|l| Line::Same(l)));
+
+    let mut keep = vec![false; lines.len()];
+    for (i, line) in lines.iter().enumerate() {
+        if !matches!(line, Line::Same(_)) {
+            let end = (i + CONTEXT + 1).min(lines.len());
+            keep[i.saturating_sub(CONTEXT)..end].fill(true);
+        }
+    }
+    let mut trimmed = Vec::new();
+    let mut skipped = 0;
+    for (line, keep) in lines.into_iter().zip(keep) {
+        if !keep {
+            skipped += 1;
+            continue;
+        }
+        if skipped > 0 {
+            trimmed.push(Line::Skipped(skipped));
+            skipped = 0;
+        }
+        trimmed.push(line);
+    }
+    if skipped > 0 {
+        trimmed.push(Line::Skipped(skipped));
+    }
+    trimmed
+}
+
+/// The edits of a longest common subsequence of the lines.
+fn changes<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<Line<'a>> {
+    if a.len().saturating_mul(b.len()) > MAX_DIFF_CELLS {
+        return a.iter().map(|l| Line::Removed(l)).chain(b.iter().map(|l| Line::Added(l))).collect();
+    }
+    // lengths[i * width + j]: the longest common subsequence of a[i..] and b[j..]
+    let width = b.len() + 1;
+    let mut lengths = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i * width + j] = if a[i] == b[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            lines.push(Line::Same(a[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            lines.push(Line::Removed(a[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(b[j]));
+            j += 1;
+        }
+    }
+    lines.extend(a[i..].iter().map(|l| Line::Removed(l)));
+    lines.extend(b[j..].iter().map(|l| Line::Added(l)));
+    lines
+}
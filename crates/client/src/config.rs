@@ -0,0 +1,113 @@
+/// The client's configuration file: named profiles, each with a host to talk
+/// to and defaults for the jobs sent there. It lives at
+/// `~/.config/ferris-compute/config.toml` (under `$XDG_CONFIG_HOME` if set,
+/// `%APPDATA%` on Windows) unless `--config` names another one:
+///
+/// ```toml
+/// default_profile = "lab"
+///
+/// [profiles.lab]
+/// server = "https://gpu-box:50051"
+/// token = "..."
+/// flags = ["-O3"]
+/// arch = "sm_80"
+/// ```
+///
+/// `--profile` picks a profile, falling back to `default_profile`. Flags given
+/// on the command line override the profile's settings; its nvcc flags come
+/// before the command line's, so those win where nvcc takes the last one.
+use crate::messages::Messages;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    pub default_profile: Option<String>,
+    pub profiles: HashMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub server: Option<String>,
+    pub token: Option<String>,
+    pub ca_cert: Option<PathBuf>,
+    pub tls_domain: Option<String>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub compress: bool,
+    pub retries: Option<u32>,
+    pub retry_delay_ms: Option<u64>,
+    /// Passed to nvcc before any `--flags`.
+    pub flags: Vec<String>,
+    /// Compiled for (`-arch=<arch>`) unless the flags pick a target themselves.
+    pub arch: Option<String>,
+}
+
+impl ClientConfig {
+    /// Reads `path`, or the default location if it exists.
+    pub fn load(path: Option<&Path>, msg: &Messages) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(ClientConfig::default()),
+            },
+        };
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| msg.get("config_unreadable", &[("path", &path.display()), ("error", &e)]))?;
+        toml::from_str(&text).map_err(|e| msg.get("config_invalid", &[("path", &path.display()), ("error", &e)]))
+    }
+
+    /// The named profile, or the default one; an empty profile if neither is set.
+    pub fn profile(mut self, name: Option<&str>, msg: &Messages) -> Result<Profile, String> {
+        let Some(name) = name.map(str::to_string).or(self.default_profile.take()) else {
+            return Ok(Profile::default());
+        };
+        self.profiles.remove(&name).ok_or_else(|| {
+            let mut known: Vec<_> = self.profiles.into_keys().collect();
+            known.sort();
+            msg.get("config_no_profile", &[("profile", &name), ("known", &known.join(", "))])
+        })
+    }
+}
+
+impl Profile {
+    /// The profile's nvcc flags followed by `flags` from the command line.
+    pub fn compiler_flags(&self, flags: Vec<String>) -> Vec<String> {
+        let mut all = self.flags.clone();
+        if let Some(arch) = &self.arch
+            && !names_target(&self.flags)
+            && !names_target(&flags)
+        {
+            all.push(format!("-arch={}", arch));
+        }
+        all.extend(flags);
+        all
+    }
+}
+
+/// Whether the flags already choose the GPU code to generate.
+fn names_target(flags: &[String]) -> bool {
+    flags.iter().any(|flag| {
+        let name = flag.split('=').next().unwrap_or(flag);
+        matches!(
+            name,
+            "-arch" | "--gpu-architecture" | "-gencode" | "--generate-code" | "-code" | "--gpu-code"
+        ) || name.starts_with("-arch")
+    })
+}
+
+fn default_path() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else {
+        match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        }
+    };
+    Some(base.join("ferris-compute").join("config.toml"))
+}
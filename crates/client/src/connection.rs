@@ -0,0 +1,137 @@
+/// The flags that say how to reach the host, merged with the config profile;
+/// the SDK does the connecting itself.
+use crate::config::Profile;
+use crate::messages::Messages;
+use colored::*;
+use common::encoding::FileEncoding;
+use ferris_compute_client::{Client, Host, RetryPolicy, Retrying};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct Connection {
+    /// Remote host address (e.g., http://192.168.1.50:50051, or https://... for TLS) [http://[::1]:50051];
+    /// `compare` takes several
+    #[arg(short, long, global = true)]
+    server: Vec<String>,
+
+    /// PEM CA certificate to verify the host against (default: system roots)
+    #[arg(long, global = true)]
+    ca_cert: Option<PathBuf>,
+
+    /// Name to expect in the host's certificate, if it differs from the server address
+    #[arg(long, global = true)]
+    tls_domain: Option<String>,
+
+    /// PEM client certificate, for hosts that require mutual TLS
+    #[arg(long, global = true, requires = "client_key")]
+    client_cert: Option<PathBuf>,
+
+    /// PEM private key matching --client-cert
+    #[arg(long, global = true, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+
+    /// Bearer token for hosts that require authentication
+    #[arg(long, global = true, env = "FERRIS_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// Compress transfers: zstd for files in both directions, gzip for messages
+    #[arg(long, global = true, env = "FERRIS_COMPRESS")]
+    compress: bool,
+
+    /// Retries when the host can't be reached or a job's stream drops (0 = fail at once) [3]
+    #[arg(long, global = true, env = "FERRIS_RETRIES")]
+    retries: Option<u32>,
+
+    /// Milliseconds before the first retry; the wait doubles each time, up to 30s [500]
+    #[arg(long, global = true, value_name = "MS")]
+    retry_delay: Option<u64>,
+}
+
+const DEFAULT_SERVER: &str = "http://[::1]:50051";
+
+impl Connection {
+    /// Fills in whatever the command line left unset from a config profile.
+    pub fn apply(&mut self, profile: Profile) {
+        if self.server.is_empty() {
+            self.server.extend(profile.server);
+        }
+        self.ca_cert = self.ca_cert.take().or(profile.ca_cert);
+        self.tls_domain = self.tls_domain.take().or(profile.tls_domain);
+        if self.client_cert.is_none() && self.client_key.is_none() {
+            self.client_cert = profile.client_cert;
+            self.client_key = profile.client_key;
+        }
+        self.token = self.token.take().or(profile.token);
+        self.compress |= profile.compress;
+        self.retries = self.retries.or(profile.retries);
+        self.retry_delay = self.retry_delay.or(profile.retry_delay_ms);
+    }
+
+    pub fn server(&self) -> &str {
+        self.server.first().map_or(DEFAULT_SERVER, String::as_str)
+    }
+
+    /// Every `--server` given, each as a connection of its own with the
+    /// other settings shared.
+    pub fn each_server(&self) -> Vec<Connection> {
+        self.server
+            .iter()
+            .map(|server| Connection {
+                server: vec![server.clone()],
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    pub fn file_encoding(&self) -> FileEncoding {
+        if self.compress {
+            FileEncoding::Zstd
+        } else {
+            FileEncoding::Identity
+        }
+    }
+
+    /// The raw gRPC client, for the calls the SDK doesn't wrap.
+    pub async fn connect(&self, msg: &Messages) -> Result<Client, Box<dyn std::error::Error>> {
+        Ok(self.host(msg).await?.grpc())
+    }
+
+    pub async fn host(&self, msg: &Messages) -> Result<Host, Box<dyn std::error::Error>> {
+        let msg = msg.clone();
+        let retry = RetryPolicy::new(
+            self.retries.unwrap_or(3),
+            Duration::from_millis(self.retry_delay.unwrap_or(500)),
+        )
+        .on_retry(move |retry: &Retrying| {
+            let delay = format!("{:.1}s", retry.delay.as_secs_f64());
+            let args: [(&str, &dyn std::fmt::Display); 4] = [
+                ("error", &retry.error),
+                ("delay", &delay),
+                ("attempt", &retry.attempt),
+                ("retries", &retry.retries),
+            ];
+            let line = match &retry.job_id {
+                Some(job) => msg.get("retry_reattach", &[&args[..], &[("job", job)]].concat()),
+                None => msg.get("retry_connect", &args),
+            };
+            eprintln!("{}", line.yellow());
+        });
+        let mut connection = ferris_compute_client::Connection::new(self.server())
+            .with_compression(self.compress)
+            .with_retry(retry);
+        if let Some(path) = &self.ca_cert {
+            connection = connection.with_ca_cert(path);
+        }
+        if let Some(domain) = &self.tls_domain {
+            connection = connection.with_tls_domain(domain);
+        }
+        if let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) {
+            connection = connection.with_client_identity(cert, key);
+        }
+        if let Some(token) = &self.token {
+            connection = connection.with_token(token);
+        }
+        Ok(connection.connect().await?)
+    }
+}
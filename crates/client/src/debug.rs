@@ -0,0 +1,33 @@
+/// `--debug`: the local end of a debug session. Once the host says its
+/// cuda-gdbserver is waiting, we listen on a loopback port and carry each
+/// connection a debugger makes there to the host over `DebugTunnel`.
+use crate::messages::Messages;
+use common::compute::DebugSession;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use tokio::net::{TcpListener, TcpStream};
+
+pub async fn listen(port: u16) -> io::Result<TcpListener> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await
+}
+
+/// The next debugger connection; never resolves without a listener.
+pub async fn accept(listener: Option<&TcpListener>) -> io::Result<TcpStream> {
+    match listener {
+        Some(listener) => Ok(listener.accept().await?.0),
+        None => std::future::pending().await,
+    }
+}
+
+/// How to attach, with the binary the host sent back for its symbols.
+pub fn instructions(msg: &Messages, out_dir: &Path, session: &DebugSession, port: u16) -> String {
+    msg.get(
+        "debug_ready",
+        &[
+            ("binary", &out_dir.join(&session.binary).display()),
+            ("port", &port),
+            ("limit", &session.time_limit_secs),
+        ],
+    )
+}
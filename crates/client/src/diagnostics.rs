@@ -0,0 +1,77 @@
+/// Rendering of the compiler diagnostics the host parses out of nvcc's
+/// output, and of the findings it parses out of compute-sanitizer's.
+use colored::*;
+use common::compute::{Diagnostic, SanitizerFinding, Severity};
+
+/// `file:line:col: severity: message`, colored by severity.
+pub fn render(d: &Diagnostic) -> String {
+    let location = match (d.file.as_str(), d.line, d.column) {
+        ("", _, _) => String::new(),
+        (file, 0, _) => format!("{}: ", file),
+        (file, line, 0) => format!("{}:{}: ", file, line),
+        (file, line, column) => format!("{}:{}:{}: ", file, line, column),
+    };
+    let severity = match d.severity() {
+        Severity::Error => "error".red().bold(),
+        Severity::Warning => "warning".yellow().bold(),
+        Severity::Note | Severity::Unspecified => "note".cyan().bold(),
+    };
+    format!("{}{}: {}", location.bold(), severity, d.message)
+}
+
+/// One JSON object per diagnostic, for editors and other tools.
+pub fn to_json(d: &Diagnostic) -> serde_json::Value {
+    serde_json::json!({
+        "file": d.file,
+        "line": d.line,
+        "column": d.column,
+        "severity": severity_name(d.severity()),
+        "message": d.message,
+    })
+}
+
+/// `tool severity: error`, where it happened, then the report as the
+/// sanitizer wrote it, dimmed.
+pub fn render_finding(f: &SanitizerFinding, report: &[u8]) -> String {
+    let tool = f.tool().as_str_name().to_lowercase();
+    let heading = match f.severity() {
+        Severity::Error => format!("{} error", tool).red().bold(),
+        _ => format!("{} warning", tool).yellow().bold(),
+    };
+    let mut text = format!("{}: {}", heading, f.error.bold());
+    let place = match (f.kernel.as_str(), f.location.as_str()) {
+        ("", "") => String::new(),
+        (kernel, "") => kernel.to_string(),
+        ("", location) => location.to_string(),
+        (kernel, location) => format!("{} at {}", kernel, location),
+    };
+    if !place.is_empty() {
+        text.push_str(&format!("\n  --> {}", place.cyan()));
+    }
+    if !f.address.is_empty() {
+        text.push_str(&format!("\n  address {}", f.address));
+    }
+    let report = String::from_utf8_lossy(report);
+    text.push_str(&format!("\n{}", report.trim_end_matches(['\r', '\n']).dimmed()));
+    text
+}
+
+pub fn finding_to_json(f: &SanitizerFinding) -> serde_json::Value {
+    serde_json::json!({
+        "tool": f.tool().as_str_name().to_lowercase(),
+        "severity": severity_name(f.severity()),
+        "error": f.error,
+        "kernel": f.kernel,
+        "location": f.location,
+        "address": f.address,
+        "backtrace": f.backtrace,
+    })
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note | Severity::Unspecified => "note",
+    }
+}
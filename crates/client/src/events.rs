@@ -0,0 +1,212 @@
+/// `--output json`: one JSON object per line on stdout, for CI systems and
+/// scripts, while the human-readable messages go to stderr. Every object has
+/// an `event` field:
+///
+/// - `accepted`: `job_id`
+/// - `compile_started`, `run_started`
+/// - `stdout`, `stderr`: `data`, a line of the program's or nvcc's output with
+///   its newline (invalid UTF-8 replaced)
+/// - `diagnostic`: a parsed compiler message (`file`, `line`, `column`,
+///   `severity`, `message`)
+/// - `sanitizer`: a compute-sanitizer report (`tool`, `severity`, `error`,
+///   `kernel`, `location`, `address`, `backtrace`), before the `stdout` or
+///   `stderr` event carrying its lines
+/// - `debug_session`: `port` to point cuda-gdb at (`target remote
+///   localhost:<port>`), `binary` and `time_limit_secs`, for `--debug` jobs
+/// - `telemetry`: `gpus`, a sample of each of the job's GPUs (`index`,
+///   `utilization_percent`, `memory_used_mib`, `memory_total_mib`,
+///   `temperature_c`, `power_watts`; 0 where the GPU doesn't say), every
+///   second while the program runs, for `--telemetry` jobs
+/// - `message`: `text` and `error`, the host's own status messages
+/// - `artifact`: `path` of a downloaded file
+/// - `finished`: `job_id`, `status`, `exit_code`, `signal`, `compile_ms`,
+///   `run_ms`, `killed`, `truncated`, `devices`, `cache_hit`,
+///   `sanitizer_errors`, `kernels` (`name`, `arch`, `registers`,
+///   `shared_bytes`, `constant_bytes`, `stack_bytes`, `spill_stores`,
+///   `spill_loads`; empty unless ptxas was asked to report), and `benchmark`
+///   (`runs_ms`, `warmup`, `min_ms`, `median_ms`, `mean_ms`, `p95_ms`,
+///   `stddev_ms`) for a `bench` job that completed its runs
+/// - `repro`: `command`, the build as an array of arguments (program first),
+///   after `finished` for `--print-repro`
+/// - `error`: `code` and `message` when the call itself failed
+/// - `matrix`: after the variants of a `matrix` run, `variants`, each with
+///   `flags`, `status`, `compile_ms`, `run_ms` and `registers` (null if the
+///   job never finished or nvcc reported none)
+/// - `compare`: after a `compare` run, `hosts`, each with `server`, `gpu`,
+///   `job_id`, `status`, `exit_code`, `compile_ms`, `run_ms` (null if the job
+///   never finished), `error` (why it didn't), `stdout_matches` (null for the
+///   host the others were compared with) and `stdout_diff` (null unless
+///   stdout differs)
+use crate::diagnostics;
+use common::compute::{ComputeResponse, DebugSession, JobPhase, JobResult, OutputKind};
+use serde_json::{Value, json};
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// The events for one non-artifact message of a job's stream.
+pub fn message(response: &ComputeResponse, first: bool) {
+    if first {
+        accepted(&response.job_id);
+    }
+    match response.phase() {
+        JobPhase::Compiling => emit(json!({ "event": "compile_started" })),
+        JobPhase::Running => emit(json!({ "event": "run_started" })),
+        _ => {}
+    }
+
+    if let Some(diagnostic) = &response.diagnostic {
+        let mut event = diagnostics::to_json(diagnostic);
+        event["event"] = "diagnostic".into();
+        emit(event);
+    }
+    if let Some(finding) = &response.finding {
+        let mut event = diagnostics::finding_to_json(finding);
+        event["event"] = "sanitizer".into();
+        emit(event);
+    }
+    if !response.telemetry.is_empty() {
+        let gpus: Vec<Value> = response
+            .telemetry
+            .iter()
+            .map(|s| {
+                json!({
+                    "index": s.index,
+                    "utilization_percent": s.utilization_percent,
+                    "memory_used_mib": s.memory_used_mib,
+                    "memory_total_mib": s.memory_total_mib,
+                    "temperature_c": s.temperature_c,
+                    "power_watts": s.power_watts,
+                })
+            })
+            .collect();
+        emit(json!({ "event": "telemetry", "gpus": gpus }));
+    }
+    let data = String::from_utf8_lossy(&response.output);
+    match response.kind() {
+        OutputKind::Stdout => emit(json!({ "event": "stdout", "data": data })),
+        OutputKind::Stderr => emit(json!({ "event": "stderr", "data": data })),
+        _ if data.is_empty() => {}
+        _ => emit(json!({ "event": "message", "text": data, "error": response.is_error })),
+    }
+
+    if let Some(result) = &response.result {
+        emit(finished(result));
+    }
+}
+
+pub fn accepted(job_id: &str) {
+    emit(json!({ "event": "accepted", "job_id": job_id }));
+}
+
+pub fn artifact(path: &Path) {
+    emit(json!({ "event": "artifact", "path": path }));
+}
+
+pub fn debug_session(session: &DebugSession, port: u16) {
+    emit(json!({
+        "event": "debug_session",
+        "port": port,
+        "binary": session.binary,
+        "time_limit_secs": session.time_limit_secs,
+    }));
+}
+
+pub fn repro(command: &[String]) {
+    emit(json!({ "event": "repro", "command": command }));
+}
+
+pub fn error(status: &tonic::Status) {
+    emit(json!({ "event": "error", "code": status.code().to_string(), "message": status.message() }));
+}
+
+pub fn matrix<'a>(variants: impl Iterator<Item = (&'a str, Option<&'a JobResult>, Option<u32>)>) {
+    let variants: Vec<Value> = variants
+        .map(|(flags, result, registers)| {
+            json!({
+                "flags": flags,
+                "status": result.map(|r| r.status().as_str_name()),
+                "compile_ms": result.map(|r| r.compile_ms),
+                "run_ms": result.map(|r| r.run_ms),
+                "registers": registers,
+            })
+        })
+        .collect();
+    emit(json!({ "event": "matrix", "variants": variants }));
+}
+
+/// One host of a `compare` run.
+pub struct Compared<'a> {
+    pub server: &'a str,
+    pub gpu: &'a str,
+    pub result: Option<&'a JobResult>,
+    pub error: Option<&'a str>,
+    pub stdout_matches: Option<bool>,
+    pub stdout_diff: Option<String>,
+}
+
+pub fn compare<'a>(hosts: impl Iterator<Item = Compared<'a>>) {
+    let hosts: Vec<Value> = hosts
+        .map(|host| {
+            json!({
+                "server": host.server,
+                "gpu": host.gpu,
+                "job_id": host.result.map(|r| &r.job_id),
+                "status": host.result.map(|r| r.status().as_str_name()),
+                "exit_code": host.result.and_then(|r| r.exit_code),
+                "compile_ms": host.result.map(|r| r.compile_ms),
+                "run_ms": host.result.map(|r| r.run_ms),
+                "error": host.error,
+                "stdout_matches": host.stdout_matches,
+                "stdout_diff": host.stdout_diff,
+            })
+        })
+        .collect();
+    emit(json!({ "event": "compare", "hosts": hosts }));
+}
+
+fn finished(result: &JobResult) -> Value {
+    json!({
+        "event": "finished",
+        "job_id": result.job_id,
+        "status": result.status().as_str_name(),
+        "exit_code": result.exit_code,
+        "signal": result.signal,
+        "compile_ms": result.compile_ms,
+        "run_ms": result.run_ms,
+        "killed": result.killed,
+        "truncated": result.truncated,
+        "devices": result.devices,
+        "cache_hit": result.cache_hit,
+        "sanitizer_errors": result.sanitizer_errors,
+        "kernels": result.kernels.iter().map(|k| json!({
+            "name": k.name,
+            "arch": k.arch,
+            "registers": k.registers,
+            "shared_bytes": k.shared_bytes,
+            "constant_bytes": k.constant_bytes,
+            "stack_bytes": k.stack_bytes,
+            "spill_stores": k.spill_stores,
+            "spill_loads": k.spill_loads,
+        })).collect::<Vec<_>>(),
+        "benchmark": result.benchmark.as_ref().map(|b| json!({
+            "runs_ms": b.runs_ms,
+            "warmup": b.warmup,
+            "min_ms": b.min_ms,
+            "median_ms": b.median_ms,
+            "mean_ms": b.mean_ms,
+            "p95_ms": b.p95_ms,
+            "stddev_ms": b.stddev_ms,
+        })),
+    })
+}
+
+fn emit(event: Value) {
+    let mut out = io::stdout().lock();
+    let _ = writeln!(out, "{}", event).and_then(|_| out.flush());
+}
@@ -0,0 +1,104 @@
+/// The `info` subcommand: what the host has to offer.
+use crate::connection::Connection;
+use crate::messages::Messages;
+use colored::*;
+use common::compute::HostInfoRequest;
+
+pub async fn run(connection: &Connection, msg: &Messages) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = connection.connect(msg).await?;
+    let info = client.get_host_info(HostInfoRequest {}).await?.into_inner();
+
+    println!("{}", msg.get("info_header", &[("server", &connection.server().cyan())]).bold());
+    println!(
+        "{}",
+        msg.get(
+            "info_toolchain",
+            &[
+                ("nvcc", &info.nvcc_version),
+                ("driver", &info.driver_version),
+                ("cuda", &info.cuda_version),
+            ],
+        )
+    );
+    if !info.backends.is_empty() {
+        let backends: Vec<String> = info
+            .backends
+            .iter()
+            .map(|backend| format!("{} ({} {})", backend.accelerator().as_str_name(), backend.compiler, backend.version))
+            .collect();
+        println!("{}", msg.get("info_backends", &[("backends", &backends.join(", "))]));
+    }
+    if !info.toolchains.is_empty() {
+        println!(
+            "{}",
+            msg.get("info_toolchains", &[("toolchains", &info.toolchains.join(", "))])
+        );
+    }
+    if !info.profilers.is_empty() {
+        println!("{}", msg.get("info_profilers", &[("profilers", &info.profilers.join(", "))]));
+    }
+    if !info.libraries.is_empty() {
+        let libraries: Vec<String> = info
+            .libraries
+            .iter()
+            .map(|library| format!("{} {}", library.name, library.version))
+            .collect();
+        println!("{}", msg.get("info_libraries", &[("libraries", &libraries.join(", "))]));
+    }
+    println!(
+        "{}",
+        msg.get(
+            "info_limits",
+            &[
+                ("jobs", &info.max_concurrent_jobs),
+                ("timeout", &info.max_timeout_secs),
+            ],
+        )
+    );
+
+    if info.gpus.is_empty() {
+        println!("{}", msg.get("info_no_gpus", &[]).yellow());
+    }
+    for gpu in &info.gpus {
+        println!(
+            "{}",
+            msg.get(
+                "info_gpu",
+                &[
+                    ("index", &gpu.index),
+                    ("name", &gpu.name.green()),
+                    ("sm", &gpu.compute_capability.replace('.', "")),
+                    ("free", &gpu.memory_free_mib),
+                    ("total", &gpu.memory_total_mib),
+                ],
+            )
+        );
+    }
+    for slice in &info.mig_slices {
+        let line = msg.get(
+            "info_mig_slice",
+            &[
+                ("gpu", &slice.gpu),
+                ("device", &slice.device),
+                ("profile", &slice.profile.green()),
+                ("memory", &slice.memory_mib),
+                ("uuid", &slice.uuid),
+            ],
+        );
+        match slice.busy {
+            true => println!("{} {}", line, msg.get("info_mig_busy", &[]).yellow()),
+            false => println!("{}", line),
+        }
+    }
+    if info.mps_clients > 0 {
+        println!("{}", msg.get("info_mps", &[("clients", &info.mps_clients)]));
+    }
+    if !info.notifiers.is_empty() {
+        println!("{}", msg.get("info_notifiers", &[("notifiers", &info.notifiers.join(", "))]));
+    }
+    if info.max_chunk_bytes > 0 {
+        println!("{}", msg.get("info_max_chunk", &[("kib", &(info.max_chunk_bytes / 1024))]));
+    }
+
+    Ok(())
+}
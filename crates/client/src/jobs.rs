@@ -0,0 +1,216 @@
+/// The `status`, `logs`, `attach`, `history` and `purge` subcommands: checking
+/// on a job submitted with `--detach`, getting back to one whose stream
+/// dropped, looking up past jobs, and removing the workspaces they left.
+use crate::connection::Connection;
+use crate::messages::Messages;
+use colored::*;
+use common::compute::{
+    GetJobRequest, JobOutputRequest, JobPhase, JobRecord, JobStatusRequest,
+    ListJobsRequest, PurgeWorkspacesRequest,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub async fn status(connection: &Connection, msg: &Messages, job_id: String) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = connection.connect(msg).await?;
+    let status = client
+        .get_job_status(JobStatusRequest { job_id: job_id.clone() })
+        .await?
+        .into_inner();
+
+    match status.phase() {
+        JobPhase::Queued if status.queue_position > 0 => println!(
+            "{}",
+            msg.get("status_queued", &[("job", &job_id), ("position", &status.queue_position)])
+        ),
+        phase => println!(
+            "{}",
+            msg.get("status_phase", &[("job", &job_id), ("phase", &phase.as_str_name())])
+        ),
+    }
+    if let Some(result) = &status.result {
+        println!("{}", crate::summary(msg, result).dimmed());
+    }
+    if !status.error.is_empty() {
+        eprintln!("{}", status.error.red());
+    }
+    Ok(())
+}
+
+/// Prints everything the job has output so far, from entry `from` on.
+pub async fn logs(
+    connection: &Connection,
+    msg: &Messages,
+    job_id: String,
+    from: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = connection.connect(msg).await?;
+    let mut offset = from;
+
+    loop {
+        let page = client
+            .get_job_output(JobOutputRequest {
+                job_id: job_id.clone(),
+                from_offset: offset,
+                max_entries: 0,
+            })
+            .await?
+            .into_inner();
+
+        if page.first_offset > offset {
+            eprintln!("{}", msg.get("logs_dropped", &[("count", &(page.first_offset - offset))]).yellow());
+        }
+        for entry in &page.entries {
+            crate::output::print(entry);
+        }
+        offset = page.next_offset;
+        if page.done {
+            return Ok(());
+        }
+        if page.entries.is_empty() {
+            break;
+        }
+    }
+
+    eprintln!("{}", msg.get("logs_more", &[("offset", &offset)]).dimmed());
+    Ok(())
+}
+
+/// Streams the job's output from entry `from` until it ends. Ctrl-C only
+/// detaches; the job keeps running.
+pub async fn attach(
+    connection: &Connection,
+    msg: &Messages,
+    job_id: String,
+    from: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let host = connection.host(msg).await?;
+    println!("{}", msg.get("attaching", &[("job", &job_id.cyan())]));
+    let mut job = host.attach(&job_id, from).await?;
+
+    let mut result = None;
+    while let Some(response) = job.next_message().await? {
+        crate::show(false, &response, false);
+        if response.result.is_some() {
+            result = response.result;
+        }
+    }
+
+    println!("\n{}", msg.get("finished", &[]).bold().green());
+    if let Some(result) = &result {
+        println!("{}", crate::summary(msg, result).dimmed());
+        if !result.kernels.is_empty() {
+            crate::kernels::print(msg, &result.kernels);
+        }
+    }
+    crate::exit_for(result.as_ref())
+}
+
+/// Lists recent jobs, or shows one in full.
+pub async fn history(
+    connection: &Connection,
+    msg: &Messages,
+    job_id: Option<String>,
+    submitter: Option<String>,
+    limit: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = connection.connect(msg).await?;
+
+    if let Some(job_id) = job_id {
+        let record = client.get_job(GetJobRequest { job_id }).await?.into_inner();
+        println!(
+            "{}",
+            msg.get(
+                "history_detail",
+                &[
+                    ("job", &record.job_id.cyan()),
+                    ("submitter", &record.submitter),
+                    ("submitted", &ago(msg, record.submitted_at_ms)),
+                    ("files", &record.files.join(" ")),
+                    ("flags", &record.compiler_flags.join(" ")),
+                    ("hash", &record.source_hash),
+                ],
+            )
+        );
+        if let Some(result) = &record.result {
+            println!("{}", crate::summary(msg, result).dimmed());
+        }
+        if !record.error.is_empty() {
+            eprintln!("{}", record.error.red());
+        }
+        return Ok(());
+    }
+
+    let jobs = client
+        .list_jobs(ListJobsRequest {
+            limit,
+            submitter: submitter.unwrap_or_default(),
+        })
+        .await?
+        .into_inner()
+        .jobs;
+    if jobs.is_empty() {
+        println!("{}", msg.get("history_empty", &[]));
+    }
+    for record in &jobs {
+        println!(
+            "{}",
+            msg.get(
+                "history_row",
+                &[
+                    ("job", &record.job_id.cyan()),
+                    ("submitted", &ago(msg, record.submitted_at_ms)),
+                    ("submitter", &record.submitter),
+                    ("status", &outcome(record)),
+                    ("files", &record.files.join(" ")),
+                ],
+            )
+        );
+    }
+    Ok(())
+}
+
+fn outcome(record: &JobRecord) -> String {
+    match (&record.result, record.finished_at_ms) {
+        (Some(result), _) => match result.exit_code {
+            Some(code) => format!("{} ({})", result.status().as_str_name(), code),
+            None => result.status().as_str_name().to_string(),
+        },
+        (None, 0) => "UNFINISHED".into(),
+        (None, _) => "ERROR".into(),
+    }
+}
+
+/// "5m ago" style age of a Unix timestamp in milliseconds.
+pub fn ago(msg: &Messages, unix_ms: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let secs = (now - unix_ms).max(0) / 1000;
+    let (key, count) = match secs {
+        s if s < 60 => ("ago_seconds", s),
+        s if s < 3600 => ("ago_minutes", s / 60),
+        s if s < 86400 => ("ago_hours", s / 3600),
+        s => ("ago_days", s / 86400),
+    };
+    msg.get(key, &[("count", &count)])
+}
+
+pub async fn purge(
+    connection: &Connection,
+    msg: &Messages,
+    job_id: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = connection.connect(msg).await?;
+    let response = client
+        .purge_workspaces(PurgeWorkspacesRequest {
+            job_id: job_id.unwrap_or_default(),
+        })
+        .await?
+        .into_inner();
+    println!("{}", msg.get("purged", &[("count", &response.purged)]));
+    for failure in &response.failures {
+        eprintln!("{}", msg.get("purge_failed", &[("failure", failure)]).yellow());
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+/// The per-kernel resource table, from the ptxas report the host parses
+/// when the compiler flags ask for it (`-Xptxas -v` and the like).
+use crate::messages::Messages;
+use crate::table;
+use colored::*;
+use common::compute::KernelStats;
+
+pub fn print(msg: &Messages, kernels: &[KernelStats]) {
+    println!("\n{}", msg.get("kernels_title", &[]).bold());
+    let mut rows = vec![table::header(&msg.get("kernels_columns", &[]))];
+    for k in kernels {
+        rows.push(vec![
+            k.name.clone(),
+            k.arch.clone(),
+            k.registers.to_string(),
+            bytes(k.shared_bytes),
+            bytes(k.constant_bytes),
+            bytes(k.stack_bytes),
+            bytes(k.spill_stores),
+            bytes(k.spill_loads),
+        ]);
+    }
+    table::print(&rows);
+}
+
+fn bytes(n: u64) -> String {
+    format!("{} B", n)
+}
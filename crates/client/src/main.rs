@@ -1,66 +1,739 @@
+/// Human-readable output: stdout normally, stderr when stdout carries JSON.
+/// Defined before the modules so they can use it too.
+macro_rules! say {
+    ($json:expr, $($arg:tt)*) => {
+        if $json { eprintln!($($arg)*) } else { println!($($arg)*) }
+    };
+}
+
 /// This code handles the connection, file reading, and the asynchronous loop that listens to the server's stream.
-use clap::Parser;
+mod admin;
+mod compare;
+mod config;
+mod connection;
+mod debug;
+mod diagnostics;
+mod events;
+mod info;
+mod jobs;
+mod kernels;
+mod matrix;
+mod messages;
+mod output;
+mod repro;
+mod table;
+mod watch;
+
+use clap::{Parser, Subcommand};
 use colored::*;
-use common::compute::cuda_executor_client::CudaExecutorClient;
-use common::compute::ComputeRequest;
+use common::compute::{Accelerator, BenchmarkOptions, BuildSystem, ComputeRequest, ComputeResponse, Emit, JobResult, JobStatus, Priority, Profiler, Sanitizer};
+use config::ClientConfig;
+use connection::Connection;
+use events::OutputFormat;
+use messages::Messages;
+use output::ColorMode;
+use ferris_compute_client::{self as sdk, ArtifactWriter, Job};
 use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tonic::Code;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Remote CUDA Executor Client")]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    /// Path to the .cu file
-    file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    job: JobArgs,
+
+    #[command(flatten)]
+    connection: Connection,
+
+    /// Client config file with named profiles [~/.config/ferris-compute/config.toml]
+    #[arg(long, global = true, env = "FERRIS_CLIENT_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Profile from the config file to use (default: its default_profile)
+    #[arg(short = 'p', long, global = true, env = "FERRIS_PROFILE")]
+    profile: Option<String>,
+
+    /// Language for client messages (defaults to $LANG, then English)
+    #[arg(long, global = true)]
+    lang: Option<String>,
 
-    /// Remote host address (e.g., http://192.168.1.50:50051)
-    #[arg(short, long, default_value = "http://[::1]:50051")]
-    server: String,
+    /// TOML file overriding individual client messages
+    #[arg(long, global = true)]
+    messages: Option<PathBuf>,
+
+    /// Color our messages and the program's stderr: auto (when stdout is a terminal), always or never
+    #[arg(long, global = true, value_enum, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// Same as --color never; also strips the program's own color codes
+    #[arg(long, global = true, conflicts_with = "color")]
+    no_color: bool,
+}
+
+/// What to submit and how; shared by a plain run and `watch`.
+#[derive(clap::Args, Debug)]
+struct JobArgs {
+    /// .cu/.hip/.cuh/.h files, or a project directory, to submit
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
 
     /// Extra flags for nvcc (e.g., "-arch=sm_80")
     #[arg(short, long)]
     flags: Vec<String>,
+
+    /// The one file given is an executable already built; run it as it is instead of compiling
+    #[arg(long, conflicts_with_all = ["build", "compile_only", "fetch_binary", "no_cache"])]
+    prebuilt: bool,
+
+    /// Link these libraries the host has set up, e.g. "cublas,cufft" (see `info`)
+    #[arg(long = "with", value_name = "LIB", value_delimiter = ',', conflicts_with = "prebuilt")]
+    libraries: Vec<String>,
+
+    /// GPU backend to build and run with [the host's first, see `info`]
+    #[arg(long, value_enum)]
+    accelerator: Option<Backend>,
+
+    /// Build the project with its own build system instead of one nvcc call
+    #[arg(long, value_enum)]
+    build: Option<BuildTool>,
+
+    /// Extra argument for the build system: "-DUSE_CUBLAS=ON" for cmake, a target or "VAR=value" for make (repeatable)
+    #[arg(long = "build-arg", value_name = "ARG", allow_hyphen_values = true, requires = "build")]
+    build_args: Vec<String>,
+
+    /// With --build, the program to run: a cmake target, or a path in the project for make
+    #[arg(long, requires = "build")]
+    target: Option<String>,
+
+    /// Kill the remote program after this many seconds (0 = host maximum)
+    #[arg(short, long, default_value_t = 0)]
+    timeout: u32,
+
+    /// Print a shell snippet that reproduces the build locally, with the flags the host added
+    #[arg(long)]
+    print_repro: bool,
+
+    /// Run on this specific GPU index
+    #[arg(short, long, conflicts_with = "gpus")]
+    device: Option<u32>,
+
+    /// Number of GPUs the program needs
+    #[arg(long, default_value_t = 1)]
+    gpus: u32,
+
+    /// GPU memory the program needs on each GPU; the host won't start it without that much free
+    #[arg(long, value_name = "MIB", default_value_t = 0)]
+    gpu_memory: u64,
+
+    /// Run on a MIG slice: "any" (the smallest with --gpu-memory free), a UUID, or "<gpu>/<device>" (see `info`)
+    #[arg(long, value_name = "SLICE", conflicts_with_all = ["device", "gpus"])]
+    mig: Option<String>,
+
+    /// Share a GPU with other small jobs through MPS, if the host allows it; needs --gpu-memory
+    #[arg(long, conflicts_with_all = ["device", "gpus", "mig"])]
+    shared: bool,
+
+    /// Always recompile instead of reusing a cached binary on the host
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Compile and run inside this container image on the host
+    #[arg(long)]
+    image: Option<String>,
+
+    /// Build with this named CUDA toolkit on the host (see `info`)
+    #[arg(long, conflicts_with = "image")]
+    toolchain: Option<String>,
+
+    /// Only compile; don't run the program or claim a GPU
+    #[arg(long)]
+    compile_only: bool,
+
+    /// Download the compiled binary (or PTX/cubin with -f=-ptx/-cubin)
+    #[arg(long)]
+    fetch_binary: bool,
+
+    /// Only compile, to PTX (printed) or a cubin (saved to --out-dir), and run nothing
+    #[arg(long, value_enum, conflicts_with_all = ["build", "prebuilt", "debug", "profiler", "sanitizer", "stdin"])]
+    emit: Option<EmitFormat>,
+
+    /// Upload this data file or directory into the job's working directory (repeatable)
+    #[arg(long = "input", value_name = "PATH")]
+    inputs: Vec<PathBuf>,
+
+    /// Download files the program wrote that match this glob (repeatable, e.g. "out/*.csv")
+    #[arg(long = "fetch", value_name = "GLOB")]
+    fetch: Vec<String>,
+
+    /// Where downloaded files are written
+    #[arg(long, default_value = ".")]
+    out_dir: PathBuf,
+
+    /// Print the job as JSON lines on stdout (see events.rs); messages go to stderr
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Same as --output json
+    #[arg(long, conflicts_with = "output")]
+    json: bool,
+
+    /// Forward this terminal's stdin to the remote program
+    #[arg(short = 'i', long)]
+    stdin: bool,
+
+    /// Run the program under Nsight Systems or Nsight Compute; the report is saved to --out-dir
+    #[arg(long, value_enum)]
+    profiler: Option<ProfilerTool>,
+
+    /// Run the program under compute-sanitizer with this tool, reporting each error it finds
+    #[arg(long, value_enum, conflicts_with = "profiler")]
+    sanitizer: Option<SanitizerTool>,
+
+    /// Build with -g -G and run the program under cuda-gdbserver; attach cuda-gdb through a local port
+    #[arg(long, conflicts_with_all = ["profiler", "sanitizer", "compile_only"])]
+    debug: bool,
+
+    /// Local port to take debugger connections on (0 = any free port)
+    #[arg(long, default_value_t = 0, requires = "debug")]
+    debug_port: u16,
+
+    /// Keep the job's workspace on the host for debugging (see `purge`)
+    #[arg(long)]
+    keep_workspace: bool,
+
+    /// Place in the host's queue; high needs the host's permission [normal]
+    #[arg(long, value_enum, env = "FERRIS_PRIORITY")]
+    priority: Option<JobPriority>,
+
+    /// Show the GPUs' utilization, memory, temperature and power while the program runs
+    #[arg(long)]
+    telemetry: bool,
+
+    /// Have the host report the result to its notifiers (Slack, email...) when the job finishes (see `info`)
+    #[arg(long)]
+    notify: bool,
+
+    /// Have the host POST the result as JSON to this URL when the job finishes
+    #[arg(long, value_name = "URL", env = "FERRIS_NOTIFY_URL")]
+    notify_url: Option<String>,
+
+    /// Exit once the job is accepted instead of streaming its output
+    #[arg(long, conflicts_with_all = ["stdin", "fetch", "fetch_binary", "profiler", "debug"])]
+    detach: bool,
+
+    /// Arguments passed to the remote program (after `--`)
+    #[arg(last = true)]
+    run_args: Vec<String>,
+
+    /// Set by `bench`
+    #[arg(skip)]
+    benchmark: Option<BenchmarkOptions>,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ProfilerTool {
+    Nsys,
+    Ncu,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum BuildTool {
+    Nvcc,
+    Cmake,
+    Make,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Backend {
+    /// NVIDIA GPUs, with nvcc
+    Cuda,
+    /// AMD GPUs, with hipcc
+    Rocm,
+    /// No GPU: C++ with OpenMP, built with g++ or clang++
+    Cpu,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum EmitFormat {
+    Ptx,
+    Cubin,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum JobPriority {
+    /// Waits behind normal and high-priority jobs
+    Low,
+    Normal,
+    /// Goes ahead of waiting normal and low-priority jobs
+    High,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum SanitizerTool {
+    Memcheck,
+    Racecheck,
+    Initcheck,
+    Synccheck,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the host's GPUs, driver and toolchain versions, and limits
+    Info,
+    /// Show whether a job is queued, compiling, running or done
+    Status { job_id: String },
+    /// Stream a job's output, e.g. after the connection dropped mid-run
+    Attach {
+        job_id: String,
+        /// Resume after this many output entries (printed when a stream drops)
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+    },
+    /// List past jobs from the host's history, or show one of them
+    History {
+        job_id: Option<String>,
+        /// Only jobs submitted by this user (or address); other users' jobs are for admins
+        #[arg(long)]
+        submitter: Option<String>,
+        /// How many jobs to list (0 = host default)
+        #[arg(short = 'n', long, default_value_t = 0)]
+        limit: u32,
+    },
+    /// Delete workspaces the host kept for debugging: one of your jobs', or all (admins only)
+    Purge { job_id: Option<String> },
+    /// Operational control of the host, for its admins
+    Admin {
+        #[command(subcommand)]
+        command: admin::AdminCommand,
+    },
+    /// Run the job, then run it again whenever one of its files changes
+    Watch {
+        #[command(flatten)]
+        job: JobArgs,
+    },
+    /// Run the program repeatedly on the host and report min/median/mean/p95/stddev run times
+    Bench {
+        #[command(flatten)]
+        job: JobArgs,
+        /// Timed runs
+        #[arg(short = 'n', long, default_value_t = 10)]
+        iterations: u32,
+        /// Untimed runs before them
+        #[arg(long, default_value_t = 1)]
+        warmup: u32,
+    },
+    /// Build and run the job once per set of nvcc flags, then compare compile time, run time and registers
+    Matrix {
+        #[command(flatten)]
+        job: JobArgs,
+        /// nvcc flags of one variant, space-separated (e.g. "-O3 --use_fast_math"); repeat for each
+        #[arg(short = 'V', long = "variant", value_name = "FLAGS", required = true, allow_hyphen_values = true)]
+        variants: Vec<String>,
+    },
+    /// Run the job on every --server at once, then compare the results and diff their stdout
+    Compare {
+        #[command(flatten)]
+        job: JobArgs,
+    },
+    /// Print a job's output so far
+    Logs {
+        job_id: String,
+        /// Skip this many entries (see the hint printed while a job is running)
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    output::init(if args.no_color { ColorMode::Never } else { args.color });
+    let msg = Messages::load(args.lang.as_deref(), args.messages.as_deref())?;
+    let profile = ClientConfig::load(args.config.as_deref(), &msg)?.profile(args.profile.as_deref(), &msg)?;
+    args.job.flags = profile.compiler_flags(std::mem::take(&mut args.job.flags));
+    if let Some(Command::Watch { job } | Command::Bench { job, .. } | Command::Matrix { job, .. } | Command::Compare { job }) =
+        &mut args.command
+    {
+        job.flags = profile.compiler_flags(std::mem::take(&mut job.flags));
+    }
+    args.connection.apply(profile);
+    if args.connection.each_server().len() > 1 && !matches!(args.command, Some(Command::Compare { .. })) {
+        return Err(msg.get("servers_compare_only", &[]).into());
+    }
 
-    // 1. Read the local CUDA file
-    let source_code = std::fs::read_to_string(&args.file)
-        .map_err(|e| format!("Could not read file {}: {}", args.file.display(), e))?;
+    match args.command {
+        Some(Command::Info) => info::run(&args.connection, &msg).await,
+        Some(Command::Status { job_id }) => jobs::status(&args.connection, &msg, job_id).await,
+        Some(Command::Attach { job_id, from }) => jobs::attach(&args.connection, &msg, job_id, from).await,
+        Some(Command::History { job_id, submitter, limit }) => {
+            jobs::history(&args.connection, &msg, job_id, submitter, limit).await
+        }
+        Some(Command::Purge { job_id }) => jobs::purge(&args.connection, &msg, job_id).await,
+        Some(Command::Admin { command }) => admin::run(&args.connection, &msg, command).await,
+        Some(Command::Logs { job_id, from }) => jobs::logs(&args.connection, &msg, job_id, from).await,
+        Some(Command::Watch { job }) => watch::run(job, &args.connection, &msg).await,
+        Some(Command::Matrix { job, variants }) => matrix::run(job, variants, &args.connection, &msg).await,
+        Some(Command::Compare { job }) => compare::run(job, &args.connection, &msg).await,
+        Some(Command::Bench { mut job, iterations, warmup }) => {
+            job.benchmark = Some(BenchmarkOptions { iterations, warmup });
+            run(job, &args.connection, &msg).await
+        }
+        None => run(args.job, &args.connection, &msg).await,
+    }
+}
 
-    let file_name = args.file
-        .file_name()
-        .unwrap_or_default()
-        .to_string_lossy()
-        .to_string();
+/// Submits the files, streams the job's output until it finishes, and exits
+/// with the program's exit code.
+async fn run(args: JobArgs, connection: &Connection, msg: &Messages) -> Result<(), Box<dyn std::error::Error>> {
+    let (tx, mut interrupts) = mpsc::channel(1);
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() && tx.send(Interrupt::CtrlC).await.is_ok() {}
+    });
+    let result = execute(&args, connection, msg, &mut interrupts).await?;
+    if args.detach {
+        return Ok(());
+    }
+    exit_for(result.as_ref())
+}
+
+/// Why `execute` should stop the job in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// Cancels the job; a second one gives up immediately.
+    CtrlC,
+    /// `watch` saw a file change: cancel the job, once, as soon as it has an id.
+    Changed,
+}
 
-    println!("{} Connecting to host at {}...", "🚀".bold(), args.server.cyan());
+/// Runs one job to the end and returns its result; none when detaching or
+/// when the stream ended without one. An interrupt asks the host to cancel
+/// the job and reading goes on, so the final "cancelled" message still
+/// arrives.
+async fn execute(
+    args: &JobArgs,
+    connection: &Connection,
+    msg: &Messages,
+    interrupts: &mut mpsc::Receiver<Interrupt>,
+) -> Result<Option<JobResult>, Box<dyn std::error::Error>> {
+    let json = args.json || args.output == OutputFormat::Json;
+    // 1. Find the local files; they are read while being streamed to the host
+    let sources = locate(&args.files, msg)?;
+    let inputs = locate(&args.inputs, msg)?;
+    let paths: Vec<String> = sources.iter().map(|(path, _)| path.clone()).collect();
+    let prebuilt = match (args.prebuilt, sources.as_slice()) {
+        (false, _) => None,
+        (true, [binary]) => Some(binary.clone()),
+        (true, _) => return Err(msg.get("prebuilt_single", &[]).into()),
+    };
+
+    say!(json, "{}", msg.get("connecting", &[("server", &connection.server().cyan())]));
 
     // 2. Connect to the host
-    let mut client = CudaExecutorClient::connect(args.server).await?;
+    let host = connection.host(msg).await?;
 
-    let request = tonic::Request::new(ComputeRequest {
-        source_code,
-        file_name,
-        compiler_flags: args.flags,
-    });
+    let start = request(args);
+
+    if let [only] = paths.as_slice() {
+        say!(json, "{}", msg.get("sending", &[("file", &only.yellow())]));
+    } else {
+        say!(json, "{}", msg.get("sending_many", &[("count", &paths.len().to_string().yellow())]));
+    }
+    if !inputs.is_empty() {
+        say!(json, "{}", msg.get("uploading", &[("count", &inputs.len())]));
+    }
+
+    // 3. Upload and wait for the host to accept the job
+    let submit = async {
+        match prebuilt {
+            Some(binary) => host.run_binary(binary, inputs, start).await,
+            None => host.submit(sources, inputs, start).await,
+        }
+    };
+    tokio::pin!(submit);
+    // A change seen before the job had an id
+    let mut cancel_pending = false;
+    let mut job = loop {
+        tokio::select! {
+            job = &mut submit => match job {
+                Ok(job) => break job,
+                Err(e) => {
+                    if json && let sdk::Error::Status(status) = &e {
+                        events::error(status);
+                    }
+                    return Err(e.into());
+                }
+            },
+            Some(interrupt) = interrupts.recv() => match interrupt {
+                Interrupt::CtrlC => return Err(msg.get("interrupted", &[]).into()),
+                Interrupt::Changed => cancel_pending = true,
+            }
+        }
+    };
+    if args.detach {
+        // The host keeps running the job after we hang up
+        if json {
+            events::accepted(job.id());
+        }
+        say!(json, "{}", msg.get("detached", &[("job", &job.id())]));
+        return Ok(None);
+    }
+    if args.stdin {
+        tokio::spawn(job.write_stdin(tokio::io::stdin()));
+    }
+    let mut cancel_sent = false;
+    if cancel_pending {
+        cancel(&job, msg, Interrupt::Changed).await?;
+        cancel_sent = true;
+    }
+
+    // 4. Receive the stream; a dropped one is reattached (see --retries)
+    let mut first = true;
+    let mut result = None;
+    let mut artifacts = ArtifactWriter::new(args.out_dir.clone(), connection.file_encoding());
+    let mut debugger = None;
+
+    loop {
+        tokio::select! {
+            socket = debug::accept(debugger.as_ref()) => match socket {
+                Ok(socket) => {
+                    say!(json, "{}", msg.get("debug_attached", &[]).cyan());
+                    let (tunnel, msg) = (job.tunnel(socket), msg.clone());
+                    tokio::spawn(async move {
+                        if let Err(e) = tunnel.await {
+                            eprintln!("{}", msg.get("debug_tunnel_failed", &[("error", &e)]).yellow());
+                        }
+                    });
+                }
+                Err(e) => eprintln!("{}", msg.get("debug_tunnel_failed", &[("error", &e)]).yellow()),
+            },
+            message = job.next_message() => {
+                let response = match message {
+                    Ok(Some(response)) => response,
+                    Ok(None) => break,
+                    Err(e) => {
+                        if let sdk::Error::Status(status) = &e {
+                            // The job keeps running on the host; say how to get back to it
+                            if matches!(status.code(), Code::Unavailable | Code::Unknown | Code::Cancelled) {
+                                eprintln!(
+                                    "{}",
+                                    msg.get("stream_lost", &[("job", &job.id()), ("offset", &job.offset())]).yellow()
+                                );
+                            }
+                            if json {
+                                events::error(status);
+                            }
+                        }
+                        return Err(e.into());
+                    }
+                };
+                if let Some(chunk) = response.artifact {
+                    if let Some(path) = artifacts.write(chunk).await? {
+                        if json {
+                            events::artifact(&path);
+                        }
+                        say!(json, "{}", msg.get("artifact_saved", &[("path", &path.display())]));
+                    }
+                    continue;
+                }
+                show(json, &response, first);
+                first = false;
+                if let Some(session) = &response.debug_session {
+                    let listener = debug::listen(args.debug_port).await?;
+                    let port = listener.local_addr()?.port();
+                    if json {
+                        events::debug_session(session, port);
+                    }
+                    say!(json, "{}", debug::instructions(msg, &args.out_dir, session, port).bold());
+                    debugger = Some(listener);
+                }
+                if response.result.is_some() {
+                    result = response.result;
+                }
+            }
+            Some(interrupt) = interrupts.recv() => match interrupt {
+                Interrupt::CtrlC if cancel_sent => {
+                    return Err(msg.get("interrupted", &[]).into());
+                }
+                Interrupt::Changed if cancel_sent => {}
+                _ => {
+                    cancel(&job, msg, interrupt).await?;
+                    cancel_sent = true;
+                }
+            }
+        }
+    }
 
-    println!("{} Sending {} to remote GPU...", "📤".bold(), file_name.yellow());
+    say!(json, "\n{}", msg.get("finished", &[]).bold().green());
+    if let Some(result) = &result {
+        say!(json, "{}", summary(msg, result).dimmed());
+        if result.sanitizer_errors > 0 {
+            say!(json, "{}", msg.get("sanitizer_errors", &[("count", &result.sanitizer_errors)]).red());
+        }
+        if !json && !result.kernels.is_empty() {
+            kernels::print(msg, &result.kernels);
+        }
+    }
 
-    // 3. Receive the stream
-    let mut stream = client.execute_code(request).await?.into_inner();
+    if args.print_repro {
+        let words = repro::repro_words(result.as_ref(), compiler_words(args.accelerator), &paths, &args.flags);
+        if json {
+            events::repro(&words);
+        }
+        say!(json, "\n{}", msg.get("repro_header", &[]).bold());
+        say!(json, "{}", repro::repro_command(&words));
+    }
 
-    while let Some(response) = stream.message().await? {
-        if response.is_error {
-            // Print compiler errors or stderr in red
-            eprintln!("{}", response.output.red());
-        } else {
-            // Print standard output in green/white
-            println!("{}", response.output);
+    Ok(result)
+}
+
+/// The job settings sent after the files.
+fn request(args: &JobArgs) -> ComputeRequest {
+    ComputeRequest {
+        // A prebuilt binary ignores them, but the host refuses them too
+        compiler_flags: if args.prebuilt { Vec::new() } else { args.flags.clone() },
+        timeout_secs: args.timeout,
+        run_args: args.run_args.clone(),
+        forward_stdin: args.stdin,
+        device_index: args.device,
+        gpu_count: args.gpus,
+        gpu_memory_mib: args.gpu_memory,
+        telemetry: args.telemetry,
+        mig: args.mig.clone().unwrap_or_default(),
+        shared: args.shared,
+        notify: args.notify,
+        notify_url: args.notify_url.clone().unwrap_or_default(),
+        no_cache: args.no_cache,
+        container_image: args.image.clone().unwrap_or_default(),
+        toolchain: args.toolchain.clone().unwrap_or_default(),
+        compile_only: args.compile_only,
+        return_binary: args.fetch_binary,
+        artifact_patterns: args.fetch.clone(),
+        keep_workspace: args.keep_workspace,
+        benchmark: args.benchmark,
+        profiler: match args.profiler {
+            None => Profiler::None,
+            Some(ProfilerTool::Nsys) => Profiler::Nsys,
+            Some(ProfilerTool::Ncu) => Profiler::Ncu,
+        }
+        .into(),
+        sanitizer: match args.sanitizer {
+            None => Sanitizer::None,
+            Some(SanitizerTool::Memcheck) => Sanitizer::Memcheck,
+            Some(SanitizerTool::Racecheck) => Sanitizer::Racecheck,
+            Some(SanitizerTool::Initcheck) => Sanitizer::Initcheck,
+            Some(SanitizerTool::Synccheck) => Sanitizer::Synccheck,
+        }
+        .into(),
+        debug: args.debug,
+        build_system: match args.build {
+            None | Some(BuildTool::Nvcc) => BuildSystem::Nvcc,
+            Some(BuildTool::Cmake) => BuildSystem::Cmake,
+            Some(BuildTool::Make) => BuildSystem::Make,
+        }
+        .into(),
+        build_args: args.build_args.clone(),
+        run_target: args.target.clone().unwrap_or_default(),
+        emit: match args.emit {
+            None => Emit::Executable,
+            Some(EmitFormat::Ptx) => Emit::Ptx,
+            Some(EmitFormat::Cubin) => Emit::Cubin,
         }
+        .into(),
+        libraries: args.libraries.clone(),
+        accelerator: match args.accelerator {
+            None => Accelerator::Any,
+            Some(Backend::Cuda) => Accelerator::Cuda,
+            Some(Backend::Rocm) => Accelerator::Rocm,
+            Some(Backend::Cpu) => Accelerator::Cpu,
+        }
+        .into(),
+        priority: match args.priority {
+            None | Some(JobPriority::Normal) => Priority::Normal,
+            Some(JobPriority::Low) => Priority::Low,
+            Some(JobPriority::High) => Priority::High,
+        }
+        .into(),
+        ..Default::default()
     }
+}
 
-    println!("\n{} Execution finished.", "✅".bold().green());
+/// The compiler a repro command calls when the host didn't report its own,
+/// with any flags the host always adds; without a choice, the likelier one.
+fn compiler_words(accelerator: Option<Backend>) -> &'static [&'static str] {
+    match accelerator {
+        Some(Backend::Rocm) => &["hipcc"],
+        Some(Backend::Cpu) => &["g++", "-fopenmp"],
+        None | Some(Backend::Cuda) => &["nvcc"],
+    }
+}
 
+/// Asks the host to cancel the job, saying why.
+async fn cancel(job: &Job, msg: &Messages, why: Interrupt) -> Result<(), sdk::Error> {
+    let key = match why {
+        Interrupt::CtrlC => "cancelling",
+        Interrupt::Changed => "watch_changed",
+    };
+    eprintln!("{}", msg.get(key, &[("job", &job.id())]).yellow());
+    job.cancel().await?;
     Ok(())
+}
+
+/// Prints one non-artifact message of a job's stream.
+fn show(json: bool, response: &ComputeResponse, first: bool) {
+    if json {
+        events::message(response, first);
+        return;
+    }
+    if !response.telemetry.is_empty() {
+        output::status(&response.telemetry);
+        return;
+    }
+    output::clear_status();
+    if let Some(diagnostic) = &response.diagnostic {
+        eprintln!("{}", diagnostics::render(diagnostic));
+    } else if let Some(finding) = &response.finding {
+        eprintln!("{}", diagnostics::render_finding(finding, &response.output));
+    } else {
+        output::print(response);
+    }
+}
+
+/// Scripts can rely on our exit code: the program's own, or 1 for any other failure
+fn exit_for(result: Option<&JobResult>) -> Result<(), Box<dyn std::error::Error>> {
+    match result {
+        Some(r) if r.status() == JobStatus::Succeeded => Ok(()),
+        Some(r) => std::process::exit(r.exit_code.filter(|c| *c != 0).unwrap_or(1)),
+        None => std::process::exit(1),
+    }
+}
+
+/// Resolves command-line paths to `(host path, local path)` pairs.
+fn locate(paths: &[PathBuf], msg: &Messages) -> Result<Vec<(String, PathBuf)>, String> {
+    sdk::locate(paths).map_err(|e| {
+        let paths = paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>();
+        msg.get("read_failed", &[("path", &paths.join(", ")), ("error", &e)])
+    })
+}
+
+pub fn summary(msg: &Messages, result: &JobResult) -> String {
+    let exit = match (result.exit_code, result.signal) {
+        (Some(code), _) => code.to_string(),
+        (None, Some(signal)) => msg.get("exit_signal", &[("signal", &signal)]),
+        (None, None) => "-".into(),
+    };
+    msg.get(
+        "summary",
+        &[
+            ("job", &result.job_id),
+            ("status", &result.status().as_str_name()),
+            ("exit", &exit),
+            ("compile", &format!("{:.2}s", result.compile_ms as f64 / 1000.0)),
+            ("run", &format!("{:.2}s", result.run_ms as f64 / 1000.0)),
+        ],
+    )
 }
\ No newline at end of file
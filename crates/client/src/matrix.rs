@@ -0,0 +1,126 @@
+/// `matrix`: one project built and run with several sets of nvcc flags, one
+/// job per variant, then a table comparing them.
+///
+/// Every variant is compiled afresh (the host's binary cache would make
+/// compile times meaningless) with `--resource-usage`, so the result carries
+/// ptxas's per-kernel stats; the table shows the most registers any kernel
+/// uses.
+/// Ctrl-C cancels the variant in flight and skips the rest.
+use crate::connection::Connection;
+use crate::messages::Messages;
+use crate::{JobArgs, events, locate, request, show, table};
+use colored::*;
+use common::compute::{CancelJobRequest, JobResult, JobStatus};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Makes ptxas report per-kernel resource usage.
+const RESOURCE_USAGE_FLAG: &str = "--resource-usage";
+
+struct Row {
+    flags: String,
+    result: Option<JobResult>,
+    registers: Option<u32>,
+}
+
+pub async fn run(
+    args: JobArgs,
+    variants: Vec<String>,
+    connection: &Connection,
+    msg: &Messages,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.detach || args.stdin || args.debug || args.prebuilt || args.fetch_binary || !args.fetch.is_empty() {
+        return Err(msg.get("matrix_unsupported", &[]).into());
+    }
+    let json = args.json || args.output == events::OutputFormat::Json;
+    let sources = locate(&args.files, msg)?;
+    let inputs = locate(&args.inputs, msg)?;
+
+    say!(json, "{}", msg.get("connecting", &[("server", &connection.server().cyan())]));
+    let host = connection.host(msg).await?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut rows = Vec::new();
+    for (i, variant) in variants.iter().enumerate() {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        say!(
+            json,
+            "\n{}",
+            msg.get(
+                "matrix_variant",
+                &[("index", &(i + 1)), ("count", &variants.len()), ("flags", &variant.yellow())]
+            )
+            .bold()
+        );
+        let mut start = request(&args);
+        start.compiler_flags.extend(variant.split_whitespace().map(str::to_string));
+        start.compiler_flags.push(RESOURCE_USAGE_FLAG.into());
+        start.no_cache = true;
+
+        let mut job = host.submit(sources.clone(), inputs.clone(), start).await?;
+        let canceller = tokio::spawn({
+            let (stop, msg, job_id) = (stop.clone(), msg.clone(), job.id().to_string());
+            let client = host.grpc();
+            async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    stop.store(true, Ordering::Relaxed);
+                    eprintln!("{}", msg.get("cancelling", &[("job", &job_id)]).yellow());
+                    let request = CancelJobRequest { job_id };
+                    let _ = client.clone().cancel_job(request).await;
+                }
+            }
+        });
+
+        let mut row = Row {
+            flags: variant.clone(),
+            result: None,
+            registers: None,
+        };
+        let mut first = true;
+        while let Some(response) = job.next_message().await? {
+            show(json, &response, first);
+            first = false;
+            if let Some(result) = response.result {
+                row.registers = result.kernels.iter().map(|k| k.registers).max();
+                row.result = Some(result);
+            }
+        }
+        canceller.abort();
+        rows.push(row);
+    }
+
+    if json {
+        events::matrix(rows.iter().map(|row| (row.flags.as_str(), row.result.as_ref(), row.registers)));
+    } else {
+        println!("\n{}", msg.get("matrix_title", &[]).bold());
+        print_table(msg, &rows);
+    }
+
+    let all_succeeded = rows.len() == variants.len()
+        && rows
+            .iter()
+            .all(|row| row.result.as_ref().is_some_and(|r| r.status() == JobStatus::Succeeded));
+    if !all_succeeded {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn print_table(msg: &Messages, rows: &[Row]) {
+    let mut table = vec![table::header(&msg.get("matrix_columns", &[]))];
+    for (i, row) in rows.iter().enumerate() {
+        let (status, compile, run) = match &row.result {
+            Some(r) => (
+                r.status().as_str_name().to_string(),
+                format!("{:.2}s", r.compile_ms as f64 / 1000.0),
+                format!("{:.2}s", r.run_ms as f64 / 1000.0),
+            ),
+            None => ("-".into(), "-".into(), "-".into()),
+        };
+        let registers = row.registers.map_or_else(|| "-".into(), |r| r.to_string());
+        table.push(vec![(i + 1).to_string(), row.flags.clone(), status, compile, run, registers]);
+    }
+    table::print(&table);
+}
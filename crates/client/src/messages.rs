@@ -0,0 +1,167 @@
+/// Message catalog for every string the client itself prints.
+///
+/// Remote program output and compiler diagnostics are passed through untouched;
+/// only client-generated text goes through here. Templates use `{name}`
+/// placeholders. Lookup order is: override file, selected locale, English,
+/// and finally the bare key, so a missing entry can never panic.
+use std::collections::HashMap;
+use std::path::Path;
+
+const EN: &[(&str, &str)] = &[
+    ("read_failed", "Could not read file {path}: {error}"),
+    ("connecting", "🚀 Connecting to host at {server}..."),
+    ("sending", "📤 Sending {file} to remote GPU..."),
+    ("sending_many", "📤 Sending {count} files to remote GPU..."),
+    ("prebuilt_single", "--prebuilt takes exactly one file: the executable to run"),
+    ("finished", "✅ Execution finished."),
+    ("summary", "Job {job}: {status} · exit {exit} · compile {compile} · run {run}"),
+    ("cancelling", "🛑 Cancelling job {job}... (Ctrl-C again to quit)"),
+    ("interrupted", "Interrupted"),
+    ("uploading", "📦 Uploading {count} input file(s)..."),
+    ("artifact_saved", "📦 Saved {path}"),
+    ("info_header", "🖥️ Host {server}"),
+    ("info_toolchain", "nvcc: {nvcc}\ndriver: {driver} (CUDA {cuda})"),
+    ("info_toolchains", "toolchains: {toolchains}"),
+    ("info_limits", "max concurrent jobs: {jobs} · max run time: {timeout}s"),
+    ("info_backends", "backends: {backends}"),
+    ("info_profilers", "profilers: {profilers}"),
+    ("info_libraries", "libraries: {libraries}"),
+    ("info_gpu", "GPU {index}: {name} · sm_{sm} · {free}/{total} MiB free"),
+    ("info_no_gpus", "No GPUs reported."),
+    ("info_mig_slice", "  MIG {gpu}/{device}: {profile} · {memory} MiB · {uuid}"),
+    ("info_mig_busy", "(busy)"),
+    ("info_mps", "shared GPUs: up to {clients} --shared jobs each, through MPS"),
+    ("info_notifiers", "--notify reaches: {notifiers}"),
+    ("info_max_chunk", "streamed files and output travel in chunks of up to {kib} KiB"),
+    ("detached", "📋 Job {job} submitted. Check on it with `status {job}` and `logs {job}`."),
+    ("status_phase", "Job {job}: {phase}"),
+    ("status_queued", "Job {job}: QUEUED at position {position}"),
+    ("stream_lost", "⚠️ Lost the connection; job {job} is still on the host. Resume with `attach {job} --from {offset}`."),
+    ("retry_connect", "🔌 {error}; retrying in {delay} ({attempt}/{retries})..."),
+    ("retry_reattach", "🔌 Lost the connection to job {job} ({error}); reattaching in {delay} ({attempt}/{retries})..."),
+    ("attaching", "🔗 Attaching to job {job}..."),
+    ("logs_dropped", "… {count} earlier entries were dropped by the host"),
+    ("logs_more", "… still running; continue with --from {offset}"),
+    ("history_row", "{job}  {submitted}  {submitter}  {status}  {files}"),
+    ("history_detail", "Job {job}\nsubmitted {submitted} by {submitter}\nfiles: {files}\nflags: {flags}\nsource sha256: {hash}"),
+    ("history_empty", "No jobs recorded yet."),
+    ("ago_seconds", "{count}s ago"),
+    ("ago_minutes", "{count}m ago"),
+    ("ago_hours", "{count}h ago"),
+    ("ago_days", "{count}d ago"),
+    ("purged", "🧹 Purged {count} kept workspace(s)."),
+    ("purge_failed", "⚠️ Not purged on {failure}"),
+    ("admin_jobs_columns", "job|submitter|phase|submitted"),
+    ("admin_no_jobs", "No jobs are queued or running."),
+    ("admin_killed", "🔪 Job {job} killed."),
+    ("admin_not_active", "Job {job} is not queued or running"),
+    ("admin_drained", "🧹 Cancelled {count} queued job(s)."),
+    ("admin_paused", "⏸️ The host is no longer accepting new jobs."),
+    ("admin_resumed", "▶️ The host is accepting new jobs again."),
+    ("admin_purge_what", "Say what to purge: --cache, --workspaces, or both"),
+    ("admin_purged", "🧹 Purged {cache} compile cache entries and {workspaces} kept workspace(s)."),
+    ("admin_orphans_columns", "directory|given up|error"),
+    ("admin_no_orphans", "Every directory the host tried to delete is gone."),
+    ("repro_header", "🔁 Reproduce locally:"),
+    ("kernels_title", "🧮 Kernel resources (ptxas):"),
+    ("kernels_columns", "kernel|arch|registers|smem|cmem|stack|spill stores|spill loads"),
+    ("debug_ready", "🐞 Attach within {limit}s with:\n   cuda-gdb {binary} -ex \"target remote localhost:{port}\""),
+    ("debug_attached", "🐞 Debugger connected"),
+    ("debug_tunnel_failed", "⚠️ Debugger connection failed: {error}"),
+    ("sanitizer_errors", "🧪 compute-sanitizer reported {count} error(s)"),
+    ("matrix_variant", "🧪 Variant {index}/{count}: {flags}"),
+    ("matrix_title", "📊 Flag matrix:"),
+    ("matrix_columns", "#|flags|status|compile|run|registers"),
+    ("matrix_unsupported", "matrix runs one job per variant; it can't be combined with --detach, --stdin, --debug, --prebuilt, --fetch or --fetch-binary"),
+    ("compare_unsupported", "compare runs the job once per server; it can't be combined with --detach, --stdin, --debug, --prebuilt, --fetch or --fetch-binary"),
+    ("compare_servers", "compare needs at least two hosts: repeat --server for each"),
+    ("compare_accepted", "📋 {server}: job {job} accepted"),
+    ("compare_finished", "🏁 {server}: {summary}"),
+    ("compare_failed", "❌ {server}: {error}"),
+    ("compare_title", "📊 Hosts compared:"),
+    ("compare_columns", "#|server|GPU|status|exit|compile|run|stdout"),
+    ("compare_stdout_reference", "reference"),
+    ("compare_stdout_same", "same as #{reference_index}"),
+    ("compare_stdout_differs", "differs from #{reference_index}"),
+    ("compare_diff", "🔀 stdout of #{index} ({server}) against #{reference_index} ({reference}):"),
+    ("compare_same", "✅ stdout is identical on every host"),
+    ("compare_skipped", "@@ {count} unchanged line(s) @@"),
+    ("exit_signal", "signal {signal}"),
+    ("config_unreadable", "Could not read config file {path}: {error}"),
+    ("config_invalid", "Invalid config file {path}: {error}"),
+    ("config_no_profile", "No profile \"{profile}\" in the config file (have: {known})"),
+    ("servers_compare_only", "Only compare takes more than one --server"),
+    ("watch_waiting", "👀 Watching for changes... (Ctrl-C to quit)"),
+    ("watch_changed", "🔁 Files changed; cancelling job {job} to start over..."),
+    ("watch_rerun", "🔁 Files changed; running again."),
+    ("watch_detach", "watch streams every run; it can't be combined with --detach or --stdin"),
+    ("watch_out_dir", "Downloads would land in the watched project {path}; pick an --out-dir outside it"),
+];
+
+/// Returns the built-in catalog for a language code, if one ships with the client.
+fn builtin(lang: &str) -> Option<&'static [(&'static str, &'static str)]> {
+    match lang {
+        "en" => Some(EN),
+        _ => None,
+    }
+}
+
+#[derive(Clone)]
+pub struct Messages {
+    locale: &'static [(&'static str, &'static str)],
+    overrides: HashMap<String, String>,
+}
+
+impl Messages {
+    /// Picks the locale from `--lang`, falling back to `LANG` (e.g. `de_DE.UTF-8` -> `de`)
+    /// and then English, and layers an optional TOML override file on top.
+    pub fn load(lang: Option<&str>, overrides: Option<&Path>) -> Result<Self, String> {
+        let env_lang = std::env::var("LANG").ok();
+        let code = lang
+            .or(env_lang.as_deref())
+            .map(|l| l.split(['_', '.', '-']).next().unwrap_or(l).to_lowercase())
+            .unwrap_or_default();
+
+        let mut messages = Messages {
+            locale: builtin(&code).unwrap_or(EN),
+            overrides: HashMap::new(),
+        };
+
+        if let Some(path) = overrides {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| format!("Could not read messages file {}: {}", path.display(), e))?;
+            let table: toml::Table = text
+                .parse()
+                .map_err(|e| format!("Invalid messages file {}: {}", path.display(), e))?;
+            for (key, value) in table {
+                if let toml::Value::String(template) = value {
+                    messages.overrides.insert(key, template);
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    fn template<'a>(&'a self, key: &'a str) -> &'a str {
+        let lookup = |catalog: &'static [(&'static str, &'static str)]| {
+            catalog.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+        };
+
+        self.overrides
+            .get(key)
+            .map(String::as_str)
+            .or_else(|| lookup(self.locale))
+            .or_else(|| lookup(EN))
+            .unwrap_or(key)
+    }
+
+    /// Renders `key`, substituting each `{name}` with its value from `args`.
+    pub fn get(&self, key: &str, args: &[(&str, &dyn std::fmt::Display)]) -> String {
+        let mut text = self.template(key).to_string();
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), &value.to_string());
+        }
+        text
+    }
+}
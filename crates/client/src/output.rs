@@ -0,0 +1,155 @@
+/// Writing a job's output. The program's stdout and stderr arrive as raw
+/// bytes, newlines included, and are written to ours unchanged, so binary
+/// output, other encodings and the program's own ANSI colors survive. Only
+/// when stderr is a terminal is it decoded, to show it in red, and lines that
+/// already carry colors are left alone. Host messages are text and get a line
+/// each.
+///
+/// GPU telemetry is a status line on stderr, rewritten in place with each
+/// sample when stderr is a terminal and erased before anything else is
+/// printed; otherwise each sample gets a line.
+///
+/// `--color never` (or `--no-color`) turns our colors off and strips escape
+/// sequences from the program's output too; `always` forces our colors on;
+/// `auto` colors when stdout is a terminal, honoring NO_COLOR and CLICOLOR.
+use colored::*;
+use common::compute::{ComputeResponse, GpuSample, JobPhase, OutputKind};
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by `--color never`.
+static STRIP: AtomicBool = AtomicBool::new(false);
+/// Set while a telemetry status line is showing.
+static STATUS: AtomicBool = AtomicBool::new(false);
+
+const ESC: u8 = 0x1b;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Applies the color mode for the rest of the process.
+pub fn init(mode: ColorMode) {
+    match mode {
+        ColorMode::Auto => {}
+        ColorMode::Always => colored::control::set_override(true),
+        ColorMode::Never => {
+            colored::control::set_override(false);
+            STRIP.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+pub fn print(response: &ComputeResponse) {
+    if response.phase() != JobPhase::Unspecified && response.output.is_empty() {
+        // Phase changes are for `--output json`; the text says the same
+        return;
+    }
+    if !response.telemetry.is_empty() {
+        // Only live samples are shown, by `status`
+        return;
+    }
+    let stripped;
+    let output = if STRIP.load(Ordering::Relaxed) {
+        stripped = strip_ansi(&response.output);
+        &stripped
+    } else {
+        &response.output
+    };
+    match response.kind() {
+        OutputKind::Stdout => write_raw(&mut io::stdout().lock(), output),
+        OutputKind::Stderr
+            if colored::control::SHOULD_COLORIZE.should_colorize()
+                && io::stderr().is_terminal()
+                && !output.contains(&ESC) =>
+        {
+            let text = String::from_utf8_lossy(output);
+            eprintln!("{}", text.trim_end_matches(['\r', '\n']).red());
+        }
+        OutputKind::Stderr => write_raw(&mut io::stderr().lock(), output),
+        // Hosts without `kind` send text lines, stderr flagged by `is_error`
+        OutputKind::System | OutputKind::Unspecified => {
+            let text = String::from_utf8_lossy(output);
+            if response.is_error {
+                eprintln!("{}", text.red());
+            } else {
+                println!("{}", text);
+            }
+        }
+    }
+}
+
+/// Shows a sample of the job's GPUs, e.g.
+/// "📈 GPU 0: 87% · 3120/24576 MiB · 67°C · 215 W".
+pub fn status(samples: &[GpuSample]) {
+    let line = samples
+        .iter()
+        .map(|s| {
+            format!(
+                "GPU {}: {}% · {}/{} MiB · {}°C · {:.0} W",
+                s.index, s.utilization_percent, s.memory_used_mib, s.memory_total_mib, s.temperature_c, s.power_watts
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("  ");
+    let mut err = io::stderr().lock();
+    if err.is_terminal() {
+        let _ = write!(err, "\r\x1b[2K{}", format!("📈 {}", line).dimmed()).and_then(|_| err.flush());
+        STATUS.store(true, Ordering::Relaxed);
+    } else {
+        let _ = writeln!(err, "📈 {}", line);
+    }
+}
+
+/// Erases the status line, if one is showing.
+pub fn clear_status() {
+    if STATUS.swap(false, Ordering::Relaxed) {
+        let mut err = io::stderr().lock();
+        let _ = write!(err, "\r\x1b[2K").and_then(|_| err.flush());
+    }
+}
+
+fn write_raw(out: &mut impl Write, bytes: &[u8]) {
+    // A closed pipe (e.g. `| head`) is not worth failing the job over
+    let _ = out.write_all(bytes).and_then(|_| out.flush());
+}
+
+/// Removes escape sequences: CSI (colors, cursor movement), OSC (titles,
+/// hyperlinks) and two-byte escapes.
+fn strip_ansi(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut rest = bytes.iter().copied().peekable();
+    while let Some(b) = rest.next() {
+        if b != ESC {
+            out.push(b);
+            continue;
+        }
+        match rest.next() {
+            // Parameters and intermediates, up to a final byte in @..~
+            Some(b'[') => {
+                for b in rest.by_ref() {
+                    if (0x40..=0x7e).contains(&b) {
+                        break;
+                    }
+                }
+            }
+            // Up to BEL or ESC \
+            Some(b']') => {
+                while let Some(b) = rest.next() {
+                    if b == 0x07 {
+                        break;
+                    }
+                    if b == ESC && rest.peek() == Some(&b'\\') {
+                        rest.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
@@ -0,0 +1,95 @@
+/// Builds a copy-pasteable shell snippet that reproduces the remote build locally.
+/// Hosts report the exact compiler invocation they ran in the job's result,
+/// flags they added included (target architectures, `--with` libraries, debug
+/// flags, a toolchain's nvcc). Without one (older hosts, cmake and make
+/// builds) it is rebuilt from the client's own arguments instead.
+use common::compute::JobResult;
+
+/// The build as a list of arguments, program first. `compiler` is nvcc, hipcc
+/// or a C++ compiler with its fixed flags and `paths` are the submitted
+/// (project-relative) file paths, for when the host reported nothing.
+pub fn repro_words(result: Option<&JobResult>, compiler: &[&str], paths: &[String], flags: &[String]) -> Vec<String> {
+    if let Some(result) = result
+        && !result.build_command.is_empty()
+    {
+        return result.build_command.clone();
+    }
+
+    let bin_name = if cfg!(windows) { "app.exe" } else { "app.out" };
+    let device = match compiler.first() {
+        Some(&"nvcc") => ".cu",
+        Some(&"hipcc") => ".hip",
+        _ => ".cpp",
+    };
+    let is_unit = |p: &&String| {
+        [device, ".cpp", ".cc", ".cxx", ".c"]
+            .iter()
+            .any(|ext| p.ends_with(ext))
+    };
+
+    let mut words: Vec<String> = compiler.iter().map(|word| word.to_string()).collect();
+    words.extend(paths.iter().filter(is_unit).cloned());
+    words.extend(flags.iter().cloned());
+    words.push("-o".into());
+    words.push(bin_name.into());
+    words
+}
+
+/// `words` as one shell command line.
+pub fn repro_command(words: &[String]) -> String {
+    words.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ")
+}
+
+/// POSIX shell quoting: safe words are left alone, everything else is wrapped in
+/// single quotes with embedded quotes spelled as `'\''`.
+pub fn shell_quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c));
+
+    if safe {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn awkward_flags_are_quoted() {
+        let flags = strings(&["-DGREETING=hello world", "-DNAME='x'", "-DQ=\"q\"", "", "-O3"]);
+        let words = repro_words(None, &["nvcc"], &strings(&["src/main.cu", "data.txt"]), &flags);
+        assert_eq!(
+            repro_command(&words),
+            r#"nvcc src/main.cu '-DGREETING=hello world' '-DNAME='\''x'\''' '-DQ="q"' '' -O3 -o app.out"#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_shell_sees_the_original_words() {
+        let words = strings(&["nvcc", "a b.cu", "-DS='it''s'", "-DD=\"$HOME\"", "`id`", "tab\there", "", "-o", "app.out"]);
+        let script = format!("printf '%s\\n' {}", repro_command(&words));
+        let output = std::process::Command::new("sh").arg("-c").arg(script).output().unwrap();
+        let printed = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(printed.lines().collect::<Vec<_>>(), words);
+    }
+
+    #[test]
+    fn the_host_reported_command_wins() {
+        let result = JobResult {
+            build_command: strings(&["/opt/cuda-12.4/bin/nvcc", "main.cu", "-G", "-arch=sm_80", "-o", "app.out"]),
+            ..Default::default()
+        };
+        let words = repro_words(Some(&result), &["nvcc"], &strings(&["main.cu"]), &[]);
+        assert_eq!(repro_command(&words), "/opt/cuda-12.4/bin/nvcc main.cu -G -arch=sm_80 -o app.out");
+    }
+}
@@ -0,0 +1,28 @@
+/// Plain-text tables: columns padded to their widest cell, the header dimmed.
+use colored::*;
+
+/// Prints `rows`, the first of which is the header.
+pub fn print(rows: &[Vec<String>]) {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|c| rows.iter().filter_map(|r| r.get(c)).map(|cell| cell.chars().count()).max().unwrap_or(0))
+        .collect();
+    for (n, row) in rows.iter().enumerate() {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        if n == 0 {
+            println!("{}", line.trim_end().dimmed());
+        } else {
+            println!("{}", line.trim_end());
+        }
+    }
+}
+
+/// The header row from a `|`-separated catalog entry.
+pub fn header(columns: &str) -> Vec<String> {
+    columns.split('|').map(str::to_string).collect()
+}
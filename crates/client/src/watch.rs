@@ -0,0 +1,120 @@
+/// `watch`: runs the job, then runs it again whenever one of its files
+/// changes. A change while a job is still running cancels it first.
+///
+/// A file argument is watched through its directory, so editors that save by
+/// replacing the file are noticed too; a directory argument is watched
+/// recursively, hidden entries excepted, so new files count as changes. Ctrl-C
+/// cancels the job in flight and quits.
+use crate::connection::Connection;
+use crate::messages::Messages;
+use crate::{Interrupt, JobArgs, execute};
+use colored::*;
+use common::compute::JobStatus;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Editors often write a file several times per save; changes this close
+/// together start one run.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// A submitted file or project directory, as an absolute path like the ones
+/// the watcher reports.
+struct Target {
+    path: PathBuf,
+    dir: bool,
+}
+
+impl Target {
+    fn matches(&self, changed: &Path) -> bool {
+        if !self.dir {
+            return changed == self.path;
+        }
+        changed.strip_prefix(&self.path).is_ok_and(|rel| {
+            !rel.components()
+                .any(|c| matches!(c, Component::Normal(name) if name.to_string_lossy().starts_with('.')))
+        })
+    }
+}
+
+pub async fn run(args: JobArgs, connection: &Connection, msg: &Messages) -> Result<(), Box<dyn std::error::Error>> {
+    if args.detach || args.stdin {
+        return Err(msg.get("watch_detach", &[]).into());
+    }
+    let targets: Vec<Target> = args
+        .files
+        .iter()
+        .map(|file| {
+            let path = std::path::absolute(file)?;
+            Ok(Target { dir: path.is_dir(), path })
+        })
+        .collect::<std::io::Result<_>>()?;
+    if args.fetch_binary || !args.fetch.is_empty() {
+        let out_dir = std::path::absolute(&args.out_dir)?;
+        if let Some(project) = targets.iter().find(|t| t.dir && out_dir.starts_with(&t.path)) {
+            return Err(msg.get("watch_out_dir", &[("path", &project.path.display())]).into());
+        }
+    }
+
+    // Both file changes and Ctrl-C interrupt the job in flight; `quit` tells them apart
+    let (tx, mut interrupts) = mpsc::channel(1);
+    let quit = Arc::new(AtomicBool::new(false));
+    let watched: Vec<(PathBuf, RecursiveMode)> = targets
+        .iter()
+        .map(|t| match t.path.parent() {
+            Some(parent) if !t.dir => (parent.to_path_buf(), RecursiveMode::NonRecursive),
+            _ => (t.path.clone(), RecursiveMode::Recursive),
+        })
+        .collect();
+    let changes = tx.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event
+            && matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+            && event.paths.iter().any(|p| targets.iter().any(|t| t.matches(p)))
+        {
+            let _ = changes.try_send(Interrupt::Changed);
+        }
+    })?;
+    for (path, mode) in watched {
+        watcher.watch(&path, mode)?;
+    }
+    let stop = quit.clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            stop.store(true, Ordering::Relaxed);
+            if tx.send(Interrupt::CtrlC).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        let cancelled = match execute(&args, connection, msg, &mut interrupts).await {
+            Ok(result) => result.is_some_and(|r| r.status() == JobStatus::Cancelled),
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                false
+            }
+        };
+        if quit.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        // A job cancelled for a change starts over right away
+        if !cancelled {
+            eprintln!("\n{}", msg.get("watch_waiting", &[]).dimmed());
+            interrupts.recv().await;
+            if quit.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(DEBOUNCE).await;
+        while interrupts.try_recv().is_ok() {}
+        if quit.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        eprintln!("\n{}", msg.get("watch_rerun", &[]).cyan());
+    }
+}
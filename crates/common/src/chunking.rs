@@ -0,0 +1,270 @@
+/// The single chunking policy for bulk bytes on the wire.
+///
+/// Anything that carries potentially large payloads in stream messages (file
+/// uploads, artifacts, long output) must split them with `chunk_bytes` and put
+/// them back together with `Reassembler`, so client and host agree on the limits.
+/// Senders attach `digest` of the whole payload so the receiver can verify it.
+/// Streamed output is read with `read_line_chunk`, which never holds more than
+/// one chunk of a line.
+use prost::bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Largest payload a single message may carry. Comfortably below tonic's
+/// default 4 MiB decode limit once protobuf framing is added.
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Splits `data` into `MAX_CHUNK_SIZE` pieces. Empty input yields no chunks.
+pub fn chunk_bytes(data: &[u8]) -> impl Iterator<Item = Bytes> + '_ {
+    chunk_bytes_with(data, MAX_CHUNK_SIZE)
+}
+
+/// Same as `chunk_bytes`, with an explicit (e.g. negotiated) chunk size.
+pub fn chunk_bytes_with(data: &[u8], chunk_size: usize) -> impl Iterator<Item = Bytes> + '_ {
+    data.chunks(chunk_size.max(1)).map(Bytes::copy_from_slice)
+}
+
+/// Reads the next line into `buf`, newline included, but never lets `buf` grow
+/// past `MAX_CHUNK_SIZE`: a longer line comes back in pieces. Returns the
+/// length of `buf` once it holds a line, a piece or the unterminated end of
+/// the input, and 0 at end of input. Cancel safe: bytes read before the
+/// future is dropped stay in `buf`.
+pub async fn read_line_chunk<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<usize> {
+    read_line_chunk_with(reader, buf, MAX_CHUNK_SIZE).await
+}
+
+/// Same as `read_line_chunk`, with an explicit chunk size.
+pub async fn read_line_chunk_with<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    chunk_size: usize,
+) -> io::Result<usize> {
+    let chunk_size = chunk_size.max(1);
+    loop {
+        let room = chunk_size.saturating_sub(buf.len());
+        if room == 0 {
+            return Ok(buf.len());
+        }
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(buf.len());
+        }
+        let window = &available[..available.len().min(room)];
+        let (taken, done) = match window.iter().position(|&b| b == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (window.len(), window.len() == room),
+        };
+        buf.extend_from_slice(&window[..taken]);
+        reader.consume(taken);
+        if done {
+            return Ok(buf.len());
+        }
+    }
+}
+
+/// Hex SHA-256 of a whole payload.
+pub fn digest(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkError {
+    /// A single chunk exceeded the per-message limit.
+    ChunkTooLarge { size: usize, max: usize },
+    /// The reassembled payload would exceed the total limit.
+    TotalTooLarge { total: usize, max: usize },
+    /// The reassembled payload doesn't match the sender's digest.
+    ChecksumMismatch { expected: String, actual: String },
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::ChunkTooLarge { size, max } => {
+                write!(f, "chunk of {} bytes exceeds the {} byte chunk limit", size, max)
+            }
+            ChunkError::TotalTooLarge { total, max } => {
+                write!(f, "payload of {} bytes exceeds the {} byte total limit", total, max)
+            }
+            ChunkError::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum mismatch: expected sha256 {}, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+/// Collects chunks back into one buffer, validating both limits as it goes.
+#[derive(Debug)]
+pub struct Reassembler {
+    buf: Vec<u8>,
+    max_chunk: usize,
+    max_total: usize,
+}
+
+impl Reassembler {
+    pub fn new(max_total: usize) -> Self {
+        Self::with_limits(MAX_CHUNK_SIZE, max_total)
+    }
+
+    pub fn with_limits(max_chunk: usize, max_total: usize) -> Self {
+        Reassembler {
+            buf: Vec::new(),
+            max_chunk,
+            max_total,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), ChunkError> {
+        if chunk.len() > self.max_chunk {
+            return Err(ChunkError::ChunkTooLarge {
+                size: chunk.len(),
+                max: self.max_chunk,
+            });
+        }
+        let total = self.buf.len() + chunk.len();
+        if total > self.max_total {
+            return Err(ChunkError::TotalTooLarge {
+                total,
+                max: self.max_total,
+            });
+        }
+        self.buf.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// Like `finish`, but fails unless the payload matches `expected` (a
+    /// `digest`). An empty `expected` skips the check.
+    pub fn finish_verified(self, expected: &str) -> Result<Vec<u8>, ChunkError> {
+        if !expected.is_empty() {
+            let actual = digest(&self.buf);
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(ChunkError::ChecksumMismatch {
+                    expected: expected.to_string(),
+                    actual,
+                });
+            }
+        }
+        Ok(self.buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic xorshift, so a failing case can be replayed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.below(256) as u8).collect()
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip_for_any_size() {
+        let mut rng = Rng(0x5eed);
+        for case in 0..500 {
+            let len = rng.below(4096);
+            let data = rng.bytes(len);
+            let chunk_size = 1 + rng.below(512);
+            let mut reassembler = Reassembler::with_limits(chunk_size, data.len());
+            for chunk in chunk_bytes_with(&data, chunk_size) {
+                assert!(!chunk.is_empty() && chunk.len() <= chunk_size, "case {}", case);
+                reassembler.push(&chunk).unwrap();
+            }
+            assert_eq!(reassembler.finish_verified(&digest(&data)).unwrap(), data, "case {}", case);
+        }
+    }
+
+    #[test]
+    fn oversized_chunks_and_payloads_are_rejected() {
+        let mut rng = Rng(0xc0ffee);
+        for _ in 0..200 {
+            let max_chunk = 1 + rng.below(256);
+            let size = max_chunk + 1 + rng.below(256);
+            let mut reassembler = Reassembler::with_limits(max_chunk, usize::MAX);
+            assert_eq!(
+                reassembler.push(&rng.bytes(size)),
+                Err(ChunkError::ChunkTooLarge { size, max: max_chunk })
+            );
+
+            let max_total = rng.below(1024);
+            let data = rng.bytes(max_total + 1);
+            let mut reassembler = Reassembler::with_limits(max_chunk, max_total);
+            let rejected = chunk_bytes_with(&data, max_chunk).map(|chunk| reassembler.push(&chunk)).find(Result::is_err);
+            assert!(matches!(rejected, Some(Err(ChunkError::TotalTooLarge { max, .. })) if max == max_total));
+        }
+    }
+
+    #[test]
+    fn out_of_order_chunks_fail_the_checksum() {
+        let mut rng = Rng(0xfeed);
+        for case in 0..200 {
+            let chunk_size = 1 + rng.below(64);
+            let len = 2 * chunk_size + rng.below(1024);
+            let data = rng.bytes(len);
+            let mut chunks: Vec<_> = chunk_bytes_with(&data, chunk_size).collect();
+            let (i, j) = (rng.below(chunks.len()), rng.below(chunks.len()));
+            if chunks[i] == chunks[j] {
+                continue;
+            }
+            chunks.swap(i, j);
+            let mut reassembler = Reassembler::with_limits(chunk_size, data.len());
+            for chunk in &chunks {
+                reassembler.push(chunk).unwrap();
+            }
+            let verified = reassembler.finish_verified(&digest(&data));
+            assert!(matches!(verified, Err(ChunkError::ChecksumMismatch { .. })), "case {}", case);
+        }
+    }
+
+    async fn pieces(input: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+        // A tiny BufReader capacity exercises pieces spanning several fills
+        let mut reader = tokio::io::BufReader::with_capacity(3, input);
+        let mut pieces = Vec::new();
+        let mut buf = Vec::new();
+        while read_line_chunk_with(&mut reader, &mut buf, chunk_size).await.unwrap() > 0 {
+            pieces.push(std::mem::take(&mut buf));
+        }
+        pieces
+    }
+
+    #[tokio::test]
+    async fn read_line_chunk_splits_long_lines_and_keeps_short_ones() {
+        let got = pieces(b"ab\nabcdefghij\n\nxyz", 4).await;
+        let want: Vec<&[u8]> = vec![b"ab\n", b"abcd", b"efgh", b"ij\n", b"\n", b"xyz"];
+        assert_eq!(got, want);
+    }
+
+    #[tokio::test]
+    async fn read_line_chunk_never_exceeds_the_chunk_size() {
+        let input = vec![b'x'; 3 * MAX_CHUNK_SIZE + 5];
+        let got = pieces(&input, MAX_CHUNK_SIZE).await;
+        assert!(got.iter().all(|p| p.len() <= MAX_CHUNK_SIZE));
+        assert_eq!(got.concat(), input);
+    }
+}
@@ -0,0 +1,67 @@
+/// Optional compression of file payloads (uploaded sources and inputs,
+/// returned artifacts).
+///
+/// A client opts in per call by sending the `FILE_ENCODING_HEADER` request
+/// header; every file in both directions of that call is then encoded as a
+/// whole before `chunking` splits it. The `sha256` of a `FileChunk` covers the
+/// bytes as sent, i.e. after encoding.
+use std::io::{self, Read};
+
+pub const FILE_ENCODING_HEADER: &str = "x-ferris-file-encoding";
+
+/// zstd's default level: a good ratio on source and text output while staying
+/// far faster than the link it is meant to save.
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileEncoding {
+    #[default]
+    Identity,
+    Zstd,
+}
+
+impl FileEncoding {
+    /// Reads the header value; anything unrecognised means no encoding.
+    pub fn from_header(value: Option<&str>) -> Self {
+        match value.map(str::trim) {
+            Some(v) if v.eq_ignore_ascii_case("zstd") => FileEncoding::Zstd,
+            _ => FileEncoding::Identity,
+        }
+    }
+
+    pub fn header_value(self) -> &'static str {
+        match self {
+            FileEncoding::Identity => "identity",
+            FileEncoding::Zstd => "zstd",
+        }
+    }
+
+    pub fn encode(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            FileEncoding::Identity => Ok(data.to_vec()),
+            FileEncoding::Zstd => zstd::encode_all(data, ZSTD_LEVEL),
+        }
+    }
+
+    /// Reverses `encode`, refusing output larger than `max` bytes so a small
+    /// compressed payload can't expand past the receiver's limits.
+    pub fn decode(self, data: Vec<u8>, max: usize) -> io::Result<Vec<u8>> {
+        let decoded = match self {
+            FileEncoding::Identity => data,
+            FileEncoding::Zstd => {
+                let mut out = Vec::new();
+                zstd::Decoder::new(data.as_slice())?
+                    .take(max as u64 + 1)
+                    .read_to_end(&mut out)?;
+                out
+            }
+        };
+        if decoded.len() > max {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("decoded payload exceeds the {} byte limit", max),
+            ));
+        }
+        Ok(decoded)
+    }
+}
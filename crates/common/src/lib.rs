@@ -1,4 +1,10 @@
+pub mod auth;
+pub mod chunking;
+pub mod encoding;
+
 // This macro pulls in the code generated by the build script.
 pub mod compute {
+    // SubmitMessage's start marker, a whole ComputeRequest, dwarfs its chunks
+    #![allow(clippy::large_enum_variant)]
     tonic::include_proto!("compute"); 
 }
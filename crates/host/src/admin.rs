@@ -0,0 +1,175 @@
+/// The Admin service: operational control of the host for the users named
+/// in `admin_users`. Callers must be authenticated (a token or a client
+/// certificate); without either the service refuses every call.
+///
+/// It works on the executor's own state: the job registry and logs, the
+/// compile cache, kept workspaces, the cleanup queue, and the switch that
+/// pauses submissions.
+use crate::auth::User;
+use crate::cache::CompileCache;
+use crate::cleanup::CleanupQueue;
+use crate::joblog::JobLogs;
+use crate::registry::JobRegistry;
+use crate::retention::Retention;
+use common::compute::admin_server::Admin;
+use common::compute::{
+    ActiveJob, DrainQueueRequest, DrainQueueResponse, GetConfigRequest, GetConfigResponse, JobPhase, KillJobRequest,
+    KillJobResponse, ListActiveJobsRequest, ListActiveJobsResponse, ListOrphansRequest, ListOrphansResponse, Orphan,
+    PurgeRequest, PurgeResponse, SetAcceptingRequest, SetAcceptingResponse,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+pub struct AdminService {
+    pub registry: JobRegistry,
+    pub logs: JobLogs,
+    pub cache: Arc<CompileCache>,
+    pub retention: Retention,
+    pub cleanup: CleanupQueue,
+    /// Set while submissions are paused; the executor checks it too.
+    pub paused: Arc<AtomicBool>,
+    pub admin_users: Vec<String>,
+    /// The settings in effect, rendered at startup.
+    pub config: String,
+}
+
+impl AdminService {
+    /// The admin making `request`.
+    #[allow(clippy::result_large_err)]
+    fn authorize<T>(&self, request: &Request<T>) -> Result<String, Status> {
+        match request.extensions().get::<User>() {
+            Some(User(name)) if self.admin_users.contains(name) => Ok(name.clone()),
+            Some(User(name)) => Err(Status::permission_denied(format!("{} is not an admin of this host", name))),
+            None => Err(Status::permission_denied(
+                "Admin calls need an authenticated user named in the host's admin_users",
+            )),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    async fn list_active_jobs(
+        &self,
+        request: Request<ListActiveJobsRequest>,
+    ) -> Result<Response<ListActiveJobsResponse>, Status> {
+        self.authorize(&request)?;
+        let jobs = self
+            .registry
+            .list()
+            .into_iter()
+            .map(|job| {
+                let status = self.logs.get(&job.job_id).map(|log| log.status()).unwrap_or_default();
+                (job, status)
+            })
+            // Cancelled jobs stay registered for a moment while they wind down
+            .filter(|(_, status)| status.phase() != JobPhase::Done)
+            .map(|(job, status)| {
+                ActiveJob {
+                    phase: status.phase,
+                    queue_position: status.queue_position,
+                    submitted_at_ms: job
+                        .submitted
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |since| since.as_millis() as i64),
+                    job_id: job.job_id,
+                    submitter: job.submitter,
+                }
+            })
+            .collect();
+        Ok(Response::new(ListActiveJobsResponse { jobs }))
+    }
+
+    async fn kill_job(&self, request: Request<KillJobRequest>) -> Result<Response<KillJobResponse>, Status> {
+        let admin = self.authorize(&request)?;
+        let job_id = request.into_inner().job_id;
+        let killed = self.registry.cancel(&job_id);
+        if killed {
+            info!("🔪 Job {} killed by admin {}", job_id, admin);
+        }
+        Ok(Response::new(KillJobResponse { killed }))
+    }
+
+    async fn drain_queue(&self, request: Request<DrainQueueRequest>) -> Result<Response<DrainQueueResponse>, Status> {
+        let admin = self.authorize(&request)?;
+        let mut cancelled = 0;
+        for job in self.registry.list() {
+            let queued = self
+                .logs
+                .get(&job.job_id)
+                .is_some_and(|log| log.status().phase() == JobPhase::Queued);
+            if queued && self.registry.cancel(&job.job_id) {
+                cancelled += 1;
+            }
+        }
+        info!("🧹 Queue drained by admin {}: {} job(s) cancelled", admin, cancelled);
+        Ok(Response::new(DrainQueueResponse { cancelled }))
+    }
+
+    async fn set_accepting(
+        &self,
+        request: Request<SetAcceptingRequest>,
+    ) -> Result<Response<SetAcceptingResponse>, Status> {
+        let admin = self.authorize(&request)?;
+        let accepting = request.into_inner().accepting;
+        let was_accepting = !self.paused.swap(!accepting, Ordering::Relaxed);
+        match accepting {
+            true => info!("▶️ Accepting new jobs again, by admin {}", admin),
+            false => info!("⏸️ Not accepting new jobs, by admin {}", admin),
+        }
+        Ok(Response::new(SetAcceptingResponse { was_accepting }))
+    }
+
+    async fn purge(&self, request: Request<PurgeRequest>) -> Result<Response<PurgeResponse>, Status> {
+        let admin = self.authorize(&request)?;
+        let request = request.into_inner();
+        let mut response = PurgeResponse::default();
+        if request.cache {
+            response.cache_entries = self
+                .cache
+                .clear()
+                .await
+                .map_err(|e| Status::internal(format!("Could not empty the compile cache: {}", e)))?;
+        }
+        if request.workspaces {
+            response.workspaces = self
+                .retention
+                .purge(None)
+                .await
+                .map_err(|e| Status::internal(format!("Could not purge workspaces: {}", e)))?;
+        }
+        info!(
+            "🧹 Purged by admin {}: {} cache entries, {} kept workspace(s)",
+            admin, response.cache_entries, response.workspaces
+        );
+        Ok(Response::new(response))
+    }
+
+    async fn get_config(&self, request: Request<GetConfigRequest>) -> Result<Response<GetConfigResponse>, Status> {
+        self.authorize(&request)?;
+        Ok(Response::new(GetConfigResponse {
+            toml: self.config.clone(),
+        }))
+    }
+
+    async fn list_orphans(&self, request: Request<ListOrphansRequest>) -> Result<Response<ListOrphansResponse>, Status> {
+        self.authorize(&request)?;
+        let orphans = self
+            .cleanup
+            .orphans()
+            .into_iter()
+            .map(|orphan| Orphan {
+                path: orphan.path.display().to_string(),
+                error: orphan.error,
+                since_ms: orphan
+                    .since
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_millis() as i64),
+            })
+            .collect();
+        Ok(Response::new(ListOrphansResponse { orphans }))
+    }
+}
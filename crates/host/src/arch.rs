@@ -0,0 +1,47 @@
+/// Picks `-arch`/`-gencode` flags matching the host's GPUs, so a binary built
+/// without an explicit target still has a kernel image for the device it
+/// lands on.
+use common::compute::GpuInfo;
+use std::collections::BTreeSet;
+
+/// Flags through which a client already chose its target architecture.
+const ARCH_FLAGS: &[&str] = &[
+    "-arch",
+    "--gpu-architecture",
+    "-gencode",
+    "--generate-code",
+    "-code",
+    "--gpu-code",
+];
+
+/// Flags targeting every distinct compute capability among `gpus`: a single
+/// `-arch=sm_XX` when they all agree, one `-gencode` per capability otherwise.
+/// Empty when no capability is known.
+pub fn flags_for(gpus: &[GpuInfo]) -> Vec<String> {
+    let sms: BTreeSet<String> = gpus
+        .iter()
+        .map(|g| g.compute_capability.replace('.', ""))
+        .filter(|sm| !sm.is_empty() && sm.bytes().all(|b| b.is_ascii_digit()))
+        .collect();
+
+    match sms.len() {
+        0 => Vec::new(),
+        1 => vec![format!("-arch=sm_{}", sms.first().unwrap())],
+        _ => sms
+            .iter()
+            .map(|sm| format!("-gencode=arch=compute_{sm},code=sm_{sm}"))
+            .collect(),
+    }
+}
+
+/// Whether `flags` already pick a target architecture.
+pub fn specified(flags: &[String]) -> bool {
+    flags.iter().any(|flag| {
+        ARCH_FLAGS.iter().any(|arch| {
+            flag == arch
+                || flag
+                    .strip_prefix(arch)
+                    .is_some_and(|rest| rest.starts_with('='))
+        })
+    })
+}
@@ -0,0 +1,91 @@
+/// Output files a job asked to get back. After the program exits, every
+/// regular file in the workspace matching one of the job's glob patterns is
+/// streamed to the client as artifact chunks.
+use crate::stream::JobStream;
+use glob::{MatchOptions, Pattern};
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+
+/// Total bytes of artifacts returned per job; files beyond it are skipped.
+pub const MAX_ARTIFACT_BYTES: u64 = 512 * 1024 * 1024;
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    // `*` stays within a directory; `**` crosses them
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Compiles the request's patterns, rejecting any that could point outside
+/// the workspace.
+pub fn patterns(globs: &[String]) -> Result<Vec<Pattern>, String> {
+    globs
+        .iter()
+        .map(|glob| {
+            let escapes = Path::new(glob)
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+            if glob.is_empty() || escapes {
+                return Err(format!("Artifact pattern {:?} must be relative to the workspace", glob));
+            }
+            Pattern::new(glob).map_err(|e| format!("Bad artifact pattern {:?}: {}", glob, e))
+        })
+        .collect()
+}
+
+/// Streams every file under `workspace` matching `patterns`.
+pub async fn send_matching(stream: &JobStream, workspace: &Path, patterns: &[Pattern]) {
+    let mut budget = MAX_ARTIFACT_BYTES;
+    let mut sent = 0;
+
+    for path in regular_files(workspace).await {
+        let Ok(rel) = path.strip_prefix(workspace) else { continue };
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        if !patterns.iter().any(|p| p.matches_with(&rel, MATCH_OPTIONS)) {
+            continue;
+        }
+
+        let data = match fs::read(&path).await {
+            Ok(data) => data,
+            Err(e) => {
+                stream.send(format!("⚠️ Could not read {}: {}", rel, e), true).await;
+                continue;
+            }
+        };
+        if data.len() as u64 > budget {
+            let message = format!(
+                "⚠️ Skipping {}: artifacts are limited to {} MiB per job",
+                rel,
+                MAX_ARTIFACT_BYTES >> 20
+            );
+            stream.send(message, true).await;
+            continue;
+        }
+        budget -= data.len() as u64;
+        stream.send_artifact(&rel, &data).await;
+        sent += 1;
+    }
+
+    if sent == 0 {
+        stream.send("⚠️ No files matched the artifact patterns.", true).await;
+    }
+}
+
+/// All regular files below `dir`, sorted. Symlinks are not followed, so a
+/// program can't smuggle out files from elsewhere on the host.
+async fn regular_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else { continue };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            match entry.file_type().await {
+                Ok(t) if t.is_dir() => pending.push(entry.path()),
+                Ok(t) if t.is_file() => files.push(entry.path()),
+                _ => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
@@ -0,0 +1,65 @@
+/// Benchmark jobs: the program is run `warmup + iterations` times back to
+/// back on the same GPUs, and the timed runs are summarized in
+/// `JobResult.benchmark`. The job's timeout covers all runs together.
+use common::compute::{BenchmarkOptions, BenchmarkStats};
+use std::time::Duration;
+
+/// Most runs (warmup included) one job may ask for.
+pub const MAX_RUNS: u32 = 1000;
+
+/// Checks the options of a request before it is queued.
+pub fn validate(options: &BenchmarkOptions, forward_stdin: bool) -> Result<(), String> {
+    if options.iterations == 0 {
+        return Ok(());
+    }
+    if forward_stdin {
+        return Err("A benchmark runs the program many times; it can't read stdin".into());
+    }
+    if options.iterations.saturating_add(options.warmup) > MAX_RUNS {
+        return Err(format!("A benchmark can have at most {} runs, warmup included", MAX_RUNS));
+    }
+    Ok(())
+}
+
+pub fn stats(warmup: u32, runs: &[Duration]) -> BenchmarkStats {
+    let runs_ms: Vec<f64> = runs.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+    let mut sorted = runs_ms.clone();
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len();
+    if n == 0 {
+        return BenchmarkStats { warmup, ..Default::default() };
+    }
+
+    let mean = sorted.iter().sum::<f64>() / n as f64;
+    let variance = sorted.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / n as f64;
+    let median = if n.is_multiple_of(2) {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    } else {
+        sorted[n / 2]
+    };
+    // Nearest-rank percentile
+    let p95 = sorted[((0.95 * n as f64).ceil() as usize).clamp(1, n) - 1];
+
+    BenchmarkStats {
+        runs_ms,
+        warmup,
+        min_ms: sorted[0],
+        median_ms: median,
+        mean_ms: mean,
+        p95_ms: p95,
+        stddev_ms: variance.sqrt(),
+    }
+}
+
+/// One-line summary for the job's stream.
+pub fn summary(stats: &BenchmarkStats) -> String {
+    format!(
+        "⏱️ {} runs: min {:.3} ms · median {:.3} ms · mean {:.3} ms · p95 {:.3} ms · stddev {:.3} ms",
+        stats.runs_ms.len(),
+        stats.min_ms,
+        stats.median_ms,
+        stats.mean_ms,
+        stats.p95_ms,
+        stats.stddev_ms
+    )
+}
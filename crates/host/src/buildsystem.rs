@@ -0,0 +1,125 @@
+/// Projects built by their own build system instead of one nvcc invocation.
+///
+/// A cmake project is configured and built out of tree, in `build/` under the
+/// workspace; the job's compiler flags and the host's nvcc (or the job's
+/// toolchain) are passed in through the usual cache variables:
+///
+/// ```text
+/// cmake -S . -B build -DCMAKE_CUDA_COMPILER=<nvcc> -DCMAKE_CUDA_FLAGS=<flags> <build_args>
+/// cmake --build build
+/// ```
+///
+/// A make project is built in place by running `make` at its root, with
+/// `NVCC` and `NVCCFLAGS` set on the command line and the job's targets and
+/// variables after them.
+///
+/// Every step's output is streamed and parsed like nvcc's own.
+use common::compute::BuildSystem;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Build directory of cmake projects, relative to the workspace.
+pub const CMAKE_BUILD_DIR: &str = "build";
+
+/// Files one of which a project needs at its root for `system` to build it.
+pub fn manifests(system: BuildSystem) -> &'static [&'static str] {
+    match system {
+        BuildSystem::Nvcc => &[],
+        BuildSystem::Cmake => &["CMakeLists.txt"],
+        // In the order make looks for them
+        BuildSystem::Make => &["GNUmakefile", "makefile", "Makefile"],
+    }
+}
+
+/// Name of the build tool, for messages.
+pub fn tool(system: BuildSystem) -> &'static str {
+    match system {
+        BuildSystem::Nvcc => "nvcc",
+        BuildSystem::Cmake => "cmake",
+        BuildSystem::Make => "make",
+    }
+}
+
+/// The arguments of each cmake invocation, in order. `nvcc` is `None` inside
+/// a job's image, whose own nvcc cmake finds by itself.
+pub fn cmake_steps(nvcc: Option<&Path>, flags: &[String], build_args: &[String]) -> Vec<Vec<OsString>> {
+    let mut configure: Vec<OsString> = ["-S", ".", "-B", CMAKE_BUILD_DIR].into_iter().map(OsString::from).collect();
+    if let Some(nvcc) = nvcc {
+        let mut define = OsString::from("-DCMAKE_CUDA_COMPILER=");
+        define.push(nvcc);
+        configure.push(define);
+    }
+    if !flags.is_empty() {
+        configure.push(format!("-DCMAKE_CUDA_FLAGS={}", flags.join(" ")).into());
+    }
+    configure.extend(build_args.iter().map(OsString::from));
+    let build = ["--build", CMAKE_BUILD_DIR].into_iter().map(OsString::from).collect();
+    vec![configure, build]
+}
+
+/// The make invocation, likewise.
+pub fn make_args(nvcc: Option<&Path>, flags: &[String], build_args: &[String]) -> Vec<OsString> {
+    let mut args = Vec::new();
+    if let Some(nvcc) = nvcc {
+        let mut variable = OsString::from("NVCC=");
+        variable.push(nvcc);
+        args.push(variable);
+    }
+    if !flags.is_empty() {
+        args.push(format!("NVCCFLAGS={}", flags.join(" ")).into());
+    }
+    args.extend(build_args.iter().map(OsString::from));
+    args
+}
+
+/// The program a project built with `system` should run.
+pub fn program(system: BuildSystem, workspace: &Path, target: &str) -> Option<PathBuf> {
+    match system {
+        BuildSystem::Nvcc => None,
+        BuildSystem::Cmake => find_program(&workspace.join(CMAKE_BUILD_DIR), target),
+        BuildSystem::Make => Some(workspace.join(target)).filter(|path| path.is_file()),
+    }
+}
+
+/// The executable built for `target`: a path relative to `build_dir` if it
+/// has a separator, otherwise the shallowest file of that name under it.
+fn find_program(build_dir: &Path, target: &str) -> Option<PathBuf> {
+    if target.contains('/') {
+        let path = build_dir.join(target);
+        return path.is_file().then_some(path);
+    }
+    let name = if cfg!(windows) { format!("{}.exe", target) } else { target.to_string() };
+    let mut dirs = VecDeque::from([build_dir.to_path_buf()]);
+    while let Some(dir) = dirs.pop_front() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut subdirs = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                // cmake's own bookkeeping, with compiler probes in it
+                if entry.file_name() != "CMakeFiles" {
+                    subdirs.push(path);
+                }
+            } else if entry.file_name().to_str() == Some(name.as_str()) && is_executable(&path) {
+                return Some(path);
+            }
+        }
+        subdirs.sort();
+        dirs.extend(subdirs);
+    }
+    None
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
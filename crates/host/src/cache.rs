@@ -0,0 +1,201 @@
+/// Content-addressed cache of compiled binaries.
+///
+/// The key covers every submitted file (path and contents), the compiler flags,
+/// and the nvcc version, so any change that could alter the binary misses. The
+/// cache is bounded by total size; the least recently used entries are evicted
+/// first (a hit refreshes the entry's mtime).
+use common::compute::SourceFile;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+pub struct CompileCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    // Serializes stores/evictions so two jobs don't evict each other's entries mid-copy.
+    write_lock: Mutex<()>,
+}
+
+impl CompileCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        CompileCache {
+            dir,
+            max_bytes,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn key(files: &[SourceFile], flags: &[String], compiler_version: &str) -> String {
+        let mut sorted: Vec<&SourceFile> = files.iter().collect();
+        sorted.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut hasher = Sha256::new();
+        // Length prefixes keep ("ab", "c") and ("a", "bc") from colliding.
+        let mut field = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+        field(compiler_version.as_bytes());
+        for flag in flags {
+            field(flag.as_bytes());
+        }
+        for file in sorted {
+            field(file.path.as_bytes());
+            field(file.contents.as_bytes());
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", key))
+    }
+
+    /// Copies a cached binary to `dest`. Returns false on a miss.
+    pub async fn restore(&self, key: &str, dest: &Path) -> bool {
+        let entry = self.entry(key);
+        if fs::copy(&entry, dest).await.is_err() {
+            return false;
+        }
+        // Mark as recently used for LRU eviction
+        if let Ok(file) = std::fs::File::options().write(true).open(&entry) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        true
+    }
+
+    /// Adds a freshly built binary, then evicts down to the size limit.
+    pub async fn store(&self, key: &str, binary: &Path) -> io::Result<()> {
+        let _guard = self.write_lock.lock().await;
+        fs::create_dir_all(&self.dir).await?;
+
+        // Copy then rename, so readers never see a half-written entry
+        let tmp = self.dir.join(format!("{}.tmp", key));
+        fs::copy(binary, &tmp).await?;
+        fs::rename(&tmp, self.entry(key)).await?;
+
+        self.evict().await
+    }
+
+    /// Deletes every entry. Returns how many there were.
+    pub async fn clear(&self) -> io::Result<u32> {
+        let _guard = self.write_lock.lock().await;
+        let mut dir = match fs::read_dir(&self.dir).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut removed = 0;
+        while let Some(entry) = dir.next_entry().await? {
+            if entry.file_type().await?.is_file() && fs::remove_file(entry.path()).await.is_ok() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0u64;
+        let mut dir = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let meta = entry.metadata().await?;
+            if meta.is_file() {
+                total += meta.len();
+                let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                entries.push((used, meta.len(), entry.path()));
+            }
+        }
+
+        entries.sort();
+        for (_, size, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).await.is_ok() {
+                total -= size;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, contents: &str) -> SourceFile {
+        SourceFile {
+            path: path.into(),
+            contents: contents.into(),
+        }
+    }
+
+    fn flags(flags: &[&str]) -> Vec<String> {
+        flags.iter().map(|flag| flag.to_string()).collect()
+    }
+
+    #[test]
+    fn the_key_ignores_file_order_only() {
+        let files = [file("main.cu", "int main() {}"), file("util.cuh", "#pragma once")];
+        let key = CompileCache::key(&files, &flags(&["-O3", "-G"]), "12.4");
+        let reversed = [files[1].clone(), files[0].clone()];
+        assert_eq!(CompileCache::key(&reversed, &flags(&["-O3", "-G"]), "12.4"), key);
+        assert_eq!(key.len(), 64);
+
+        let changed = [
+            CompileCache::key(&files, &flags(&["-G", "-O3"]), "12.4"),
+            CompileCache::key(&files, &flags(&["-O3"]), "12.4"),
+            CompileCache::key(&files, &flags(&["-O3", "-G"]), "12.5"),
+            CompileCache::key(&[file("main.cu", "int main() {}")], &flags(&["-O3", "-G"]), "12.4"),
+            CompileCache::key(&[files[0].clone(), file("util.cuh", "")], &flags(&["-O3", "-G"]), "12.4"),
+            CompileCache::key(&[files[0].clone(), file("util.h", "#pragma once")], &flags(&["-O3", "-G"]), "12.4"),
+        ];
+        for other in changed {
+            assert_ne!(other, key);
+        }
+    }
+
+    #[test]
+    fn fields_cannot_run_into_each_other() {
+        let key = |flags: &[&str]| CompileCache::key(&[], &self::flags(flags), "");
+        assert_ne!(key(&["ab", "c"]), key(&["a", "bc"]));
+        assert_ne!(key(&["-O3"]), key(&["-O", "3"]));
+        assert_ne!(
+            CompileCache::key(&[file("a", "bc")], &[], ""),
+            CompileCache::key(&[file("ab", "c")], &[], "")
+        );
+    }
+
+    #[tokio::test]
+    async fn the_least_recently_used_entries_are_evicted() {
+        let dir = std::env::temp_dir().join(format!("ferris-cache-{}", std::process::id()));
+        let binary = dir.join("app.out");
+        fs::create_dir_all(&dir).await.unwrap();
+        fs::write(&binary, [0u8; 100]).await.unwrap();
+        let cache = CompileCache::new(dir.join("cache"), 250);
+
+        cache.store("old", &binary).await.unwrap();
+        cache.store("used", &binary).await.unwrap();
+        let past = SystemTime::now() - std::time::Duration::from_secs(60);
+        for key in ["old", "used"] {
+            std::fs::File::options().write(true).open(cache.entry(key)).unwrap().set_modified(past).unwrap();
+        }
+        // A hit makes "used" the most recent, so "old" goes first
+        assert!(cache.restore("used", &dir.join("restored")).await);
+        cache.store("new", &binary).await.unwrap();
+
+        assert!(!cache.restore("old", &dir.join("restored")).await);
+        assert!(cache.restore("used", &dir.join("restored")).await);
+        assert!(cache.restore("new", &dir.join("restored")).await);
+        assert_eq!(cache.clear().await.unwrap(), 2);
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+/// Workspace deletion is routed through a single background queue instead of
+/// fire-and-forget `remove_dir_all` calls, so transient failures (a straggler
+/// child still holding a file, NFS silly-renames, Windows sharing violations)
+/// are retried. Persistent ones are logged, counted in the
+/// `cleanup_failures_total` metric and listed by `admin orphans` until a later
+/// attempt (the scratch GC's, say) succeeds.
+use crate::metrics;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+
+/// How many times a directory is attempted before we give up on it.
+const MAX_ATTEMPTS: u32 = 5;
+/// First retry delay; doubled after every failed attempt.
+const BASE_BACKOFF: Duration = Duration::from_millis(200);
+
+#[derive(Clone)]
+pub struct CleanupQueue {
+    tx: mpsc::UnboundedSender<PathBuf>,
+    /// Directories scheduled but not yet removed or given up on.
+    pending: watch::Sender<usize>,
+    /// Directories given up on, by path.
+    orphans: Arc<Mutex<BTreeMap<PathBuf, Orphan>>>,
+}
+
+/// A directory the queue failed to delete.
+#[derive(Clone, Debug)]
+pub struct Orphan {
+    pub path: PathBuf,
+    /// The last attempt's error.
+    pub error: String,
+    pub since: SystemTime,
+}
+
+impl CleanupQueue {
+    /// Starts the background worker and returns a handle for scheduling deletions.
+    pub fn spawn() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let pending = watch::Sender::new(0);
+        let orphans = Arc::default();
+        tokio::spawn(run(rx, pending.clone(), Arc::clone(&orphans)));
+        CleanupQueue { tx, pending, orphans }
+    }
+
+    /// Queues a directory for deletion. Never blocks the caller.
+    pub fn schedule(&self, dir: PathBuf) {
+        self.pending.send_modify(|n| *n += 1);
+        if let Err(e) = self.tx.send(dir) {
+            self.pending.send_modify(|n| *n -= 1);
+            warn!("⚠️ Cleanup queue is closed, leaking {}", e.0.display());
+        }
+    }
+
+    /// Waits until every scheduled directory has been dealt with.
+    pub async fn idle(&self) {
+        let _ = self.pending.subscribe().wait_for(|n| *n == 0).await;
+    }
+
+    /// Directories given up on and still not deleted, by path.
+    pub fn orphans(&self) -> Vec<Orphan> {
+        self.orphans.lock().unwrap().values().cloned().collect()
+    }
+}
+
+async fn run(
+    mut rx: mpsc::UnboundedReceiver<PathBuf>,
+    pending: watch::Sender<usize>,
+    orphans: Arc<Mutex<BTreeMap<PathBuf, Orphan>>>,
+) {
+    while let Some(first) = rx.recv().await {
+        // Drain whatever else is already waiting so a burst of finished jobs
+        // is processed as one batch.
+        let mut batch = vec![first];
+        while let Ok(dir) = rx.try_recv() {
+            batch.push(dir);
+        }
+
+        let mut tasks = JoinSet::new();
+        for dir in batch {
+            tasks.spawn(async move {
+                let result = remove_with_retry(&dir, BASE_BACKOFF, |dir| async move {
+                    fs::remove_dir_all(&dir).await
+                })
+                .await;
+                (dir, result)
+            });
+        }
+        while let Some(joined) = tasks.join_next().await {
+            if let Ok((dir, result)) = joined {
+                let mut orphans = orphans.lock().unwrap();
+                match result {
+                    Ok(()) => {
+                        orphans.remove(&dir);
+                    }
+                    Err(e) => {
+                        metrics::cleanup_failed();
+                        let since = orphans.get(&dir).map_or_else(SystemTime::now, |orphan| orphan.since);
+                        orphans.insert(
+                            dir.clone(),
+                            Orphan {
+                                path: dir,
+                                error: e.to_string(),
+                                since,
+                            },
+                        );
+                    }
+                }
+            }
+            pending.send_modify(|n| *n -= 1);
+        }
+    }
+}
+
+/// Deletes `dir` with `remove`, up to MAX_ATTEMPTS times with a doubling
+/// backoff from `base_backoff`. A missing directory counts as deleted.
+async fn remove_with_retry<F, Fut>(dir: &Path, base_backoff: Duration, mut remove: F) -> io::Result<()>
+where
+    F: FnMut(PathBuf) -> Fut,
+    Fut: Future<Output = io::Result<()>>,
+{
+    let mut backoff = base_backoff;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        if attempt == MAX_ATTEMPTS {
+            // Last chance: whatever is still holding the directory has to go.
+            kill_holders(dir).await;
+        }
+
+        match remove(dir.to_path_buf()).await {
+            Ok(()) => {
+                info!("🧹 Cleaned up {}", dir.display());
+                return Ok(());
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                warn!(
+                    "⚠️ Cleanup of {} failed (attempt {}/{}, errno {:?}): {}",
+                    dir.display(),
+                    attempt,
+                    MAX_ATTEMPTS,
+                    e.raw_os_error(),
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => {
+                error!(
+                    "❌ Giving up on {} after {} attempts (errno {:?}): {}",
+                    dir.display(),
+                    MAX_ATTEMPTS,
+                    e.raw_os_error(),
+                    e
+                );
+                return Err(e);
+            }
+        }
+    }
+    unreachable!("the last attempt returns")
+}
+
+/// Kills any process whose working directory or open files live under `dir`.
+#[cfg(target_os = "linux")]
+async fn kill_holders(dir: &Path) {
+    let Ok(dir) = fs::canonicalize(dir).await else {
+        return;
+    };
+    let Ok(mut procs) = fs::read_dir("/proc").await else {
+        return;
+    };
+
+    while let Ok(Some(entry)) = procs.next_entry().await {
+        let name = entry.file_name();
+        let Some(pid) = name.to_str().filter(|s| s.bytes().all(|b| b.is_ascii_digit())) else {
+            continue;
+        };
+
+        let mut holds = matches!(fs::read_link(entry.path().join("cwd")).await, Ok(p) if p.starts_with(&dir));
+        if !holds && let Ok(mut fds) = fs::read_dir(entry.path().join("fd")).await {
+            while let Ok(Some(fd)) = fds.next_entry().await {
+                if matches!(fs::read_link(fd.path()).await, Ok(p) if p.starts_with(&dir)) {
+                    holds = true;
+                    break;
+                }
+            }
+        }
+
+        // Never the host itself, which may have a file open there too
+        let Ok(pid) = pid.parse::<libc::pid_t>() else {
+            continue;
+        };
+        if holds && pid as u32 != std::process::id() {
+            warn!("🔪 Killing pid {} still holding {}", pid, dir.display());
+            // SAFETY: kill(2) takes no pointers; a pid that has exited since is ESRCH.
+            if unsafe { libc::kill(pid, libc::SIGKILL) } != 0 {
+                warn!("⚠️ Could not kill pid {}: {}", pid, io::Error::last_os_error());
+            }
+        }
+    }
+}
+
+/// On Windows the retry loop itself is the remedy for sharing violations;
+/// there is no portable way to find the holder.
+#[cfg(not(target_os = "linux"))]
+async fn kill_holders(_dir: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Runs `remove_with_retry` with a removal that fails `failures` times with
+    /// `kind`, returning its result and the times of the attempts.
+    async fn retry(failures: usize, kind: io::ErrorKind) -> (io::Result<()>, Vec<Duration>) {
+        let start = Instant::now();
+        let mut attempts = Vec::new();
+        let dir = Path::new("/nonexistent/ferris-cleanup-test");
+        let result = remove_with_retry(dir, Duration::from_millis(5), |_| {
+            attempts.push(start.elapsed());
+            let fail = attempts.len() <= failures;
+            async move { if fail { Err(io::Error::from(kind)) } else { Ok(()) } }
+        })
+        .await;
+        (result, attempts)
+    }
+
+    fn gaps(attempts: &[Duration]) -> Vec<Duration> {
+        attempts.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    #[tokio::test]
+    async fn failed_removals_are_retried_with_a_doubling_backoff() {
+        let (result, attempts) = retry(2, io::ErrorKind::PermissionDenied).await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.len(), 3);
+        let gaps = gaps(&attempts);
+        assert!(gaps[0] >= Duration::from_millis(5), "{:?}", gaps);
+        assert!(gaps[1] >= Duration::from_millis(10), "{:?}", gaps);
+    }
+
+    #[tokio::test]
+    async fn removal_is_given_up_after_the_last_attempt() {
+        let (result, attempts) = retry(usize::MAX, io::ErrorKind::PermissionDenied).await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(attempts.len(), MAX_ATTEMPTS as usize);
+        for (i, gap) in gaps(&attempts).into_iter().enumerate() {
+            assert!(gap >= Duration::from_millis(5) * 2u32.pow(i as u32), "attempt {}: {:?}", i + 2, gap);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_missing_directory_is_not_retried() {
+        let (result, attempts) = retry(usize::MAX, io::ErrorKind::NotFound).await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn undeletable_directories_are_listed_until_they_go() {
+        let queue = CleanupQueue::spawn();
+        let dir = std::env::temp_dir().join(format!("ferris-cleanup-{}", std::process::id()));
+        let orphan = Orphan {
+            path: dir.clone(),
+            error: "busy".into(),
+            since: SystemTime::now(),
+        };
+        queue.orphans.lock().unwrap().insert(dir.clone(), orphan);
+        assert_eq!(queue.orphans()[0].path, dir);
+
+        std::fs::create_dir_all(&dir).unwrap();
+        queue.schedule(dir.clone());
+        queue.idle().await;
+        assert!(!dir.exists());
+        assert!(queue.orphans().is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+/// Membership in a cluster. With `--broker`, the host registers with the
+/// broker's WorkerRegistry under a worker id chosen at startup, announcing
+/// the address clients' jobs should reach it at and what it can run, then
+/// heartbeats its load every few seconds.
+///
+/// A broker that misses heartbeats for long enough declares the host dead
+/// and runs its jobs elsewhere. When the host gets through again (or the
+/// broker restarts and has forgotten it), the heartbeat says so and the host
+/// registers again under the same id, cancelling the jobs the broker has
+/// since moved.
+use crate::context::HostContext;
+use crate::queue::JobQueue;
+use crate::registry::JobRegistry;
+use common::compute::worker_registry_client::WorkerRegistryClient;
+use common::compute::{HeartbeatRequest, RegisterWorkerRequest};
+use std::time::Duration;
+use tonic::Request;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tracing::{info, warn};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct Worker {
+    pub broker: String,
+    /// Where the broker reaches this host.
+    pub address: String,
+    pub token: Option<String>,
+    pub ctx: HostContext,
+    pub queue: JobQueue,
+    pub registry: JobRegistry,
+}
+
+impl Worker {
+    pub fn spawn(self) -> Result<(), String> {
+        let mut endpoint = Endpoint::from_shared(self.broker.clone())
+            .map_err(|e| format!("Invalid broker address {:?}: {}", self.broker, e))?;
+        // A broker serving TLS is checked against the system's roots
+        if self.broker.starts_with("https://") {
+            endpoint = endpoint
+                .tls_config(ClientTlsConfig::new().with_native_roots())
+                .map_err(|e| format!("TLS setup for broker {} failed: {}", self.broker, e))?;
+        }
+        let channel = endpoint.connect_lazy();
+        let authorization = self
+            .token
+            .as_ref()
+            .map(|t| format!("Bearer {}", t).parse())
+            .transpose()
+            .map_err(|_| "The broker token isn't a valid header value".to_string())?;
+        let id = uuid::Uuid::new_v4().to_string();
+        tokio::spawn(async move {
+            let mut client = WorkerRegistryClient::new(channel);
+            // Only changes are logged; `None` until the first attempt
+            let mut connected = None;
+            let mut registered = false;
+            loop {
+                let result = match registered {
+                    true => self.heartbeat(&mut client, &id, authorization.as_ref()).await,
+                    false => self.register(&mut client, &id, authorization.as_ref()).await,
+                };
+                match (&result, connected) {
+                    (Ok(_), Some(true)) | (Err(_), Some(false)) => {}
+                    (Ok(_), _) => info!("🛰 Connected to broker {} as worker {} at {}", self.broker, id, self.address),
+                    (Err(e), _) => warn!("⚠️ Lost the broker {} (retrying): {}", self.broker, e),
+                }
+                connected = Some(result.is_ok());
+                // A heartbeat the broker didn't recognize means registering again
+                registered = result.unwrap_or(false);
+                if registered || connected == Some(false) {
+                    tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    async fn register(
+        &self,
+        client: &mut WorkerRegistryClient<Channel>,
+        id: &str,
+        authorization: Option<&MetadataValue<Ascii>>,
+    ) -> Result<bool, String> {
+        let request = RegisterWorkerRequest {
+            address: self.address.clone(),
+            worker_id: id.to_string(),
+            info: Some(self.ctx.info(&self.queue).await),
+        };
+        client.register(authorized(request, authorization)).await.map_err(|e| e.message().to_string())?;
+        Ok(true)
+    }
+
+    /// Whether the broker still knows this worker.
+    async fn heartbeat(
+        &self,
+        client: &mut WorkerRegistryClient<Channel>,
+        id: &str,
+        authorization: Option<&MetadataValue<Ascii>>,
+    ) -> Result<bool, String> {
+        let (running, waiting, _) = self.queue.load();
+        let request = HeartbeatRequest {
+            worker_id: id.to_string(),
+            running_jobs: running as u32,
+            queued_jobs: waiting as u32,
+            active_jobs: self.registry.ids(),
+        };
+        let response = client
+            .heartbeat(authorized(request, authorization))
+            .await
+            .map_err(|e| e.message().to_string())?
+            .into_inner();
+        for job_id in response.cancel_jobs {
+            if self.registry.cancel(&job_id) {
+                info!("🛑 Cancelling job {}: the broker has run it on another host", job_id);
+            }
+        }
+        if !response.registered {
+            info!("🛰 Broker {} no longer knows this host; registering again", self.broker);
+        }
+        Ok(response.registered)
+    }
+}
+
+fn authorized<T>(message: T, authorization: Option<&MetadataValue<Ascii>>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(value) = authorization {
+        request.metadata_mut().insert("authorization", value.clone());
+    }
+    request
+}
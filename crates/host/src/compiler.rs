@@ -0,0 +1,286 @@
+/// The compilers behind single-command builds, one per accelerator backend:
+/// nvcc for NVIDIA GPUs, hipcc for AMD ones under ROCm, and a plain C++
+/// compiler with OpenMP for jobs that need no GPU at all. A job names its
+/// backend in `accelerator`, or gets the host's first GPU one.
+///
+/// A backend is offered when its compiler runs at startup. A host where
+/// neither GPU compiler does still offers CUDA, as it did before ROCm, since
+/// its nvcc may live in the job's container image.
+///
+/// Programs built for a GPU see their leased GPUs through
+/// `CUDA_VISIBLE_DEVICES`, which the HIP runtime honors as well.
+use crate::probe;
+use common::compute::{Accelerator, BackendInfo};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub trait Compiler: Send + Sync {
+    fn accelerator(&self) -> Accelerator;
+
+    /// As messages and logs name it, e.g. "nvcc".
+    fn name(&self) -> &'static str;
+
+    fn path(&self) -> &Path;
+
+    /// Its `--version` as probed at startup; part of every cache key.
+    fn version(&self) -> &str;
+
+    /// Extensions of the files it compiles; others are only `#include`d.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Whether it compiles `path`, rather than leaving it to an `#include`.
+    fn compiles(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| self.extensions().contains(&e))
+    }
+
+    /// Arguments compiling `units` with `flags` into `output`.
+    fn args(&self, units: &[PathBuf], flags: &[String], output: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = units.iter().map(|unit| unit.as_os_str().to_owned()).collect();
+        args.extend(flags.iter().map(Into::into));
+        args.extend(["-o".into(), output.into()]);
+        args
+    }
+
+    /// The flag that has the program look for libraries in `dir` at run time.
+    fn rpath(&self, dir: &Path) -> String {
+        format!("-Wl,-rpath,{}", dir.display())
+    }
+
+    /// Refuses flags this compiler wouldn't understand.
+    fn check_flags(&self, _flags: &[String]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// nvcc options that mean nothing to a C++ compiler, alone or as `flag=value`.
+const NVCC_ONLY: &[&str] = &[
+    "-arch",
+    "--gpu-architecture",
+    "-gencode",
+    "--generate-code",
+    "-code",
+    "--gpu-code",
+    "-G",
+    "--device-debug",
+    "-lineinfo",
+    "--generate-line-info",
+    "-rdc",
+    "--relocatable-device-code",
+    "-dc",
+    "--device-c",
+    "-dlink",
+    "--device-link",
+    "-ptx",
+    "--ptx",
+    "-cubin",
+    "--cubin",
+    "-fatbin",
+    "--fatbin",
+    "-res-usage",
+    "--resource-usage",
+    "-maxrregcount",
+    "--maxrregcount",
+    "-use_fast_math",
+    "--use_fast_math",
+    "-ccbin",
+    "--compiler-bindir",
+    "-cudart",
+    "--cudart",
+    "-Xcompiler",
+    "--compiler-options",
+    "-Xptxas",
+    "--ptxas-options",
+    "-Xnvlink",
+    "--nvlink-options",
+    "-Xcudafe",
+    "--expt-relaxed-constexpr",
+    "--expt-extended-lambda",
+    "--extended-lambda",
+];
+
+pub struct Nvcc {
+    path: PathBuf,
+    version: String,
+}
+
+impl Compiler for Nvcc {
+    fn accelerator(&self) -> Accelerator {
+        Accelerator::Cuda
+    }
+
+    fn name(&self) -> &'static str {
+        "nvcc"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["cu", "cpp", "cc", "cxx", "c"]
+    }
+
+    fn rpath(&self, dir: &Path) -> String {
+        format!("-Xlinker=-rpath,{}", dir.display())
+    }
+}
+
+pub struct Hipcc {
+    path: PathBuf,
+    version: String,
+}
+
+impl Compiler for Hipcc {
+    fn accelerator(&self) -> Accelerator {
+        Accelerator::Rocm
+    }
+
+    fn name(&self) -> &'static str {
+        "hipcc"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["hip", "cpp", "cc", "cxx", "c"]
+    }
+
+    fn check_flags(&self, flags: &[String]) -> Result<(), String> {
+        crate::policy::check_host_compiler(flags)
+    }
+}
+
+/// g++ or clang++, building with OpenMP on.
+pub struct Cxx {
+    path: PathBuf,
+    version: String,
+}
+
+impl Compiler for Cxx {
+    fn accelerator(&self) -> Accelerator {
+        Accelerator::Cpu
+    }
+
+    fn name(&self) -> &'static str {
+        "c++"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["cpp", "cc", "cxx", "c"]
+    }
+
+    /// `-fopenmp` comes first, so a job's `-fno-openmp` still wins.
+    fn args(&self, units: &[PathBuf], flags: &[String], output: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = units.iter().map(|unit| unit.as_os_str().to_owned()).collect();
+        args.push("-fopenmp".into());
+        args.extend(flags.iter().map(Into::into));
+        args.extend(["-o".into(), output.into()]);
+        args
+    }
+
+    fn check_flags(&self, flags: &[String]) -> Result<(), String> {
+        let nvcc_only = flags.iter().find(|flag| {
+            NVCC_ONLY.iter().any(|option| {
+                flag.strip_prefix(option)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+            })
+        });
+        match nvcc_only {
+            Some(flag) => Err(format!("{} is an nvcc flag; CPU jobs are built with a C++ compiler", flag)),
+            None => crate::policy::check_host_compiler(flags),
+        }
+    }
+}
+
+/// The backends a host offers, in order of preference.
+pub struct Backends {
+    compilers: Vec<Arc<dyn Compiler>>,
+}
+
+impl Backends {
+    /// Probes every compiler.
+    pub async fn probe(nvcc: &Path, hipcc: &Path, cxx: &Path) -> Self {
+        let nvcc = Nvcc {
+            path: nvcc.to_path_buf(),
+            version: probe::nvcc_version(nvcc).await,
+        };
+        let hipcc = Hipcc {
+            path: hipcc.to_path_buf(),
+            version: probe::hipcc_version(hipcc).await,
+        };
+        let mut compilers: Vec<Arc<dyn Compiler>> = Vec::new();
+        let rocm = hipcc.version != "unknown";
+        if nvcc.version != "unknown" || !rocm {
+            compilers.push(Arc::new(nvcc));
+        }
+        if rocm {
+            compilers.push(Arc::new(hipcc));
+        }
+        let cxx = Cxx {
+            path: cxx.to_path_buf(),
+            version: probe::cxx_version(cxx).await,
+        };
+        if cxx.version != "unknown" {
+            compilers.push(Arc::new(cxx));
+        }
+        Backends { compilers }
+    }
+
+    /// The compiler for `accelerator`; `Any` is the host's first, which is
+    /// never the CPU one.
+    pub fn select(&self, accelerator: Accelerator) -> Result<Arc<dyn Compiler>, String> {
+        self.compilers
+            .iter()
+            .find(|c| accelerator == Accelerator::Any || c.accelerator() == accelerator)
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "This host has no {} backend (available: {})",
+                    accelerator.as_str_name(),
+                    self.compilers.iter().map(|c| c.accelerator().as_str_name()).collect::<Vec<_>>().join(", ")
+                )
+            })
+    }
+
+    /// The compiler jobs get when they don't pick a backend.
+    pub fn preferred(&self) -> Arc<dyn Compiler> {
+        self.compilers[0].clone()
+    }
+
+    pub fn offers(&self, accelerator: Accelerator) -> bool {
+        self.compilers.iter().any(|c| c.accelerator() == accelerator)
+    }
+
+    /// For `HostInfo`.
+    pub fn info(&self) -> Vec<BackendInfo> {
+        self.compilers
+            .iter()
+            .map(|c| BackendInfo {
+                accelerator: c.accelerator().into(),
+                compiler: c.name().into(),
+                version: c.version().into(),
+            })
+            .collect()
+    }
+}
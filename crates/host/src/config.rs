@@ -0,0 +1,633 @@
+/// Host settings. Each one comes from, in order of precedence: a command-line
+/// flag, its `FERRIS_*` environment variable, the TOML file given with
+/// `--config`, and finally the built-in default.
+///
+/// The file uses the flag names with underscores, e.g.
+///
+/// ```toml
+/// listen = "0.0.0.0:50051"
+/// scratch_dir = "/var/lib/ferris/scratch"
+/// max_concurrent_jobs = 2
+///
+/// [toolchains]
+/// "cuda-11.8" = "/usr/local/cuda-11.8"
+///
+/// [profilers]
+/// nsys = "/usr/local/cuda/bin/nsys"
+///
+/// [limits]
+/// cpu_secs = 600
+/// memory_mb = 8192
+///
+/// [[notifiers]]
+/// kind = "slack"
+/// url = "https://hooks.slack.com/services/..."
+///
+/// [libraries.cufft]
+/// include_dirs = ["/usr/local/cuda/include"]
+/// lib_dirs = ["/usr/local/cuda/lib64"]
+/// libs = ["cufft"]
+/// ```
+use crate::gpumem::GpuMemoryEnforcement;
+use crate::libraries::LibraryPreset;
+use crate::limits::JobLimits;
+use crate::logging::LogFormat;
+use crate::notify::Notifier;
+use crate::output::OverflowAction;
+use crate::profiler::ProfilerConfig;
+use crate::quota::QuotaLimits;
+use crate::retention::RetainPolicy;
+use crate::sandbox::SandboxKind;
+use crate::toolchain;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostConfig {
+    pub listen: SocketAddr,
+    /// `EnvFilter` directive.
+    pub log_level: String,
+    pub log_format: LogFormat,
+    /// OTLP/gRPC collector to export traces to; `None` disables tracing export.
+    pub otlp_endpoint: Option<String>,
+    /// `None` disables the `/metrics` endpoint.
+    pub metrics_listen: Option<SocketAddr>,
+    pub scratch_dir: PathBuf,
+    pub nvcc: PathBuf,
+    pub hipcc: PathBuf,
+    pub cxx: PathBuf,
+    pub compute_sanitizer: PathBuf,
+    pub cuda_gdbserver: PathBuf,
+    pub cmake: PathBuf,
+    pub make: PathBuf,
+    pub max_concurrent_jobs: usize,
+    pub max_timeout_secs: u64,
+    /// 0 turns debug sessions off.
+    pub max_debug_secs: u64,
+    /// 0 means unlimited.
+    pub compile_timeout_secs: u64,
+    pub stream_buffer: usize,
+    pub shutdown_grace_secs: u64,
+    /// `None` means every GPU nvidia-smi reports.
+    pub gpus: Option<Vec<u32>>,
+    pub cache_dir: PathBuf,
+    pub cache_max_mb: u64,
+    pub retain_workspaces: RetainPolicy,
+    pub retain_hours: u64,
+    pub scratch_max_age_hours: u64,
+    pub max_stdout_bytes: u64,
+    pub max_stderr_bytes: u64,
+    /// All of a job's source files together.
+    pub max_source_bytes: u64,
+    /// Largest single gRPC message the host will decode.
+    pub max_message_bytes: usize,
+    pub on_output_limit: OverflowAction,
+    pub history_db: PathBuf,
+    pub auto_arch: bool,
+    pub sandbox: SandboxKind,
+    pub sandbox_image: String,
+    pub gpu_memory_enforcement: GpuMemoryEnforcement,
+    /// Shared jobs that may run on one GPU through MPS; 0 shares none.
+    pub mps_clients: u32,
+    /// What each job's processes may use of the host.
+    pub limits: JobLimits,
+    /// cgroup v2 directory to give each job a cgroup in; `None` uses rlimits only.
+    pub cgroup_root: Option<PathBuf>,
+    pub allowed_images: Vec<String>,
+    /// Glob patterns of nvcc flags; empty allows any flag the built-in rules do.
+    pub allowed_flags: Vec<String>,
+    pub denied_flags: Vec<String>,
+    /// Name to CUDA_HOME.
+    pub toolchains: BTreeMap<String, PathBuf>,
+    pub profilers: ProfilerConfig,
+    /// Link presets by name; only set in the file.
+    pub libraries: BTreeMap<String, LibraryPreset>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_client_ca: Option<PathBuf>,
+    pub token: Option<String>,
+    pub tokens_file: Option<PathBuf>,
+    /// Limits every identity gets unless it has its own entry in `user_quotas`.
+    pub quotas: QuotaLimits,
+    pub user_quotas: HashMap<String, QuotaLimits>,
+    /// Identities (as for quotas) that may submit high-priority jobs.
+    pub high_priority_users: Vec<String>,
+    /// Authenticated identities that may call the Admin service.
+    pub admin_users: Vec<String>,
+    /// Told about the jobs that ask for it when they finish; only set in the file.
+    pub notifiers: Vec<Notifier>,
+    /// Hosts a job's `notify_url` may point at; "*" allows any.
+    pub job_webhook_hosts: Vec<String>,
+    /// Broker to register with; `None` serves clients directly only.
+    pub broker: Option<String>,
+    /// The address registered with the broker; `None` means `http://<listen>`.
+    pub advertise: Option<String>,
+    pub broker_token: Option<String>,
+    /// The file this configuration was read from, if any.
+    #[serde(skip)]
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        HostConfig {
+            listen: "[::1]:50051".parse().unwrap(),
+            log_level: "info".into(),
+            log_format: LogFormat::Text,
+            otlp_endpoint: None,
+            metrics_listen: None,
+            scratch_dir: "scratch".into(),
+            nvcc: "nvcc".into(),
+            hipcc: "hipcc".into(),
+            cxx: "g++".into(),
+            compute_sanitizer: "compute-sanitizer".into(),
+            cuda_gdbserver: "cuda-gdbserver".into(),
+            cmake: "cmake".into(),
+            make: "make".into(),
+            max_concurrent_jobs: 1,
+            max_timeout_secs: 600,
+            max_debug_secs: 1800,
+            compile_timeout_secs: 0,
+            stream_buffer: 100,
+            shutdown_grace_secs: 30,
+            gpus: None,
+            cache_dir: "cache".into(),
+            cache_max_mb: 1024,
+            retain_workspaces: RetainPolicy::Requested,
+            retain_hours: 24,
+            scratch_max_age_hours: 6,
+            max_stdout_bytes: 64 * 1024 * 1024,
+            max_stderr_bytes: 16 * 1024 * 1024,
+            max_source_bytes: 64 * 1024 * 1024,
+            max_message_bytes: 4 * 1024 * 1024,
+            on_output_limit: OverflowAction::Mute,
+            history_db: "history.sqlite3".into(),
+            auto_arch: false,
+            sandbox: SandboxKind::None,
+            gpu_memory_enforcement: GpuMemoryEnforcement::None,
+            mps_clients: 0,
+            limits: JobLimits::default(),
+            cgroup_root: None,
+            sandbox_image: "nvidia/cuda:12.4.1-base-ubuntu22.04".into(),
+            allowed_images: Vec::new(),
+            allowed_flags: Vec::new(),
+            denied_flags: Vec::new(),
+            toolchains: BTreeMap::new(),
+            profilers: ProfilerConfig::default(),
+            libraries: BTreeMap::new(),
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            token: None,
+            tokens_file: None,
+            quotas: QuotaLimits::default(),
+            user_quotas: HashMap::new(),
+            high_priority_users: Vec::new(),
+            admin_users: Vec::new(),
+            notifiers: Vec::new(),
+            job_webhook_hosts: Vec::new(),
+            broker: None,
+            advertise: None,
+            broker_token: None,
+            config_file: None,
+        }
+    }
+}
+
+/// Flags left unset fall through to the config file, then to the defaults
+/// shown in brackets.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Remote CUDA Executor Host")]
+struct Args {
+    /// TOML file with any of the settings below
+    #[arg(long, env = "FERRIS_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Address to serve gRPC on [[::1]:50051]
+    #[arg(long, env = "FERRIS_LISTEN")]
+    listen: Option<SocketAddr>,
+
+    /// Log verbosity, e.g. "debug" or "host=debug,tower=warn" [info]
+    #[arg(long, env = "FERRIS_LOG")]
+    log_level: Option<String>,
+
+    /// Log as readable lines or as one JSON object per event [text]
+    #[arg(long, env = "FERRIS_LOG_FORMAT", value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// OpenTelemetry collector to export job traces to over OTLP/gRPC, e.g. http://localhost:4317 [off]
+    #[arg(long, env = "FERRIS_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    /// Address to serve Prometheus metrics on over HTTP, at /metrics [off]
+    #[arg(long, env = "FERRIS_METRICS_LISTEN")]
+    metrics_listen: Option<SocketAddr>,
+
+    /// Directory for job workspaces, uploads and kept workspaces [scratch]
+    #[arg(long, env = "FERRIS_SCRATCH_DIR")]
+    scratch_dir: Option<PathBuf>,
+
+    /// nvcc used by jobs that don't pick a toolchain [nvcc, from PATH]
+    #[arg(long, env = "FERRIS_NVCC")]
+    nvcc: Option<PathBuf>,
+
+    /// hipcc used by ROCm jobs; the backend is offered if it runs [hipcc, from PATH]
+    #[arg(long, env = "FERRIS_HIPCC")]
+    hipcc: Option<PathBuf>,
+
+    /// C++ compiler used by CPU jobs, g++ or clang++; the backend is offered if it runs [g++, from PATH]
+    #[arg(long, env = "FERRIS_CXX")]
+    cxx: Option<PathBuf>,
+
+    /// compute-sanitizer used by jobs that don't pick a toolchain [compute-sanitizer, from PATH]
+    #[arg(long, env = "FERRIS_COMPUTE_SANITIZER")]
+    compute_sanitizer: Option<PathBuf>,
+
+    /// cuda-gdbserver used by debug jobs that don't pick a toolchain [cuda-gdbserver, from PATH]
+    #[arg(long, env = "FERRIS_CUDA_GDBSERVER")]
+    cuda_gdbserver: Option<PathBuf>,
+
+    /// cmake used to build jobs' cmake projects [cmake, from PATH]
+    #[arg(long, env = "FERRIS_CMAKE")]
+    cmake: Option<PathBuf>,
+
+    /// make used to build jobs' make projects [make, from PATH]
+    #[arg(long, env = "FERRIS_MAKE")]
+    make: Option<PathBuf>,
+
+    /// How many jobs may compile/run at the same time; the rest wait in a queue [1]
+    #[arg(long, env = "FERRIS_MAX_JOBS")]
+    max_concurrent_jobs: Option<usize>,
+
+    /// Longest a program may run; also the default for jobs that don't ask [600]
+    #[arg(long, env = "FERRIS_MAX_TIMEOUT_SECS")]
+    max_timeout_secs: Option<u64>,
+
+    /// Longest a debug session may last (0 = no debug sessions) [1800]
+    #[arg(long, env = "FERRIS_MAX_DEBUG_SECS")]
+    max_debug_secs: Option<u64>,
+
+    /// Longest a compilation may take (0 = unlimited) [0]
+    #[arg(long, env = "FERRIS_COMPILE_TIMEOUT_SECS")]
+    compile_timeout_secs: Option<u64>,
+
+    /// Messages buffered per job stream before a slow client holds the job up [100]
+    #[arg(long, env = "FERRIS_STREAM_BUFFER")]
+    stream_buffer: Option<usize>,
+
+    /// On SIGTERM/Ctrl-C, how long running jobs may take to finish before they are killed [30]
+    #[arg(long, env = "FERRIS_SHUTDOWN_GRACE_SECS")]
+    shutdown_grace_secs: Option<u64>,
+
+    /// GPU indices jobs may use, e.g. "0,1" [every GPU nvidia-smi reports]
+    #[arg(long, env = "FERRIS_GPUS", value_delimiter = ',')]
+    gpus: Option<Vec<u32>>,
+
+    /// Where compiled binaries are cached between jobs [cache]
+    #[arg(long, env = "FERRIS_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Size limit of the binary cache in MiB; least recently used entries go first [1024]
+    #[arg(long, env = "FERRIS_CACHE_MAX_MB")]
+    cache_max_mb: Option<u64>,
+
+    /// Which finished jobs keep their workspace for debugging [requested]
+    #[arg(long, env = "FERRIS_RETAIN_WORKSPACES", value_enum)]
+    retain_workspaces: Option<RetainPolicy>,
+
+    /// Delete kept workspaces after this many hours (0 = keep until purged) [24]
+    #[arg(long, env = "FERRIS_RETAIN_HOURS")]
+    retain_hours: Option<u64>,
+
+    /// Remove scratch entries no running job owns once they are this many hours old (0 = never) [6]
+    #[arg(long, env = "FERRIS_SCRATCH_MAX_AGE_HOURS")]
+    scratch_max_age_hours: Option<u64>,
+
+    /// Stop forwarding a program's stdout after this many bytes (0 = unlimited) [64 MiB]
+    #[arg(long, env = "FERRIS_MAX_STDOUT_BYTES")]
+    max_stdout_bytes: Option<u64>,
+
+    /// Stop forwarding a program's stderr after this many bytes (0 = unlimited) [16 MiB]
+    #[arg(long, env = "FERRIS_MAX_STDERR_BYTES")]
+    max_stderr_bytes: Option<u64>,
+
+    /// Refuse jobs whose source files add up to more than this [64 MiB]
+    #[arg(long, env = "FERRIS_MAX_SOURCE_BYTES")]
+    max_source_bytes: Option<u64>,
+
+    /// Refuse gRPC messages larger than this; bigger projects must be streamed [4 MiB]
+    #[arg(long, env = "FERRIS_MAX_MESSAGE_BYTES")]
+    max_message_bytes: Option<usize>,
+
+    /// What happens to a program that goes over an output limit [mute]
+    #[arg(long, env = "FERRIS_ON_OUTPUT_LIMIT", value_enum)]
+    on_output_limit: Option<OverflowAction>,
+
+    /// SQLite database recording every job, kept across restarts [history.sqlite3]
+    #[arg(long, env = "FERRIS_HISTORY_DB")]
+    history_db: Option<PathBuf>,
+
+    /// Compile for the detected GPUs' architecture when a job doesn't pass -arch itself
+    #[arg(long, env = "FERRIS_AUTO_ARCH")]
+    auto_arch: bool,
+
+    /// How compiled programs are isolated from the host [none]
+    #[arg(long, env = "FERRIS_SANDBOX", value_enum)]
+    sandbox: Option<SandboxKind>,
+
+    /// How jobs' GPU memory budgets are enforced while they run: not at all, by MPS, or by polling nvidia-smi [none]
+    #[arg(long, env = "FERRIS_GPU_MEMORY_ENFORCEMENT", value_enum)]
+    gpu_memory_enforcement: Option<GpuMemoryEnforcement>,
+
+    /// Shared jobs that may run on one GPU at once through the MPS control daemon (0 = no sharing) [0]
+    #[arg(long, env = "FERRIS_MPS_CLIENTS")]
+    mps_clients: Option<u32>,
+
+    /// Container image for --sandbox docker [nvidia/cuda:12.4.1-base-ubuntu22.04]
+    #[arg(long, env = "FERRIS_SANDBOX_IMAGE")]
+    sandbox_image: Option<String>,
+
+    /// Container images jobs may compile and run in, e.g. "nvcr.io/nvidia/cuda:*" [none]
+    #[arg(long, env = "FERRIS_ALLOWED_IMAGES", value_delimiter = ',')]
+    allowed_images: Option<Vec<String>>,
+
+    /// Only accept nvcc flags matching these patterns, e.g. "-O?,-arch*,-std*,-D*,-lineinfo" [any]
+    #[arg(long, env = "FERRIS_ALLOWED_FLAGS", value_delimiter = ',', allow_hyphen_values = true)]
+    allowed_flags: Option<Vec<String>>,
+
+    /// Refuse nvcc flags matching these patterns, on top of the built-in rules [none]
+    #[arg(long, env = "FERRIS_DENIED_FLAGS", value_delimiter = ',', allow_hyphen_values = true)]
+    denied_flags: Option<Vec<String>>,
+
+    /// Extra CUDA toolkits jobs may pick by name, e.g. "cuda-11.8=/usr/local/cuda-11.8"
+    #[arg(long = "toolchain", env = "FERRIS_TOOLCHAINS", value_delimiter = ',', value_parser = toolchain::parse_spec)]
+    toolchains: Vec<(String, PathBuf)>,
+
+    /// Nsight Systems binary; lets jobs ask for an nsys profile [off]
+    #[arg(long, env = "FERRIS_NSYS")]
+    nsys: Option<PathBuf>,
+
+    /// Nsight Compute binary; lets jobs ask for an ncu profile [off]
+    #[arg(long, env = "FERRIS_NCU")]
+    ncu: Option<PathBuf>,
+
+    /// PEM certificate to serve TLS with (plaintext if unset)
+    #[arg(long, env = "FERRIS_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key matching --tls-cert
+    #[arg(long, env = "FERRIS_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// Require client certificates signed by this PEM CA; their Common Name becomes the user
+    #[arg(long, env = "FERRIS_TLS_CLIENT_CA")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// Accept this bearer token (authenticates as user "default")
+    #[arg(long, env = "FERRIS_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// File of accepted tokens, one "user:token" per line
+    #[arg(long, env = "FERRIS_TOKENS_FILE")]
+    tokens_file: Option<PathBuf>,
+
+    /// Broker to register with, e.g. "http://broker:50050", to take jobs in a cluster [none]
+    #[arg(long, env = "FERRIS_BROKER")]
+    broker: Option<String>,
+
+    /// Address the broker should reach this host at [http://<listen>]
+    #[arg(long, env = "FERRIS_ADVERTISE")]
+    advertise: Option<String>,
+
+    /// Bearer token the broker requires of hosts registering with it
+    #[arg(long, env = "FERRIS_BROKER_TOKEN", hide_env_values = true)]
+    broker_token: Option<String>,
+
+    /// Jobs each user may have queued or running at once (0 = unlimited) [0]
+    #[arg(long, env = "FERRIS_QUOTA_MAX_JOBS")]
+    quota_max_jobs: Option<u32>,
+
+    /// Jobs each user may submit per hour (0 = unlimited) [0]
+    #[arg(long, env = "FERRIS_QUOTA_JOBS_PER_HOUR")]
+    quota_jobs_per_hour: Option<u32>,
+
+    /// GPU-seconds each user's programs may use per day (0 = unlimited) [0]
+    #[arg(long, env = "FERRIS_QUOTA_GPU_SECONDS_PER_DAY")]
+    quota_gpu_seconds_per_day: Option<u64>,
+
+    /// CPU seconds each process of a job may use (0 = unlimited) [0]
+    #[arg(long, env = "FERRIS_LIMIT_CPU_SECS")]
+    limit_cpu_secs: Option<u64>,
+
+    /// Memory a job may use, in MB: its cgroup's, or each process's data size without --cgroup-root (0 = unlimited) [0]
+    #[arg(long, env = "FERRIS_LIMIT_MEMORY_MB")]
+    limit_memory_mb: Option<u64>,
+
+    /// Processes a job may run at once: its cgroup's, or the host user's without --cgroup-root (0 = unlimited) [0]
+    #[arg(long, env = "FERRIS_LIMIT_PROCESSES")]
+    limit_processes: Option<u64>,
+
+    /// Largest file a job's processes may write, in MB (0 = unlimited) [0]
+    #[arg(long, env = "FERRIS_LIMIT_FILE_SIZE_MB")]
+    limit_file_size_mb: Option<u64>,
+
+    /// cgroup v2 directory, writable by the host, to give each job a cgroup in [none]
+    #[arg(long, env = "FERRIS_CGROUP_ROOT")]
+    cgroup_root: Option<PathBuf>,
+
+    /// Users who may submit high-priority jobs, which go ahead of waiting normal and low ones [none]
+    #[arg(long, env = "FERRIS_HIGH_PRIORITY_USERS", value_delimiter = ',')]
+    high_priority_users: Option<Vec<String>>,
+
+    /// Authenticated users (token or certificate names) who may call the Admin service [none]
+    #[arg(long, env = "FERRIS_ADMIN_USERS", value_delimiter = ',')]
+    admin_users: Option<Vec<String>>,
+
+    /// Hosts jobs may have their results POSTed to with --notify-url ("*" = any) [none]
+    #[arg(long, env = "FERRIS_JOB_WEBHOOK_HOSTS", value_delimiter = ',')]
+    job_webhook_hosts: Option<Vec<String>>,
+}
+
+impl HostConfig {
+    /// Parses the command line, reads the config file it names (if any) and
+    /// layers the two.
+    pub fn load() -> Result<Self, String> {
+        let args = Args::parse();
+        let mut config = match &args.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+                toml::from_str(&text).map_err(|e| format!("Invalid config {}: {}", path.display(), e))?
+            }
+            None => HostConfig::default(),
+        };
+        config.config_file = args.config.clone();
+        config.apply(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply(&mut self, args: Args) {
+        macro_rules! overlay {
+            ($($field:ident),*) => {
+                $(if let Some(value) = args.$field {
+                    self.$field = value;
+                })*
+            };
+        }
+        overlay!(
+            listen, log_level, log_format, scratch_dir, nvcc, hipcc, cxx, compute_sanitizer, cuda_gdbserver, cmake, make,
+            max_concurrent_jobs, max_timeout_secs, max_debug_secs, compile_timeout_secs, stream_buffer, shutdown_grace_secs, cache_dir, cache_max_mb, retain_workspaces, retain_hours,
+            scratch_max_age_hours, max_stdout_bytes, max_stderr_bytes, max_source_bytes, max_message_bytes,
+            on_output_limit, history_db,
+            sandbox, sandbox_image, gpu_memory_enforcement, mps_clients, allowed_images, allowed_flags, denied_flags, high_priority_users,
+            admin_users, job_webhook_hosts
+        );
+
+        if args.otlp_endpoint.is_some() {
+            self.otlp_endpoint = args.otlp_endpoint;
+        }
+        if args.metrics_listen.is_some() {
+            self.metrics_listen = args.metrics_listen;
+        }
+        if args.gpus.is_some() {
+            self.gpus = args.gpus;
+        }
+        self.auto_arch |= args.auto_arch;
+        // Toolchains from flags are added to the file's, replacing same-named ones
+        self.toolchains.extend(args.toolchains);
+        if args.nsys.is_some() {
+            self.profilers.nsys = args.nsys;
+        }
+        if args.ncu.is_some() {
+            self.profilers.ncu = args.ncu;
+        }
+        for (setting, value) in [
+            (&mut self.tls_cert, args.tls_cert),
+            (&mut self.tls_key, args.tls_key),
+            (&mut self.tls_client_ca, args.tls_client_ca),
+            (&mut self.tokens_file, args.tokens_file),
+        ] {
+            if value.is_some() {
+                *setting = value;
+            }
+        }
+        if args.token.is_some() {
+            self.token = args.token;
+        }
+        for (setting, value) in [
+            (&mut self.broker, args.broker),
+            (&mut self.advertise, args.advertise),
+            (&mut self.broker_token, args.broker_token),
+        ] {
+            if value.is_some() {
+                *setting = value;
+            }
+        }
+        if let Some(max_jobs) = args.quota_max_jobs {
+            self.quotas.max_jobs = max_jobs;
+        }
+        if let Some(jobs_per_hour) = args.quota_jobs_per_hour {
+            self.quotas.jobs_per_hour = jobs_per_hour;
+        }
+        if let Some(gpu_seconds) = args.quota_gpu_seconds_per_day {
+            self.quotas.gpu_seconds_per_day = gpu_seconds;
+        }
+        for (setting, value) in [
+            (&mut self.limits.cpu_secs, args.limit_cpu_secs),
+            (&mut self.limits.memory_mb, args.limit_memory_mb),
+            (&mut self.limits.processes, args.limit_processes),
+            (&mut self.limits.file_size_mb, args.limit_file_size_mb),
+        ] {
+            if let Some(value) = value {
+                *setting = value;
+            }
+        }
+        if args.cgroup_root.is_some() {
+            self.cgroup_root = args.cgroup_root;
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.max_concurrent_jobs == 0 {
+            return Err("max_concurrent_jobs must be at least 1".into());
+        }
+        if self.max_timeout_secs == 0 {
+            return Err("max_timeout_secs must be at least 1".into());
+        }
+        if self.stream_buffer == 0 {
+            return Err("stream_buffer must be at least 1".into());
+        }
+        if self.max_message_bytes == 0 {
+            return Err("max_message_bytes must be at least 1".into());
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err("tls_cert and tls_key must be given together".into());
+        }
+        if self.tls_client_ca.is_some() && self.tls_cert.is_none() {
+            return Err("tls_client_ca requires tls_cert".into());
+        }
+        Ok(())
+    }
+
+    pub fn max_timeout(&self) -> Duration {
+        Duration::from_secs(self.max_timeout_secs)
+    }
+
+    pub fn max_debug(&self) -> Option<Duration> {
+        (self.max_debug_secs > 0).then(|| Duration::from_secs(self.max_debug_secs))
+    }
+
+    /// The settings in effect, as a config file would give them, with the
+    /// tokens and notifiers' credentials blanked out.
+    pub fn to_toml(&self) -> Result<String, String> {
+        let mut table = toml::Table::try_from(self).map_err(|e| e.to_string())?;
+        for secret in ["token", "broker_token"] {
+            if let Some(value) = table.get_mut(secret) {
+                *value = "<redacted>".into();
+            }
+        }
+        // Webhook URLs carry their credentials
+        if let Some(toml::Value::Array(notifiers)) = table.get_mut("notifiers") {
+            for notifier in notifiers.iter_mut().filter_map(toml::Value::as_table_mut) {
+                for secret in ["url", "password"] {
+                    if let Some(value) = notifier.get_mut(secret) {
+                        *value = "<redacted>".into();
+                    }
+                }
+            }
+        }
+        toml::to_string(&table).map_err(|e| e.to_string())
+    }
+
+    /// Host paths sandboxed jobs must not see: other jobs' workspaces and
+    /// uploads, the cache and history, and files holding secrets.
+    pub fn private_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.scratch_dir.clone(), self.cache_dir.clone(), self.history_db.clone()];
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let mut journal = self.history_db.clone().into_os_string();
+            journal.push(suffix);
+            paths.push(journal.into());
+        }
+        paths.extend(
+            [&self.config_file, &self.tls_cert, &self.tls_key, &self.tls_client_ca, &self.tokens_file]
+                .into_iter()
+                .flatten()
+                .cloned(),
+        );
+        paths
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    pub fn compile_timeout(&self) -> Option<Duration> {
+        (self.compile_timeout_secs > 0).then(|| Duration::from_secs(self.compile_timeout_secs))
+    }
+}
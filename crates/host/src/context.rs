@@ -0,0 +1,89 @@
+/// Host-wide components that every job needs access to.
+use crate::cache::CompileCache;
+use crate::compiler::Backends;
+use crate::devices::DeviceAllocator;
+use crate::gpumem::GpuMemoryEnforcement;
+use crate::libraries::Libraries;
+use crate::notify::Notifiers;
+use crate::output::OutputLimits;
+use crate::policy::FlagPolicy;
+use crate::probe;
+use crate::profiler::ProfilerConfig;
+use crate::queue::JobQueue;
+use crate::sandbox::Sandbox;
+use crate::toolchain::Toolchains;
+use common::chunking;
+use common::compute::{Accelerator, HostInfo};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone)]
+pub struct HostContext {
+    pub devices: DeviceAllocator,
+    pub cache: Arc<CompileCache>,
+    /// The default `nvcc`, and `hipcc` and the C++ compiler if the host has
+    /// them, for jobs that don't pick a toolchain.
+    pub backends: Arc<Backends>,
+    /// The default `compute-sanitizer` and `cuda-gdbserver`, likewise.
+    pub compute_sanitizer: Arc<Path>,
+    pub cuda_gdbserver: Arc<Path>,
+    /// Build jobs' cmake and make projects.
+    pub cmake: Arc<Path>,
+    pub make: Arc<Path>,
+    /// Toolkits jobs can choose instead of the default `nvcc`.
+    pub toolchains: Arc<Toolchains>,
+    /// Link presets jobs can ask for by name.
+    pub libraries: Arc<Libraries>,
+    /// Appended to jobs that don't pick an architecture; empty when disabled.
+    pub arch_flags: Arc<[String]>,
+    pub sandbox: Sandbox,
+    pub output_limits: OutputLimits,
+    pub flag_policy: Arc<FlagPolicy>,
+    pub profilers: Arc<ProfilerConfig>,
+    /// Largest project accepted, all source files together.
+    pub max_source_bytes: u64,
+    /// Cap on, and default for, a program's run time.
+    pub max_timeout: Duration,
+    /// How long a debug session may last; `None` when they're off.
+    pub max_debug: Option<Duration>,
+    pub compile_timeout: Option<Duration>,
+    /// How jobs' GPU memory budgets are held to once they run.
+    pub gpu_memory_enforcement: GpuMemoryEnforcement,
+    /// Where finished jobs are reported, for those that ask.
+    pub notifiers: Notifiers,
+}
+
+impl HostContext {
+    /// What the host offers and how busy `queue` is, for `GetHostInfo` and
+    /// for registering with a broker.
+    pub async fn info(&self, queue: &JobQueue) -> HostInfo {
+        let usable = self.devices.devices();
+        let gpus = probe::gpus()
+            .await
+            .into_iter()
+            .filter(|g| usable.contains(&g.index))
+            .collect();
+        let (driver_version, cuda_version) = probe::driver_versions().await;
+        let (running, waiting, _) = queue.load();
+
+        HostInfo {
+            gpus,
+            driver_version,
+            cuda_version,
+            nvcc_version: self.backends.select(Accelerator::Cuda).map_or_else(|_| "unknown".into(), |c| c.version().into()),
+            max_concurrent_jobs: queue.max_concurrent() as u32,
+            max_timeout_secs: self.max_timeout.as_secs() as u32,
+            toolchains: self.toolchains.names().map(str::to_string).collect(),
+            profilers: self.profilers.available(),
+            libraries: self.libraries.info(),
+            backends: self.backends.info(),
+            running_jobs: running as u32,
+            queued_jobs: waiting as u32,
+            mig_slices: self.devices.slices(),
+            mps_clients: self.devices.mps_clients(),
+            notifiers: self.notifiers.kinds(),
+            max_chunk_bytes: chunking::MAX_CHUNK_SIZE as u32,
+        }
+    }
+}
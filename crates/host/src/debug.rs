@@ -0,0 +1,93 @@
+/// Debug jobs: the program is built with `-g -G` and started under
+/// `cuda-gdbserver`, listening on a loopback port that only `DebugTunnel`
+/// reaches, so the debugger's traffic rides the job's authenticated gRPC
+/// connection instead of needing a port opened on the host.
+///
+/// The session is the job's run: it ends when the program exits, the job is
+/// cancelled, or the host's debug time limit passes, and in every case the
+/// gdbserver's process group is killed like any other program's.
+use common::compute::DebugData;
+use std::ffi::OsString;
+use std::io;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tonic::{Status, Streaming};
+
+/// How long a tunnel waits for the gdbserver to start listening.
+const CONNECT_WAIT: Duration = Duration::from_secs(10);
+
+/// Adds the flags for host and device debug info, unless already there.
+pub fn flags(flags: &mut Vec<String>) {
+    for flag in ["-g", "-G"] {
+        if !flags.iter().any(|f| f == flag) {
+            flags.push(flag.into());
+        }
+    }
+}
+
+/// A loopback port that's free right now, for the gdbserver to listen on.
+pub fn free_port() -> io::Result<u16> {
+    Ok(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?.local_addr()?.port())
+}
+
+/// The gdbserver to launch and its arguments, ending with `program` and `args`.
+pub fn wrap(gdbserver: &Path, port: u16, program: &Path, args: &[String]) -> (PathBuf, Vec<OsString>) {
+    let mut wrapped = vec![OsString::from(format!("{}:{}", Ipv4Addr::LOCALHOST, port)), program.into()];
+    wrapped.extend(args.iter().map(OsString::from));
+    (gdbserver.to_path_buf(), wrapped)
+}
+
+/// Relays bytes between a `DebugTunnel` call and the gdbserver on `port`
+/// until either side closes.
+pub async fn tunnel(port: u16, mut incoming: Streaming<DebugData>, tx: mpsc::Sender<Result<DebugData, Status>>) {
+    let socket = match connect(port).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            let message = format!("Could not reach the job's gdbserver: {}", e);
+            let _ = tx.send(Err(Status::unavailable(message))).await;
+            return;
+        }
+    };
+    let (mut reader, mut writer) = socket.into_split();
+
+    let upstream = async {
+        while let Ok(Some(message)) = incoming.message().await {
+            if writer.write_all(&message.data).await.is_err() {
+                break;
+            }
+        }
+        let _ = writer.shutdown().await;
+    };
+    let downstream = async {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf).await.unwrap_or(0);
+            let data = DebugData {
+                data: buf[..n].to_vec(),
+                ..Default::default()
+            };
+            if n == 0 || tx.send(Ok(data)).await.is_err() {
+                break;
+            }
+        }
+    };
+    // The debugger hanging up closes our write side, after which the
+    // gdbserver drops the connection; it exiting ends the call
+    tokio::join!(upstream, downstream);
+}
+
+/// Connects to the gdbserver, which may still be starting.
+async fn connect(port: u16) -> io::Result<TcpStream> {
+    let deadline = tokio::time::Instant::now() + CONNECT_WAIT;
+    loop {
+        match TcpStream::connect((Ipv4Addr::LOCALHOST, port)).await {
+            Ok(socket) => return Ok(socket),
+            Err(e) if tokio::time::Instant::now() >= deadline => return Err(e),
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
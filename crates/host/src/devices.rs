@@ -0,0 +1,429 @@
+/// GPU allocator: hands out device indices to jobs and makes jobs wait while
+/// the devices they need are busy. The lease sets `CUDA_VISIBLE_DEVICES`, so a
+/// program always sees its devices as 0..n regardless of the physical index.
+///
+/// Besides whole GPUs it hands out MIG slices, to jobs that ask for one (a
+/// GPU split into slices is never leased whole), and, on hosts that allow
+/// it, places on a GPU shared through MPS by jobs whose memory budgets fit
+/// in it together. A GPU is either leased whole or shared, never both.
+use common::compute::MigSlice;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// What a job asks for.
+#[derive(Debug, Clone, Copy)]
+pub enum DeviceRequest {
+    /// One specific physical device.
+    Index(u32),
+    /// Any `n` devices.
+    Count(usize),
+    /// One MIG slice: a given one (see `find_slice`), or the smallest free
+    /// one with at least `min_memory_mib`.
+    Slice { slice: Option<usize>, min_memory_mib: u64 },
+    /// A place on a GPU shared through MPS, for a program that needs
+    /// `memory_mib` of it.
+    Shared { memory_mib: u64 },
+}
+
+/// How GPUs are shared through MPS.
+#[derive(Debug, Clone, Default)]
+pub struct Sharing {
+    /// Jobs per GPU; 0 turns sharing off.
+    pub clients: u32,
+    /// Each GPU's memory, which the budgets of the jobs sharing it must fit in.
+    pub memory_mib: HashMap<u32, u64>,
+}
+
+#[derive(Clone)]
+pub struct DeviceAllocator {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    all: Vec<u32>,
+    /// The GPUs not split into MIG slices.
+    whole: Vec<u32>,
+    slices: Vec<MigSlice>,
+    sharing: Sharing,
+    state: Mutex<State>,
+    released: Notify,
+}
+
+#[derive(Default)]
+struct State {
+    /// Whole GPUs nothing holds.
+    free: BTreeSet<u32>,
+    shared: BTreeMap<u32, Share>,
+    /// Positions in `Inner::slices`.
+    busy_slices: BTreeSet<usize>,
+}
+
+/// The jobs on a GPU shared through MPS.
+#[derive(Default)]
+struct Share {
+    jobs: u32,
+    memory_mib: u64,
+}
+
+/// What a lease holds, to give back.
+enum Held {
+    Whole,
+    Slice(usize),
+    Shared { memory_mib: u64 },
+}
+
+/// Devices held by a job; returned to the pool on drop.
+pub struct DeviceLease {
+    allocator: DeviceAllocator,
+    pub devices: Vec<u32>,
+    held: Held,
+}
+
+impl DeviceAllocator {
+    /// `slices` are the MIG slices of `devices`; their GPUs are only handed
+    /// out slice by slice.
+    pub fn new(devices: Vec<u32>, slices: Vec<MigSlice>, sharing: Sharing) -> Self {
+        let whole: Vec<u32> = devices
+            .iter()
+            .copied()
+            .filter(|d| !slices.iter().any(|s| s.gpu == *d))
+            .collect();
+        DeviceAllocator {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    free: whole.iter().copied().collect(),
+                    ..Default::default()
+                }),
+                all: devices,
+                whole,
+                slices,
+                sharing,
+                released: Notify::new(),
+            }),
+        }
+    }
+
+    pub fn devices(&self) -> &[u32] {
+        &self.inner.all
+    }
+
+    /// Devices currently leased to jobs, whole, shared or by the slice.
+    pub fn in_use(&self) -> Vec<u32> {
+        let state = self.inner.state.lock().unwrap();
+        self.inner
+            .all
+            .iter()
+            .copied()
+            .filter(|d| {
+                let sliced = state.busy_slices.iter().any(|&i| self.inner.slices[i].gpu == *d);
+                (self.inner.whole.contains(d) && !state.free.contains(d)) || sliced
+            })
+            .collect()
+    }
+
+    /// The host's MIG slices, marked busy where leased.
+    pub fn slices(&self) -> Vec<MigSlice> {
+        let state = self.inner.state.lock().unwrap();
+        let mut slices = self.inner.slices.clone();
+        for &i in &state.busy_slices {
+            slices[i].busy = true;
+        }
+        slices
+    }
+
+    /// Shared jobs per GPU; 0 when GPUs aren't shared.
+    pub fn mps_clients(&self) -> u32 {
+        self.inner.sharing.clients
+    }
+
+    /// The slice a job names, by UUID or as "<gpu>/<device>"; `None` for "any".
+    pub fn find_slice(&self, name: &str) -> Result<Option<usize>, String> {
+        if name == "any" {
+            return Ok(None);
+        }
+        self.inner
+            .slices
+            .iter()
+            .position(|s| s.uuid == name || format!("{}/{}", s.gpu, s.device) == name)
+            .map(Some)
+            .ok_or_else(|| format!("No MIG slice {:?} on this host (see `info`)", name))
+    }
+
+    /// Rejects requests this host can never satisfy, so they fail fast instead
+    /// of waiting forever.
+    pub fn validate(&self, request: DeviceRequest) -> Result<(), String> {
+        let inner = &self.inner;
+        match request {
+            DeviceRequest::Index(i) if !inner.all.contains(&i) => Err(format!(
+                "GPU {} does not exist on this host (available: {:?})",
+                i, inner.all
+            )),
+            DeviceRequest::Index(i) if !inner.whole.contains(&i) => {
+                Err(format!("GPU {} is split into MIG slices; ask for one of those instead", i))
+            }
+            DeviceRequest::Count(n) if n > inner.whole.len() && inner.whole.len() < inner.all.len() => Err(format!(
+                "Requested {} GPUs but this host has {} that aren't split into MIG slices",
+                n,
+                inner.whole.len()
+            )),
+            DeviceRequest::Count(n) if n > inner.whole.len() => Err(format!(
+                "Requested {} GPUs but this host has {}",
+                n,
+                inner.whole.len()
+            )),
+            DeviceRequest::Slice { .. } if inner.slices.is_empty() => Err("This host has no MIG slices".into()),
+            DeviceRequest::Slice { slice: None, min_memory_mib } => {
+                let largest = inner.slices.iter().map(|s| s.memory_mib).max().unwrap_or(0);
+                if largest < min_memory_mib {
+                    return Err(format!(
+                        "No MIG slice on this host has {} MiB (the largest has {} MiB)",
+                        min_memory_mib, largest
+                    ));
+                }
+                Ok(())
+            }
+            DeviceRequest::Slice { slice: Some(i), min_memory_mib } if inner.slices[i].memory_mib < min_memory_mib => {
+                Err(format!(
+                    "MIG slice {}/{} has {} MiB, less than the job's {} MiB budget",
+                    inner.slices[i].gpu, inner.slices[i].device, inner.slices[i].memory_mib, min_memory_mib
+                ))
+            }
+            DeviceRequest::Shared { .. } if inner.sharing.clients == 0 => {
+                Err("This host doesn't share GPUs through MPS".into())
+            }
+            DeviceRequest::Shared { memory_mib: 0 } => {
+                Err("A shared job must declare how much GPU memory it needs".into())
+            }
+            DeviceRequest::Shared { memory_mib } if !inner.whole.iter().any(|d| memory_mib <= inner.memory(*d)) => {
+                Err(format!("No GPU on this host that can be shared has {} MiB", memory_mib))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Non-blocking attempt; `None` if the request can't be satisfied right now.
+    pub fn try_acquire(&self, request: DeviceRequest) -> Option<DeviceLease> {
+        let inner = &self.inner;
+        let mut state = self.inner.state.lock().unwrap();
+        let (devices, held) = match request {
+            DeviceRequest::Index(i) => (state.free.remove(&i).then(|| vec![i])?, Held::Whole),
+            DeviceRequest::Count(n) if state.free.len() >= n => {
+                let picked: Vec<u32> = state.free.iter().take(n).copied().collect();
+                for d in &picked {
+                    state.free.remove(d);
+                }
+                (picked, Held::Whole)
+            }
+            DeviceRequest::Count(_) => return None,
+            DeviceRequest::Slice { slice, min_memory_mib } => {
+                // The smallest slice that will do, leaving larger ones for larger jobs
+                let picked = match slice {
+                    Some(i) => (!state.busy_slices.contains(&i)).then_some(i)?,
+                    None => (0..inner.slices.len())
+                        .filter(|i| !state.busy_slices.contains(i) && inner.slices[*i].memory_mib >= min_memory_mib)
+                        .min_by_key(|i| inner.slices[*i].memory_mib)?,
+                };
+                state.busy_slices.insert(picked);
+                (vec![inner.slices[picked].gpu], Held::Slice(picked))
+            }
+            DeviceRequest::Shared { memory_mib } => {
+                // GPUs already shared are filled up before another is taken
+                let gpu = state
+                    .shared
+                    .iter()
+                    .find(|(gpu, share)| {
+                        share.jobs < inner.sharing.clients && share.memory_mib + memory_mib <= inner.memory(**gpu)
+                    })
+                    .map(|(gpu, _)| *gpu)
+                    .or_else(|| state.free.iter().copied().find(|gpu| memory_mib <= inner.memory(*gpu)))?;
+                state.free.remove(&gpu);
+                let share = state.shared.entry(gpu).or_default();
+                share.jobs += 1;
+                share.memory_mib += memory_mib;
+                (vec![gpu], Held::Shared { memory_mib })
+            }
+        };
+        Some(DeviceLease {
+            allocator: self.clone(),
+            devices,
+            held,
+        })
+    }
+
+    /// Waits until the request can be satisfied.
+    pub async fn acquire(&self, request: DeviceRequest) -> DeviceLease {
+        loop {
+            // Register interest before checking, so a release between the
+            // check and the await isn't missed.
+            let released = self.inner.released.notified();
+            if let Some(lease) = self.try_acquire(request) {
+                return lease;
+            }
+            released.await;
+        }
+    }
+}
+
+impl Inner {
+    /// A GPU's memory as far as sharing knows; 0 if `nvidia-smi` didn't say.
+    fn memory(&self, gpu: u32) -> u64 {
+        self.sharing.memory_mib.get(&gpu).copied().unwrap_or(0)
+    }
+}
+
+impl DeviceLease {
+    /// Value for `CUDA_VISIBLE_DEVICES`: the indices, or a slice's UUID.
+    pub fn visible_devices(&self) -> String {
+        if let Some(slice) = self.slice() {
+            return slice.uuid.clone();
+        }
+        self.devices
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn slice(&self) -> Option<&MigSlice> {
+        match self.held {
+            Held::Slice(i) => Some(&self.allocator.inner.slices[i]),
+            _ => None,
+        }
+    }
+
+    /// For a place on a shared GPU, the share of its SMs the program gets
+    /// (`CUDA_MPS_ACTIVE_THREAD_PERCENTAGE`).
+    pub fn thread_percentage(&self) -> Option<u32> {
+        match self.held {
+            Held::Shared { .. } => Some(100 / self.allocator.inner.sharing.clients.max(1)),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for DeviceLease {
+    fn drop(&mut self) {
+        let mut state = self.allocator.inner.state.lock().unwrap();
+        match self.held {
+            Held::Whole => state.free.extend(self.devices.iter().copied()),
+            Held::Slice(i) => {
+                state.busy_slices.remove(&i);
+            }
+            Held::Shared { memory_mib } => {
+                let gpu = self.devices[0];
+                if let Some(share) = state.shared.get_mut(&gpu) {
+                    share.jobs -= 1;
+                    share.memory_mib -= memory_mib;
+                    if share.jobs == 0 {
+                        state.shared.remove(&gpu);
+                        state.free.insert(gpu);
+                    }
+                }
+            }
+        }
+        drop(state);
+        self.allocator.inner.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slice(gpu: u32, device: u32, memory_mib: u64) -> MigSlice {
+        MigSlice {
+            gpu,
+            device,
+            uuid: format!("MIG-{}-{}", gpu, device),
+            memory_mib,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn whole_gpus_are_leased_until_dropped() {
+        let devices = DeviceAllocator::new(vec![0, 1, 2], Vec::new(), Sharing::default());
+        let two = devices.try_acquire(DeviceRequest::Count(2)).unwrap();
+        assert_eq!(two.devices, [0, 1]);
+        assert_eq!(two.visible_devices(), "0,1");
+        assert!(devices.try_acquire(DeviceRequest::Count(2)).is_none());
+        assert!(devices.try_acquire(DeviceRequest::Index(1)).is_none());
+        assert_eq!(devices.in_use(), [0, 1]);
+
+        drop(two);
+        assert!(devices.in_use().is_empty());
+        assert_eq!(devices.try_acquire(DeviceRequest::Index(1)).unwrap().devices, [1]);
+    }
+
+    #[test]
+    fn impossible_requests_are_refused_up_front() {
+        let devices = DeviceAllocator::new(vec![0, 1], vec![slice(1, 0, 10_000)], Sharing::default());
+        assert!(devices.validate(DeviceRequest::Index(0)).is_ok());
+        assert!(devices.validate(DeviceRequest::Index(5)).unwrap_err().contains("does not exist"));
+        assert!(devices.validate(DeviceRequest::Index(1)).unwrap_err().contains("MIG slices"));
+        assert!(devices.validate(DeviceRequest::Count(2)).unwrap_err().contains("aren't split"));
+        assert!(devices.validate(DeviceRequest::Slice { slice: None, min_memory_mib: 20_000 }).is_err());
+        assert!(devices.validate(DeviceRequest::Shared { memory_mib: 1_000 }).is_err());
+
+        let plain = DeviceAllocator::new(vec![0], Vec::new(), Sharing::default());
+        assert!(plain.validate(DeviceRequest::Count(2)).unwrap_err().contains("this host has 1"));
+        assert!(plain.validate(DeviceRequest::Slice { slice: None, min_memory_mib: 0 }).is_err());
+    }
+
+    #[test]
+    fn the_smallest_slice_that_fits_is_picked() {
+        let slices = vec![slice(0, 0, 20_000), slice(0, 1, 10_000), slice(0, 2, 10_000)];
+        let devices = DeviceAllocator::new(vec![0], slices, Sharing::default());
+        assert_eq!(devices.find_slice("0/2"), Ok(Some(2)));
+        assert_eq!(devices.find_slice("MIG-0-0"), Ok(Some(0)));
+        assert_eq!(devices.find_slice("any"), Ok(None));
+        assert!(devices.find_slice("3/0").is_err());
+
+        let small = devices.try_acquire(DeviceRequest::Slice { slice: None, min_memory_mib: 5_000 }).unwrap();
+        assert_eq!(small.visible_devices(), "MIG-0-1");
+        let large = devices.try_acquire(DeviceRequest::Slice { slice: None, min_memory_mib: 15_000 }).unwrap();
+        assert_eq!(large.visible_devices(), "MIG-0-0");
+        assert!(devices.try_acquire(DeviceRequest::Slice { slice: None, min_memory_mib: 15_000 }).is_none());
+        assert!(devices.try_acquire(DeviceRequest::Slice { slice: Some(1), min_memory_mib: 0 }).is_none());
+        assert_eq!(devices.slices().iter().filter(|s| s.busy).count(), 2);
+        assert_eq!(devices.in_use(), [0]);
+    }
+
+    #[test]
+    fn shared_gpus_fill_up_within_their_memory() {
+        let sharing = Sharing {
+            clients: 2,
+            memory_mib: HashMap::from([(0, 16_000), (1, 16_000)]),
+        };
+        let devices = DeviceAllocator::new(vec![0, 1], Vec::new(), sharing);
+        let first = devices.try_acquire(DeviceRequest::Shared { memory_mib: 6_000 }).unwrap();
+        let second = devices.try_acquire(DeviceRequest::Shared { memory_mib: 6_000 }).unwrap();
+        assert_eq!((first.devices[0], second.devices[0]), (0, 0));
+        assert_eq!(first.thread_percentage(), Some(50));
+
+        // GPU 0 has room for no third client, and GPU 1 not for 20 GB
+        let third = devices.try_acquire(DeviceRequest::Shared { memory_mib: 6_000 }).unwrap();
+        assert_eq!(third.devices, [1]);
+        assert!(devices.try_acquire(DeviceRequest::Index(1)).is_none());
+        assert!(devices.validate(DeviceRequest::Shared { memory_mib: 20_000 }).is_err());
+        assert!(devices.validate(DeviceRequest::Shared { memory_mib: 0 }).is_err());
+
+        drop((first, second));
+        assert_eq!(devices.try_acquire(DeviceRequest::Index(0)).unwrap().thread_percentage(), None);
+    }
+
+    #[tokio::test]
+    async fn acquire_waits_for_a_release() {
+        let devices = DeviceAllocator::new(vec![0], Vec::new(), Sharing::default());
+        let held = devices.try_acquire(DeviceRequest::Count(1)).unwrap();
+        let waiter = tokio::spawn({
+            let devices = devices.clone();
+            async move { devices.acquire(DeviceRequest::Index(0)).await.devices.clone() }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+        drop(held);
+        assert_eq!(waiter.await.unwrap(), [0]);
+    }
+}
@@ -0,0 +1,95 @@
+/// Recognises compiler diagnostics in nvcc's output, so clients get file,
+/// line and severity as data instead of scraping text.
+///
+/// Formats understood:
+/// - front end (cudafe/EDG): `k.cu(12): error: identifier "x" is undefined`
+/// - host compiler (gcc/clang): `k.cu:12:5: warning: unused variable 'y'`
+/// - toolchain stages: `ptxas error   : Entry function uses too much shared data`
+use common::compute::{Diagnostic, Severity};
+use std::path::Path;
+
+/// Stages that report without a source location.
+const TOOLS: &[&str] = &["nvcc", "ptxas", "nvlink", "fatbinary", "cicc"];
+
+/// Parses one output line. Paths inside `workspace` are made relative to it.
+pub fn parse(line: &str, workspace: &Path) -> Option<Diagnostic> {
+    let line = line.trim_end();
+    let (location, rest) = split_location(line)?;
+    let (severity, message) = split_severity(rest)?;
+
+    let (file, line, column, message) = match location {
+        Location::Tool(tool) => (String::new(), 0, 0, format!("{}: {}", tool, message)),
+        Location::Source { file, line, column } => {
+            let file = Path::new(file)
+                .strip_prefix(workspace)
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| file.to_string());
+            (file, line, column, message.to_string())
+        }
+    };
+
+    let mut diagnostic = Diagnostic {
+        file,
+        line,
+        column,
+        message,
+        ..Default::default()
+    };
+    diagnostic.set_severity(severity);
+    Some(diagnostic)
+}
+
+enum Location<'a> {
+    Tool(&'a str),
+    Source { file: &'a str, line: u32, column: u32 },
+}
+
+/// Splits off the location prefix and returns the remainder after its colon.
+fn split_location(line: &str) -> Option<(Location<'_>, &str)> {
+    // `ptxas error   : ...`
+    if let Some((tool, _)) = line.split_once(' ')
+        && TOOLS.contains(&tool)
+    {
+        return Some((Location::Tool(tool), &line[tool.len()..]));
+    }
+
+    // `file(line): ...`
+    if let Some(open) = line.find('(').filter(|&i| i > 0)
+        && let Some(close) = line[open..].find("):").map(|i| open + i)
+        && let Ok(n) = line[open + 1..close].trim().parse()
+    {
+        let location = Location::Source {
+            file: &line[..open],
+            line: n,
+            column: 0,
+        };
+        return Some((location, &line[close + 2..]));
+    }
+
+    // `file:line:col: ...` or `file:line: ...`
+    let mut parts = line.splitn(3, ':');
+    let file = parts.next().filter(|f| !f.is_empty())?;
+    let n = parts.next()?.parse().ok()?;
+    let rest = parts.next()?;
+    if let Some((column, after)) = rest.split_once(':')
+        && let Ok(column) = column.parse()
+    {
+        return Some((Location::Source { file, line: n, column }, after));
+    }
+    Some((Location::Source { file, line: n, column: 0 }, rest))
+}
+
+/// `" error: msg"`, `" warning #177-D: msg"`, `" error   : msg"` and the like.
+fn split_severity(rest: &str) -> Option<(Severity, &str)> {
+    let (label, message) = rest.split_once(':')?;
+    let label = label.trim();
+    // Drop a diagnostic number such as `#177-D`
+    let word = label.split(" #").next()?.trim();
+    let severity = match word {
+        "error" | "fatal" | "fatal error" | "catastrophic error" => Severity::Error,
+        "warning" => Severity::Warning,
+        "note" | "remark" | "info" => Severity::Note,
+        _ => return None,
+    };
+    Some((severity, message.trim()))
+}
@@ -0,0 +1,243 @@
+/// The few CUDA driver API calls the kernel launcher makes, resolved from
+/// libcuda when it first runs, so the host still builds and serves jobs on
+/// machines without a driver.
+///
+/// Only the launcher's child process uses this: it loads one module, runs
+/// one kernel and exits, so nothing is ever freed or unloaded here.
+use std::ffi::{CStr, CString, c_char, c_int, c_uint, c_void};
+use std::ptr;
+
+type CuResult = c_int;
+type CuDevice = c_int;
+type CuContext = *mut c_void;
+type CuModule = *mut c_void;
+type CuFunction = *mut c_void;
+pub type DevicePtr = u64;
+
+const CUDA_SUCCESS: CuResult = 0;
+const CU_JIT_ERROR_LOG_BUFFER: c_int = 5;
+const CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES: c_int = 6;
+/// Room for ptxas's complaints about a bad module.
+const JIT_LOG_BYTES: usize = 16 * 1024;
+
+#[cfg(target_os = "linux")]
+const LIBRARY: &str = "libcuda.so.1";
+#[cfg(not(target_os = "linux"))]
+const LIBRARY: &str = "libcuda.so";
+
+#[allow(non_snake_case)]
+struct Api {
+    cuInit: unsafe extern "C" fn(c_uint) -> CuResult,
+    cuDeviceGet: unsafe extern "C" fn(*mut CuDevice, c_int) -> CuResult,
+    cuDevicePrimaryCtxRetain: unsafe extern "C" fn(*mut CuContext, CuDevice) -> CuResult,
+    cuCtxSetCurrent: unsafe extern "C" fn(CuContext) -> CuResult,
+    cuModuleLoadDataEx:
+        unsafe extern "C" fn(*mut CuModule, *const c_void, c_uint, *mut c_int, *mut *mut c_void) -> CuResult,
+    cuModuleGetFunction: unsafe extern "C" fn(*mut CuFunction, CuModule, *const c_char) -> CuResult,
+    cuMemAlloc_v2: unsafe extern "C" fn(*mut DevicePtr, usize) -> CuResult,
+    cuMemsetD8_v2: unsafe extern "C" fn(DevicePtr, u8, usize) -> CuResult,
+    cuMemcpyHtoD_v2: unsafe extern "C" fn(DevicePtr, *const c_void, usize) -> CuResult,
+    cuMemcpyDtoH_v2: unsafe extern "C" fn(*mut c_void, DevicePtr, usize) -> CuResult,
+    #[allow(clippy::type_complexity)]
+    cuLaunchKernel: unsafe extern "C" fn(
+        CuFunction,
+        c_uint,
+        c_uint,
+        c_uint,
+        c_uint,
+        c_uint,
+        c_uint,
+        c_uint,
+        *mut c_void,
+        *mut *mut c_void,
+        *mut *mut c_void,
+    ) -> CuResult,
+    cuCtxSynchronize: unsafe extern "C" fn() -> CuResult,
+    cuGetErrorName: unsafe extern "C" fn(CuResult, *mut *const c_char) -> CuResult,
+}
+
+/// The driver, initialized, with device 0's primary context current (the
+/// launcher's `CUDA_VISIBLE_DEVICES` holds just the leased GPU).
+pub struct Driver {
+    api: Api,
+}
+
+pub struct Module(CuModule);
+pub struct Function(CuFunction);
+
+impl Driver {
+    pub fn load() -> Result<Self, String> {
+        let api = open()?;
+        let driver = Driver { api };
+        let mut device = 0;
+        let mut context = ptr::null_mut();
+        // SAFETY: every pointer passed is to a live local of the right type.
+        unsafe {
+            driver.check("cuInit", (driver.api.cuInit)(0))?;
+            driver.check("cuDeviceGet", (driver.api.cuDeviceGet)(&mut device, 0))?;
+            driver.check("cuDevicePrimaryCtxRetain", (driver.api.cuDevicePrimaryCtxRetain)(&mut context, device))?;
+            driver.check("cuCtxSetCurrent", (driver.api.cuCtxSetCurrent)(context))?;
+        }
+        Ok(driver)
+    }
+
+    /// JIT-compiles a PTX module; a rejected one comes back with ptxas's log.
+    pub fn load_module(&self, ptx: &str) -> Result<Module, String> {
+        let ptx = CString::new(ptx).map_err(|_| "The PTX contains a NUL byte".to_string())?;
+        let mut log = vec![0u8; JIT_LOG_BYTES];
+        let mut options = [CU_JIT_ERROR_LOG_BUFFER, CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES];
+        // The driver takes option values as pointers, sizes included
+        let mut values = [log.as_mut_ptr() as *mut c_void, JIT_LOG_BYTES as *mut c_void];
+        let mut module = ptr::null_mut();
+        // SAFETY: the option arrays and the log buffer outlive the call, and
+        // `ptx` is NUL-terminated.
+        let result = unsafe {
+            (self.api.cuModuleLoadDataEx)(
+                &mut module,
+                ptx.as_ptr() as *const c_void,
+                options.len() as c_uint,
+                options.as_mut_ptr(),
+                values.as_mut_ptr(),
+            )
+        };
+        if result != CUDA_SUCCESS {
+            let log = CStr::from_bytes_until_nul(&log).map(|l| l.to_string_lossy()).unwrap_or_default();
+            let error = self.error("cuModuleLoadDataEx", result);
+            return Err(if log.trim().is_empty() { error } else { format!("{}\n{}", error, log.trim_end()) });
+        }
+        Ok(Module(module))
+    }
+
+    pub fn function(&self, module: &Module, name: &str) -> Result<Function, String> {
+        let name = CString::new(name).map_err(|_| "The kernel name contains a NUL byte".to_string())?;
+        let mut function = ptr::null_mut();
+        // SAFETY: `module` was loaded by this driver and `name` is NUL-terminated.
+        let result = unsafe { (self.api.cuModuleGetFunction)(&mut function, module.0, name.as_ptr()) };
+        self.check("cuModuleGetFunction", result)?;
+        Ok(Function(function))
+    }
+
+    /// A zeroed device buffer of `size` bytes, starting with `data`.
+    pub fn buffer(&self, data: &[u8], size: usize) -> Result<DevicePtr, String> {
+        let mut buffer = 0;
+        // SAFETY: `data` is valid for `data.len()` bytes, which the caller
+        // keeps within `size`.
+        unsafe {
+            self.check("cuMemAlloc", (self.api.cuMemAlloc_v2)(&mut buffer, size.max(1)))?;
+            self.check("cuMemsetD8", (self.api.cuMemsetD8_v2)(buffer, 0, size))?;
+            if !data.is_empty() {
+                let result = (self.api.cuMemcpyHtoD_v2)(buffer, data.as_ptr() as *const c_void, data.len());
+                self.check("cuMemcpyHtoD", result)?;
+            }
+        }
+        Ok(buffer)
+    }
+
+    pub fn read(&self, buffer: DevicePtr, size: usize) -> Result<Vec<u8>, String> {
+        let mut data = vec![0u8; size];
+        // SAFETY: `data` has room for `size` bytes.
+        let result = unsafe { (self.api.cuMemcpyDtoH_v2)(data.as_mut_ptr() as *mut c_void, buffer, size) };
+        self.check("cuMemcpyDtoH", result)?;
+        Ok(data)
+    }
+
+    /// Launches on the default stream and waits for the kernel to finish.
+    /// `params` point at each argument's value, in order.
+    pub fn launch(
+        &self,
+        function: &Function,
+        grid: [u32; 3],
+        block: [u32; 3],
+        shared_bytes: u32,
+        params: &mut [*mut c_void],
+    ) -> Result<(), String> {
+        // SAFETY: `params` holds one pointer per kernel parameter, each to a
+        // value the caller keeps alive across the call.
+        unsafe {
+            let result = (self.api.cuLaunchKernel)(
+                function.0,
+                grid[0],
+                grid[1],
+                grid[2],
+                block[0],
+                block[1],
+                block[2],
+                shared_bytes,
+                ptr::null_mut(),
+                params.as_mut_ptr(),
+                ptr::null_mut(),
+            );
+            self.check("cuLaunchKernel", result)?;
+            self.check("cuCtxSynchronize", (self.api.cuCtxSynchronize)())
+        }
+    }
+
+    fn check(&self, call: &str, result: CuResult) -> Result<(), String> {
+        match result {
+            CUDA_SUCCESS => Ok(()),
+            _ => Err(self.error(call, result)),
+        }
+    }
+
+    /// `cuLaunchKernel failed: CUDA_ERROR_INVALID_VALUE (1)`
+    fn error(&self, call: &str, result: CuResult) -> String {
+        let mut name = ptr::null();
+        // SAFETY: the driver points `name` at a static string, or leaves it null.
+        let known = unsafe { (self.api.cuGetErrorName)(result, &mut name) } == CUDA_SUCCESS && !name.is_null();
+        let name = if known {
+            // SAFETY: checked non-null above; the driver's strings are NUL-terminated.
+            unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned()
+        } else {
+            "unknown error".into()
+        };
+        format!("{} failed: {} ({})", call, name, result)
+    }
+}
+
+#[cfg(unix)]
+fn open() -> Result<Api, String> {
+    let library = CString::new(LIBRARY).expect("no NUL in the name");
+    // SAFETY: dlopen and dlsym are given NUL-terminated names; the library
+    // stays loaded for the life of the process since it's never closed.
+    unsafe {
+        let handle = libc::dlopen(library.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            let error = CStr::from_ptr(libc::dlerror()).to_string_lossy().into_owned();
+            return Err(format!("The CUDA driver isn't available: {}", error));
+        }
+        Ok(Api {
+            cuInit: symbol(handle, "cuInit")?,
+            cuDeviceGet: symbol(handle, "cuDeviceGet")?,
+            cuDevicePrimaryCtxRetain: symbol(handle, "cuDevicePrimaryCtxRetain")?,
+            cuCtxSetCurrent: symbol(handle, "cuCtxSetCurrent")?,
+            cuModuleLoadDataEx: symbol(handle, "cuModuleLoadDataEx")?,
+            cuModuleGetFunction: symbol(handle, "cuModuleGetFunction")?,
+            cuMemAlloc_v2: symbol(handle, "cuMemAlloc_v2")?,
+            cuMemsetD8_v2: symbol(handle, "cuMemsetD8_v2")?,
+            cuMemcpyHtoD_v2: symbol(handle, "cuMemcpyHtoD_v2")?,
+            cuMemcpyDtoH_v2: symbol(handle, "cuMemcpyDtoH_v2")?,
+            cuLaunchKernel: symbol(handle, "cuLaunchKernel")?,
+            cuCtxSynchronize: symbol(handle, "cuCtxSynchronize")?,
+            cuGetErrorName: symbol(handle, "cuGetErrorName")?,
+        })
+    }
+}
+
+/// A function of the driver; `T` must be its pointer type.
+#[cfg(unix)]
+unsafe fn symbol<T>(handle: *mut c_void, name: &str) -> Result<T, String> {
+    let c_name = CString::new(name).expect("no NUL in the name");
+    // SAFETY: `handle` came from dlopen, and the caller vouches for `T`.
+    unsafe {
+        let symbol = libc::dlsym(handle, c_name.as_ptr());
+        if symbol.is_null() {
+            return Err(format!("{} has no {}", LIBRARY, name));
+        }
+        Ok(std::mem::transmute_copy::<*mut c_void, T>(&symbol))
+    }
+}
+
+#[cfg(not(unix))]
+fn open() -> Result<Api, String> {
+    Err(format!("Launching kernels needs {}, which this platform doesn't load", LIBRARY))
+}
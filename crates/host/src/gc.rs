@@ -0,0 +1,91 @@
+/// Garbage collector for `scratch/` entries nothing owns any more: job
+/// workspaces and uploads left behind when the host crashed or was killed
+/// mid-job.
+///
+/// Runs once at startup and then periodically. An entry is removed only if it
+/// is older than the configured age *and* no running job or pending upload
+/// claims it. Kept workspaces (`retained/`) have their own expiry.
+use crate::cleanup::CleanupQueue;
+use crate::registry::JobRegistry;
+use crate::uploads::UploadStore;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tracing::{info, warn};
+
+/// How often scratch is scanned after the startup pass.
+const SCAN_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+pub struct ScratchGc {
+    pub root: PathBuf,
+    pub max_age: Duration,
+    pub registry: JobRegistry,
+    pub uploads: UploadStore,
+    pub cleanup: CleanupQueue,
+}
+
+impl ScratchGc {
+    pub fn spawn(self) {
+        tokio::spawn(async move {
+            loop {
+                match self.scan().await {
+                    Ok(0) => {}
+                    Ok(n) => info!("🗑️ Removing {} orphaned scratch entr{}", n, if n == 1 { "y" } else { "ies" }),
+                    Err(e) => warn!("⚠️ Could not scan {}: {}", self.root.display(), e),
+                }
+                tokio::time::sleep(SCAN_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Schedules every orphan for deletion and returns how many there were.
+    async fn scan(&self) -> io::Result<usize> {
+        let mut orphans = Vec::new();
+        for (name, path) in entries(&self.root).await? {
+            match name.as_str() {
+                "retained" => {}
+                "uploads" => {
+                    for (id, upload) in entries(&path).await? {
+                        if !self.uploads.is_pending(&id) && self.expired(&upload).await {
+                            orphans.push(upload);
+                        }
+                    }
+                }
+                job_id => {
+                    if !self.registry.is_running(job_id) && self.expired(&path).await {
+                        orphans.push(path);
+                    }
+                }
+            }
+        }
+
+        let count = orphans.len();
+        for orphan in orphans {
+            self.cleanup.schedule(orphan);
+        }
+        Ok(count)
+    }
+
+    async fn expired(&self, path: &Path) -> bool {
+        fs::symlink_metadata(path)
+            .await
+            .and_then(|m| m.modified())
+            .map(|modified| modified.elapsed().unwrap_or_default() > self.max_age)
+            .unwrap_or(false)
+    }
+}
+
+/// `(file name, path)` of each entry; a missing directory has none.
+async fn entries(dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut read = match fs::read_dir(dir).await {
+        Ok(read) => read,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = read.next_entry().await? {
+        entries.push((entry.file_name().to_string_lossy().into_owned(), entry.path()));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,118 @@
+/// GPU memory budgets. A job may declare how much memory its program needs
+/// on each of its GPUs; it is refused outright if no GPU here has that much,
+/// and isn't started if the GPUs it was given have less than that free.
+///
+/// The host can also hold the program to the budget: `mps` sets the MPS
+/// pinned device memory limit (the MPS control daemon must be running, and
+/// the program must run outside docker), `watch` polls `nvidia-smi` and
+/// kills a program whose processes go over it. Processes are matched to a
+/// job by their working directory being inside its workspace.
+use crate::probe;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::AbortOnDropHandle;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuMemoryEnforcement {
+    /// Budgets are only checked before the program starts.
+    None,
+    Mps,
+    Watch,
+}
+
+/// Refuses a budget larger than every usable GPU. Passes when `nvidia-smi`
+/// can't say.
+pub async fn check_capacity(usable: &[u32], budget_mib: u64) -> Result<(), String> {
+    let largest = probe::gpus()
+        .await
+        .into_iter()
+        .filter(|g| usable.contains(&g.index))
+        .map(|g| g.memory_total_mib)
+        .max();
+    match largest {
+        Some(total) if total < budget_mib => Err(format!(
+            "The job's GPU memory budget of {} MiB is more than any GPU on this host has ({} MiB)",
+            budget_mib, total
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks each of `devices` has `budget_mib` free now, next to whatever else
+/// runs on it.
+pub async fn check_free(devices: &[u32], budget_mib: u64) -> Result<(), String> {
+    for gpu in probe::gpus().await.into_iter().filter(|g| devices.contains(&g.index)) {
+        if gpu.memory_free_mib < budget_mib {
+            return Err(format!(
+                "GPU {} has {} MiB free, less than the job's {} MiB budget",
+                gpu.index, gpu.memory_free_mib, budget_mib
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `CUDA_MPS_PINNED_DEVICE_MEM_LIMIT` for a program seeing `devices` GPUs,
+/// e.g. "0=2048M,1=2048M".
+pub fn mps_limit(devices: usize, budget_mib: u64) -> String {
+    (0..devices)
+        .map(|i| format!("{}={}M", i, budget_mib))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Polls the GPU memory a job's processes use, cancelling `kill` once they
+/// go over budget on any one GPU. Stops when dropped.
+pub struct Watch {
+    exceeded: Arc<OnceLock<u64>>,
+    _task: AbortOnDropHandle<()>,
+}
+
+impl Watch {
+    pub fn spawn(workspace: &Path, budget_mib: u64, kill: CancellationToken) -> Watch {
+        // /proc reports absolute working directories
+        let workspace = std::fs::canonicalize(workspace).unwrap_or_else(|_| workspace.to_path_buf());
+        let exceeded = Arc::new(OnceLock::new());
+        let task = tokio::spawn({
+            let exceeded = exceeded.clone();
+            async move {
+                loop {
+                    tokio::time::sleep(WATCH_INTERVAL).await;
+                    let used = used_by(&workspace).await;
+                    if used > budget_mib {
+                        let _ = exceeded.set(used);
+                        kill.cancel();
+                        return;
+                    }
+                }
+            }
+        });
+        Watch {
+            exceeded,
+            _task: AbortOnDropHandle::new(task),
+        }
+    }
+
+    /// The MiB in use when the program was caught over its budget.
+    pub fn exceeded(&self) -> Option<u64> {
+        self.exceeded.get().copied()
+    }
+}
+
+/// The most memory the processes working in `workspace` use on one GPU.
+async fn used_by(workspace: &Path) -> u64 {
+    let mut per_gpu: HashMap<String, u64> = HashMap::new();
+    for app in probe::compute_apps().await {
+        let cwd = std::fs::read_link(format!("/proc/{}/cwd", app.pid));
+        if cwd.is_ok_and(|cwd| cwd.starts_with(workspace)) {
+            *per_gpu.entry(app.gpu_uuid).or_default() += app.used_mib;
+        }
+    }
+    per_gpu.into_values().max().unwrap_or(0)
+}
@@ -0,0 +1,105 @@
+/// Readiness as seen by `grpc.health.v1.Health`, for load balancers and
+/// Kubernetes probes.
+///
+/// Both the whole server ("") and the executor service report NOT_SERVING
+/// while the preferred backend's compiler can't be run, none of the host's
+/// GPUs is visible to nvidia-smi (on hosts offering CUDA),
+/// the queue is saturated (every slot busy and more jobs waiting than there
+/// are slots), or the host is shutting down.
+use crate::probe;
+use crate::queue::JobQueue;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tonic::server::NamedService;
+use tonic_health::ServingStatus;
+use tonic_health::server::HealthReporter;
+use tracing::{info, warn};
+
+/// How often readiness is re-evaluated.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct HealthCheck {
+    pub reporter: HealthReporter,
+    /// Name of the service reported alongside the server as a whole.
+    pub service: &'static str,
+    /// nvcc or hipcc.
+    pub compiler: Arc<Path>,
+    /// Whether nvidia-smi should see the GPUs.
+    pub cuda: bool,
+    /// GPU indices jobs may use.
+    pub devices: Vec<u32>,
+    pub queue: JobQueue,
+    pub draining: CancellationToken,
+}
+
+impl HealthCheck {
+    pub fn for_service<S: NamedService>(
+        reporter: HealthReporter,
+        compiler: Arc<Path>,
+        cuda: bool,
+        devices: Vec<u32>,
+        queue: JobQueue,
+        draining: CancellationToken,
+    ) -> Self {
+        HealthCheck {
+            reporter,
+            service: S::NAME,
+            compiler,
+            cuda,
+            devices,
+            queue,
+            draining,
+        }
+    }
+
+    pub fn spawn(mut self) {
+        tokio::spawn(async move {
+            let mut last: Option<Option<String>> = None;
+            loop {
+                let problem = self.problem().await;
+                if last.as_ref() != Some(&problem) {
+                    let status = match &problem {
+                        Some(reason) => {
+                            warn!("🩺 Not serving: {}", reason);
+                            ServingStatus::NotServing
+                        }
+                        None => {
+                            if last.is_some() {
+                                info!("🩺 Serving again");
+                            }
+                            ServingStatus::Serving
+                        }
+                    };
+                    self.reporter.set_service_status("", status).await;
+                    self.reporter.set_service_status(self.service, status).await;
+                    last = Some(problem);
+                }
+
+                tokio::select! {
+                    _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+                    _ = self.draining.cancelled(), if !self.draining.is_cancelled() => {}
+                }
+            }
+        });
+    }
+
+    /// Why the host can't take jobs right now, if it can't.
+    async fn problem(&self) -> Option<String> {
+        if self.draining.is_cancelled() {
+            return Some("shutting down".into());
+        }
+        if probe::version_output(&self.compiler).await.is_none() {
+            return Some(format!("{} cannot be run", self.compiler.display()));
+        }
+        if self.cuda && !probe::gpus().await.iter().any(|g| self.devices.contains(&g.index)) {
+            return Some("no GPU visible".into());
+        }
+        let (running, waiting, slots) = self.queue.load();
+        if running >= slots && waiting > slots {
+            return Some(format!("queue saturated ({} running, {} waiting)", running, waiting));
+        }
+        None
+    }
+}
@@ -0,0 +1,206 @@
+/// Persistent job history in a SQLite database, so `ListJobs`/`GetJob` keep
+/// working across host restarts.
+///
+/// A row is written when a job is submitted and completed when it ends. Jobs
+/// that were still unfinished when the host went down are closed on startup.
+/// rusqlite is blocking, so every query runs on the blocking thread pool.
+use crate::cache::CompileCache;
+use common::compute::{ComputeRequest, JobRecord, JobResult, JobStatus, JobStatusResponse, SourceFile};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// `ListJobs` page size when the client doesn't say.
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 1000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS jobs (
+    job_id       TEXT PRIMARY KEY,
+    submitter    TEXT NOT NULL,
+    source_hash  TEXT NOT NULL,
+    files        TEXT NOT NULL,
+    flags        TEXT NOT NULL,
+    submitted_at INTEGER NOT NULL,
+    finished_at  INTEGER,
+    status       TEXT,
+    exit_code    INTEGER,
+    signal       INTEGER,
+    compile_ms   INTEGER,
+    run_ms       INTEGER,
+    cache_hit    INTEGER,
+    error        TEXT
+);
+CREATE INDEX IF NOT EXISTS jobs_by_time ON jobs (submitted_at);
+";
+
+const COLUMNS: &str = "job_id, submitter, source_hash, files, flags, submitted_at, finished_at, \
+                       status, exit_code, signal, compile_ms, run_ms, cache_hit, error";
+
+#[derive(Clone)]
+pub struct JobHistory {
+    db: Arc<Mutex<Connection>>,
+}
+
+impl JobHistory {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let db = Connection::open(path)?;
+        db.execute_batch(SCHEMA)?;
+        let interrupted = db.execute(
+            "UPDATE jobs SET finished_at = ?1, error = 'The host stopped while the job was unfinished'
+             WHERE finished_at IS NULL",
+            params![now_ms()],
+        )?;
+        if interrupted > 0 {
+            info!("🗂️ Marked {} unfinished job(s) from a previous run as interrupted", interrupted);
+        }
+        Ok(JobHistory {
+            db: Arc::new(Mutex::new(db)),
+        })
+    }
+
+    pub async fn submitted(&self, job_id: &str, submitter: &str, req: &ComputeRequest) {
+        let files = crate::job::project_files(req);
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        let row = (
+            job_id.to_string(),
+            submitter.to_string(),
+            source_hash(&files),
+            paths.join("\n"),
+            req.compiler_flags.join("\n"),
+            now_ms(),
+        );
+        self.write(move |db| {
+            db.execute(
+                "INSERT INTO jobs (job_id, submitter, source_hash, files, flags, submitted_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![row.0, row.1, row.2, row.3, row.4, row.5],
+            )
+        })
+        .await;
+    }
+
+    /// Records how the job ended, from its log's final status.
+    pub async fn finished(&self, status: JobStatusResponse) {
+        self.write(move |db| {
+            let result = status.result.as_ref();
+            db.execute(
+                "UPDATE jobs SET finished_at = ?2, status = ?3, exit_code = ?4, signal = ?5,
+                     compile_ms = ?6, run_ms = ?7, cache_hit = ?8, error = ?9
+                 WHERE job_id = ?1",
+                params![
+                    status.job_id,
+                    now_ms(),
+                    result.map(|r| r.status().as_str_name()),
+                    result.and_then(|r| r.exit_code),
+                    result.and_then(|r| r.signal),
+                    result.map(|r| r.compile_ms as i64),
+                    result.map(|r| r.run_ms as i64),
+                    result.map(|r| r.cache_hit),
+                    (!status.error.is_empty()).then_some(status.error.as_str()),
+                ],
+            )
+        })
+        .await;
+    }
+
+    /// Newest first, optionally only one submitter's.
+    pub async fn list(&self, submitter: String, limit: u32) -> rusqlite::Result<Vec<JobRecord>> {
+        let limit = match limit {
+            0 => DEFAULT_LIMIT,
+            n => n.min(MAX_LIMIT),
+        };
+        self.read(move |db| {
+            let mut query = db.prepare(&format!(
+                "SELECT {} FROM jobs WHERE ?1 = '' OR submitter = ?1
+                 ORDER BY submitted_at DESC LIMIT ?2",
+                COLUMNS
+            ))?;
+            let rows = query.query_map(params![submitter, limit], record)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn get(&self, job_id: String) -> rusqlite::Result<Option<JobRecord>> {
+        self.read(move |db| {
+            db.query_row(
+                &format!("SELECT {} FROM jobs WHERE job_id = ?1", COLUMNS),
+                params![job_id],
+                record,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// History is best effort: a failed write is logged, never fatal to the job.
+    async fn write(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<usize> + Send + 'static) {
+        if let Err(e) = self.read(f).await {
+            warn!("⚠️ Could not update job history: {}", e);
+        }
+    }
+
+    async fn read<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> rusqlite::Result<T> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db.lock().unwrap()))
+            .await
+            .expect("history queries don't panic")
+    }
+}
+
+/// Identical sources hash alike regardless of flags or compiler.
+fn source_hash(files: &[SourceFile]) -> String {
+    CompileCache::key(files, &[], "")
+}
+
+fn record(row: &Row) -> rusqlite::Result<JobRecord> {
+    let split = |text: String| -> Vec<String> {
+        match text.as_str() {
+            "" => Vec::new(),
+            _ => text.split('\n').map(str::to_string).collect(),
+        }
+    };
+    let job_id: String = row.get("job_id")?;
+    let status: Option<String> = row.get("status")?;
+    let result = match status {
+        Some(status) => {
+            let mut result = JobResult {
+                job_id: job_id.clone(),
+                exit_code: row.get("exit_code")?,
+                signal: row.get("signal")?,
+                compile_ms: row.get::<_, Option<i64>>("compile_ms")?.unwrap_or(0) as u64,
+                run_ms: row.get::<_, Option<i64>>("run_ms")?.unwrap_or(0) as u64,
+                cache_hit: row.get::<_, Option<bool>>("cache_hit")?.unwrap_or(false),
+                ..Default::default()
+            };
+            result.set_status(JobStatus::from_str_name(&status).unwrap_or(JobStatus::Unspecified));
+            Some(result)
+        }
+        None => None,
+    };
+
+    Ok(JobRecord {
+        job_id,
+        submitter: row.get("submitter")?,
+        source_hash: row.get("source_hash")?,
+        files: split(row.get("files")?),
+        compiler_flags: split(row.get("flags")?),
+        submitted_at_ms: row.get("submitted_at")?,
+        finished_at_ms: row.get::<_, Option<i64>>("finished_at")?.unwrap_or(0),
+        result,
+        error: row.get::<_, Option<String>>("error")?.unwrap_or_default(),
+    })
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,1246 @@
+/// The per-job pipeline: workspace setup, compilation, and execution.
+use crate::arch;
+use crate::artifacts;
+use crate::bench;
+use crate::buildsystem;
+use crate::cache::CompileCache;
+use crate::context::HostContext;
+use crate::debug;
+use crate::devices::DeviceRequest;
+use crate::diagnostics;
+use crate::gpumem::{self, GpuMemoryEnforcement};
+use crate::output::{OutputBudget, OverflowAction, Verdict};
+use crate::process;
+use crate::profiler;
+use crate::ptxas::{self, ResourceUsage};
+use crate::queue::{Permit, Ticket};
+use crate::registry::JobControl;
+use crate::sandbox::SandboxKind;
+use crate::sanitizer::{self, Findings, Report};
+use crate::stream::JobStream;
+use crate::telemetry;
+use common::chunking;
+use common::compute::{Accelerator, BuildSystem, ComputeRequest, Emit, JobPhase, JobResult, JobStatus, OutputKind, Profiler, Sanitizer, SourceFile};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::fs;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::AbortOnDropHandle;
+use tonic::Status;
+use tracing::{Instrument, info, info_span, warn};
+
+/// How a streamed child process ended.
+enum RunOutcome {
+    Exited(ExitStatus),
+    Cancelled,
+    TimedOut,
+    /// Killed for going over its output limit.
+    OutputLimit,
+    /// Killed for using this many MiB of a GPU, over its budget.
+    GpuMemory(u64),
+}
+
+pub async fn run_job(
+    req: ComputeRequest,
+    stream: JobStream,
+    working_dir: PathBuf,
+    control: JobControl,
+    ticket: Ticket,
+    ctx: HostContext,
+) {
+    let JobControl { cancel, stdin, debug_port } = control;
+    let compiler = match ctx.backends.select(req.accelerator()) {
+        Ok(compiler) => compiler,
+        Err(e) => {
+            stream.fail(e).await;
+            return;
+        }
+    };
+    // CPU jobs leave the GPUs to everyone else
+    let uses_gpu = compiler.accelerator() != Accelerator::Cpu;
+    let gpu_memory = if uses_gpu { req.gpu_memory_mib } else { 0 };
+    let device_request = match (req.mig.as_str(), req.device_index) {
+        ("", _) if req.shared => Ok(DeviceRequest::Shared { memory_mib: gpu_memory }),
+        ("", Some(i)) => Ok(DeviceRequest::Index(i)),
+        ("", None) => Ok(DeviceRequest::Count(req.gpu_count.max(1) as usize)),
+        (name, _) => ctx.devices.find_slice(name).map(|slice| DeviceRequest::Slice {
+            slice,
+            min_memory_mib: gpu_memory,
+        }),
+    };
+    let device_request = match device_request.and_then(|request| ctx.devices.validate(request).map(|_| request)) {
+        Ok(request) => request,
+        Err(e) if uses_gpu => {
+            stream.fail(e).await;
+            return;
+        }
+        Err(_) => DeviceRequest::Count(1),
+    };
+    // A slice's memory is checked when it's picked
+    let whole_gpus = !matches!(device_request, DeviceRequest::Slice { .. });
+    if gpu_memory > 0
+        && whole_gpus
+        && let Err(e) = gpumem::check_capacity(ctx.devices.devices(), gpu_memory).await
+    {
+        stream.fail(e).await;
+        return;
+    }
+    let artifact_patterns = match artifacts::patterns(&req.artifact_patterns) {
+        Ok(patterns) => patterns,
+        Err(e) => {
+            stream.fail(e).await;
+            return;
+        }
+    };
+    let toolchain = match req.toolchain.as_str() {
+        "" => None,
+        _ if !req.container_image.is_empty() => {
+            stream.fail("A toolchain can't be combined with a container image").await;
+            return;
+        }
+        name => match ctx.toolchains.get(name) {
+            Ok(toolchain) => Some(toolchain),
+            Err(e) => {
+                stream.fail(e).await;
+                return;
+            }
+        },
+    };
+    let sandbox = if req.container_image.is_empty() {
+        ctx.sandbox.clone()
+    } else {
+        match ctx.sandbox.for_image(&req.container_image) {
+            Ok(sandbox) => sandbox,
+            Err(e) => {
+                stream.fail(e).await;
+                return;
+            }
+        }
+    };
+
+    // The first message carries the job id, so the client can cancel at any point
+    stream.send(format!("📋 Job {} accepted.", stream.job_id()), false).await;
+
+    fail_point("queue");
+    // 0. Wait for a free slot; the permit is held until the job ends
+    let Some(_permit) = wait_in_queue(ticket, &stream, &cancel).instrument(info_span!("queue")).await else {
+        let mut result = JobResult::default();
+        result.set_status(JobStatus::Cancelled);
+        stream.finish("🛑 Job cancelled while queued.", result).await;
+        return;
+    };
+    stream.set_phase(JobPhase::Compiling).await;
+
+    // 1. Create temporary workspace
+    if let Err(e) = fs::create_dir_all(&working_dir).await {
+        stream.fail(format!("Failed to create workspace: {}", e)).await;
+        return;
+    }
+    // The compiler and the binary run with the workspace as cwd, so every path we hand
+    // them must be absolute.
+    let working_dir = fs::canonicalize(&working_dir).await.unwrap_or(working_dir);
+
+    // Platform agnostic binary extension, or the device code format asked for
+    // (for other build systems, the program is found after the build)
+    let prebuilt = relative_path(&req.prebuilt_binary);
+    let mut bin_name = match &prebuilt {
+        Some(rel) => rel.to_string_lossy().into_owned(),
+        None => output_name(&req.compiler_flags, req.emit()).to_string(),
+    };
+    let mut bin_path = working_dir.join(&bin_name);
+
+    // 2. Recreate the project tree in the workspace
+    let files = project_files(&req);
+    let mut units = Vec::new();
+    for file in &files {
+        let Some(rel) = relative_path(&file.path) else {
+            stream.fail(format!("Invalid file path: {:?}", file.path)).await;
+            return;
+        };
+        let dest = working_dir.join(&rel);
+        if let Some(parent) = dest.parent()
+            && let Err(e) = fs::create_dir_all(parent).await
+        {
+            stream.fail(format!("Failed to create {}: {}", rel.display(), e)).await;
+            return;
+        }
+        if let Err(e) = fs::write(&dest, &file.contents).await {
+            stream.fail(format!("Failed to write {}: {}", rel.display(), e)).await;
+            return;
+        }
+        if compiler.compiles(&rel) {
+            units.push(rel);
+        }
+    }
+
+    let build_system = req.build_system();
+    if prebuilt.is_some() {
+        // Nothing to build
+    } else if let Some(manifest) = buildsystem::manifests(build_system).last() {
+        if !buildsystem::manifests(build_system).iter().any(|m| working_dir.join(m).is_file()) {
+            let message = format!("A {} build needs a {} at the project root", buildsystem::tool(build_system), manifest);
+            stream.fail(message).await;
+            return;
+        }
+    } else if units.is_empty() {
+        let extensions: Vec<String> = compiler.extensions().iter().map(|e| format!(".{}", e)).collect();
+        let message = format!("No {} source files for {} to compile", extensions.join("/"), compiler.name());
+        stream.fail(message).await;
+        return;
+    }
+
+    fail_point("compile");
+    // 3. Compile with nvcc, hipcc or the project's build system (unless an
+    // identical single-command build is cached), forwarding diagnostics as
+    // they appear
+    let mut result = JobResult::default();
+    let mut flags = req.compiler_flags.clone();
+    if prebuilt.is_none()
+        && compiler.accelerator() == Accelerator::Cuda
+        && !ctx.arch_flags.is_empty()
+        && !arch::specified(&flags)
+    {
+        flags.extend(ctx.arch_flags.iter().cloned());
+        stream
+            .send(format!("🎯 Targeting {}", ctx.arch_flags.join(" ")), false)
+            .await;
+    }
+    match ctx.libraries.flags(&req.libraries, compiler.as_ref()) {
+        Ok(library_flags) => flags.extend(library_flags),
+        Err(e) => {
+            stream.fail(e).await;
+            return;
+        }
+    }
+    if req.debug {
+        debug::flags(&mut flags);
+    }
+    if let Some(flag) = emit_flag(req.emit())
+        && !flags.iter().any(|f| f == flag)
+    {
+        flags.push(flag.into());
+    }
+    // Emitting device code leaves nothing to run
+    let compile_only = req.compile_only || req.emit() != Emit::Executable;
+    let compiler_version = match (sandbox.toolchain_image(), toolchain) {
+        (Some(image), _) => format!("image {}", image),
+        (None, Some(toolchain)) => toolchain.version.clone(),
+        (None, None) => compiler.version().to_string(),
+    };
+    let cache_key = (!req.no_cache && prebuilt.is_none() && build_system == BuildSystem::Nvcc)
+        .then(|| CompileCache::key(&files, &flags, &compiler_version));
+
+    let compiler_path = toolchain.map_or_else(|| compiler.path().to_path_buf(), |t| t.nvcc());
+    if prebuilt.is_none() && build_system == BuildSystem::Nvcc {
+        // Inside a job's image, the image's own compiler of that name
+        let program = match sandbox.toolchain_image() {
+            Some(_) => compiler.name().to_string(),
+            None => compiler_path.to_string_lossy().into_owned(),
+        };
+        let args = compiler.args(&units, &flags, Path::new(&bin_name));
+        result.build_command = std::iter::once(program)
+            .chain(args.iter().map(|arg| arg.to_string_lossy().into_owned()))
+            .collect();
+    }
+
+    // ptxas only reports on a real compile
+    let report_usage = ptxas::requested(&flags);
+    if prebuilt.is_some() {
+        if let Err(e) = make_executable(&bin_path).await {
+            result.set_status(JobStatus::Error);
+            stream.finish(format!("❌ Can't run {}: {}", bin_name, e), result).await;
+            return;
+        }
+        info!("📦 Running a prebuilt binary");
+        stream.send(format!("📦 Running prebuilt {}...", bin_name), false).await;
+    } else if let Some(key) = &cache_key
+        && !report_usage
+        && ctx.cache.restore(key, &bin_path).await
+    {
+        result.cache_hit = true;
+        info!("♻️ Compile skipped, binary restored from cache");
+        let next = if compile_only { "" } else { " Running..." };
+        stream.send(format!("♻️ Reusing cached binary.{}", next), false).await;
+    } else {
+        let (tool, steps) = match build_system {
+            BuildSystem::Nvcc => (compiler_path.clone(), vec![compiler.args(&units, &flags, &bin_path)]),
+            BuildSystem::Cmake => {
+                let nvcc = sandbox.toolchain_image().is_none().then_some(compiler_path.as_path());
+                (ctx.cmake.to_path_buf(), buildsystem::cmake_steps(nvcc, &flags, &req.build_args))
+            }
+            BuildSystem::Make => {
+                let nvcc = sandbox.toolchain_image().is_none().then_some(compiler_path.as_path());
+                (ctx.make.to_path_buf(), vec![buildsystem::make_args(nvcc, &flags, &req.build_args)])
+            }
+        };
+        let tool_name = match build_system {
+            BuildSystem::Nvcc => compiler.name(),
+            _ => buildsystem::tool(build_system),
+        };
+
+        let compile_start = Instant::now();
+        info!(flags = %flags.join(" "), tool = tool_name, "🔨 Compile started");
+        let mut usage = ResourceUsage::default();
+        let mut compiled = Ok(RunOutcome::Exited(ExitStatus::default()));
+        for args in steps {
+            let mut step = sandbox.compiler(stream.job_id(), &working_dir, &tool);
+            step.args(args);
+            if let Some(toolchain) = toolchain {
+                toolchain.apply(&mut step);
+            }
+            let parse = Parse::Compiler(&working_dir, &mut usage);
+            compiled = run_streaming(step, &stream, &cancel, ctx.compile_timeout, None, parse, None)
+                .instrument(info_span!("compile"))
+                .await;
+            if !matches!(&compiled, Ok(RunOutcome::Exited(s)) if s.success()) {
+                break;
+            }
+        }
+        result.compile_ms = elapsed_ms(compile_start);
+        result.kernels = usage.into_kernels();
+        let ok = matches!(&compiled, Ok(RunOutcome::Exited(s)) if s.success());
+        info!(compile_ms = result.compile_ms, ok, "🔨 Compile finished");
+        if !matches!(compiled, Ok(RunOutcome::Exited(_))) {
+            sandbox.release(stream.job_id()).await;
+        }
+
+        match compiled {
+            Ok(RunOutcome::Exited(s)) if s.success() => {}
+            Ok(RunOutcome::Cancelled) => {
+                result.set_status(JobStatus::Cancelled);
+                result.killed = true;
+                stream.finish("🛑 Job cancelled.", result).await;
+                return;
+            }
+            Ok(RunOutcome::TimedOut) => {
+                result.set_status(JobStatus::CompileFailed);
+                result.killed = true;
+                let secs = ctx.compile_timeout.unwrap_or_default().as_secs();
+                let message = format!("⏰ Compilation timed out after {}s; {} killed.", secs, tool_name);
+                stream.finish(message, result).await;
+                return;
+            }
+            Ok(RunOutcome::Exited(_) | RunOutcome::OutputLimit | RunOutcome::GpuMemory(_)) => {
+                result.set_status(JobStatus::CompileFailed);
+                stream.finish("❌ Compilation failed.", result).await;
+                return;
+            }
+            Err(e) => {
+                result.set_status(JobStatus::Error);
+                stream.finish(format!("❌ Failed to launch {}: {}", tool_name, e), result).await;
+                return;
+            }
+        }
+
+        if build_system != BuildSystem::Nvcc && !compile_only {
+            let Some(program) = buildsystem::program(build_system, &working_dir, &req.run_target) else {
+                result.set_status(JobStatus::CompileFailed);
+                let message = format!("❌ The build produced no program {:?}.", req.run_target);
+                stream.finish(message, result).await;
+                return;
+            };
+            bin_name = program.strip_prefix(&working_dir).unwrap_or(&program).to_string_lossy().into_owned();
+            bin_path = program;
+        }
+
+        if let Some(key) = &cache_key
+            && let Err(e) = ctx.cache.store(key, &bin_path).await
+        {
+            warn!("⚠️ Could not cache binary: {}", e);
+        }
+        let next = if compile_only { "" } else { " Running..." };
+        stream.send(format!("🚀 Compilation successful.{}", next), false).await;
+    }
+
+    if req.emit() == Emit::Ptx {
+        match fs::read(&bin_path).await {
+            Ok(ptx) => {
+                for line in ptx.split_inclusive(|b| *b == b'\n') {
+                    stream.send_output(line.to_vec(), OutputKind::Stdout, None).await;
+                }
+            }
+            Err(e) => stream.send(format!("⚠️ Could not read {}: {}", bin_name, e), true).await,
+        }
+    }
+    // A debugger needs the binary for its symbols
+    if req.return_binary || req.debug || req.emit() == Emit::Cubin {
+        match fs::read(&bin_path).await {
+            Ok(data) => stream.send_artifact(&bin_name, &data).await,
+            Err(e) => stream.send(format!("⚠️ Could not read {}: {}", bin_name, e), true).await,
+        }
+    }
+    if req.emit() != Emit::Executable {
+        result.set_status(JobStatus::Succeeded);
+        stream.finish(format!("🧪 Emitted {}; nothing to run.", bin_name), result).await;
+        return;
+    }
+    if compile_only {
+        result.set_status(JobStatus::Succeeded);
+        stream.finish("🧪 Compile-only job: skipping execution.", result).await;
+        return;
+    }
+
+    // 4. Claim GPUs, if the job runs on any, then execute the binary under
+    // the effective timeout
+    let lease = if !uses_gpu {
+        None
+    } else if let Some(lease) = ctx.devices.try_acquire(device_request) {
+        Some(lease)
+    } else {
+        stream.send("⏳ Waiting for a free GPU...", false).await;
+        tokio::select! {
+            lease = ctx.devices.acquire(device_request) => Some(lease),
+            _ = cancel.cancelled() => {
+                result.set_status(JobStatus::Cancelled);
+                stream.finish("🛑 Job cancelled while waiting for a GPU.", result).await;
+                return;
+            }
+        }
+    };
+    if let Some(lease) = &lease
+        && gpu_memory > 0
+        && lease.slice().is_none()
+        && let Err(e) = gpumem::check_free(&lease.devices, gpu_memory).await
+    {
+        result.set_status(JobStatus::Error);
+        stream.finish(format!("💾 {}; not starting the program.", e), result).await;
+        return;
+    }
+    // Empty for a CPU job, which hides every GPU from it
+    let visible_devices = lease.as_ref().map_or_else(String::new, |lease| lease.visible_devices());
+    result.devices = lease.as_ref().map(|lease| lease.devices.clone()).unwrap_or_default();
+    stream.set_phase(JobPhase::Running).await;
+    let placed = match &lease {
+        None => "🖥 Running on the CPU".to_string(),
+        Some(lease) => match (lease.slice(), lease.thread_percentage()) {
+            (Some(slice), _) => format!("🎮 Running on MIG slice {}/{} ({})", slice.gpu, slice.device, slice.profile),
+            (None, Some(_)) => format!("🎮 Running on GPU {}, shared through MPS", visible_devices),
+            (None, None) => format!("🎮 Running on GPU {}", visible_devices),
+        },
+    };
+    stream.send(placed, false).await;
+    let thread_percentage = lease.as_ref().and_then(|lease| lease.thread_percentage());
+
+    let timeout = match ctx.max_debug {
+        Some(limit) if req.debug => limit,
+        _ => effective_timeout(req.timeout_secs, ctx.max_timeout),
+    };
+    let gdbserver_port = match req.debug.then(debug::free_port).transpose() {
+        Ok(port) => port,
+        Err(e) => {
+            result.set_status(JobStatus::Error);
+            stream.finish(format!("❌ Found no free port for cuda-gdbserver: {}", e), result).await;
+            return;
+        }
+    };
+    let cuda_gdbserver = toolchain.map_or_else(|| ctx.cuda_gdbserver.to_path_buf(), |t| t.cuda_gdbserver());
+    let benchmark = req.benchmark.filter(|b| b.iterations > 0);
+    let (warmup, runs) = benchmark.map_or((0, 1), |b| (b.warmup, b.warmup + b.iterations));
+    let compute_sanitizer = toolchain.map_or_else(|| ctx.compute_sanitizer.to_path_buf(), |t| t.compute_sanitizer());
+    let program = || {
+        let (path, args) = ctx
+            .profilers
+            .wrap(req.profiler(), &bin_path, &req.run_args)
+            .or_else(|| sanitizer::wrap(&compute_sanitizer, req.sanitizer(), &bin_path, &req.run_args))
+            .or_else(|| gdbserver_port.map(|port| debug::wrap(&cuda_gdbserver, port, &bin_path, &req.run_args)))
+            .unwrap_or_else(|| (bin_path.clone(), req.run_args.iter().map(Into::into).collect()));
+        let mut program = sandbox.command(stream.job_id(), &working_dir, &path, &args, &visible_devices);
+        if let Some(toolchain) = toolchain {
+            toolchain.apply(&mut program);
+        }
+        // A shared GPU always holds its jobs to their budgets
+        if gpu_memory > 0 && (ctx.gpu_memory_enforcement == GpuMemoryEnforcement::Mps || thread_percentage.is_some()) {
+            let limit = gpumem::mps_limit(result.devices.len(), gpu_memory);
+            program.env("CUDA_MPS_PINNED_DEVICE_MEM_LIMIT", limit);
+        }
+        if let Some(percentage) = thread_percentage {
+            program.env("CUDA_MPS_ACTIVE_THREAD_PERCENTAGE", percentage.to_string());
+        }
+        program
+    };
+
+    if let Some(port) = gdbserver_port {
+        let _ = debug_port.set(port);
+        stream.send_debug_session(&bin_name, timeout).await;
+    }
+
+    fail_point("run");
+    let run_start = Instant::now();
+    info!(gpus = %visible_devices, timeout_secs = timeout.as_secs(), runs, "🚀 Run started");
+    let mut stdin = req.forward_stdin.then_some(stdin);
+    let mut budget = OutputBudget::new(ctx.output_limits);
+    let mut timed = Vec::new();
+    let mut findings = Findings::new(req.sanitizer());
+    let mut run = 0;
+    // The watch kills the program by cancelling its run, not the job
+    let run_cancel = cancel.child_token();
+    let watch = (gpu_memory > 0 && ctx.gpu_memory_enforcement == GpuMemoryEnforcement::Watch)
+        .then(|| gpumem::Watch::spawn(&working_dir, gpu_memory, run_cancel.clone()));
+    let sampled = if req.telemetry { result.devices.clone() } else { Vec::new() };
+    let ran = telemetry::alongside(&stream, &sampled, async {
+        // A benchmark repeats the program until a run fails; only the first one's output is forwarded
+        loop {
+            let mut silent = OutputBudget::silent();
+            let output = if run == 0 { &mut budget } else { &mut silent };
+            let remaining = timeout.saturating_sub(run_start.elapsed());
+            let parse = match req.sanitizer() {
+                Sanitizer::None => Parse::Nothing,
+                _ => Parse::Sanitizer(&mut findings),
+            };
+            let started = Instant::now();
+            let ran = run_streaming(program(), &stream, &run_cancel, Some(remaining), stdin.take(), parse, Some(output))
+                .instrument(info_span!("run", run))
+                .await;
+            let took = started.elapsed();
+            run += 1;
+            let ok = matches!(&ran, Ok(RunOutcome::Exited(s)) if s.success());
+            if ok && run > warmup && benchmark.is_some() {
+                timed.push(took);
+                let message = format!("⏱️ Run {}/{}: {:.3} ms", timed.len(), runs - warmup, took.as_secs_f64() * 1000.0);
+                stream.send(message, false).await;
+            }
+            if !ok || run == runs {
+                break ran;
+            }
+        }
+    })
+    .await;
+    let ran = match (ran, watch.and_then(|watch| watch.exceeded())) {
+        (Ok(RunOutcome::Cancelled), Some(used)) => Ok(RunOutcome::GpuMemory(used)),
+        (ran, _) => ran,
+    };
+    result.run_ms = elapsed_ms(run_start);
+    info!(run_ms = result.run_ms, "🚀 Run finished");
+    result.truncated = budget.truncated();
+    result.sanitizer_errors = findings.errors();
+    if benchmark.is_some() && timed.len() as u32 == runs - warmup {
+        let stats = bench::stats(warmup, &timed);
+        stream.send(bench::summary(&stats), false).await;
+        result.benchmark = Some(stats);
+    }
+    if !matches!(ran, Ok(RunOutcome::Exited(_))) {
+        sandbox.release(stream.job_id()).await;
+    }
+    if let Some(report) = profiler::report_name(req.profiler()) {
+        match fs::read(working_dir.join(report)).await {
+            Ok(data) => stream.send_artifact(report, &data).await,
+            Err(_) => stream.send(format!("⚠️ The profiler wrote no {}", report), true).await,
+        }
+    }
+    // Whatever the program managed to write is returned, even if it failed
+    if !artifact_patterns.is_empty() {
+        artifacts::send_matching(&stream, &working_dir, &artifact_patterns).await;
+    }
+
+    fail_point("report");
+    match ran {
+        Ok(RunOutcome::Exited(status)) => {
+            record_exit(&mut result, status);
+            if let Some(limit) = result.signal.and_then(|signal| sandbox.limit_hit(stream.job_id(), signal)) {
+                stream.send(format!("🚧 The program was killed for reaching {}.", limit), true).await;
+            }
+            result.set_status(if status.success() {
+                JobStatus::Succeeded
+            } else {
+                JobStatus::Failed
+            });
+            stream.finish("🏁 Program exited.", result).await
+        }
+        Ok(RunOutcome::Cancelled) => {
+            result.set_status(JobStatus::Cancelled);
+            result.killed = true;
+            stream.finish("🛑 Job cancelled.", result).await
+        }
+        Ok(RunOutcome::TimedOut) => {
+            result.set_status(JobStatus::TimedOut);
+            result.killed = true;
+            let message = format!("⏰ Timed out after {}s; process killed.", timeout.as_secs());
+            stream.finish(message, result).await
+        }
+        Ok(RunOutcome::OutputLimit) => {
+            result.set_status(JobStatus::Failed);
+            result.killed = true;
+            stream.finish("✂️ Output limit exceeded; process killed.", result).await
+        }
+        Ok(RunOutcome::GpuMemory(used)) => {
+            result.set_status(JobStatus::Failed);
+            result.killed = true;
+            let message = format!(
+                "💾 The program used {} MiB of GPU memory, over its {} MiB budget; process killed.",
+                used, gpu_memory
+            );
+            stream.finish(message, result).await
+        }
+        Err(e) => {
+            result.set_status(JobStatus::Error);
+            stream.finish(format!("❌ Failed to launch binary: {}", e), result).await;
+        }
+    }
+}
+
+#[cfg(test)]
+tokio::task_local! {
+    /// The stage a test wants the job body to panic at.
+    static FAIL_AT: &'static str;
+}
+
+/// Panics if a test asked for a failure at `_stage`; a no-op otherwise.
+fn fail_point(_stage: &str) {
+    #[cfg(test)]
+    if FAIL_AT.try_with(|at| *at == _stage).unwrap_or(false) {
+        panic!("injected failure at {}", _stage);
+    }
+}
+
+/// Streams queue position changes until the job is admitted. Returns `None`
+/// if the job is cancelled first (dropping the ticket leaves the queue).
+async fn wait_in_queue(
+    mut ticket: Ticket,
+    stream: &JobStream,
+    cancel: &CancellationToken,
+) -> Option<Permit> {
+    let mut place = ticket.place.clone();
+    let mut queued = false;
+    let mut last_reported = 0;
+    let mut overtaken_by = 0;
+
+    loop {
+        let current = *place.borrow_and_update();
+        if current.position > 0 && current.position != usize::MAX && current.position != last_reported {
+            queued = true;
+            last_reported = current.position;
+            stream.set_queue_position(current.position);
+            if current.overtaken_by > overtaken_by {
+                let message = format!(
+                    "⏬ Moved back to position {}: a higher-priority job was queued ahead of this one.",
+                    current.position
+                );
+                stream.send(message, false).await;
+            } else {
+                stream.send(format!("⏳ Queued at position {}.", current.position), false).await;
+            }
+        }
+        overtaken_by = current.overtaken_by;
+
+        tokio::select! {
+            permit = ticket.admitted() => {
+                if queued {
+                    stream.send("▶️ Slot available, starting job.", false).await;
+                }
+                return Some(permit);
+            }
+            changed = place.changed() => {
+                if changed.is_err() {
+                    // Sender gone means we were admitted; the grant is ready.
+                    return Some(ticket.admitted().await);
+                }
+            }
+            _ = cancel.cancelled() => return None,
+        }
+    }
+}
+
+fn elapsed_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+fn record_exit(result: &mut JobResult, status: ExitStatus) {
+    result.exit_code = status.code();
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        result.signal = status.signal();
+    }
+}
+
+/// Spawns `cmd` with piped stdio and forwards each line to the client as soon as
+/// it is produced, stdout as regular output and stderr as errors. Cancelling
+/// `cancel` or passing `timeout` kills the child's whole process group. With
+/// `stdin`, chunks from the client are written to the child's stdin, which is
+/// closed once the client signals EOF; otherwise stdin is /dev/null. Lines
+/// are looked at as `parse` says. With `budget`, output beyond the job's
+/// limits is muted or gets the child killed.
+async fn run_streaming(
+    mut cmd: Command,
+    stream: &JobStream,
+    cancel: &CancellationToken,
+    timeout: Option<Duration>,
+    stdin: Option<mpsc::Receiver<Vec<u8>>>,
+    mut parse: Parse<'_>,
+    mut budget: Option<&mut OutputBudget>,
+) -> io::Result<RunOutcome> {
+    // Without a timeout the deadline is simply never selected.
+    let deadline = Instant::now() + timeout.unwrap_or_default();
+
+    process::isolate(&mut cmd);
+    let mut child = cmd
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Dropped (and thereby aborted) whenever we return
+    let _stdin_pump = stdin.map(|rx| {
+        let pipe = child.stdin.take().expect("stdin is piped");
+        AbortOnDropHandle::new(tokio::spawn(pump_stdin(rx, pipe)))
+    });
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let (mut out, mut err) = (BufReader::new(stdout), BufReader::new(stderr));
+    // Partial lines survive a select! that another branch wins
+    let (mut out_line, mut err_line) = (Vec::new(), Vec::new());
+    let (mut out_done, mut err_done) = (false, false);
+
+    // Both pipes are drained from this one task so the JobStream keeps a
+    // single owner (see the ordering contract in stream.rs).
+    while !(out_done && err_done) {
+        let (line, kind) = tokio::select! {
+            read = chunking::read_line_chunk(&mut out, &mut out_line), if !out_done => match read {
+                Ok(n) if n > 0 => (std::mem::take(&mut out_line), OutputKind::Stdout),
+                _ => {
+                    out_done = true;
+                    continue;
+                }
+            },
+            read = chunking::read_line_chunk(&mut err, &mut err_line), if !err_done => match read {
+                Ok(n) if n > 0 => (std::mem::take(&mut err_line), OutputKind::Stderr),
+                _ => {
+                    err_done = true;
+                    continue;
+                }
+            },
+            _ = cancel.cancelled() => {
+                process::kill_tree(&mut child);
+                child.wait().await?;
+                return Ok(RunOutcome::Cancelled);
+            }
+            _ = tokio::time::sleep_until(deadline), if timeout.is_some() => {
+                process::kill_tree(&mut child);
+                child.wait().await?;
+                return Ok(RunOutcome::TimedOut);
+            }
+        };
+
+        let verdict = match budget.as_deref_mut() {
+            Some(budget) => budget.admit(kind == OutputKind::Stderr, line.len()),
+            None => Verdict::Forward,
+        };
+        match verdict {
+            Verdict::Forward => match &mut parse {
+                Parse::Nothing => stream.send_output(line, kind, None).await,
+                Parse::Compiler(workspace, usage) => {
+                    let text = String::from_utf8_lossy(&line);
+                    let text = text.trim_end_matches(['\r', '\n']);
+                    usage.push(text);
+                    let diagnostic = diagnostics::parse(text, workspace);
+                    stream.send_output(line, kind, diagnostic).await;
+                }
+                Parse::Sanitizer(findings) => {
+                    for report in findings.push(line, kind) {
+                        send_report(stream, report).await;
+                    }
+                }
+            },
+            Verdict::Drop => {}
+            Verdict::Truncate { limit, action } => {
+                let pipe = if kind == OutputKind::Stderr { "stderr" } else { "stdout" };
+                stream.send(format!("✂️ {} truncated after {} bytes", pipe, limit), true).await;
+                if action == OverflowAction::Kill {
+                    process::kill_tree(&mut child);
+                    child.wait().await?;
+                    return Ok(RunOutcome::OutputLimit);
+                }
+            }
+        }
+    }
+
+    if let Parse::Sanitizer(findings) = &mut parse
+        && let Some(report) = findings.finish()
+    {
+        send_report(stream, report).await;
+    }
+
+    // The pipes can close before the process exits, so keep watching for cancellation.
+    tokio::select! {
+        status = child.wait() => Ok(RunOutcome::Exited(status?)),
+        _ = cancel.cancelled() => {
+            process::kill_tree(&mut child);
+            child.wait().await?;
+            Ok(RunOutcome::Cancelled)
+        }
+        _ = tokio::time::sleep_until(deadline), if timeout.is_some() => {
+            process::kill_tree(&mut child);
+            child.wait().await?;
+            Ok(RunOutcome::TimedOut)
+        }
+    }
+}
+
+/// What `run_streaming` makes of the lines it forwards.
+enum Parse<'a> {
+    /// Nothing; they're sent as they are.
+    Nothing,
+    /// nvcc's output: diagnostics, with paths made relative to this
+    /// directory, and ptxas's resource usage report.
+    Compiler(&'a Path, &'a mut ResourceUsage),
+    /// compute-sanitizer reports, each sent as one message.
+    Sanitizer(&'a mut Findings),
+}
+
+async fn send_report(stream: &JobStream, report: Report) {
+    match report {
+        Report::Line(line, kind) => stream.send_output(line, kind, None).await,
+        Report::Finding(lines, kind, finding) => stream.send_finding(lines, kind, finding).await,
+    }
+}
+
+/// The requested timeout clamped to the host maximum (0 = use the maximum).
+pub fn effective_timeout(requested_secs: u32, max: Duration) -> Duration {
+    match requested_secs {
+        0 => max,
+        secs => Duration::from_secs(secs.into()).min(max),
+    }
+}
+
+async fn pump_stdin(mut rx: mpsc::Receiver<Vec<u8>>, mut pipe: ChildStdin) {
+    while let Some(data) = rx.recv().await {
+        if pipe.write_all(&data).await.is_err() {
+            // The program closed its stdin; nothing more to deliver
+            return;
+        }
+    }
+    // Dropping `pipe` here is what delivers EOF to the program
+}
+
+/// The submitted files, treating a legacy single-file request as a one-file
+/// project; none for a prebuilt binary.
+pub fn project_files(req: &ComputeRequest) -> Vec<SourceFile> {
+    if !req.prebuilt_binary.is_empty() && req.files.is_empty() && req.source_code.is_empty() {
+        Vec::new()
+    } else if req.files.is_empty() {
+        vec![SourceFile {
+            path: req.file_name.clone(),
+            contents: req.source_code.clone(),
+        }]
+    } else {
+        req.files.clone()
+    }
+}
+
+/// Rejects a request before it is queued: every file must have a path inside
+/// the workspace, used once, the sources must fit the host's size limit, a
+/// prebuilt binary must come without anything to build, device code can only
+/// be emitted by nvcc from a job that runs nothing, any benchmark, profiler,
+/// sanitizer or debug session must be runnable, the backend and libraries
+/// must be ones it has, ROCm and CPU jobs must stick to what their compiler
+/// on the host can do, and the flags must suit the compiler and pass the
+/// host's policy.
+#[allow(clippy::result_large_err)]
+pub fn validate(req: &ComputeRequest, ctx: &HostContext) -> Result<(), Status> {
+    let files = project_files(req);
+    let mut seen = HashSet::new();
+    let mut total = 0u64;
+    for file in &files {
+        let Some(rel) = relative_path(&file.path) else {
+            return Err(Status::invalid_argument(format!(
+                "Invalid file path {:?}: must be relative, without \"..\"",
+                file.path
+            )));
+        };
+        if !seen.insert(rel) {
+            return Err(Status::invalid_argument(format!("{} is sent more than once", file.path)));
+        }
+        total += file.contents.len() as u64;
+    }
+    let compiler = ctx.backends.select(req.accelerator()).map_err(Status::invalid_argument)?;
+    if compiler.accelerator() != Accelerator::Cuda
+        && (req.build_system() != BuildSystem::Nvcc
+            || !req.toolchain.is_empty()
+            || !req.container_image.is_empty()
+            || ctx.sandbox.kind() == SandboxKind::Docker
+            || req.emit() != Emit::Executable
+            || req.debug
+            || req.profiler() != Profiler::None
+            || req.sanitizer() != Sanitizer::None)
+    {
+        return Err(Status::invalid_argument(format!(
+            "{} jobs build with a single {} command outside containers; cmake and make projects, toolchains, emitting PTX or cubins, debugging, profiling and compute-sanitizer are CUDA-only",
+            compiler.accelerator().as_str_name(),
+            compiler.name()
+        )));
+    }
+    compiler.check_flags(&req.compiler_flags).map_err(Status::invalid_argument)?;
+    ctx.notifiers.check(req).map_err(Status::invalid_argument)?;
+    if !req.prebuilt_binary.is_empty() {
+        if relative_path(&req.prebuilt_binary).is_none() {
+            return Err(Status::invalid_argument(format!(
+                "Invalid prebuilt_binary {:?}: must be relative, without \"..\"",
+                req.prebuilt_binary
+            )));
+        }
+        if !files.is_empty()
+            || !req.compiler_flags.is_empty()
+            || !req.libraries.is_empty()
+            || req.build_system() != BuildSystem::Nvcc
+            || req.compile_only
+            || req.return_binary
+        {
+            return Err(Status::invalid_argument(
+                "A prebuilt binary is run as it is; it can't come with sources, compiler flags, libraries, a build system, compile_only or return_binary",
+            ));
+        }
+    }
+    if req.emit() != Emit::Executable {
+        if req.build_system() != BuildSystem::Nvcc || !req.prebuilt_binary.is_empty() {
+            return Err(Status::invalid_argument("Only nvcc builds can emit PTX or cubins"));
+        }
+        if req.debug
+            || req.profiler() != Profiler::None
+            || req.sanitizer() != Sanitizer::None
+            || req.benchmark.is_some_and(|b| b.iterations > 0)
+        {
+            return Err(Status::invalid_argument(
+                "A job emitting device code runs nothing; it can't be debugged, profiled, sanitized or benchmarked",
+            ));
+        }
+    }
+    if let Some(benchmark) = &req.benchmark {
+        bench::validate(benchmark, req.forward_stdin).map_err(Status::invalid_argument)?;
+    }
+    if req.profiler() != Profiler::None {
+        ctx.profilers.check(req.profiler()).map_err(Status::invalid_argument)?;
+        if req.benchmark.is_some_and(|b| b.iterations > 0) {
+            return Err(Status::invalid_argument("A job can't be both profiled and benchmarked"));
+        }
+        if !req.container_image.is_empty() || ctx.sandbox.kind() == SandboxKind::Docker {
+            return Err(Status::invalid_argument("Profiling isn't available for jobs in containers"));
+        }
+    }
+    if req.build_system() != BuildSystem::Nvcc
+        && req.container_image.is_empty()
+        && ctx.sandbox.kind() == SandboxKind::Docker
+    {
+        return Err(Status::invalid_argument(format!(
+            "{} projects run their own scripts while building; on this host they need a container_image",
+            buildsystem::tool(req.build_system())
+        )));
+    }
+    if req.build_system() != BuildSystem::Nvcc && !req.compile_only {
+        if req.run_target.is_empty() {
+            return Err(Status::invalid_argument(format!(
+                "A {} build needs run_target to name the program to run",
+                buildsystem::tool(req.build_system())
+            )));
+        }
+        if relative_path(&req.run_target).is_none() {
+            return Err(Status::invalid_argument(format!(
+                "Invalid run_target {:?}: must be relative, without \"..\"",
+                req.run_target
+            )));
+        }
+    }
+    if req.debug {
+        if ctx.max_debug.is_none() {
+            return Err(Status::failed_precondition("This host doesn't allow debug sessions"));
+        }
+        if req.compile_only
+            || req.profiler() != Profiler::None
+            || req.sanitizer() != Sanitizer::None
+            || req.benchmark.is_some_and(|b| b.iterations > 0)
+        {
+            return Err(Status::invalid_argument(
+                "A debug job runs the program once under cuda-gdbserver; it can't be compile-only, profiled, sanitized or benchmarked",
+            ));
+        }
+        if !req.container_image.is_empty() || ctx.sandbox.kind() == SandboxKind::Docker {
+            return Err(Status::invalid_argument("Debugging isn't available for jobs in containers"));
+        }
+    }
+    if req.sanitizer() != Sanitizer::None {
+        if req.profiler() != Profiler::None || req.benchmark.is_some_and(|b| b.iterations > 0) {
+            return Err(Status::invalid_argument(
+                "A job run under compute-sanitizer can't also be profiled or benchmarked",
+            ));
+        }
+        if !req.container_image.is_empty() || ctx.sandbox.kind() == SandboxKind::Docker {
+            return Err(Status::invalid_argument("compute-sanitizer isn't available for jobs in containers"));
+        }
+    }
+    if total > ctx.max_source_bytes {
+        return Err(Status::resource_exhausted(format!(
+            "Sources are {} bytes; this host accepts at most {}",
+            total, ctx.max_source_bytes
+        )));
+    }
+    ctx.libraries.flags(&req.libraries, compiler.as_ref()).map_err(Status::invalid_argument)?;
+    ctx.flag_policy.check(&req.compiler_flags).map_err(Status::invalid_argument)
+}
+
+/// Normalizes a plain relative path, dropping `.` components. Anything with a
+/// root, drive prefix or `..` is refused, so a project or input file can never
+/// land outside the workspace.
+pub fn relative_path(path: &str) -> Option<PathBuf> {
+    let mut rel = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => rel.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!rel.as_os_str().is_empty()).then_some(rel)
+}
+
+/// Gives an uploaded program the execute permission its upload couldn't carry.
+async fn make_executable(path: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(path).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = metadata.permissions();
+        permissions.set_mode(permissions.mode() | 0o755);
+        fs::set_permissions(path, permissions).await?;
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    Ok(())
+}
+
+/// The nvcc flag that emits `emit`, if it isn't an executable.
+fn emit_flag(emit: Emit) -> Option<&'static str> {
+    match emit {
+        Emit::Executable => None,
+        Emit::Ptx => Some("-ptx"),
+        Emit::Cubin => Some("-cubin"),
+    }
+}
+
+/// Name of nvcc's output file: the executable, unless the request or flags
+/// ask for device code only.
+fn output_name(flags: &[String], emit: Emit) -> &'static str {
+    match emit {
+        Emit::Executable => {}
+        Emit::Ptx => return "app.ptx",
+        Emit::Cubin => return "app.cubin",
+    }
+    for flag in flags {
+        match flag.as_str() {
+            "-ptx" | "--ptx" => return "app.ptx",
+            "-cubin" | "--cubin" => return "app.cubin",
+            "-fatbin" | "--fatbin" => return "app.fatbin",
+            _ => {}
+        }
+    }
+    if cfg!(windows) { "app.exe" } else { "app.out" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Backends;
+    use crate::devices::{DeviceAllocator, Sharing};
+    use crate::joblog::JobLogs;
+    use crate::libraries::Libraries;
+    use crate::limits::Limits;
+    use crate::notify::Notifiers;
+    use crate::output::OutputLimits;
+    use crate::policy::FlagPolicy;
+    use crate::profiler::ProfilerConfig;
+    use crate::queue::JobQueue;
+    use crate::registry::JobRegistry;
+    use crate::sandbox::Sandbox;
+    use crate::toolchain::Toolchains;
+    use common::compute::{ComputeResponse, Priority};
+    use std::sync::Arc;
+
+    /// A host with no GPUs, building CPU jobs with the system's c++.
+    async fn context(scratch: &Path) -> HostContext {
+        let missing = Path::new("/nonexistent");
+        HostContext {
+            devices: DeviceAllocator::new(Vec::new(), Vec::new(), Sharing::default()),
+            cache: Arc::new(CompileCache::new(scratch.join("cache"), 0)),
+            backends: Arc::new(Backends::probe(missing, missing, Path::new("c++")).await),
+            compute_sanitizer: missing.into(),
+            cuda_gdbserver: missing.into(),
+            cmake: missing.into(),
+            make: missing.into(),
+            toolchains: Arc::new(Toolchains::default()),
+            libraries: Arc::new(Libraries::default()),
+            arch_flags: Vec::new().into(),
+            sandbox: Sandbox::new(SandboxKind::None, "", Vec::new(), Vec::new(), Limits::default()),
+            output_limits: OutputLimits {
+                stdout_bytes: 0,
+                stderr_bytes: 0,
+                action: OverflowAction::Mute,
+            },
+            flag_policy: Arc::new(FlagPolicy::default()),
+            profilers: Arc::new(ProfilerConfig::default()),
+            max_source_bytes: 1 << 20,
+            max_timeout: Duration::from_secs(30),
+            max_debug: None,
+            compile_timeout: None,
+            gpu_memory_enforcement: GpuMemoryEnforcement::None,
+            notifiers: Notifiers::default(),
+        }
+    }
+
+    /// Runs a hello-world CPU job, panicking at `stage` ("" for none), and
+    /// collects everything its stream sent until the stream ended.
+    async fn run(stage: &'static str) -> Vec<Result<ComputeResponse, Status>> {
+        let scratch = std::env::temp_dir().join(format!("ferris-job-test-{}-{}", std::process::id(), stage));
+        let (tx, mut rx) = mpsc::channel(100);
+        let stream = JobStream::new(tx, "job", JobLogs::default().create("job", "tester"));
+        let control = JobRegistry::default().register("job", "tester");
+        let ticket = JobQueue::new(1).enqueue("tester", Priority::Normal);
+        let req = ComputeRequest {
+            accelerator: Accelerator::Cpu.into(),
+            file_name: "hello.cpp".into(),
+            source_code: "#include <cstdio>\nint main() { std::puts(\"hello\"); }\n".into(),
+            ..Default::default()
+        };
+        let job = run_job(req, stream, scratch.join("job"), control, ticket, context(&scratch).await);
+        let _ = tokio::spawn(FAIL_AT.scope(stage, job)).await;
+
+        let mut sent = Vec::new();
+        while let Some(message) = rx.recv().await {
+            sent.push(message);
+        }
+        let _ = std::fs::remove_dir_all(&scratch);
+        sent
+    }
+
+    fn terminal_status(sent: &[Result<ComputeResponse, Status>]) -> Option<JobStatus> {
+        let results: Vec<_> = sent.iter().filter_map(|m| m.as_ref().ok()?.result.as_ref()).collect();
+        assert_eq!(results.len(), 1, "exactly one terminal message");
+        let last = sent.last()?.as_ref().expect("no error status");
+        last.result.as_ref().map(JobResult::status)
+    }
+
+    #[tokio::test]
+    async fn a_job_ends_with_its_result() {
+        let sent = run("").await;
+        assert_eq!(terminal_status(&sent), Some(JobStatus::Succeeded));
+        assert!(sent.iter().flatten().any(|m| m.output == b"hello\n"));
+        let result = sent.iter().flatten().find_map(|m| m.result.as_ref()).unwrap();
+        assert_eq!(result.build_command, ["c++", "hello.cpp", "-fopenmp", "-o", "app.out"]);
+    }
+
+    #[tokio::test]
+    async fn a_panic_at_any_stage_still_ends_with_an_error_result() {
+        for stage in ["queue", "compile", "run", "report"] {
+            let sent = run(stage).await;
+            assert_eq!(terminal_status(&sent), Some(JobStatus::Error), "panic at {}", stage);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_host_side_failure_ends_with_an_error_result() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let log = JobLogs::default().create("job", "tester");
+        JobStream::new(tx, "job", log.clone()).fail("Failed to create workspace").await;
+        let sent = vec![rx.recv().await.unwrap()];
+        assert!(rx.recv().await.is_none());
+        assert_eq!(terminal_status(&sent), Some(JobStatus::Error));
+        assert_eq!(log.status().phase(), JobPhase::Done);
+        assert_eq!(log.status().error, "Failed to create workspace");
+    }
+
+    #[test]
+    fn relative_paths_stay_inside_the_workspace() {
+        assert_eq!(relative_path("src/kernel.cu"), Some(PathBuf::from("src/kernel.cu")));
+        assert_eq!(relative_path("./src/./kernel.cu"), Some(PathBuf::from("src/kernel.cu")));
+        assert_eq!(relative_path("src//kernel.cu"), Some(PathBuf::from("src/kernel.cu")));
+        for path in ["", ".", "./", "../kernel.cu", "src/../../etc/passwd", "src/..", "/etc/passwd"] {
+            assert_eq!(relative_path(path), None, "{:?}", path);
+        }
+    }
+
+    /// A CPU job with `files`, as the request a client would send.
+    fn request(files: &[(&str, &str)]) -> ComputeRequest {
+        ComputeRequest {
+            accelerator: Accelerator::Cpu.into(),
+            files: files
+                .iter()
+                .map(|(path, contents)| SourceFile {
+                    path: path.to_string(),
+                    contents: contents.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn validate_refuses_what_the_host_cannot_run() {
+        let scratch = std::env::temp_dir().join(format!("ferris-validate-{}", std::process::id()));
+        let mut ctx = context(&scratch).await;
+        ctx.max_source_bytes = 10;
+        let refused = |req: &ComputeRequest, ctx: &HostContext| validate(req, ctx).unwrap_err();
+
+        assert!(validate(&request(&[("main.cpp", "int x;"), ("include/a.h", "")]), &ctx).is_ok());
+        for path in ["../main.cpp", "/tmp/main.cpp", ""] {
+            let status = refused(&request(&[(path, "")]), &ctx);
+            assert_eq!(status.code(), tonic::Code::InvalidArgument, "{:?}", path);
+        }
+        let twice = refused(&request(&[("main.cpp", ""), ("./main.cpp", "")]), &ctx);
+        assert!(twice.message().contains("more than once"), "{}", twice.message());
+        let large = refused(&request(&[("main.cpp", "int main() {}")]), &ctx);
+        assert_eq!(large.code(), tonic::Code::ResourceExhausted);
+
+        let prebuilt = ComputeRequest {
+            prebuilt_binary: "app".into(),
+            ..request(&[("main.cpp", "")])
+        };
+        assert!(refused(&prebuilt, &ctx).message().contains("prebuilt binary"));
+        let outside = ComputeRequest {
+            prebuilt_binary: "../app".into(),
+            ..Default::default()
+        };
+        assert!(refused(&outside, &ctx).message().contains("prebuilt_binary"));
+
+        let mut debug = request(&[("main.cpp", "")]);
+        debug.debug = true;
+        assert_eq!(refused(&debug, &ctx).code(), tonic::Code::InvalidArgument);
+        let mut cmake = request(&[("CMakeLists.txt", "")]);
+        cmake.set_build_system(BuildSystem::Cmake);
+        assert!(refused(&cmake, &ctx).message().contains("CPU jobs build with a single"));
+        let _ = std::fs::remove_dir_all(&scratch);
+    }
+
+    #[tokio::test]
+    async fn validate_refuses_host_compiler_escapes_on_cpu() {
+        let scratch = std::env::temp_dir().join(format!("ferris-escapes-{}", std::process::id()));
+        let ctx = context(&scratch).await;
+        let with_flags = |flags: &[&str]| ComputeRequest {
+            compiler_flags: flags.iter().map(|flag| flag.to_string()).collect(),
+            ..request(&[("main.cpp", "")])
+        };
+
+        assert!(validate(&with_flags(&["-O2", "-Wl,-rpath,lib", "-Xlinker", "--as-needed"]), &ctx).is_ok());
+        for flags in [
+            &["-fplugin=./evil.so"][..],
+            &["-fpass-plugin=./evil.so"],
+            &["-B/tmp"],
+            &["-B", "/tmp"],
+            &["-wrapper", "gdb"],
+            &["-specs=evil.specs"],
+            &["-Wl,-plugin=./evil.so"],
+            &["-Wl,--as-needed,-o,/tmp/app"],
+            &["-Xlinker", "-plugin=./evil.so"],
+            &["-o/tmp/app"],
+            &["@flags.txt"],
+        ] {
+            let status = validate(&with_flags(flags), &ctx).unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument, "{:?}", flags);
+        }
+        let _ = std::fs::remove_dir_all(&scratch);
+    }
+}
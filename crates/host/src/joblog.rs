@@ -0,0 +1,184 @@
+/// Output of recent jobs, kept so clients can poll for it (`GetJobStatus`,
+/// `GetJobOutput`) or reattach to it (`AttachJob`) instead of holding one
+/// stream open for the whole run.
+///
+/// Every message a job's `JobStream` sends, except artifact chunks, is also
+/// appended to the job's log, so offsets into the log count the non-artifact
+/// messages of the original stream. A log is bounded by dropping its oldest
+/// entries and is kept for `LOG_TTL` after the job ends.
+use crate::stream::ResponseSender;
+use common::compute::{ComputeResponse, JobOutputResponse, JobPhase, JobStatusResponse};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Output text kept per job.
+const MAX_LOG_BYTES: usize = 8 * 1024 * 1024;
+/// How long a finished job can still be polled.
+const LOG_TTL: Duration = Duration::from_secs(60 * 60);
+/// Entries per `GetJobOutput` reply when the client doesn't say.
+const DEFAULT_PAGE: usize = 1000;
+const MAX_PAGE: usize = 10_000;
+
+#[derive(Clone, Default)]
+pub struct JobLogs {
+    logs: Arc<Mutex<HashMap<String, JobLog>>>,
+}
+
+/// One job's log; cheap to clone.
+#[derive(Clone)]
+pub struct JobLog {
+    inner: Arc<Mutex<LogState>>,
+    changed: Arc<Notify>,
+    /// Who may read it, besides admins.
+    submitter: Arc<str>,
+}
+
+struct LogState {
+    entries: VecDeque<ComputeResponse>,
+    /// Offset of `entries[0]`.
+    first: u64,
+    bytes: usize,
+    phase: JobPhase,
+    queue_position: u32,
+    status: JobStatusResponse,
+}
+
+impl JobLogs {
+    pub fn create(&self, job_id: &str, submitter: &str) -> JobLog {
+        let log = JobLog {
+            inner: Arc::new(Mutex::new(LogState {
+                entries: VecDeque::new(),
+                first: 0,
+                bytes: 0,
+                phase: JobPhase::Queued,
+                queue_position: 0,
+                status: JobStatusResponse {
+                    job_id: job_id.to_string(),
+                    ..Default::default()
+                },
+            })),
+            changed: Arc::default(),
+            submitter: submitter.into(),
+        };
+        self.logs.lock().unwrap().insert(job_id.to_string(), log.clone());
+        log
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<JobLog> {
+        self.logs.lock().unwrap().get(job_id).cloned()
+    }
+
+    /// Forgets a finished job's log once `LOG_TTL` has passed.
+    pub fn expire_later(&self, job_id: String) {
+        let logs = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(LOG_TTL).await;
+            logs.logs.lock().unwrap().remove(&job_id);
+        });
+    }
+}
+
+impl JobLog {
+    /// Records a message the job sent. The terminal message (the one with a
+    /// result) marks the job done.
+    pub fn push(&self, message: &ComputeResponse) {
+        if message.artifact.is_some() {
+            return;
+        }
+        let mut state = self.inner.lock().unwrap();
+        if let Some(result) = &message.result {
+            state.phase = JobPhase::Done;
+            state.status.result = Some(result.clone());
+        }
+        state.bytes += message.output.len();
+        state.entries.push_back(message.clone());
+        while state.bytes > MAX_LOG_BYTES && state.entries.len() > 1 {
+            let dropped = state.entries.pop_front().expect("len > 1");
+            state.bytes -= dropped.output.len();
+            state.first += 1;
+        }
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    /// Records why the job is ending in a host-side error, ahead of its
+    /// terminal message.
+    pub fn set_error(&self, reason: &str) {
+        self.inner.lock().unwrap().status.error = reason.to_string();
+    }
+
+    pub fn submitter(&self) -> &str {
+        &self.submitter
+    }
+
+    pub fn set_phase(&self, phase: JobPhase) {
+        let mut state = self.inner.lock().unwrap();
+        state.phase = phase;
+        state.queue_position = 0;
+    }
+
+    pub fn set_queue_position(&self, position: u32) {
+        self.inner.lock().unwrap().queue_position = position;
+    }
+
+    pub fn status(&self) -> JobStatusResponse {
+        let state = self.inner.lock().unwrap();
+        let mut status = state.status.clone();
+        status.set_phase(state.phase);
+        status.queue_position = state.queue_position;
+        status
+    }
+
+    /// Entries from `from_offset` on. Offsets that were already dropped
+    /// resume at the oldest entry still kept, offsets past the end at the end.
+    pub fn read(&self, from_offset: u64, max_entries: u32) -> JobOutputResponse {
+        let state = self.inner.lock().unwrap();
+        let limit = match max_entries as usize {
+            0 => DEFAULT_PAGE,
+            n => n.min(MAX_PAGE),
+        };
+        let end = state.first + state.entries.len() as u64;
+        let start = from_offset.clamp(state.first, end);
+        let skip = (start - state.first) as usize;
+        let entries: Vec<ComputeResponse> = state.entries.iter().skip(skip).take(limit).cloned().collect();
+        let next_offset = start + entries.len() as u64;
+
+        JobOutputResponse {
+            entries,
+            next_offset,
+            first_offset: state.first,
+            done: state.phase == JobPhase::Done && next_offset >= end,
+        }
+    }
+
+    /// Replays the log from `from_offset` into `tx` and keeps forwarding new
+    /// entries until the job's terminal message has been sent or the
+    /// receiver goes away.
+    pub async fn follow(self, from_offset: u64, tx: ResponseSender) {
+        let mut offset = from_offset;
+        loop {
+            // Register before reading, so an entry pushed in between isn't missed
+            let changed = self.changed.notified();
+            let page = self.read(offset, 0);
+            let caught_up = page.entries.is_empty();
+            for entry in page.entries {
+                if tx.send(Ok(entry)).await.is_err() {
+                    return;
+                }
+            }
+            offset = page.next_offset;
+
+            if page.done {
+                return;
+            }
+            if caught_up {
+                tokio::select! {
+                    _ = changed => {}
+                    _ = tx.closed() => return,
+                }
+            }
+        }
+    }
+}
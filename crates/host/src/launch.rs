@@ -0,0 +1,267 @@
+/// Kernels launched straight from PTX through the CUDA driver, for clients
+/// that generate their own device code and only need a GPU to run it on: no
+/// nvcc, no workspace, buffers in and out with the call.
+///
+/// Each launch runs in a child process, the host binary started with
+/// `CHILD_FLAG` on its leased GPU: a kernel that faults leaves its context
+/// unusable, and one that hangs can only be stopped by killing its process.
+/// The request goes to the child's stdin and the response comes back on its
+/// stdout, both protobuf-encoded; a failure is a message on stderr and an
+/// exit code saying whose fault it was. The child is held to the host's
+/// per-job resource limits.
+///
+/// The output buffers come back in one response, so together they must fit
+/// in a message: clients decode no more than the host does.
+use crate::driver::Driver;
+use crate::limits::Limits;
+use crate::process;
+use crate::uploads::MAX_UPLOAD_BYTES;
+use common::compute::kernel_arg::Value;
+use common::compute::{Dim3, LaunchKernelRequest, LaunchKernelResponse};
+use prost::Message;
+use std::ffi::c_void;
+use std::io::{Read, Write};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tonic::Status;
+
+/// First argument of the host binary when it runs as a launcher.
+pub const CHILD_FLAG: &str = "--launch-kernel";
+/// The module, kernel or arguments were rejected.
+const EXIT_INVALID: i32 = 2;
+const EXIT_NO_DRIVER: i32 = 3;
+/// Any other failure is the kernel's own, e.g. an illegal address.
+const EXIT_FAILED: i32 = 1;
+/// Room in a response for everything but the output buffers' bytes.
+const RESPONSE_OVERHEAD: u64 = 1024;
+
+/// Rejects a launch before it waits for a GPU, including one whose outputs
+/// wouldn't fit in a response of `max_message_bytes`.
+#[allow(clippy::result_large_err)]
+pub fn validate(req: &LaunchKernelRequest, max_message_bytes: u64) -> Result<(), Status> {
+    if req.ptx.trim().is_empty() || req.kernel.is_empty() {
+        return Err(Status::invalid_argument("A launch needs PTX and the name of a kernel in it"));
+    }
+    let mut total = 0u64;
+    let mut returned = 0u64;
+    for (i, arg) in req.args.iter().enumerate() {
+        match &arg.value {
+            None => return Err(Status::invalid_argument(format!("Argument {} has no value", i))),
+            Some(Value::Buffer(buffer)) => {
+                if buffer.size != 0 && buffer.size < buffer.data.len() as u64 {
+                    return Err(Status::invalid_argument(format!(
+                        "Argument {}: a {}-byte buffer can't hold its {} bytes of data",
+                        i,
+                        buffer.size,
+                        buffer.data.len()
+                    )));
+                }
+                let size = buffer.size.max(buffer.data.len() as u64);
+                total += size;
+                if buffer.output {
+                    returned += size;
+                }
+            }
+            Some(Value::Scalar(bytes)) if bytes.is_empty() => {
+                return Err(Status::invalid_argument(format!("Argument {} is an empty scalar", i)));
+            }
+            Some(Value::Scalar(_)) => {}
+        }
+    }
+    if total > MAX_UPLOAD_BYTES {
+        return Err(Status::resource_exhausted(format!(
+            "Buffers add up to {} bytes; a launch may allocate at most {}",
+            total, MAX_UPLOAD_BYTES
+        )));
+    }
+    let room = max_message_bytes.saturating_sub(RESPONSE_OVERHEAD);
+    if returned > room {
+        return Err(Status::resource_exhausted(format!(
+            "Output buffers add up to {} bytes; a launch may return at most {}",
+            returned, room
+        )));
+    }
+    Ok(())
+}
+
+/// Runs the launch on `visible_devices` within `limits`, killing it after
+/// `timeout`. `id` names the launch's cgroup, released once it has ended.
+pub async fn run(
+    req: &LaunchKernelRequest,
+    visible_devices: &str,
+    timeout: Duration,
+    limits: &Limits,
+    id: &str,
+) -> Result<LaunchKernelResponse, Status> {
+    let result = launch_child(req, visible_devices, timeout, limits, id).await;
+    limits.release(id).await;
+    result
+}
+
+async fn launch_child(
+    req: &LaunchKernelRequest,
+    visible_devices: &str,
+    timeout: Duration,
+    limits: &Limits,
+    id: &str,
+) -> Result<LaunchKernelResponse, Status> {
+    let exe = std::env::current_exe()
+        .map_err(|e| Status::internal(format!("Can't find the host binary to launch with: {}", e)))?;
+    let mut cmd = Command::new(exe);
+    cmd.arg(CHILD_FLAG)
+        .env("CUDA_VISIBLE_DEVICES", visible_devices)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    process::isolate(&mut cmd);
+    limits.apply(&mut cmd, id);
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| Status::internal(format!("Failed to start the launcher: {}", e)))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = req.encode_to_vec();
+    let send = async move {
+        // A child that dies early closes the pipe; its exit says why
+        let _ = stdin.write_all(&input).await;
+    };
+    // Dropping the child on timeout kills it
+    let finished = tokio::time::timeout(timeout, async {
+        let (_, output) = tokio::join!(send, child.wait_with_output());
+        output
+    })
+    .await;
+    let output = match finished {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(Status::internal(format!("The launcher failed: {}", e))),
+        Err(_) => {
+            return Err(Status::deadline_exceeded(format!(
+                "The kernel was killed after {}s",
+                timeout.as_secs()
+            )));
+        }
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    match output.status.code() {
+        Some(0) => LaunchKernelResponse::decode(output.stdout.as_slice())
+            .map_err(|e| Status::internal(format!("The launcher's response was unreadable: {}", e))),
+        Some(EXIT_INVALID) => Err(Status::invalid_argument(stderr)),
+        Some(EXIT_NO_DRIVER) => Err(Status::failed_precondition(stderr)),
+        Some(_) => Err(Status::aborted(stderr)),
+        None => Err(Status::aborted(format!("The launcher was killed by a signal: {}", stderr))),
+    }
+}
+
+/// The child's side: reads the request, launches, writes the response, and
+/// returns the exit code.
+pub fn child() -> i32 {
+    let mut input = Vec::new();
+    if let Err(e) = std::io::stdin().read_to_end(&mut input) {
+        eprintln!("Could not read the launch request: {}", e);
+        return EXIT_FAILED;
+    }
+    let req = match LaunchKernelRequest::decode(input.as_slice()) {
+        Ok(req) => req,
+        Err(e) => {
+            eprintln!("Invalid launch request: {}", e);
+            return EXIT_INVALID;
+        }
+    };
+    match launch(&req) {
+        Ok(response) => {
+            let mut stdout = std::io::stdout().lock();
+            match stdout.write_all(&response.encode_to_vec()).and_then(|_| stdout.flush()) {
+                Ok(()) => 0,
+                Err(_) => EXIT_FAILED,
+            }
+        }
+        Err((code, message)) => {
+            eprintln!("{}", message);
+            code
+        }
+    }
+}
+
+fn launch(req: &LaunchKernelRequest) -> Result<LaunchKernelResponse, (i32, String)> {
+    let invalid = |e| (EXIT_INVALID, e);
+    let failed = |e| (EXIT_FAILED, e);
+    let driver = Driver::load().map_err(|e| (EXIT_NO_DRIVER, e))?;
+    let module = driver.load_module(&req.ptx).map_err(invalid)?;
+    let function = driver.function(&module, &req.kernel).map_err(invalid)?;
+
+    // Each parameter's value as the kernel takes it: a buffer's device
+    // pointer, or a scalar's own bytes
+    let mut values: Vec<Vec<u8>> = Vec::with_capacity(req.args.len());
+    let mut outputs = Vec::new();
+    for arg in &req.args {
+        match arg.value.as_ref().expect("validated") {
+            Value::Buffer(buffer) => {
+                let size = buffer.size.max(buffer.data.len() as u64) as usize;
+                let pointer = driver.buffer(&buffer.data, size).map_err(failed)?;
+                if buffer.output {
+                    outputs.push((pointer, size));
+                }
+                values.push(pointer.to_ne_bytes().to_vec());
+            }
+            Value::Scalar(bytes) => values.push(bytes.clone()),
+        }
+    }
+    let mut params: Vec<*mut c_void> = values.iter_mut().map(|v| v.as_mut_ptr() as *mut c_void).collect();
+
+    let started = Instant::now();
+    driver
+        .launch(&function, dims(req.grid), dims(req.block), req.shared_bytes, &mut params)
+        .map_err(failed)?;
+    let kernel_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let outputs = outputs
+        .into_iter()
+        .map(|(pointer, size)| driver.read(pointer, size))
+        .collect::<Result<_, _>>()
+        .map_err(failed)?;
+    Ok(LaunchKernelResponse {
+        outputs,
+        kernel_ms,
+        ..Default::default()
+    })
+}
+
+/// Unset or zero dimensions count as 1.
+fn dims(dim: Option<Dim3>) -> [u32; 3] {
+    let dim = dim.unwrap_or_default();
+    [dim.x, dim.y, dim.z].map(|d| d.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::compute::{DeviceBuffer, KernelArg};
+
+    fn buffer(size: u64, output: bool) -> KernelArg {
+        KernelArg {
+            value: Some(Value::Buffer(DeviceBuffer {
+                size,
+                output,
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn outputs_must_fit_in_one_response() {
+        let limit = 4 * 1024 * 1024;
+        let launch = |args| LaunchKernelRequest {
+            ptx: ".version 8.0".into(),
+            kernel: "scale".into(),
+            args,
+            ..Default::default()
+        };
+        assert!(validate(&launch(vec![buffer(limit, false), buffer(1024, true)]), limit).is_ok());
+        let status = validate(&launch(vec![buffer(limit / 2, true), buffer(limit / 2, true)]), limit).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert!(validate(&launch(vec![buffer(1024, true)]), 1024).is_err());
+    }
+}
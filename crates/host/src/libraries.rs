@@ -0,0 +1,147 @@
+/// Link presets: libraries the host has installed that jobs ask for by name
+/// (`libraries = ["cublas"]`) instead of spelling out include paths and `-l`
+/// flags themselves. They're declared in the host config:
+///
+/// ```toml
+/// [libraries.cublas]
+/// include_dirs = ["/usr/local/cuda/include"]
+/// lib_dirs = ["/usr/local/cuda/lib64"]
+/// libs = ["cublas", "cublasLt"]
+///
+/// [libraries.thrust]
+/// include_dirs = ["/usr/local/cuda/include"]
+/// version = "2.2.0"
+/// ```
+///
+/// Every directory and library must exist when the host starts. The version
+/// reported in `HostInfo` is `version` if set, otherwise read off the first
+/// library's versioned file name (`libcublas.so.12.4.5.8` is 12.4.5.8).
+///
+/// A preset's flags are the host's own, so the flag policy doesn't apply to
+/// them; programs find the libraries at run time through an rpath, spelled
+/// the way the job's compiler takes it.
+use crate::compiler::Compiler;
+use common::compute::LibraryInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LibraryPreset {
+    pub include_dirs: Vec<PathBuf>,
+    pub lib_dirs: Vec<PathBuf>,
+    /// Names as `-l` takes them, e.g. "cufft".
+    pub libs: Vec<String>,
+    /// Any other nvcc flags the library needs, e.g. "-DUSE_CUDNN".
+    pub flags: Vec<String>,
+    pub version: Option<String>,
+}
+
+struct Library {
+    preset: LibraryPreset,
+    version: String,
+}
+
+#[derive(Default)]
+pub struct Libraries {
+    named: BTreeMap<String, Library>,
+}
+
+impl Libraries {
+    /// Checks every preset against the filesystem.
+    pub fn load(presets: &BTreeMap<String, LibraryPreset>) -> Result<Self, String> {
+        let mut named = BTreeMap::new();
+        for (name, preset) in presets {
+            let library = resolve(preset).map_err(|e| format!("Library preset {}: {}", name, e))?;
+            named.insert(name.clone(), library);
+        }
+        Ok(Libraries { named })
+    }
+
+    /// The flags for `names`, in order, as `compiler` takes them.
+    pub fn flags(&self, names: &[String], compiler: &dyn Compiler) -> Result<Vec<String>, String> {
+        let mut flags = Vec::new();
+        for name in names {
+            let library = self.named.get(name).ok_or_else(|| {
+                format!(
+                    "Unknown library {} (available: {})",
+                    name,
+                    self.named.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            })?;
+            let preset = &library.preset;
+            flags.extend(preset.include_dirs.iter().map(|dir| format!("-I{}", dir.display())));
+            for dir in &preset.lib_dirs {
+                flags.push(format!("-L{}", dir.display()));
+                flags.push(compiler.rpath(dir));
+            }
+            flags.extend(preset.libs.iter().map(|lib| format!("-l{}", lib)));
+            flags.extend(preset.flags.iter().cloned());
+        }
+        Ok(flags)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.named.keys().map(String::as_str)
+    }
+
+    /// For `HostInfo`.
+    pub fn info(&self) -> Vec<LibraryInfo> {
+        self.named
+            .iter()
+            .map(|(name, library)| LibraryInfo {
+                name: name.clone(),
+                version: library.version.clone(),
+                libs: library.preset.libs.clone(),
+            })
+            .collect()
+    }
+}
+
+fn resolve(preset: &LibraryPreset) -> Result<Library, String> {
+    for dir in preset.include_dirs.iter().chain(&preset.lib_dirs) {
+        check_dir(dir)?;
+    }
+    let mut found = Vec::new();
+    for lib in &preset.libs {
+        let file = find_lib(&preset.lib_dirs, lib)
+            .ok_or_else(|| format!("lib{} not found in {:?}", lib, preset.lib_dirs))?;
+        found.push(file);
+    }
+
+    let version = preset
+        .version
+        .clone()
+        .or_else(|| found.first().and_then(|file| version_of(file)))
+        .unwrap_or_else(|| "unknown".into());
+    Ok(Library {
+        preset: preset.clone(),
+        version,
+    })
+}
+
+fn check_dir(dir: &Path) -> Result<(), String> {
+    match dir.is_dir() {
+        true => Ok(()),
+        false => Err(format!("{} is not a directory", dir.display())),
+    }
+}
+
+/// The shared library the linker would pick for `-l<lib>`, or else the
+/// static one.
+fn find_lib(dirs: &[PathBuf], lib: &str) -> Option<PathBuf> {
+    ["so", "a"].iter().find_map(|ext| {
+        dirs.iter()
+            .map(|dir| dir.join(format!("lib{}.{}", lib, ext)))
+            .find(|path| path.exists())
+    })
+}
+
+/// `12.4.5.8` from the file `libcublas.so` links to, `libcublas.so.12.4.5.8`.
+fn version_of(file: &Path) -> Option<String> {
+    let target = std::fs::canonicalize(file).ok()?;
+    let name = target.file_name()?.to_str()?;
+    let (_, version) = name.split_once(".so.")?;
+    Some(version.to_string())
+}
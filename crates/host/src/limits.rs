@@ -0,0 +1,287 @@
+/// Host-side limits on what one job's processes may use: CPU time, memory,
+/// processes and file size. They cover the build (nvcc, cmake, make) and the
+/// program alike, so a program that forks or allocates without end fails on
+/// its own instead of taking the host down.
+///
+/// Each process gets rlimits: RLIMIT_CPU and RLIMIT_FSIZE, and, without
+/// cgroups, RLIMIT_DATA for memory and RLIMIT_NPROC for processes. Linux
+/// counts RLIMIT_NPROC across every process of the host's user, the host
+/// included, and RLIMIT_DATA per process.
+///
+/// With `cgroup_root`, a cgroup v2 directory the host may write to with the
+/// memory and pids controllers enabled for its children, every job gets a
+/// cgroup of its own there, so memory and processes are limited for the job
+/// as a whole (`memory.max`, `pids.max`). Docker containers get the matching
+/// `docker run` options instead.
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::warn;
+
+/// 0 leaves a resource unlimited.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobLimits {
+    /// CPU time of each process.
+    pub cpu_secs: u64,
+    pub memory_mb: u64,
+    pub processes: u64,
+    /// Largest file a process may write.
+    pub file_size_mb: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct Limits {
+    limits: JobLimits,
+    cgroup_root: Option<Arc<Path>>,
+}
+
+impl Limits {
+    pub fn new(limits: JobLimits, cgroup_root: Option<PathBuf>) -> Self {
+        Limits {
+            limits,
+            cgroup_root: cgroup_root.map(Into::into),
+        }
+    }
+
+    /// Confines `cmd`, one of the job's processes, run directly on the host.
+    /// The job's cgroup is created on first use.
+    pub fn apply(&self, cmd: &mut Command, job_id: &str) {
+        let limits = self.limits;
+        let procs = match self.cgroup(job_id) {
+            Ok(cgroup) => cgroup.map(|dir| dir.join("cgroup.procs")),
+            Err(e) => {
+                warn!("⚠️ Could not set up the job's cgroup: {}", e);
+                None
+            }
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let procs = procs.and_then(|path| std::ffi::CString::new(path.as_os_str().as_bytes()).ok());
+            let in_cgroup = procs.is_some();
+            // SAFETY: between fork and exec the closure only makes syscalls
+            // (open, write, close, setrlimit) on memory prepared beforehand.
+            unsafe {
+                cmd.pre_exec(move || {
+                    if let Some(procs) = &procs {
+                        join_cgroup(procs)?;
+                    }
+                    // A second's headroom, so SIGXCPU says why before SIGKILL
+                    set_rlimit(libc::RLIMIT_CPU, limits.cpu_secs, limits.cpu_secs + 1)?;
+                    let file_size = limits.file_size_mb * 1024 * 1024;
+                    set_rlimit(libc::RLIMIT_FSIZE, file_size, file_size)?;
+                    if !in_cgroup {
+                        let memory = limits.memory_mb * 1024 * 1024;
+                        set_rlimit(libc::RLIMIT_DATA, memory, memory)?;
+                        set_rlimit(libc::RLIMIT_NPROC, limits.processes, limits.processes)?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (cmd, limits, procs);
+    }
+
+    /// `docker run` options that apply the limits inside a container.
+    pub fn docker_args(&self) -> Vec<OsString> {
+        let JobLimits { cpu_secs, memory_mb, processes, file_size_mb } = self.limits;
+        let mut args: Vec<String> = Vec::new();
+        if memory_mb > 0 {
+            // Swap included, so the limit can't be sidestepped by swapping
+            args.extend(["--memory".into(), format!("{}m", memory_mb)]);
+            args.extend(["--memory-swap".into(), format!("{}m", memory_mb)]);
+        }
+        if processes > 0 {
+            args.extend(["--pids-limit".into(), processes.to_string()]);
+        }
+        if cpu_secs > 0 {
+            args.extend(["--ulimit".into(), format!("cpu={}", cpu_secs)]);
+        }
+        if file_size_mb > 0 {
+            args.extend(["--ulimit".into(), format!("fsize={}", file_size_mb * 1024 * 1024)]);
+        }
+        args.into_iter().map(Into::into).collect()
+    }
+
+    /// Names the limit a program killed by `signal` ran into, if any.
+    pub fn explain(&self, job_id: &str, signal: i32) -> Option<String> {
+        #[cfg(unix)]
+        match signal {
+            libc::SIGXCPU => return Some(format!("the host's CPU time limit of {}s", self.limits.cpu_secs)),
+            libc::SIGXFSZ => return Some(format!("the host's file size limit of {} MB", self.limits.file_size_mb)),
+            libc::SIGKILL => {
+                let dir = self.cgroup_dir(job_id)?;
+                let events = std::fs::read_to_string(dir.join("memory.events")).ok()?;
+                let oom_kills = events
+                    .lines()
+                    .find_map(|line| line.strip_prefix("oom_kill "))
+                    .and_then(|n| n.trim().parse::<u64>().ok())?;
+                if oom_kills > 0 {
+                    return Some(format!("the host's memory limit of {} MB", self.limits.memory_mb));
+                }
+            }
+            _ => {}
+        }
+        let _ = (job_id, signal);
+        None
+    }
+
+    /// Kills whatever is left in the job's cgroup and removes it.
+    pub async fn release(&self, job_id: &str) {
+        let Some(dir) = self.cgroup_dir(job_id) else {
+            return;
+        };
+        if !dir.exists() {
+            return;
+        }
+        let _ = tokio::fs::write(dir.join("cgroup.kill"), "1").await;
+        // The kernel takes a moment to empty it
+        for _ in 0..10 {
+            if tokio::fs::remove_dir(&dir).await.is_ok() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        warn!("⚠️ Could not remove cgroup {}", dir.display());
+    }
+
+    fn cgroup_dir(&self, job_id: &str) -> Option<PathBuf> {
+        let root = self.cgroup_root.as_ref()?;
+        Some(root.join(format!("ferris-{}", job_id)))
+    }
+
+    /// The job's cgroup, created with its limits if it doesn't exist yet.
+    fn cgroup(&self, job_id: &str) -> io::Result<Option<PathBuf>> {
+        let Some(dir) = self.cgroup_dir(job_id) else {
+            return Ok(None);
+        };
+        if !dir.exists() {
+            std::fs::create_dir(&dir)?;
+            if self.limits.memory_mb > 0 {
+                std::fs::write(dir.join("memory.max"), (self.limits.memory_mb * 1024 * 1024).to_string())?;
+                // Absent when the host has no swap accounting
+                let _ = std::fs::write(dir.join("memory.swap.max"), "0");
+            }
+            if self.limits.processes > 0 {
+                std::fs::write(dir.join("pids.max"), self.limits.processes.to_string())?;
+            }
+        }
+        Ok(Some(dir))
+    }
+}
+
+/// Moves the calling process into the cgroup whose `cgroup.procs` is `procs`.
+#[cfg(unix)]
+fn join_cgroup(procs: &std::ffi::CStr) -> io::Result<()> {
+    // SAFETY: plain syscalls on a valid NUL-terminated path and a static buffer
+    unsafe {
+        let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // "0" means the writing process
+        let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+        libc::close(fd);
+        if written != 1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+/// Sets a limit unless `soft` is 0 (unlimited).
+#[cfg(unix)]
+fn set_rlimit(resource: Resource, soft: u64, hard: u64) -> io::Result<()> {
+    if soft == 0 {
+        return Ok(());
+    }
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // SAFETY: setrlimit only reads `limit`
+    match unsafe { libc::setrlimit(resource, &limit) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> JobLimits {
+        JobLimits {
+            cpu_secs: 7,
+            memory_mb: 512,
+            processes: 64,
+            file_size_mb: 2,
+        }
+    }
+
+    #[test]
+    fn containers_get_the_matching_docker_options() {
+        let args: Vec<String> = Limits::new(limits(), None)
+            .docker_args()
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        assert_eq!(
+            args,
+            [
+                "--memory", "512m", "--memory-swap", "512m", "--pids-limit", "64", "--ulimit", "cpu=7", "--ulimit",
+                "fsize=2097152"
+            ]
+        );
+        assert!(Limits::default().docker_args().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn processes_run_under_the_rlimits() {
+        let mut cmd = Command::new("cat");
+        cmd.arg("/proc/self/limits");
+        Limits::new(limits(), None).apply(&mut cmd, "job");
+        let output = cmd.output().await.unwrap();
+        let text = String::from_utf8(output.stdout).unwrap();
+        let limit = |name: &str| {
+            let line = text.lines().find(|line| line.starts_with(name)).unwrap();
+            line[name.len()..].split_whitespace().take(2).collect::<Vec<_>>().join(" ")
+        };
+        assert_eq!(limit("Max cpu time"), "7 8");
+        assert_eq!(limit("Max file size"), "2097152 2097152");
+        assert_eq!(limit("Max data size"), "536870912 536870912");
+        assert_eq!(limit("Max processes"), "64 64");
+    }
+
+    #[test]
+    fn a_job_cgroup_gets_the_limits_and_explains_oom_kills() {
+        let root = std::env::temp_dir().join(format!("ferris-limits-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let limits = Limits::new(limits(), Some(root.clone()));
+
+        let dir = limits.cgroup("job").unwrap().unwrap();
+        assert_eq!(dir, root.join("ferris-job"));
+        assert_eq!(std::fs::read_to_string(dir.join("memory.max")).unwrap(), "536870912");
+        assert_eq!(std::fs::read_to_string(dir.join("pids.max")).unwrap(), "64");
+
+        assert_eq!(limits.explain("job", libc::SIGKILL), None);
+        std::fs::write(dir.join("memory.events"), "low 0\nhigh 0\nmax 3\noom 1\noom_kill 1\n").unwrap();
+        assert_eq!(limits.explain("job", libc::SIGKILL).unwrap(), "the host's memory limit of 512 MB");
+        assert_eq!(limits.explain("job", libc::SIGXCPU).unwrap(), "the host's CPU time limit of 7s");
+        assert_eq!(limits.explain("job", libc::SIGXFSZ).unwrap(), "the host's file size limit of 2 MB");
+        assert_eq!(limits.explain("job", libc::SIGSEGV), None);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
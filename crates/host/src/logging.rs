@@ -0,0 +1,101 @@
+/// Host logging through `tracing`: human-readable lines by default, or one
+/// JSON object per event for log shippers. Events inside a job carry its
+/// `job` span (job id, client and file name).
+///
+/// With an OTLP endpoint configured, spans are also exported as OpenTelemetry
+/// traces. A job's span continues the trace named by the W3C `traceparent`
+/// metadata of the call that submitted it, and has `queue`, `compile` and
+/// `run` child spans.
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{Context, KeyValue, global};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{Resource, runtime};
+use std::io::IsTerminal;
+use tonic::Request;
+use tonic::metadata::{KeyRef, MetadataMap};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// Keeps the trace exporter alive; `shutdown` flushes spans not yet sent.
+pub struct Logging {
+    provider: Option<TracerProvider>,
+}
+
+/// Installs the global subscriber. `level` is an `EnvFilter` directive such as
+/// "info" or "host=debug,tower=warn".
+pub fn init(level: &str, format: LogFormat, otlp_endpoint: Option<&str>) -> Result<Logging, String> {
+    let filter = EnvFilter::try_new(level).map_err(|e| format!("Invalid log level {:?}: {}", level, e))?;
+    let fmt = tracing_subscriber::fmt::layer().with_ansi(std::io::stdout().is_terminal());
+    let fmt = match format {
+        LogFormat::Text => fmt.boxed(),
+        LogFormat::Json => fmt.json().with_current_span(true).with_span_list(false).boxed(),
+    };
+
+    let provider = match otlp_endpoint {
+        Some(endpoint) => {
+            use opentelemetry_otlp::WithExportConfig;
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()
+                .map_err(|e| format!("Invalid OTLP endpoint {}: {}", endpoint, e))?;
+            let provider = TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_resource(Resource::new([KeyValue::new("service.name", "ferris-compute-host")]))
+                .build();
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            Some(provider)
+        }
+        None => None,
+    };
+    let otel = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer("host")));
+
+    tracing_subscriber::registry().with(filter).with(fmt).with(otel).init();
+    Ok(Logging { provider })
+}
+
+impl Logging {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider
+            && let Err(e) = provider.shutdown()
+        {
+            tracing::warn!("⚠️ Could not flush traces: {}", e);
+        }
+    }
+}
+
+/// The trace a call's `traceparent`/`tracestate` metadata names; an empty
+/// context (a new trace) when there is none or OTLP is off.
+pub fn remote_parent<T>(request: &Request<T>) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&Metadata(request.metadata())))
+}
+
+struct Metadata<'a>(&'a MetadataMap);
+
+impl Extractor for Metadata<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter_map(|key| match key {
+                KeyRef::Ascii(key) => Some(key.as_str()),
+                KeyRef::Binary(_) => None,
+            })
+            .collect()
+    }
+}
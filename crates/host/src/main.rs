@@ -1,13 +1,322 @@
+mod admin;
+mod arch;
+mod artifacts;
+mod auth;
+mod bench;
+mod buildsystem;
+mod cache;
+mod cleanup;
+mod cluster;
+mod compiler;
+mod config;
+mod context;
+mod debug;
+mod devices;
+mod diagnostics;
+mod driver;
+mod gc;
+mod gpumem;
+mod health;
+mod history;
+mod job;
+mod joblog;
+mod launch;
+mod libraries;
+mod limits;
+mod logging;
+mod metrics;
+mod notify;
+mod output;
+mod policy;
+mod probe;
+mod profiler;
+mod ptxas;
+mod process;
+mod queue;
+mod quota;
+mod registry;
+mod retention;
+mod sandbox;
+mod sanitizer;
+mod shutdown;
+mod stream;
+mod telemetry;
+mod toolchain;
+mod uploads;
+
+use admin::AdminService;
+use auth::{Authenticator, User};
+use cache::CompileCache;
+use cleanup::CleanupQueue;
+use cluster::Worker;
+use compiler::Backends;
+use config::HostConfig;
+use context::HostContext;
+use gc::ScratchGc;
+use health::HealthCheck;
+use history::JobHistory;
+use joblog::JobLogs;
+use libraries::Libraries;
+use limits::Limits;
+use notify::Notifiers;
+use devices::{DeviceAllocator, DeviceRequest, Sharing};
+use common::encoding::{FileEncoding, FILE_ENCODING_HEADER};
+use common::compute::admin_server::AdminServer;
 use common::compute::cuda_executor_server::{CudaExecutor, CudaExecutorServer};
-use common::compute::{ComputeRequest, ComputeResponse};
-use std::path::Path;
+use common::compute::{
+    Accelerator, AttachJobRequest, CancelJobRequest, CancelJobResponse, ComputeRequest, ComputeResponse,
+    DebugData, FileChunk, GetJobRequest, HostInfo, HostInfoRequest, JobOutputRequest, JobOutputResponse,
+    LaunchKernelRequest, LaunchKernelResponse, Priority,
+    JobRecord, JobStatus, JobStatusRequest, JobStatusResponse, ListJobsRequest, ListJobsResponse,
+    PurgeWorkspacesRequest, PurgeWorkspacesResponse, StdinChunk, SubmitJobResponse, SubmitMessage,
+    UploadInputsResponse, WriteStdinResponse,
+};
+use output::OutputLimits;
+use policy::FlagPolicy;
+use queue::JobQueue;
+use quota::{QuotaGuard, Quotas};
+use registry::JobRegistry;
+use retention::{RetainPolicy, Retention};
+use sandbox::{Sandbox, SandboxKind};
+use shutdown::Shutdown;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use stream::JobStream;
+use toolchain::Toolchains;
+use uploads::UploadStore;
 use tokio::fs;
-use tokio::process::Command;
 use tokio::sync::mpsc;
+use tracing::{Instrument, error, info, info_span, warn};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{transport::Server, Request, Response, Status};
+use tokio_util::sync::CancellationToken;
+use tonic::codec::CompressionEncoding;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status, Streaming};
+
+pub struct HostExecutor {
+    cleanup: CleanupQueue,
+    registry: JobRegistry,
+    logs: JobLogs,
+    history: JobHistory,
+    retention: Retention,
+    queue: JobQueue,
+    quotas: Quotas,
+    /// Identities allowed to submit high-priority jobs.
+    high_priority_users: Vec<String>,
+    /// Identities that may act on other users' jobs.
+    admin_users: Vec<String>,
+    uploads: UploadStore,
+    ctx: HostContext,
+    /// Job workspaces are created here.
+    scratch: PathBuf,
+    /// Responses buffered per job stream.
+    stream_buffer: usize,
+    /// Largest message accepted, and so the largest launch response sent.
+    max_message_bytes: usize,
+    /// Cancelled when the host starts shutting down.
+    draining: CancellationToken,
+    /// Set while an admin has paused submissions.
+    paused: Arc<AtomicBool>,
+}
+
+// Errors go straight back to the client, so they are built as gRPC statuses
+#[allow(clippy::result_large_err)]
+impl HostExecutor {
+    /// Checks a job may be queued at all: the host is accepting work, the
+    /// request is valid, its submitter may ask for its priority and is within
+    /// their quota.
+    fn admit(&self, client: &str, req: &ComputeRequest) -> Result<QuotaGuard, Status> {
+        self.accepting()?;
+        job::validate(req, &self.ctx)?;
+        if req.priority() == Priority::High && !self.high_priority_users.iter().any(|user| user == client) {
+            return Err(Status::permission_denied(format!(
+                "{} may not submit high-priority jobs to this host",
+                client
+            )));
+        }
+        self.quotas.admit(client)
+    }
+
+    /// Queues a job and returns its id and response stream. Staged `inputs`,
+    /// if any, become the job's workspace.
+    async fn start_job(
+        &self,
+        client: String,
+        req: ComputeRequest,
+        inputs: Option<PathBuf>,
+        encoding: FileEncoding,
+        trace: opentelemetry::Context,
+    ) -> Result<(String, ReceiverStream<Result<ComputeResponse, Status>>), Status> {
+        let quota = match self.admit(&client, &req) {
+            Ok(quota) => quota,
+            Err(status) => {
+                if let Some(inputs) = inputs {
+                    self.cleanup.schedule(inputs);
+                }
+                return Err(status);
+            }
+        };
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let working_dir = self.scratch.join(&job_id);
+        if let Some(inputs) = inputs
+            && let Err(e) = fs::rename(&inputs, &working_dir).await
+        {
+            self.cleanup.schedule(inputs);
+            return Err(Status::internal(format!("Failed to claim input files: {}", e)));
+        }
+
+        let (tx, rx) = mpsc::channel(self.stream_buffer);
+        let cleanup = self.cleanup.clone();
+        let registry = self.registry.clone();
+        let control = registry.register(&job_id, &client);
+        let logs = self.logs.clone();
+        let log = logs.create(&job_id, &client);
+        let history = self.history.clone();
+        let retention = self.retention.clone();
+        let keep_requested = req.keep_workspace;
+        let files: Vec<String> = job::project_files(&req).into_iter().map(|f| f.path).collect();
+        let notifiers = self.ctx.notifiers.clone();
+        let (notify, notify_url) = (req.notify, req.notify_url.clone());
+        let submitter = client.clone();
+        let span = info_span!("job", id = %job_id, client = %client, file = %files.join(","));
+        span.set_parent(trace);
+        history.submitted(&job_id, &client, &req).await;
+        metrics::job_submitted();
+        span.in_scope(|| info!("📥 Job submitted"));
+        let ticket = self.queue.enqueue(&client, req.priority());
+        let ctx = self.ctx.clone();
+        let sandbox = self.ctx.sandbox.clone();
+        let id = job_id.clone();
+
+        tokio::spawn(async move {
+            // The job body runs in its own task so a panic anywhere inside it is
+            // contained here: its JobStream emits an error result while
+            // unwinding, and we still get to clean up the workspace.
+            let stream = JobStream::new(tx, job_id.clone(), log.clone()).with_file_encoding(encoding);
+            let job = tokio::spawn(job::run_job(req, stream, working_dir.clone(), control, ticket, ctx).in_current_span());
+            if let Err(e) = job.await
+                && e.is_panic()
+            {
+                error!("💥 Job panicked");
+            }
+            sandbox.finish(&job_id).await;
+            let status = log.status();
+            let succeeded = matches!(&status.result, Some(r) if r.status() == JobStatus::Succeeded);
+            metrics::job_finished(&status);
+            quota.finished(&status);
+            notifiers.finished(notify, &notify_url, &submitter, &files, &status);
+            history.finished(status).await;
+
+            // 5. Cleanup: Hand the job directory to the retrying cleanup queue,
+            // unless it is kept for debugging
+            if retention.wants(succeeded, keep_requested) {
+                match retention.keep(&job_id, &working_dir).await {
+                    Ok(kept) => info!("🗃️ Kept workspace at {}", kept.display()),
+                    Err(e) => {
+                        warn!("⚠️ Could not keep workspace: {}", e);
+                        cleanup.schedule(working_dir);
+                    }
+                }
+            } else {
+                cleanup.schedule(working_dir);
+            }
+            // Only now, so shutdown knows the workspace has been dealt with
+            registry.unregister(&job_id);
+            logs.expire_later(job_id);
+        }.instrument(span));
 
-pub struct HostExecutor;
+        Ok((id, ReceiverStream::new(rx)))
+    }
+
+    /// Turns new work away once shutdown has begun, so clients can go elsewhere.
+    fn accepting(&self) -> Result<(), Status> {
+        if self.draining.is_cancelled() {
+            return Err(Status::unavailable("The host is shutting down and not accepting jobs"));
+        }
+        match self.paused.load(Ordering::Relaxed) {
+            true => Err(Status::unavailable("The host has been paused by an admin and is not accepting jobs")),
+            false => Ok(()),
+        }
+    }
+
+    /// The staged upload an `ExecuteCode`/`SubmitJob` request refers to, if
+    /// any. Only `client`'s own uploads can be claimed.
+    fn claim_inputs(&self, req: &ComputeRequest, client: &str) -> Result<Option<PathBuf>, Status> {
+        match req.input_upload_id.as_str() {
+            "" => Ok(None),
+            id => self
+                .uploads
+                .take(id, client)
+                .map(Some)
+                .ok_or_else(|| Status::not_found("Unknown or expired input upload")),
+        }
+    }
+
+    /// A job's log, for its submitter or an admin.
+    fn job_log<T>(&self, request: &Request<T>, job_id: &str) -> Result<joblog::JobLog, Status> {
+        let log = self
+            .logs
+            .get(job_id)
+            .ok_or_else(|| Status::not_found(format!("No job {} (it may have expired)", job_id)))?;
+        self.check_owner(request, job_id, log.submitter())?;
+        Ok(log)
+    }
+
+    /// Checks the caller may act on a running or queued job.
+    fn running_job<T>(&self, request: &Request<T>, job_id: &str) -> Result<(), Status> {
+        self.check_running(&client_identity(request), self.is_admin(request), job_id)
+    }
+
+    /// The same, for streaming calls that name the job after the request is
+    /// taken apart.
+    fn check_running(&self, caller: &str, admin: bool, job_id: &str) -> Result<(), Status> {
+        match self.registry.submitter(job_id) {
+            Some(submitter) if !admin && submitter != caller => Err(Status::permission_denied(format!(
+                "Job {} was submitted by another user",
+                job_id
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Users may only see and act on their own jobs; admins on anyone's.
+    fn check_owner<T>(&self, request: &Request<T>, job_id: &str, submitter: &str) -> Result<(), Status> {
+        if client_identity(request) == submitter || self.is_admin(request) {
+            return Ok(());
+        }
+        Err(Status::permission_denied(format!("Job {} was submitted by another user", job_id)))
+    }
+
+    fn is_admin<T>(&self, request: &Request<T>) -> bool {
+        matches!(request.extensions().get::<User>(), Some(User(name)) if self.admin_users.contains(name))
+    }
+}
+
+/// The file encoding the client asked for; artifacts are sent back with it too.
+fn file_encoding<T>(request: &Request<T>) -> FileEncoding {
+    FileEncoding::from_header(
+        request
+            .metadata()
+            .get(FILE_ENCODING_HEADER)
+            .and_then(|v| v.to_str().ok()),
+    )
+}
+
+/// Fairness is per authenticated user, or per remote address without auth.
+fn client_identity<T>(request: &Request<T>) -> String {
+    match request.extensions().get::<User>() {
+        Some(User(name)) => name.clone(),
+        None => request
+            .remote_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".into()),
+    }
+}
 
 #[tonic::async_trait]
 impl CudaExecutor for HostExecutor {
@@ -17,100 +326,550 @@ impl CudaExecutor for HostExecutor {
         &self,
         request: Request<ComputeRequest>,
     ) -> Result<Response<Self::ExecuteCodeStream>, Status> {
+        let client = client_identity(&request);
+        let encoding = file_encoding(&request);
+        let trace = logging::remote_parent(&request);
         let req = request.into_inner();
-        let (tx, rx) = mpsc::channel(100);
+        let inputs = self.claim_inputs(&req, &client)?;
+        let (_, stream) = self.start_job(client, req, inputs, encoding, trace).await?;
+        Ok(Response::new(stream))
+    }
 
-        tokio::spawn(async move {
-            let job_id = uuid::Uuid::new_v4().to_string();
-            let working_dir = Path::new("scratch").join(&job_id);
+    type SubmitStream = ReceiverStream<Result<ComputeResponse, Status>>;
+
+    async fn submit(
+        &self,
+        request: Request<Streaming<SubmitMessage>>,
+    ) -> Result<Response<Self::SubmitStream>, Status> {
+        let client = client_identity(&request);
+        let encoding = file_encoding(&request);
+        let trace = logging::remote_parent(&request);
+        let submission = self
+            .uploads
+            .receive_submission(request.into_inner(), encoding, false)
+            .await?;
+        let (_, stream) = self
+            .start_job(client, submission.request, submission.inputs, encoding, trace)
+            .await?;
+        Ok(Response::new(stream))
+    }
+
+    type RunBinaryStream = ReceiverStream<Result<ComputeResponse, Status>>;
+
+    async fn run_binary(
+        &self,
+        request: Request<Streaming<SubmitMessage>>,
+    ) -> Result<Response<Self::RunBinaryStream>, Status> {
+        let client = client_identity(&request);
+        let encoding = file_encoding(&request);
+        let trace = logging::remote_parent(&request);
+        let submission = self
+            .uploads
+            .receive_submission(request.into_inner(), encoding, true)
+            .await?;
+        let (_, stream) = self
+            .start_job(client, submission.request, submission.inputs, encoding, trace)
+            .await?;
+        Ok(Response::new(stream))
+    }
+
+    async fn submit_job(
+        &self,
+        request: Request<ComputeRequest>,
+    ) -> Result<Response<SubmitJobResponse>, Status> {
+        let client = client_identity(&request);
+        let trace = logging::remote_parent(&request);
+        let req = request.into_inner();
+        let inputs = self.claim_inputs(&req, &client)?;
+        // Nobody reads the stream; the job's output goes to its log only
+        let (job_id, _) = self
+            .start_job(client, req, inputs, FileEncoding::Identity, trace)
+            .await?;
+        Ok(Response::new(SubmitJobResponse { job_id }))
+    }
+
+    async fn get_job_status(
+        &self,
+        request: Request<JobStatusRequest>,
+    ) -> Result<Response<JobStatusResponse>, Status> {
+        let log = self.job_log(&request, &request.get_ref().job_id)?;
+        Ok(Response::new(log.status()))
+    }
+
+    async fn get_job_output(
+        &self,
+        request: Request<JobOutputRequest>,
+    ) -> Result<Response<JobOutputResponse>, Status> {
+        let log = self.job_log(&request, &request.get_ref().job_id)?;
+        let request = request.into_inner();
+        Ok(Response::new(log.read(request.from_offset, request.max_entries)))
+    }
 
-            // 1. Create temporary workspace
-            if let Err(e) = fs::create_dir_all(&working_dir).await {
-                let _ = tx.send(Err(Status::internal(format!("Failed to create workspace: {}", e)))).await;
-                return;
+    async fn list_jobs(
+        &self,
+        request: Request<ListJobsRequest>,
+    ) -> Result<Response<ListJobsResponse>, Status> {
+        let caller = client_identity(&request);
+        let admin = self.is_admin(&request);
+        let mut request = request.into_inner();
+        if !admin {
+            if !request.submitter.is_empty() && request.submitter != caller {
+                return Err(Status::permission_denied("Only admins may list other users' jobs"));
             }
+            request.submitter = caller;
+        }
+        let jobs = self
+            .history
+            .list(request.submitter, request.limit)
+            .await
+            .map_err(|e| Status::internal(format!("Job history unavailable: {}", e)))?;
+        Ok(Response::new(ListJobsResponse { jobs }))
+    }
 
-            let file_path = working_dir.join(&req.file_name);
-            // Platform agnostic binary extension
-            let bin_name = if cfg!(windows) { "app.exe" } else { "app.out" };
-            let bin_path = working_dir.join(bin_name);
-
-            // 2. Write source code
-            let _ = fs::write(&file_path, &req.source_code).await;
-
-            // 3. Compile with NVCC
-            let compile_status = Command::new("nvcc")
-                .arg(&file_path)
-                .args(&req.compiler_flags)
-                .arg("-o")
-                .arg(&bin_path)
-                .current_dir(&working_dir)
-                .status()
-                .await;
-
-            match compile_status {
-                Ok(s) if s.success() => {
-                    let _ = tx.send(Ok(ComputeResponse { 
-                        output: "🚀 Compilation successful. Running...".into(), 
-                        is_error: false 
-                    })).await;
-                    
-                    // 4. Execute the binary
-                    let output = Command::new(&bin_path)
-                        .current_dir(&working_dir)
-                        .output()
-                        .await;
-
-                    if let Ok(out) = output {
-                        let stdout = String::from_utf8_lossy(&out.stdout);
-                        let stderr = String::from_utf8_lossy(&out.stderr);
-                        
-                        if !stdout.is_empty() {
-                            let _ = tx.send(Ok(ComputeResponse { 
-                                output: stdout.to_string(), 
-                                is_error: false 
-                            })).await;
-                        }
-                        if !stderr.is_empty() {
-                            let _ = tx.send(Ok(ComputeResponse { 
-                                output: stderr.to_string(), 
-                                is_error: true 
-                            })).await;
-                        }
-                    }
-                }
-                _ => {
-                    let _ = tx.send(Ok(ComputeResponse { 
-                        output: "❌ Compilation failed.".into(), 
-                        is_error: true 
-                    })).await;
-                }
+    async fn get_job(&self, request: Request<GetJobRequest>) -> Result<Response<JobRecord>, Status> {
+        let job_id = request.get_ref().job_id.clone();
+        match self.history.get(job_id.clone()).await {
+            Ok(Some(record)) => {
+                self.check_owner(&request, &job_id, &record.submitter)?;
+                Ok(Response::new(record))
             }
+            Ok(None) => Err(Status::not_found(format!("No job {} in the history", job_id))),
+            Err(e) => Err(Status::internal(format!("Job history unavailable: {}", e))),
+        }
+    }
 
-            // 5. Cleanup: Delete the entire job directory
-            let _ = fs::remove_dir_all(&working_dir).await;
-            println!("🧹 Cleaned up job {}", job_id);
-        });
+    async fn purge_workspaces(
+        &self,
+        request: Request<PurgeWorkspacesRequest>,
+    ) -> Result<Response<PurgeWorkspacesResponse>, Status> {
+        let job_id = request.get_ref().job_id.clone();
+        if job_id.is_empty() {
+            if !self.is_admin(&request) {
+                return Err(Status::permission_denied("Only admins may purge every kept workspace"));
+            }
+        } else {
+            // Without a history record there is no owner to check against
+            match self.history.get(job_id.clone()).await {
+                Ok(Some(record)) => self.check_owner(&request, &job_id, &record.submitter)?,
+                _ if self.is_admin(&request) => {}
+                _ => return Err(Status::permission_denied(format!("No record of who submitted job {}", job_id))),
+            }
+        }
+        let purged = self
+            .retention
+            .purge((!job_id.is_empty()).then_some(job_id.as_str()))
+            .await
+            .map_err(|e| Status::internal(format!("Could not purge workspaces: {}", e)))?;
+        info!("🧹 Purging {} kept workspace(s)", purged);
+        Ok(Response::new(PurgeWorkspacesResponse {
+            purged,
+            ..Default::default()
+        }))
+    }
+
+    type AttachJobStream = ReceiverStream<Result<ComputeResponse, Status>>;
 
+    async fn attach_job(
+        &self,
+        request: Request<AttachJobRequest>,
+    ) -> Result<Response<Self::AttachJobStream>, Status> {
+        let log = self.job_log(&request, &request.get_ref().job_id)?;
+        let request = request.into_inner();
+        let (tx, rx) = mpsc::channel(self.stream_buffer);
+        tokio::spawn(log.follow(request.from_offset, tx));
         Ok(Response::new(ReceiverStream::new(rx)))
     }
+
+    async fn cancel_job(
+        &self,
+        request: Request<CancelJobRequest>,
+    ) -> Result<Response<CancelJobResponse>, Status> {
+        self.running_job(&request, &request.get_ref().job_id)?;
+        let user = request.extensions().get::<User>().cloned();
+        let job_id = request.into_inner().job_id;
+        let cancelled = self.registry.cancel(&job_id);
+        if cancelled {
+            match user {
+                Some(User(name)) => info!("🛑 Cancel requested for job {} by {}", job_id, name),
+                None => info!("🛑 Cancel requested for job {}", job_id),
+            }
+        }
+        Ok(Response::new(CancelJobResponse { cancelled }))
+    }
+
+    async fn write_stdin(
+        &self,
+        request: Request<Streaming<StdinChunk>>,
+    ) -> Result<Response<WriteStdinResponse>, Status> {
+        let caller = client_identity(&request);
+        let admin = self.is_admin(&request);
+        let mut chunks = request.into_inner();
+        let mut job_id = String::new();
+        let mut bytes_written = 0u64;
+
+        while let Some(chunk) = chunks.message().await? {
+            if job_id.is_empty() {
+                job_id = chunk.job_id;
+                self.check_running(&caller, admin, &job_id)?;
+            }
+            if !chunk.data.is_empty() {
+                let stdin = self
+                    .registry
+                    .stdin(&job_id)
+                    .ok_or_else(|| Status::not_found(format!("No open stdin for job {}", job_id)))?;
+                bytes_written += chunk.data.len() as u64;
+                stdin
+                    .send(chunk.data)
+                    .await
+                    .map_err(|_| Status::failed_precondition("Program stdin is closed"))?;
+            }
+            if chunk.eof {
+                break;
+            }
+        }
+
+        // Both an explicit EOF and the client closing its stream end the input
+        self.registry.close_stdin(&job_id);
+        Ok(Response::new(WriteStdinResponse { bytes_written }))
+    }
+
+    type DebugTunnelStream = ReceiverStream<Result<DebugData, Status>>;
+
+    async fn debug_tunnel(
+        &self,
+        request: Request<Streaming<DebugData>>,
+    ) -> Result<Response<Self::DebugTunnelStream>, Status> {
+        let caller = client_identity(&request);
+        let admin = self.is_admin(&request);
+        let mut incoming = request.into_inner();
+        let job_id = match incoming.message().await? {
+            Some(first) => first.job_id,
+            None => return Err(Status::invalid_argument("DebugTunnel needs the job id first")),
+        };
+        // The debugger can run any code inside the program
+        self.check_running(&caller, admin, &job_id)?;
+        let port = self
+            .registry
+            .debug_port(&job_id)
+            .ok_or_else(|| Status::not_found(format!("No debug session for job {}", job_id)))?;
+        info!("🐞 Debugger attached to job {}", job_id);
+        let (tx, rx) = mpsc::channel(self.stream_buffer);
+        tokio::spawn(debug::tunnel(port, incoming, tx));
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn launch_kernel(
+        &self,
+        request: Request<LaunchKernelRequest>,
+    ) -> Result<Response<LaunchKernelResponse>, Status> {
+        self.accepting()?;
+        let client = client_identity(&request);
+        let req = request.into_inner();
+        launch::validate(&req, self.max_message_bytes as u64)?;
+        let device_request = req.device_index.map_or(DeviceRequest::Count(1), DeviceRequest::Index);
+        self.ctx.devices.validate(device_request).map_err(Status::invalid_argument)?;
+        let quota = self.quotas.admit(&client)?;
+
+        // Launches share the job slots and GPUs with jobs
+        let _permit = self.queue.enqueue(&client, Priority::Normal).admitted().await;
+        let lease = self.ctx.devices.acquire(device_request).await;
+        let timeout = job::effective_timeout(req.timeout_secs, self.ctx.max_timeout);
+        let span = info_span!("launch", kernel = %req.kernel, client = %client, gpu = %lease.visible_devices());
+        span.in_scope(|| info!("🧩 Launching kernel"));
+        let started = std::time::Instant::now();
+        let id = format!("launch-{}", uuid::Uuid::new_v4());
+        let limits = self.ctx.sandbox.limits();
+        let launched = launch::run(&req, &lease.visible_devices(), timeout, limits, &id)
+            .instrument(span.clone())
+            .await;
+        quota.charge(started.elapsed().as_millis() as u64, lease.devices.len());
+        match &launched {
+            Ok(response) => span.in_scope(|| info!(kernel_ms = response.kernel_ms, "🧩 Kernel finished")),
+            Err(status) => span.in_scope(|| warn!("⚠️ Launch failed: {}", status.message())),
+        }
+        let mut response = launched?;
+        response.device = lease.devices[0];
+        Ok(Response::new(response))
+    }
+
+    async fn upload_inputs(
+        &self,
+        request: Request<Streaming<FileChunk>>,
+    ) -> Result<Response<UploadInputsResponse>, Status> {
+        self.accepting()?;
+        let encoding = file_encoding(&request);
+        let client = client_identity(&request);
+        let response = self.uploads.receive(request.into_inner(), encoding, &client).await?;
+        info!(
+            "📦 Staged upload {} ({} files, {} bytes)",
+            response.upload_id, response.files, response.bytes_received
+        );
+        Ok(Response::new(response))
+    }
+
+    async fn get_host_info(
+        &self,
+        _request: Request<HostInfoRequest>,
+    ) -> Result<Response<HostInfo>, Status> {
+        Ok(Response::new(self.ctx.info(&self.queue).await))
+    }
+}
+
+/// Every GPU nvidia-smi reports, or just device 0 when it can't be queried, so
+/// hosts without nvidia-smi still run jobs on the default GPU.
+async fn detect_gpus() -> Vec<u32> {
+    let found: Vec<u32> = probe::gpus().await.iter().map(|g| g.index).collect();
+    if found.is_empty() { vec![0] } else { found }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "[::1]:50051".parse()?;
-    let executor = HostExecutor;
+    if std::env::args_os().nth(1).is_some_and(|arg| arg == launch::CHILD_FLAG) {
+        std::process::exit(launch::child());
+    }
+    let config = HostConfig::load()?;
+    let logging = logging::init(&config.log_level, config.log_format, config.otlp_endpoint.as_deref())?;
+    let addr = config.listen;
+    let gpus = match config.gpus.clone() {
+        Some(gpus) => gpus,
+        None => detect_gpus().await,
+    };
+    let mig_slices: Vec<_> = probe::mig_slices()
+        .await
+        .into_iter()
+        .filter(|s| gpus.contains(&s.gpu))
+        .collect();
+    let sharing = Sharing {
+        clients: config.mps_clients,
+        memory_mib: match config.mps_clients {
+            0 => HashMap::new(),
+            _ => probe::gpus().await.into_iter().map(|g| (g.index, g.memory_total_mib)).collect(),
+        },
+    };
+    let arch_flags = if config.auto_arch {
+        let usable: Vec<_> = probe::gpus()
+            .await
+            .into_iter()
+            .filter(|g| gpus.contains(&g.index))
+            .collect();
+        arch::flags_for(&usable)
+    } else {
+        Vec::new()
+    };
+    let auth = Authenticator::load(config.tokens_file.as_deref(), config.token.as_deref())?;
+    let cleanup = CleanupQueue::spawn();
+    let retention = Retention::new(
+        config.scratch_dir.join("retained"),
+        config.retain_workspaces,
+        (config.retain_hours > 0).then(|| Duration::from_secs(config.retain_hours * 3600)),
+        cleanup.clone(),
+    );
+    retention.spawn_sweeper();
+    let executor = HostExecutor {
+        uploads: UploadStore::new(config.scratch_dir.join("uploads"), cleanup.clone(), config.max_source_bytes),
+        cleanup,
+        registry: JobRegistry::default(),
+        logs: JobLogs::default(),
+        history: JobHistory::open(&config.history_db)?,
+        retention,
+        queue: JobQueue::new(config.max_concurrent_jobs),
+        quotas: Quotas::new(config.quotas, config.user_quotas.clone()),
+        high_priority_users: config.high_priority_users.clone(),
+        admin_users: config.admin_users.clone(),
+        ctx: HostContext {
+            devices: DeviceAllocator::new(gpus, mig_slices, sharing),
+            cache: Arc::new(CompileCache::new(config.cache_dir.clone(), config.cache_max_mb * 1024 * 1024)),
+            backends: Arc::new(Backends::probe(&config.nvcc, &config.hipcc, &config.cxx).await),
+            compute_sanitizer: config.compute_sanitizer.as_path().into(),
+            cuda_gdbserver: config.cuda_gdbserver.as_path().into(),
+            cmake: config.cmake.as_path().into(),
+            make: config.make.as_path().into(),
+            toolchains: Arc::new(Toolchains::probe(config.toolchains.clone()).await),
+            libraries: Arc::new(Libraries::load(&config.libraries)?),
+            arch_flags: arch_flags.into(),
+            sandbox: Sandbox::new(
+                config.sandbox,
+                &config.sandbox_image,
+                config.allowed_images.clone(),
+                config.private_paths(),
+                Limits::new(config.limits, config.cgroup_root.clone()),
+            ),
+            output_limits: OutputLimits {
+                stdout_bytes: config.max_stdout_bytes,
+                stderr_bytes: config.max_stderr_bytes,
+                action: config.on_output_limit,
+            },
+            flag_policy: Arc::new(FlagPolicy::new(&config.allowed_flags, &config.denied_flags)?),
+            profilers: Arc::new(config.profilers.clone()),
+            max_source_bytes: config.max_source_bytes,
+            max_timeout: config.max_timeout(),
+            max_debug: config.max_debug(),
+            compile_timeout: config.compile_timeout(),
+            gpu_memory_enforcement: config.gpu_memory_enforcement,
+            notifiers: Notifiers::new(config.notifiers.clone(), config.job_webhook_hosts.clone()),
+        },
+        scratch: config.scratch_dir.clone(),
+        stream_buffer: config.stream_buffer,
+        max_message_bytes: config.max_message_bytes,
+        draining: CancellationToken::new(),
+        paused: Arc::new(AtomicBool::new(false)),
+    };
+    if config.scratch_max_age_hours > 0 {
+        ScratchGc {
+            root: config.scratch_dir.clone(),
+            max_age: Duration::from_secs(config.scratch_max_age_hours * 3600),
+            registry: executor.registry.clone(),
+            uploads: executor.uploads.clone(),
+            cleanup: executor.cleanup.clone(),
+        }
+        .spawn();
+    }
 
     // Ensure the base scratch directory exists before we start accepting jobs
-    fs::create_dir_all("scratch").await?;
+    fs::create_dir_all(&config.scratch_dir).await?;
+
+    info!(
+        "🦀 Ferris-Compute-Cuda Host listening on {} (max {} concurrent jobs)",
+        addr, config.max_concurrent_jobs
+    );
+    info!("🎮 GPUs available to jobs: {:?}", executor.ctx.devices.devices());
+    for slice in executor.ctx.devices.slices() {
+        info!("🧩 MIG slice {}/{} ({}, {} MiB): {}", slice.gpu, slice.device, slice.profile, slice.memory_mib, slice.uuid);
+    }
+    if config.mps_clients > 0 {
+        info!("🤝 Up to {} shared jobs per GPU through MPS", config.mps_clients);
+    }
+    if !config.notifiers.is_empty() {
+        info!("🔔 Notifying finished jobs through: {}", executor.ctx.notifiers.kinds().join(", "));
+    }
+    if let Some(broker) = &config.broker {
+        if config.advertise.is_none() && addr.ip().is_unspecified() {
+            warn!("⚠️ Listening on {}; give --advertise an address the broker can reach", addr);
+        }
+        Worker {
+            broker: broker.clone(),
+            address: config.advertise.clone().unwrap_or_else(|| format!("http://{}", addr)),
+            token: config.broker_token.clone(),
+            ctx: executor.ctx.clone(),
+            queue: executor.queue.clone(),
+            registry: executor.registry.clone(),
+        }
+        .spawn()?;
+    }
+    for backend in executor.ctx.backends.info() {
+        info!("🛠 Backend {}: {} {}", backend.accelerator().as_str_name(), backend.compiler, backend.version);
+    }
+    if !executor.ctx.arch_flags.is_empty() {
+        info!("🎯 Default architecture: {}", executor.ctx.arch_flags.join(" "));
+    } else if config.auto_arch {
+        warn!("⚠️ --auto-arch: no compute capability detected, leaving -arch to nvcc");
+    }
+
+    match config.sandbox {
+        SandboxKind::None => {}
+        SandboxKind::Bubblewrap => info!("📦 Running programs under bubblewrap"),
+        SandboxKind::Docker => info!("📦 Running programs in {} containers", config.sandbox_image),
+    }
+    for name in executor.ctx.toolchains.names() {
+        let toolchain = executor.ctx.toolchains.get(name)?;
+        info!("🧰 Toolchain {}: {} ({})", name, toolchain.cuda_home.display(), toolchain.version);
+    }
+    if executor.ctx.libraries.names().next().is_some() {
+        info!("📚 Libraries jobs may link: {}", executor.ctx.libraries.names().collect::<Vec<_>>().join(", "));
+    }
+    if !executor.ctx.profilers.available().is_empty() {
+        info!("🔬 Profilers available to jobs: {}", executor.ctx.profilers.available().join(", "));
+    }
+    match (config.retain_workspaces, config.retain_hours) {
+        (RetainPolicy::Requested, _) => {}
+        (policy, 0) => info!("🗃️ Keeping workspaces ({:?}) until purged", policy),
+        (policy, hours) => info!("🗃️ Keeping workspaces ({:?}) for {}h", policy, hours),
+    }
+    if !config.allowed_flags.is_empty() {
+        info!("🚩 Jobs may only pass flags matching: {}", config.allowed_flags.join(" "));
+    }
+    if !config.allowed_images.is_empty() {
+        info!("🐳 Jobs may use container images: {}", config.allowed_images.join(", "));
+    }
+    if !auth.tokens_enabled() && config.tls_client_ca.is_none() {
+        warn!("⚠️ No tokens configured: anyone who can reach {} can run code", addr);
+    }
+
+    if let Some(metrics_addr) = config.metrics_listen {
+        let (queue, devices) = (executor.queue.clone(), executor.ctx.devices.clone());
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_addr, queue, devices).await {
+                warn!("⚠️ Metrics endpoint on {} failed: {}", metrics_addr, e);
+            }
+        });
+        info!("📈 Serving Prometheus metrics at http://{}/metrics", metrics_addr);
+    }
+
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    HealthCheck::for_service::<CudaExecutorServer<HostExecutor>>(
+        health_reporter,
+        executor.ctx.backends.preferred().path().into(),
+        executor.ctx.backends.offers(Accelerator::Cuda),
+        executor.ctx.devices.devices().to_vec(),
+        executor.queue.clone(),
+        executor.draining.clone(),
+    )
+    .spawn();
+
+    let admin = AdminService {
+        registry: executor.registry.clone(),
+        logs: executor.logs.clone(),
+        cache: executor.ctx.cache.clone(),
+        retention: executor.retention.clone(),
+        cleanup: executor.cleanup.clone(),
+        paused: executor.paused.clone(),
+        admin_users: config.admin_users.clone(),
+        config: config.to_toml()?,
+    };
+    if !config.admin_users.is_empty() && !auth.tokens_enabled() && config.tls_client_ca.is_none() {
+        warn!("⚠️ admin_users is set, but callers aren't authenticated: the Admin service will refuse every call");
+    }
 
-    println!("🦀 Ferris-Compute-Cuda Host listening on {}", addr);
+    let shutdown = Shutdown {
+        draining: executor.draining.clone(),
+        grace: config.shutdown_grace(),
+        registry: executor.registry.clone(),
+        queue: executor.queue.clone(),
+    };
+    let cleanup = executor.cleanup.clone();
 
-    // Start the gRPC server
-    Server::builder()
-        .add_service(CudaExecutorServer::new(executor))
-        .serve(addr)
+    // Start the gRPC server, over TLS when a certificate was given
+    let mut server = Server::builder();
+    if let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) {
+        let identity = Identity::from_pem(fs::read(cert).await?, fs::read(key).await?);
+        let mut tls = ServerTlsConfig::new().identity(identity);
+        if let Some(ca) = &config.tls_client_ca {
+            tls = tls.client_ca_root(Certificate::from_pem(fs::read(ca).await?));
+            info!("🪪 Requiring client certificates signed by {}", ca.display());
+        }
+        server = server.tls_config(tls)?;
+        info!("🔒 TLS enabled with {}", cert.display());
+    }
+    server
+        // Probes can't present tokens, so health is served without auth
+        .add_service(health_service)
+        .add_service(InterceptedService::new(
+            // gzip whole messages for clients that ask for it
+            CudaExecutorServer::new(executor)
+                .accept_compressed(CompressionEncoding::Gzip)
+                .send_compressed(CompressionEncoding::Gzip)
+                .max_decoding_message_size(config.max_message_bytes),
+            auth.clone(),
+        ))
+        .add_service(InterceptedService::new(AdminServer::new(admin), auth))
+        .serve_with_shutdown(addr, shutdown.drain())
         .await?;
 
+    // The last jobs' workspaces may still be on their way out
+    if tokio::time::timeout(Duration::from_secs(30), cleanup.idle()).await.is_err() {
+        warn!("⚠️ Gave up waiting for workspace cleanup");
+    }
+    info!("👋 Host stopped");
+    logging.shutdown();
     Ok(())
-}
\ No newline at end of file
+}
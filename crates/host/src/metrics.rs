@@ -0,0 +1,137 @@
+/// Prometheus metrics, served over plain HTTP at `/metrics` when the host is
+/// started with `--metrics-listen`.
+///
+/// Counters and histograms are updated as jobs go by; queue and GPU gauges are
+/// read from the queue and device allocator at scrape time.
+use crate::devices::DeviceAllocator;
+use crate::queue::JobQueue;
+use axum::Router;
+use axum::routing::get;
+use common::compute::JobStatusResponse;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+
+/// Buckets in seconds, from a cache-warm compile up to the longest run.
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+struct Metrics {
+    registry: Registry,
+    submitted: IntCounter,
+    finished: IntCounterVec,
+    compile_seconds: Histogram,
+    run_seconds: Histogram,
+    streamed_bytes: IntCounterVec,
+    queue_depth: IntGauge,
+    jobs_running: IntGauge,
+    gpu_allocated: IntGaugeVec,
+    cleanup_failures: IntCounter,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(|| {
+    let metrics = Metrics {
+        registry: Registry::new_custom(Some("ferris".into()), None).expect("valid prefix"),
+        submitted: IntCounter::new("jobs_submitted_total", "Jobs accepted by the host").unwrap(),
+        finished: IntCounterVec::new(
+            Opts::new("jobs_finished_total", "Jobs that ended, by final status"),
+            &["status"],
+        )
+        .unwrap(),
+        compile_seconds: Histogram::with_opts(
+            HistogramOpts::new("compile_duration_seconds", "nvcc time of jobs that compiled (cache misses)")
+                .buckets(DURATION_BUCKETS.to_vec()),
+        )
+        .unwrap(),
+        run_seconds: Histogram::with_opts(
+            HistogramOpts::new("run_duration_seconds", "Run time of jobs' programs").buckets(DURATION_BUCKETS.to_vec()),
+        )
+        .unwrap(),
+        streamed_bytes: IntCounterVec::new(
+            Opts::new("streamed_bytes_total", "Bytes sent to clients, by kind (output or artifact)"),
+            &["kind"],
+        )
+        .unwrap(),
+        queue_depth: IntGauge::new("queue_depth", "Jobs waiting for a slot").unwrap(),
+        jobs_running: IntGauge::new("jobs_running", "Jobs holding a slot").unwrap(),
+        gpu_allocated: IntGaugeVec::new(
+            Opts::new("gpu_allocated", "1 while a job holds the GPU"),
+            &["gpu"],
+        )
+        .unwrap(),
+        cleanup_failures: IntCounter::new(
+            "cleanup_failures_total",
+            "Directories the cleanup queue gave up deleting (see `admin orphans`)",
+        )
+        .unwrap(),
+    };
+    let registry = &metrics.registry;
+    registry.register(Box::new(metrics.submitted.clone())).unwrap();
+    registry.register(Box::new(metrics.finished.clone())).unwrap();
+    registry.register(Box::new(metrics.compile_seconds.clone())).unwrap();
+    registry.register(Box::new(metrics.run_seconds.clone())).unwrap();
+    registry.register(Box::new(metrics.streamed_bytes.clone())).unwrap();
+    registry.register(Box::new(metrics.queue_depth.clone())).unwrap();
+    registry.register(Box::new(metrics.jobs_running.clone())).unwrap();
+    registry.register(Box::new(metrics.gpu_allocated.clone())).unwrap();
+    registry.register(Box::new(metrics.cleanup_failures.clone())).unwrap();
+    metrics
+});
+
+pub fn job_submitted() {
+    METRICS.submitted.inc();
+}
+
+/// Records how a job ended, from its log's final status.
+pub fn job_finished(status: &JobStatusResponse) {
+    let Some(result) = &status.result else {
+        METRICS.finished.with_label_values(&["ERROR"]).inc();
+        return;
+    };
+    METRICS.finished.with_label_values(&[result.status().as_str_name()]).inc();
+    if !result.cache_hit && result.compile_ms > 0 {
+        METRICS.compile_seconds.observe(result.compile_ms as f64 / 1000.0);
+    }
+    if result.run_ms > 0 {
+        METRICS.run_seconds.observe(result.run_ms as f64 / 1000.0);
+    }
+}
+
+/// Counts bytes sent on a job stream; `kind` is "output" or "artifact".
+pub fn streamed(kind: &str, bytes: usize) {
+    METRICS.streamed_bytes.with_label_values(&[kind]).inc_by(bytes as u64);
+}
+
+/// Counts a directory the cleanup queue gave up on.
+pub fn cleanup_failed() {
+    METRICS.cleanup_failures.inc();
+}
+
+/// Serves `/metrics` until the process exits.
+pub async fn serve(addr: SocketAddr, queue: JobQueue, devices: DeviceAllocator) -> io::Result<()> {
+    let app = Router::new().route("/metrics", get(move || async move { render(&queue, &devices) }));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).await
+}
+
+fn render(queue: &JobQueue, devices: &DeviceAllocator) -> String {
+    let (running, waiting, _) = queue.load();
+    METRICS.jobs_running.set(running as i64);
+    METRICS.queue_depth.set(waiting as i64);
+    let in_use = devices.in_use();
+    for gpu in devices.devices() {
+        METRICS
+            .gpu_allocated
+            .with_label_values(&[&gpu.to_string()])
+            .set(in_use.contains(gpu) as i64);
+    }
+
+    let mut text = Vec::new();
+    TextEncoder::new()
+        .encode(&METRICS.registry.gather(), &mut text)
+        .expect("writing to a Vec can't fail");
+    String::from_utf8(text).expect("the text format is UTF-8")
+}
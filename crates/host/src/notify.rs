@@ -0,0 +1,686 @@
+/// Notifications when a job finishes, for long jobs nobody keeps a terminal
+/// attached to. A job that sets `notify_url` has its result POSTed there as
+/// JSON; one that sets `notify` is also reported to every notifier in the
+/// host's config: a webhook (the same JSON), a Slack incoming webhook, or
+/// email through an SMTP server.
+///
+/// Jobs may only name webhooks on the hosts in `job_webhook_hosts`, so users
+/// can't point the host at services only it can reach. Notifications are
+/// sent in the background once the job is recorded; each is tried three
+/// times before it is given up on with a warning.
+use base64::Engine;
+use common::compute::{ComputeRequest, JobStatusResponse};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_TYPE, HOST, USER_AGENT};
+use hyper::Uri;
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tracing::{Instrument, info, warn};
+
+const ATTEMPTS: u32 = 3;
+/// For one attempt, connecting included.
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest SMTP reply line read; RFC 5321 allows 512 bytes.
+const MAX_REPLY_LINE: u64 = 4096;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Notifier {
+    /// POSTs the job's result as JSON.
+    Webhook { url: String },
+    /// POSTs a one-line summary to a Slack incoming webhook.
+    Slack { url: String },
+    Email(EmailNotifier),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailNotifier {
+    /// "host:port" of the SMTP server.
+    pub server: String,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    /// For `AUTH PLAIN`; without one the server must relay unauthenticated.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade the connection with STARTTLS, usually on port 587.
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// Plain text, for a relay on the host or its network.
+    None,
+}
+
+/// The JSON a webhook receives.
+#[derive(Serialize)]
+struct Finished<'a> {
+    event: &'static str,
+    job_id: &'a str,
+    submitter: &'a str,
+    files: &'a [String],
+    /// A `JobStatus` name, or "ERROR" if the job ended without a result.
+    status: &'a str,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    compile_ms: u64,
+    run_ms: u64,
+    error: &'a str,
+}
+
+#[derive(Clone, Default)]
+pub struct Notifiers {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    notifiers: Vec<Notifier>,
+    job_webhook_hosts: Vec<String>,
+    /// Loaded on first use.
+    tls: OnceLock<TlsConnector>,
+}
+
+impl Notifiers {
+    pub fn new(notifiers: Vec<Notifier>, job_webhook_hosts: Vec<String>) -> Self {
+        Notifiers {
+            inner: Arc::new(Inner {
+                notifiers,
+                job_webhook_hosts,
+                tls: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// The kinds of notifier configured, for `HostInfo`.
+    pub fn kinds(&self) -> Vec<String> {
+        let mut kinds = Vec::new();
+        for notifier in &self.inner.notifiers {
+            if !kinds.iter().any(|k| k == notifier.kind()) {
+                kinds.push(notifier.kind().to_string());
+            }
+        }
+        kinds
+    }
+
+    /// Refuses a job asking for notifications this host can't send.
+    pub fn check(&self, req: &ComputeRequest) -> Result<(), String> {
+        if req.notify && self.inner.notifiers.is_empty() {
+            return Err("This host has no notifiers configured (see `info`)".into());
+        }
+        if req.notify_url.is_empty() {
+            return Ok(());
+        }
+        let uri: Uri = req
+            .notify_url
+            .parse()
+            .map_err(|e| format!("Invalid notify_url {:?}: {}", req.notify_url, e))?;
+        if !matches!(uri.scheme_str(), Some("http" | "https")) {
+            return Err(format!("notify_url {:?} must be an http:// or https:// URL", req.notify_url));
+        }
+        let host = uri.host().unwrap_or_default().trim_matches(['[', ']']);
+        let allowed = &self.inner.job_webhook_hosts;
+        if !allowed.iter().any(|h| h == "*" || h.eq_ignore_ascii_case(host)) {
+            return Err(match allowed.is_empty() {
+                true => "This host doesn't call jobs' webhooks".into(),
+                false => format!("This host only calls webhooks on {}", allowed.join(", ")),
+            });
+        }
+        Ok(())
+    }
+
+    /// Sends the notifications a job asked for (its `notify` and
+    /// `notify_url`) once it ended with `status`, in the background.
+    pub fn finished(&self, notify: bool, notify_url: &str, submitter: &str, files: &[String], status: &JobStatusResponse) {
+        let notifiers: Vec<Notifier> = match notify {
+            true => self.inner.notifiers.clone(),
+            false => Vec::new(),
+        };
+        let webhook = (!notify_url.is_empty()).then(|| Notifier::Webhook {
+            url: notify_url.to_string(),
+        });
+        let notifiers: Vec<Notifier> = notifiers.into_iter().chain(webhook).collect();
+        if notifiers.is_empty() {
+            return;
+        }
+
+        let result = status.result.clone().unwrap_or_default();
+        let status_name = match &status.result {
+            Some(result) => result.status().as_str_name(),
+            None => "ERROR",
+        };
+        let finished = Finished {
+            event: "job.finished",
+            job_id: &status.job_id,
+            submitter,
+            files,
+            status: status_name,
+            exit_code: result.exit_code,
+            signal: result.signal,
+            compile_ms: result.compile_ms,
+            run_ms: result.run_ms,
+            error: &status.error,
+        };
+        let json = serde_json::to_string(&finished).unwrap_or_default();
+        let summary = summary(&finished);
+        let subject = format!("[ferris] Job {} {}", status.job_id, status_name);
+
+        let this = self.clone();
+        tokio::spawn(
+            async move {
+                for notifier in &notifiers {
+                    this.send(notifier, &json, &summary, &subject).await;
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    async fn send(&self, notifier: &Notifier, json: &str, summary: &str, subject: &str) {
+        for attempt in 1..=ATTEMPTS {
+            let sent = match notifier {
+                Notifier::Webhook { url } => self.post(url, json.to_string()).await,
+                Notifier::Slack { url } => {
+                    let body = serde_json::json!({ "text": summary }).to_string();
+                    self.post(url, body).await
+                }
+                Notifier::Email(email) => self.mail(email, subject, summary).await,
+            };
+            match sent {
+                Ok(()) => {
+                    info!("🔔 Sent {} notification", notifier.kind());
+                    return;
+                }
+                Err(e) if attempt == ATTEMPTS => {
+                    warn!("⚠️ Gave up on {} notification: {}", notifier.kind(), e);
+                }
+                Err(_) => tokio::time::sleep(Duration::from_secs(5 * attempt as u64)).await,
+            }
+        }
+    }
+
+    async fn post(&self, url: &str, body: String) -> Result<(), String> {
+        tokio::time::timeout(SEND_TIMEOUT, self.try_post(url, body))
+            .await
+            .map_err(|_| "timed out".to_string())?
+    }
+
+    async fn try_post(&self, url: &str, body: String) -> Result<(), String> {
+        let uri: Uri = url.parse().map_err(|e| format!("invalid URL: {}", e))?;
+        let host = uri.host().ok_or("the URL has no host")?.trim_matches(['[', ']']);
+        let tls = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err("the URL must be http:// or https://".into()),
+        };
+        let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+        let stream = self.connect(host, port, tls).await?;
+
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::spawn(connection);
+        let request = hyper::Request::post(uri.path_and_query().map_or("/", |p| p.as_str()))
+            .header(HOST, uri.authority().map_or(host, |a| a.as_str()))
+            .header(CONTENT_TYPE, "application/json")
+            .header(USER_AGENT, "ferris-compute-cuda")
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| e.to_string())?;
+        let response = sender.send_request(request).await.map_err(|e| e.to_string())?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(format!("{} answered {}", host, status)),
+        }
+    }
+
+    async fn mail(&self, email: &EmailNotifier, subject: &str, body: &str) -> Result<(), String> {
+        tokio::time::timeout(SEND_TIMEOUT, self.try_mail(email, subject, body))
+            .await
+            .map_err(|_| "timed out".to_string())?
+    }
+
+    /// One message through SMTP (RFC 5321), to every recipient.
+    async fn try_mail(&self, email: &EmailNotifier, subject: &str, body: &str) -> Result<(), String> {
+        let (host, port) = email
+            .server
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host.trim_matches(['[', ']']), port.parse::<u16>().ok()?)))
+            .ok_or_else(|| format!("server {:?} must be \"host:port\"", email.server))?;
+        let tcp = TcpStream::connect((host, port)).await.map_err(|e| e.to_string())?;
+        let helo = match tcp.local_addr().map_err(|e| e.to_string())?.ip() {
+            std::net::IpAddr::V4(ip) => format!("[{}]", ip),
+            std::net::IpAddr::V6(ip) => format!("[IPv6:{}]", ip),
+        };
+        let stream: Box<dyn Io> = match email.tls {
+            SmtpTls::Tls => Box::new(self.tls().connect(server_name(host)?, tcp).await.map_err(|e| e.to_string())?),
+            _ => Box::new(tcp),
+        };
+        let mut smtp = BufReader::new(stream);
+        reply(&mut smtp, 2).await?;
+        command(&mut smtp, &format!("EHLO {}", helo), 2).await?;
+        if email.tls == SmtpTls::Starttls {
+            command(&mut smtp, "STARTTLS", 2).await?;
+            let tls = self
+                .tls()
+                .connect(server_name(host)?, smtp.into_inner())
+                .await
+                .map_err(|e| e.to_string())?;
+            smtp = BufReader::new(Box::new(tls));
+            command(&mut smtp, &format!("EHLO {}", helo), 2).await?;
+        }
+        if let Some(username) = &email.username {
+            let password = email.password.as_deref().unwrap_or_default();
+            let credentials =
+                base64::engine::general_purpose::STANDARD.encode(format!("\0{}\0{}", username, password));
+            command(&mut smtp, &format!("AUTH PLAIN {}", credentials), 2).await?;
+        }
+        command(&mut smtp, &format!("MAIL FROM:<{}>", email.from), 2).await?;
+        for to in &email.to {
+            command(&mut smtp, &format!("RCPT TO:<{}>", to), 2).await?;
+        }
+        command(&mut smtp, "DATA", 3).await?;
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+            email.from,
+            email.to.join(", "),
+            subject
+        );
+        message.push_str(&data_lines(body));
+        message.push('.');
+        command(&mut smtp, &message, 2).await?;
+        let _ = command(&mut smtp, "QUIT", 2).await;
+        Ok(())
+    }
+
+    async fn connect(&self, host: &str, port: u16, tls: bool) -> Result<Box<dyn Io>, String> {
+        let tcp = TcpStream::connect((host, port)).await.map_err(|e| e.to_string())?;
+        if !tls {
+            return Ok(Box::new(tcp));
+        }
+        let stream = self.tls().connect(server_name(host)?, tcp).await.map_err(|e| e.to_string())?;
+        Ok(Box::new(stream))
+    }
+
+    /// Trusts the system's root certificates.
+    fn tls(&self) -> &TlsConnector {
+        self.inner.tls.get_or_init(|| {
+            let mut roots = RootCertStore::empty();
+            let native = rustls_native_certs::load_native_certs();
+            if let Some(e) = native.errors.first() {
+                warn!("⚠️ Could not load every system root certificate: {}", e);
+            }
+            roots.add_parsable_certificates(native.certs);
+            let config = ClientConfig::builder_with_provider(Arc::new(tokio_rustls::rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .expect("ring supports the default TLS versions")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            TlsConnector::from(Arc::new(config))
+        })
+    }
+}
+
+impl Notifier {
+    fn kind(&self) -> &'static str {
+        match self {
+            Notifier::Webhook { .. } => "webhook",
+            Notifier::Slack { .. } => "slack",
+            Notifier::Email(_) => "email",
+        }
+    }
+}
+
+/// A connection, encrypted or not.
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+fn server_name(host: &str) -> Result<ServerName<'static>, String> {
+    ServerName::try_from(host.to_string()).map_err(|e| format!("{}: {}", host, e))
+}
+
+/// E.g. "✅ Job 1234 (kernel.cu) by alice: SUCCEEDED, exit 0, compiled in 1.2s, ran 3.4s".
+fn summary(job: &Finished) -> String {
+    let icon = if job.status == "SUCCEEDED" { "✅" } else { "❌" };
+    let mut line = format!("{} Job {} ({}) by {}: {}", icon, job.job_id, job.files.join(" "), job.submitter, job.status);
+    match (job.exit_code, job.signal) {
+        (Some(code), _) => line.push_str(&format!(", exit {}", code)),
+        (None, Some(signal)) => line.push_str(&format!(", signal {}", signal)),
+        (None, None) => {}
+    }
+    if job.compile_ms > 0 {
+        line.push_str(&format!(", compiled in {:.1}s", job.compile_ms as f64 / 1000.0));
+    }
+    if job.run_ms > 0 {
+        line.push_str(&format!(", ran {:.1}s", job.run_ms as f64 / 1000.0));
+    }
+    if !job.error.is_empty() {
+        line.push_str(&format!("\n{}", job.error));
+    }
+    line
+}
+
+/// `body` as the lines of an SMTP message: every line break (CRLF, or a bare
+/// CR or LF) becomes CRLF, and a leading dot is doubled, so no line can end
+/// the message early.
+fn data_lines(body: &str) -> String {
+    let mut lines = String::new();
+    for line in body.replace("\r\n", "\n").replace('\r', "\n").lines() {
+        if line.starts_with('.') {
+            lines.push('.');
+        }
+        lines.push_str(line);
+        lines.push_str("\r\n");
+    }
+    lines
+}
+
+/// Sends one command (or, for DATA, the message) and checks the reply is of
+/// `class`: 2 for done, 3 for go on.
+async fn command(smtp: &mut BufReader<Box<dyn Io>>, line: &str, class: u16) -> Result<(), String> {
+    smtp.write_all(format!("{}\r\n", line).as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    smtp.flush().await.map_err(|e| e.to_string())?;
+    reply(smtp, class).await
+}
+
+/// Reads a reply, which may span lines ("250-..." up to "250 ...").
+async fn reply(smtp: &mut BufReader<Box<dyn Io>>, class: u16) -> Result<(), String> {
+    let mut text = String::new();
+    loop {
+        let mut line = String::new();
+        let read = (&mut *smtp)
+            .take(MAX_REPLY_LINE)
+            .read_line(&mut line)
+            .await
+            .map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("the SMTP server closed the connection".into());
+        }
+        if !line.ends_with('\n') {
+            return Err(format!("the SMTP server sent a line over {} bytes", MAX_REPLY_LINE));
+        }
+        let code: u16 = line.get(..3).and_then(|c| c.parse().ok()).ok_or_else(|| format!("not SMTP: {:?}", line))?;
+        text.push_str(line.get(4..).unwrap_or_default().trim_end());
+        if line.as_bytes().get(3) == Some(&b'-') {
+            text.push(' ');
+            continue;
+        }
+        return match code / 100 == class {
+            true => Ok(()),
+            false => Err(format!("the SMTP server answered {} {}", code, text)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// What a fake server heard: SMTP commands, or HTTP request lines and
+    /// headers, and the message or request body.
+    #[derive(Debug, Default)]
+    struct Heard {
+        lines: Vec<String>,
+        body: String,
+    }
+
+    /// A one-connection SMTP server. `answer` replies to each command, or
+    /// hangs up on `None`; DATA is taken care of.
+    async fn smtp_server(mut answer: impl FnMut(&str) -> Option<String> + Send + 'static) -> (String, JoinHandle<Heard>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let task = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            let mut heard = Heard::default();
+            socket.write_all(b"220 fake ESMTP\r\n").await.unwrap();
+            while let Some(line) = next_line(&mut socket).await {
+                heard.lines.push(line.clone());
+                let reply = match line.as_str() {
+                    "DATA" => {
+                        socket.write_all(b"354 go ahead\r\n").await.unwrap();
+                        while let Some(data) = next_line(&mut socket).await.filter(|data| data != ".") {
+                            heard.body.push_str(&data);
+                            heard.body.push('\n');
+                        }
+                        "250 queued".to_string()
+                    }
+                    "QUIT" => "221 bye".to_string(),
+                    command => match answer(command) {
+                        Some(reply) => reply,
+                        None => break,
+                    },
+                };
+                socket.write_all(format!("{}\r\n", reply).as_bytes()).await.unwrap();
+            }
+            heard
+        });
+        (server, task)
+    }
+
+    /// A one-request HTTP server answering with `status`.
+    async fn http_server(status: u16) -> (String, JoinHandle<Heard>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let task = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            let mut heard = Heard::default();
+            while let Some(line) = next_line(&mut socket).await.filter(|line| !line.is_empty()) {
+                heard.lines.push(line);
+            }
+            let length = heard
+                .lines
+                .iter()
+                .find_map(|line| line.to_lowercase().strip_prefix("content-length: ")?.parse().ok())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            socket.read_exact(&mut body).await.unwrap();
+            heard.body = String::from_utf8(body).unwrap();
+            let response = format!("HTTP/1.1 {} Whatever\r\ncontent-length: 0\r\n\r\n", status);
+            socket.write_all(response.as_bytes()).await.unwrap();
+            heard
+        });
+        (format!("http://{}", address), task)
+    }
+
+    /// A line without its CRLF, or `None` at the end of the stream.
+    async fn next_line(reader: &mut (impl AsyncBufRead + Unpin)) -> Option<String> {
+        let mut line = String::new();
+        match reader.read_line(&mut line).await {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+        }
+    }
+
+    fn email(server: String, tls: SmtpTls) -> EmailNotifier {
+        EmailNotifier {
+            server,
+            tls,
+            username: None,
+            password: None,
+            from: "ferris@example.com".into(),
+            to: vec!["alice@example.com".into(), "bob@example.com".into()],
+        }
+    }
+
+    /// Answers EHLO with a multi-line reply and everything else with 250.
+    fn relay(command: &str) -> Option<String> {
+        match command.starts_with("EHLO") {
+            true => Some("250-fake\r\n250-STARTTLS\r\n250 AUTH PLAIN".into()),
+            false => Some("250 ok".into()),
+        }
+    }
+
+    #[tokio::test]
+    async fn mail_goes_to_every_recipient() {
+        let (server, heard) = smtp_server(relay).await;
+        let notifiers = Notifiers::default();
+        notifiers
+            .try_mail(&email(server, SmtpTls::None), "[ferris] Job 1 SUCCEEDED", "✅ done")
+            .await
+            .unwrap();
+
+        let heard = heard.await.unwrap();
+        assert!(heard.lines[0].starts_with("EHLO [127.0.0.1]"), "{:?}", heard.lines);
+        assert_eq!(
+            heard.lines[1..],
+            [
+                "MAIL FROM:<ferris@example.com>",
+                "RCPT TO:<alice@example.com>",
+                "RCPT TO:<bob@example.com>",
+                "DATA",
+                "QUIT"
+            ]
+        );
+        assert!(heard.body.contains("Subject: [ferris] Job 1 SUCCEEDED\n"), "{}", heard.body);
+        assert!(heard.body.ends_with("\n✅ done\n"), "{}", heard.body);
+    }
+
+    #[tokio::test]
+    async fn no_line_of_the_body_can_end_the_message() {
+        let (server, heard) = smtp_server(relay).await;
+        let body = "first\n.\n.hidden\r.bare\r\n..two\r\nlast\r.\r\nMAIL FROM:<x@y>";
+        Notifiers::default()
+            .try_mail(&email(server, SmtpTls::None), "subject", body)
+            .await
+            .unwrap();
+
+        let heard = heard.await.unwrap();
+        let (_, text) = heard.body.split_once("\n\n").unwrap();
+        assert_eq!(text, "first\n..\n..hidden\n..bare\n...two\nlast\n..\nMAIL FROM:<x@y>\n");
+        assert_eq!(heard.lines.iter().filter(|line| line.starts_with("MAIL")).count(), 1);
+        assert_eq!(data_lines("a\rb\r\nc\n"), "a\r\nb\r\nc\r\n");
+    }
+
+    #[tokio::test]
+    async fn credentials_are_sent_with_auth_plain() {
+        let (server, heard) = smtp_server(relay).await;
+        let mut email = email(server, SmtpTls::None);
+        email.username = Some("alice".into());
+        email.password = Some("s3cret".into());
+        Notifiers::default().try_mail(&email, "subject", "body").await.unwrap();
+
+        let heard = heard.await.unwrap();
+        let expected = base64::engine::general_purpose::STANDARD.encode("\0alice\0s3cret");
+        assert_eq!(heard.lines[1], format!("AUTH PLAIN {}", expected));
+        assert_eq!(heard.lines[2], "MAIL FROM:<ferris@example.com>");
+    }
+
+    #[tokio::test]
+    async fn a_rejected_login_fails_the_mail() {
+        let (server, heard) = smtp_server(|command| match command.starts_with("AUTH") {
+            true => Some("535 5.7.8 authentication failed".into()),
+            false => relay(command),
+        })
+        .await;
+        let mut email = email(server, SmtpTls::None);
+        email.username = Some("alice".into());
+        let error = Notifiers::default().try_mail(&email, "subject", "body").await.unwrap_err();
+        assert!(error.contains("535"), "{}", error);
+        assert!(!heard.await.unwrap().lines.iter().any(|line| line.starts_with("MAIL")));
+    }
+
+    #[tokio::test]
+    async fn a_refused_starttls_never_falls_back_to_plain_text() {
+        let (server, heard) = smtp_server(|command| match command {
+            "STARTTLS" => Some("454 4.7.0 TLS not available".into()),
+            command => relay(command),
+        })
+        .await;
+        let mut email = email(server, SmtpTls::Starttls);
+        email.username = Some("alice".into());
+        email.password = Some("s3cret".into());
+        let error = Notifiers::default().try_mail(&email, "subject", "body").await.unwrap_err();
+        assert!(error.contains("454"), "{}", error);
+
+        let heard = heard.await.unwrap();
+        assert_eq!(heard.lines.last().map(String::as_str), Some("STARTTLS"));
+        assert!(!heard.lines.iter().any(|line| line.starts_with("AUTH")));
+    }
+
+    #[tokio::test]
+    async fn a_failed_tls_handshake_after_starttls_fails_the_mail() {
+        // Agrees to STARTTLS, then hangs up instead of a handshake
+        let (server, heard) = smtp_server(|command| match command {
+            "STARTTLS" => Some("220 go ahead".into()),
+            command if command.starts_with("EHLO") => relay(command),
+            _ => None,
+        })
+        .await;
+        let mut email = email(server.replace("127.0.0.1", "localhost"), SmtpTls::Starttls);
+        email.username = Some("alice".into());
+        assert!(Notifiers::default().try_mail(&email, "subject", "body").await.is_err());
+        assert!(!heard.await.unwrap().lines.iter().any(|line| line.starts_with("AUTH")));
+    }
+
+    #[tokio::test]
+    async fn an_endless_reply_line_is_cut_off() {
+        let (server, _heard) = smtp_server(|_| Some(format!("250 {}", "x".repeat(MAX_REPLY_LINE as usize * 2)))).await;
+        let error = Notifiers::default()
+            .try_mail(&email(server, SmtpTls::None), "subject", "body")
+            .await
+            .unwrap_err();
+        assert!(error.contains("over 4096 bytes"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn webhooks_receive_the_result_as_json() {
+        let (url, heard) = http_server(204).await;
+        Notifiers::default()
+            .try_post(&format!("{}/hooks/ferris?team=gpu", url), r#"{"event":"job.finished"}"#.into())
+            .await
+            .unwrap();
+
+        let heard = heard.await.unwrap();
+        assert_eq!(heard.lines[0], "POST /hooks/ferris?team=gpu HTTP/1.1");
+        let headers: Vec<String> = heard.lines.iter().map(|line| line.to_lowercase()).collect();
+        assert!(headers.contains(&"content-type: application/json".to_string()), "{:?}", headers);
+        assert!(headers.contains(&format!("host: {}", url.trim_start_matches("http://"))), "{:?}", headers);
+        assert_eq!(heard.body, r#"{"event":"job.finished"}"#);
+    }
+
+    #[tokio::test]
+    async fn a_webhook_answering_an_error_fails_the_post() {
+        let (url, _heard) = http_server(503).await;
+        let error = Notifiers::default().try_post(&url, "{}".into()).await.unwrap_err();
+        assert!(error.contains("503"), "{}", error);
+    }
+
+    #[test]
+    fn jobs_may_only_name_allowed_webhook_hosts() {
+        let notifiers = Notifiers::new(Vec::new(), vec!["hooks.example.com".into()]);
+        let request = |url: &str| ComputeRequest {
+            notify_url: url.into(),
+            ..Default::default()
+        };
+        assert!(notifiers.check(&request("https://hooks.example.com/x")).is_ok());
+        assert!(notifiers.check(&request("http://HOOKS.example.com:8080/x")).is_ok());
+        assert!(notifiers.check(&request("http://169.254.169.254/latest")).is_err());
+        assert!(notifiers.check(&request("file:///etc/passwd")).is_err());
+        let notify = ComputeRequest {
+            notify: true,
+            ..Default::default()
+        };
+        assert!(notifiers.check(&notify).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+/// Per-job caps on how much program output is forwarded, so a kernel printing
+/// in a tight loop can't flood the response channel and the client's terminal.
+///
+/// stdout and stderr are counted separately. Once a pipe goes over its limit
+/// the client gets a single "truncated" notice, and the pipe is either muted
+/// (drained but no longer forwarded) or the program is killed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowAction {
+    /// Keep the program running, drop the rest of that pipe's output
+    Mute,
+    /// Kill the program
+    Kill,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutputLimits {
+    /// 0 means unlimited.
+    pub stdout_bytes: u64,
+    /// 0 means unlimited.
+    pub stderr_bytes: u64,
+    pub action: OverflowAction,
+}
+
+/// What to do with the next line of a pipe.
+pub enum Verdict {
+    Forward,
+    /// This line crossed the limit: tell the client, then apply the action.
+    Truncate { limit: u64, action: OverflowAction },
+    /// The pipe was already muted.
+    Drop,
+}
+
+/// One job run's consumption of its limits.
+pub struct OutputBudget {
+    limits: OutputLimits,
+    used: [u64; 2],
+    muted: [bool; 2],
+}
+
+impl OutputBudget {
+    pub fn new(limits: OutputLimits) -> Self {
+        OutputBudget {
+            limits,
+            used: [0; 2],
+            muted: [false; 2],
+        }
+    }
+
+    /// Forwards nothing, e.g. for benchmark runs after the first.
+    pub fn silent() -> Self {
+        OutputBudget {
+            limits: OutputLimits {
+                stdout_bytes: 0,
+                stderr_bytes: 0,
+                action: OverflowAction::Mute,
+            },
+            used: [0; 2],
+            muted: [true; 2],
+        }
+    }
+
+    /// Accounts for a line of `len` bytes (without its newline).
+    pub fn admit(&mut self, is_error: bool, len: usize) -> Verdict {
+        let pipe = is_error as usize;
+        let limit = if is_error { self.limits.stderr_bytes } else { self.limits.stdout_bytes };
+        if self.muted[pipe] {
+            return Verdict::Drop;
+        }
+        self.used[pipe] += len as u64 + 1;
+        if limit == 0 || self.used[pipe] <= limit {
+            return Verdict::Forward;
+        }
+        self.muted[pipe] = true;
+        Verdict::Truncate {
+            limit,
+            action: self.limits.action,
+        }
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.muted.iter().any(|m| *m)
+    }
+}
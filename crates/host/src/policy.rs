@@ -0,0 +1,208 @@
+/// What nvcc flags a job may pass. Some rules are built in and always apply:
+/// flags that run programs, pick the executables nvcc invokes, read more
+/// flags from files, or choose where the output goes are refused, output
+/// paths must stay inside the workspace, and options forwarded to the host
+/// compiler or linker (`-Xcompiler`, `-Xlinker`) are checked one by one.
+///
+/// On top of that the host can configure glob patterns: with an allowlist,
+/// only flags matching it are accepted; a denylist refuses matching flags.
+/// Patterns match the flag up to any `=`, e.g. "-arch*", "-O?", "-D*".
+use glob::Pattern;
+
+/// Never allowed, with the reason given to the client.
+const FORBIDDEN: &[(&str, &str)] = &[
+    ("-run", "runs the program outside the job's sandbox and limits"),
+    ("--run", "runs the program outside the job's sandbox and limits"),
+    ("-run-args", "runs the program outside the job's sandbox and limits"),
+    ("--run-args", "runs the program outside the job's sandbox and limits"),
+    ("-ccbin", "chooses the host compiler executable"),
+    ("--compiler-bindir", "chooses the host compiler executable"),
+    ("-optf", "reads further flags from a file"),
+    ("--options-file", "reads further flags from a file"),
+    ("-o", "the host chooses the output file"),
+    ("--output-file", "the host chooses the output file"),
+];
+
+/// Flags whose value is a path nvcc writes to.
+const OUTPUT_PATH_FLAGS: &[&str] = &[
+    "-odir",
+    "--output-directory",
+    "-MF",
+    "--dependency-output",
+    "-keep-dir",
+    "--keep-dir",
+];
+
+/// Flags forwarding comma-separated options to the host compiler or linker,
+/// and the option prefixes refused there: those that load code, run other
+/// programs, read options from files or redirect output.
+const PASSTHROUGH_FLAGS: &[(&str, &[&str])] = &[
+    ("-Xcompiler", COMPILER_FORBIDDEN),
+    ("--compiler-options", COMPILER_FORBIDDEN),
+    ("-Xlinker", LINKER_FORBIDDEN),
+    ("--linker-options", LINKER_FORBIDDEN),
+];
+/// `-Wl,` arguments are split on commas too, so the linker's list applies.
+const COMPILER_FORBIDDEN: &[&str] = &[
+    "-fplugin", "-fpass-plugin", "-Xclang", "-wrapper", "-B", "-specs", "--specs", "-o", "@", "-plugin",
+    "--plugin", "-dynamic-linker", "--dynamic-linker",
+];
+const LINKER_FORBIDDEN: &[&str] = &["-o", "@", "-plugin", "--plugin", "-dynamic-linker", "--dynamic-linker"];
+
+/// Flags that take the next argument as their value when not written `flag=value`.
+const VALUE_FLAGS: &[&str] = &[
+    "-Xcompiler", "--compiler-options", "-Xlinker", "--linker-options", "-Xptxas", "--ptxas-options",
+    "-Xnvlink", "--nvlink-options", "-Xarchive", "--archive-options", "-odir", "--output-directory",
+    "-MF", "--dependency-output", "-keep-dir", "--keep-dir", "-I", "--include-path", "-isystem",
+    "--system-include", "-include", "--pre-include", "-L", "--library-path", "-l", "--library", "-D",
+    "--define-macro", "-U", "--undefine-macro", "-arch", "--gpu-architecture", "-code", "--gpu-code",
+    "-gencode", "--generate-code", "-std", "--std", "-maxrregcount", "--maxrregcount", "-x", "--x",
+    "-rdc", "--relocatable-device-code", "-t", "--threads", "-Werror", "--Werror", "-diag-suppress",
+    "--diag-suppress", "-ccbin", "--compiler-bindir", "-optf", "--options-file", "-o", "--output-file",
+    "-run-args", "--run-args",
+];
+
+/// g++, clang++ and hipcc take host compiler options directly rather than
+/// through `-Xcompiler`, so for them every flag is held to the same prefixes,
+/// and whatever `-Wl,` or `-Xlinker` hands the linker to the linker's.
+pub fn check_host_compiler(flags: &[String]) -> Result<(), String> {
+    let mut args = flags.iter();
+    while let Some(arg) = args.next() {
+        if let Some(bad) = COMPILER_FORBIDDEN.iter().find(|bad| arg.starts_with(*bad)) {
+            return Err(format!("Flag {} is not allowed: it loads code, runs programs, reads files or picks the output", bad));
+        }
+        let linker: Vec<&str> = if let Some(options) = arg.strip_prefix("-Wl,") {
+            options.split(',').collect()
+        } else if arg == "-Xlinker" {
+            args.next().map(String::as_str).into_iter().collect()
+        } else if let Some(option) = arg.strip_prefix("-Xlinker=") {
+            vec![option]
+        } else {
+            continue;
+        };
+        for option in linker {
+            if let Some(bad) = LINKER_FORBIDDEN.iter().find(|bad| option.starts_with(*bad)) {
+                return Err(format!("Linker option {} is not allowed", bad));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct FlagPolicy {
+    allowed: Vec<Pattern>,
+    denied: Vec<Pattern>,
+}
+
+impl FlagPolicy {
+    pub fn new(allowed: &[String], denied: &[String]) -> Result<Self, String> {
+        let compile = |globs: &[String]| -> Result<Vec<Pattern>, String> {
+            globs
+                .iter()
+                .map(|glob| Pattern::new(glob).map_err(|e| format!("Bad flag pattern {:?}: {}", glob, e)))
+                .collect()
+        };
+        Ok(FlagPolicy {
+            allowed: compile(allowed)?,
+            denied: compile(denied)?,
+        })
+    }
+
+    /// Explains the first flag the policy refuses, if any.
+    pub fn check(&self, flags: &[String]) -> Result<(), String> {
+        let mut args = flags.iter();
+        while let Some(arg) = args.next() {
+            if arg.starts_with('@') {
+                return Err(format!("{} is not allowed: it reads further flags from a file", arg));
+            }
+            if !arg.starts_with('-') {
+                // Anything else nvcc is given is an input file
+                if crate::job::relative_path(arg).is_none() {
+                    return Err(format!("Input {:?} must be a path inside the workspace", arg));
+                }
+                continue;
+            }
+
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            if let Some((_, why)) = FORBIDDEN.iter().find(|(flag, _)| *flag == name) {
+                return Err(format!("Flag {} is not allowed: it {}", name, why));
+            }
+            // `-o` also takes its value attached, as in `-o/tmp/app`
+            if name.starts_with("-o") && !OUTPUT_PATH_FLAGS.contains(&name) {
+                return Err("Flag -o is not allowed: the host chooses the output file".to_string());
+            }
+            if self.denied.iter().any(|p| p.matches(name)) {
+                return Err(format!("Flag {} is denied by the host's policy", name));
+            }
+            if !self.allowed.is_empty() && !self.allowed.iter().any(|p| p.matches(name)) {
+                return Err(format!("Flag {} is not on the host's list of allowed flags", name));
+            }
+
+            let value = match inline {
+                Some(value) => Some(value),
+                None if VALUE_FLAGS.contains(&name) => args.next().map(String::as_str),
+                None => None,
+            };
+            let Some(value) = value else {
+                continue;
+            };
+            if OUTPUT_PATH_FLAGS.contains(&name) && crate::job::relative_path(value).is_none() {
+                return Err(format!("{} must name a path inside the workspace, not {:?}", name, value));
+            }
+            if let Some((_, forbidden)) = PASSTHROUGH_FLAGS.iter().find(|(flag, _)| *flag == name) {
+                for option in value.split([',', ' ']).map(str::trim).filter(|o| !o.is_empty()) {
+                    if let Some(bad) = forbidden.iter().find(|bad| option.starts_with(*bad)) {
+                        return Err(format!("{} option {} is not allowed", name, bad));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn refuses_what_escapes_the_workspace() {
+        let policy = FlagPolicy::default();
+        assert!(policy.check(&flags(&["-O3", "-arch=sm_80", "-odir", "out", "kernel.cu"])).is_ok());
+        for args in [
+            &["-o", "app"][..],
+            &["-o/abs/path"],
+            &["-oapp"],
+            &["--output-file=app"],
+            &["-ccbin", "/tmp/cc"],
+            &["-run"],
+            &["@/etc/shadow"],
+            &["@../x"],
+            &["@flags.txt"],
+            &["/etc/shadow"],
+            &["../x.cu"],
+            &["-odir", "/tmp"],
+            &["-MF=../deps"],
+            &["-Xcompiler", "-fplugin=./evil.so"],
+            &["-Xlinker=-plugin,./evil.so"],
+        ] {
+            assert!(policy.check(&flags(args)).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn applies_the_hosts_patterns() {
+        let policy = FlagPolicy::new(&flags(&["-O?", "-arch*"]), &flags(&["-O0"])).unwrap();
+        assert!(policy.check(&flags(&["-O3", "-arch=sm_80"])).is_ok());
+        assert!(policy.check(&flags(&["-O0"])).unwrap_err().contains("denied"));
+        assert!(policy.check(&flags(&["-G"])).unwrap_err().contains("allowed flags"));
+    }
+}
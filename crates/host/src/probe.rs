@@ -0,0 +1,213 @@
+/// Queries about the host's GPUs and toolchains, via `nvidia-smi` and the
+/// compilers' `--version`.
+use common::compute::{GpuInfo, GpuSample, MigSlice};
+use std::path::Path;
+use tokio::process::Command;
+
+/// The last line of `<nvcc> --version` (e.g. "Build cuda_12.4.r12.4/compiler.34097967_0"),
+/// or "unknown" if nvcc can't be run.
+pub async fn nvcc_version(nvcc: &Path) -> String {
+    version_output(nvcc)
+        .await
+        .and_then(|out| out.lines().rfind(|l| !l.trim().is_empty()).map(|l| l.trim().to_string()))
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// The HIP version `<hipcc> --version` reports (e.g. "6.1.40091-a8dbc0c19"),
+/// or "unknown" if hipcc can't be run.
+pub async fn hipcc_version(hipcc: &Path) -> String {
+    version_output(hipcc)
+        .await
+        .and_then(|out| {
+            out.lines()
+                .find_map(|l| l.trim().strip_prefix("HIP version:"))
+                .map(|v| v.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// The first line of `<cxx> --version` (e.g. "g++ (Debian 12.2.0-14) 12.2.0"),
+/// or "unknown" if the compiler can't be run.
+pub async fn cxx_version(cxx: &Path) -> String {
+    version_output(cxx)
+        .await
+        .and_then(|out| out.lines().next().map(|l| l.trim().to_string()))
+        .filter(|l| !l.is_empty())
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// What `<compiler> --version` prints, if it runs.
+pub async fn version_output(compiler: &Path) -> Option<String> {
+    let out = Command::new(compiler).arg("--version").output().await.ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Every GPU `nvidia-smi` reports, with live memory figures. Empty if
+/// `nvidia-smi` is missing or fails.
+pub async fn gpus() -> Vec<GpuInfo> {
+    let Some(csv) = nvidia_smi(&[
+        "--query-gpu=index,name,compute_cap,memory.total,memory.free",
+        "--format=csv,noheader,nounits",
+    ])
+    .await
+    else {
+        return Vec::new();
+    };
+
+    csv.lines()
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [index, name, cap, total, free] = cols.as_slice() else {
+                return None;
+            };
+            Some(GpuInfo {
+                index: index.parse().ok()?,
+                name: name.to_string(),
+                compute_capability: cap.to_string(),
+                memory_total_mib: total.parse().unwrap_or(0),
+                memory_free_mib: free.parse().unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// The MIG slices `nvidia-smi -L` lists, under lines such as
+///
+/// ```text
+/// GPU 0: NVIDIA A100-SXM4-40GB (UUID: GPU-5d5ba0d6-...)
+///   MIG 3g.20gb     Device  0: (UUID: MIG-c6d4f1ef-...)
+/// ```
+///
+/// Empty if MIG is off everywhere or `nvidia-smi` is missing or fails.
+pub async fn mig_slices() -> Vec<MigSlice> {
+    let Some(list) = nvidia_smi(&["-L"]).await else {
+        return Vec::new();
+    };
+
+    let mut gpu = None;
+    let mut slices = Vec::new();
+    for line in list.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("GPU ") {
+            gpu = rest.split(':').next().and_then(|i| i.parse().ok());
+            continue;
+        }
+        let (Some(gpu), Some(rest)) = (gpu, line.strip_prefix("MIG ")) else {
+            continue;
+        };
+        let mut words = rest.split_whitespace();
+        let (Some(profile), Some("Device"), Some(device)) = (words.next(), words.next(), words.next()) else {
+            continue;
+        };
+        let (Ok(device), Some(uuid)) = (device.trim_end_matches(':').parse(), rest.split("UUID:").nth(1)) else {
+            continue;
+        };
+        slices.push(MigSlice {
+            gpu,
+            device,
+            uuid: uuid.trim().trim_end_matches(')').to_string(),
+            profile: profile.to_string(),
+            memory_mib: profile_memory_mib(profile),
+            busy: false,
+        });
+    }
+    slices
+}
+
+/// The memory a MIG profile names, e.g. 20480 for "3g.20gb" or "1g.10gb+me".
+fn profile_memory_mib(profile: &str) -> u64 {
+    let gb = profile.split('.').nth(1).unwrap_or("");
+    let digits = gb.find(|c: char| !c.is_ascii_digit()).map_or(gb, |end| &gb[..end]);
+    digits.parse::<u64>().unwrap_or(0) * 1024
+}
+
+/// Current utilization, memory, temperature and power of `devices`. Empty if
+/// `nvidia-smi` is missing or fails.
+pub async fn samples(devices: &[u32]) -> Vec<GpuSample> {
+    let ids = devices.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+    let Some(csv) = nvidia_smi(&[
+        "--query-gpu=index,utilization.gpu,memory.used,memory.total,temperature.gpu,power.draw",
+        "--format=csv,noheader,nounits",
+        "-i",
+        &ids,
+    ])
+    .await
+    else {
+        return Vec::new();
+    };
+
+    // Unsupported figures read "[N/A]"
+    csv.lines()
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [index, utilization, used, total, temperature, power] = cols.as_slice() else {
+                return None;
+            };
+            Some(GpuSample {
+                index: index.parse().ok()?,
+                utilization_percent: utilization.parse().unwrap_or(0),
+                memory_used_mib: used.parse().unwrap_or(0),
+                memory_total_mib: total.parse().unwrap_or(0),
+                temperature_c: temperature.parse().unwrap_or(0),
+                power_watts: power.parse().unwrap_or(0.0),
+            })
+        })
+        .filter(|sample| devices.contains(&sample.index))
+        .collect()
+}
+
+/// A process using a GPU, as `nvidia-smi --query-compute-apps` lists it.
+pub struct ComputeApp {
+    pub gpu_uuid: String,
+    pub pid: u32,
+    pub used_mib: u64,
+}
+
+/// Every process using a GPU. Empty if `nvidia-smi` is missing or fails.
+pub async fn compute_apps() -> Vec<ComputeApp> {
+    let Some(csv) = nvidia_smi(&["--query-compute-apps=gpu_uuid,pid,used_memory", "--format=csv,noheader,nounits"]).await
+    else {
+        return Vec::new();
+    };
+
+    csv.lines()
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [gpu_uuid, pid, used] = cols.as_slice() else {
+                return None;
+            };
+            Some(ComputeApp {
+                gpu_uuid: gpu_uuid.to_string(),
+                pid: pid.parse().ok()?,
+                used_mib: used.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// (driver version, highest supported CUDA version), "unknown" where unavailable.
+pub async fn driver_versions() -> (String, String) {
+    let driver = nvidia_smi(&["--query-gpu=driver_version", "--format=csv,noheader"])
+        .await
+        .and_then(|out| out.lines().next().map(|l| l.trim().to_string()))
+        .unwrap_or_else(|| "unknown".into());
+
+    // Only the banner of plain `nvidia-smi` has it: "... CUDA Version: 12.4 |"
+    let cuda = nvidia_smi(&[])
+        .await
+        .and_then(|out| {
+            let rest = out.split("CUDA Version:").nth(1)?;
+            rest.split_whitespace().next().map(str::to_string)
+        })
+        .unwrap_or_else(|| "unknown".into());
+
+    (driver, cuda)
+}
+
+async fn nvidia_smi(args: &[&str]) -> Option<String> {
+    let out = Command::new("nvidia-smi").args(args).output().await.ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+}
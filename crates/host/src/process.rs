@@ -0,0 +1,22 @@
+/// Spawning helpers that keep every child in its own process group, so killing a
+/// job also kills anything it forked (nvcc's cicc/ptxas, or the user's own children).
+use tokio::process::{Child, Command};
+
+pub fn isolate(cmd: &mut Command) {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    cmd.kill_on_drop(true);
+}
+
+/// Kills the child's whole process group (falling back to the child alone).
+pub fn kill_tree(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: killpg has no memory-safety preconditions; `pid` is the
+        // group leader we created in `isolate`.
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    let _ = child.start_kill();
+}
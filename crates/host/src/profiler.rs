@@ -0,0 +1,100 @@
+/// Profiling with Nsight Systems (`nsys`) or Nsight Compute (`ncu`): the
+/// program runs under the profiler, whose summary is streamed like any other
+/// output, and its report file is sent back as an artifact.
+///
+/// Profilers are off until the host config names them:
+///
+/// ```toml
+/// [profilers]
+/// nsys = "/usr/local/cuda/bin/nsys"
+/// ncu = "/usr/local/cuda/bin/ncu"
+/// ncu_args = ["--set", "full"]
+/// ```
+///
+/// They run on the host's own filesystem, so they're not available to jobs
+/// in docker containers.
+use common::compute::Profiler;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Report file name, without the extension the profiler adds.
+const REPORT: &str = "profile";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfilerConfig {
+    pub nsys: Option<PathBuf>,
+    pub ncu: Option<PathBuf>,
+    /// Extra arguments after `nsys profile`, e.g. `["--trace=cuda,nvtx"]`.
+    pub nsys_args: Vec<String>,
+    /// Extra arguments to `ncu`, e.g. `["--set", "full"]`.
+    pub ncu_args: Vec<String>,
+}
+
+impl ProfilerConfig {
+    /// Names of the configured profilers, for `HostInfo`.
+    pub fn available(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        if self.nsys.is_some() {
+            names.push("nsys".to_string());
+        }
+        if self.ncu.is_some() {
+            names.push("ncu".to_string());
+        }
+        names
+    }
+
+    pub fn check(&self, profiler: Profiler) -> Result<(), String> {
+        match profiler {
+            Profiler::None => Ok(()),
+            Profiler::Nsys if self.nsys.is_some() => Ok(()),
+            Profiler::Ncu if self.ncu.is_some() => Ok(()),
+            _ => Err(format!("This host has no {} configured", name(profiler))),
+        }
+    }
+
+    /// The profiler to launch and its arguments, ending with `program` and
+    /// `args`; `None` when the job isn't profiled.
+    pub fn wrap(&self, profiler: Profiler, program: &Path, args: &[String]) -> Option<(PathBuf, Vec<OsString>)> {
+        let (tool, mut wrapped): (_, Vec<OsString>) = match profiler {
+            Profiler::None => return None,
+            Profiler::Nsys => (
+                self.nsys.clone()?,
+                ["profile", "--stats=true", "--force-overwrite=true", "-o", REPORT]
+                    .into_iter()
+                    .map(OsString::from)
+                    .chain(self.nsys_args.iter().map(OsString::from))
+                    .collect(),
+            ),
+            Profiler::Ncu => (
+                self.ncu.clone()?,
+                ["--force-overwrite", "--export", REPORT]
+                    .into_iter()
+                    .map(OsString::from)
+                    .chain(self.ncu_args.iter().map(OsString::from))
+                    .collect(),
+            ),
+        };
+        wrapped.push(program.into());
+        wrapped.extend(args.iter().map(OsString::from));
+        Some((tool, wrapped))
+    }
+}
+
+/// The report the profiler leaves in the workspace.
+pub fn report_name(profiler: Profiler) -> Option<&'static str> {
+    match profiler {
+        Profiler::None => None,
+        Profiler::Nsys => Some("profile.nsys-rep"),
+        Profiler::Ncu => Some("profile.ncu-rep"),
+    }
+}
+
+fn name(profiler: Profiler) -> &'static str {
+    match profiler {
+        Profiler::None => "profiler",
+        Profiler::Nsys => "Nsight Systems (nsys)",
+        Profiler::Ncu => "Nsight Compute (ncu)",
+    }
+}
@@ -0,0 +1,88 @@
+/// Reads ptxas's verbose report out of nvcc's output, so the resources each
+/// kernel uses come back as `JobResult.kernels` rather than text:
+///
+/// ```text
+/// ptxas info    : Compiling entry function '_Z4fillPii' for 'sm_80'
+/// ptxas info    : Function properties for _Z4fillPii
+///     16 bytes stack frame, 8 bytes spill stores, 8 bytes spill loads
+/// ptxas info    : Used 32 registers, 4096 bytes smem, 364 bytes cmem[0]
+/// ```
+///
+/// Properties of device functions that weren't inlined are reported too;
+/// only entry functions (kernels) are kept.
+use common::compute::KernelStats;
+
+/// Whether nvcc's flags ask for ptxas's report.
+pub fn requested(flags: &[String]) -> bool {
+    let verbose = |options: &str| options.split(',').any(|o| o == "-v" || o == "--verbose");
+    flags.iter().enumerate().any(|(i, flag)| match flag.as_str() {
+        "--resource-usage" | "-res-usage" => true,
+        "-Xptxas" | "--ptxas-options" => flags.get(i + 1).is_some_and(|next| verbose(next)),
+        _ => flag
+            .strip_prefix("-Xptxas=")
+            .or_else(|| flag.strip_prefix("--ptxas-options="))
+            .is_some_and(verbose),
+    })
+}
+
+#[derive(Default)]
+pub struct ResourceUsage {
+    kernels: Vec<KernelStats>,
+    /// The function the next `stack frame` line is about.
+    properties_of: Option<String>,
+}
+
+impl ResourceUsage {
+    /// Takes one line of nvcc's output; anything but ptxas's report is ignored.
+    pub fn push(&mut self, line: &str) {
+        let line = line.trim();
+        if let Some(info) = line.strip_prefix("ptxas info").map(|rest| rest.trim_start().trim_start_matches(':').trim()) {
+            if let Some(entry) = info.strip_prefix("Compiling entry function ") {
+                let mut quoted = entry.split('\'').skip(1).step_by(2);
+                self.kernels.push(KernelStats {
+                    name: quoted.next().unwrap_or_default().to_string(),
+                    arch: quoted.next().unwrap_or_default().to_string(),
+                    ..Default::default()
+                });
+            } else if let Some(function) = info.strip_prefix("Function properties for ") {
+                self.properties_of = Some(function.trim().to_string());
+            } else if let Some(used) = info.strip_prefix("Used ")
+                && let Some(kernel) = self.kernels.last_mut()
+            {
+                for (amount, what) in amounts(used) {
+                    match what {
+                        "registers" => kernel.registers = amount as u32,
+                        "bytes smem" => kernel.shared_bytes += amount,
+                        w if w.starts_with("bytes cmem") => kernel.constant_bytes += amount,
+                        _ => {}
+                    }
+                }
+            }
+        } else if line.contains("bytes stack frame")
+            && let Some(function) = self.properties_of.take()
+            && let Some(kernel) = self.kernels.iter_mut().rev().find(|k| k.name == function)
+        {
+            for (amount, what) in amounts(line) {
+                match what {
+                    "bytes stack frame" => kernel.stack_bytes = amount,
+                    "bytes spill stores" => kernel.spill_stores = amount,
+                    "bytes spill loads" => kernel.spill_loads = amount,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub fn into_kernels(self) -> Vec<KernelStats> {
+        self.kernels
+    }
+}
+
+/// `(32, "registers")`, `(4096, "bytes smem")`, ... from a comma-separated
+/// list such as `32 registers, 4096 bytes smem`.
+fn amounts(list: &str) -> impl Iterator<Item = (u64, &str)> {
+    list.split(',').filter_map(|item| {
+        let (amount, what) = item.trim().split_once(' ')?;
+        Some((amount.parse().ok()?, what.trim()))
+    })
+}
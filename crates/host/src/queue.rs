@@ -0,0 +1,317 @@
+/// Admission queue limiting how many jobs compile/run at once.
+///
+/// Fairness policy: when a slot frees up, the waiting job of the highest
+/// priority wins; among those, the one whose client has the fewest jobs
+/// running or ahead of it, ties broken by arrival order. A client that submits
+/// ten jobs at once therefore can't starve a client that submits one; the two
+/// are interleaved.
+///
+/// Priority preempts places in line only: a high-priority job overtakes
+/// waiting lower-priority ones, which are told so, but never interrupts a
+/// running job.
+use common::compute::Priority;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, watch};
+
+#[derive(Clone)]
+pub struct JobQueue {
+    inner: Arc<Mutex<State>>,
+}
+
+struct State {
+    max_concurrent: usize,
+    running: usize,
+    running_per_client: HashMap<String, usize>,
+    waiting: Vec<Waiter>,
+    next_seq: u64,
+    /// Set on shutdown: nothing more is admitted.
+    closed: bool,
+}
+
+struct Waiter {
+    seq: u64,
+    client: String,
+    priority: Priority,
+    grant: oneshot::Sender<Permit>,
+    place: watch::Sender<Place>,
+}
+
+/// Where a waiting job stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Place {
+    /// 1-based position among waiting jobs; 0 once admitted.
+    pub position: usize,
+    /// Higher-priority jobs ahead of this one that were queued after it.
+    pub overtaken_by: usize,
+}
+
+/// Held for the lifetime of an admitted job; dropping it frees the slot.
+pub struct Permit {
+    queue: JobQueue,
+    client: String,
+    armed: bool,
+}
+
+/// A job's place in line. Dropping it leaves the queue.
+pub struct Ticket {
+    queue: JobQueue,
+    seq: u64,
+    grant: oneshot::Receiver<Permit>,
+    /// `position` is `usize::MAX` until first published.
+    pub place: watch::Receiver<Place>,
+}
+
+impl JobQueue {
+    pub fn new(max_concurrent: usize) -> Self {
+        JobQueue {
+            inner: Arc::new(Mutex::new(State {
+                max_concurrent: max_concurrent.max(1),
+                running: 0,
+                running_per_client: HashMap::new(),
+                waiting: Vec::new(),
+                next_seq: 0,
+                closed: false,
+            })),
+        }
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.inner.lock().unwrap().max_concurrent
+    }
+
+    /// Jobs admitted and not yet finished.
+    pub fn running(&self) -> usize {
+        self.inner.lock().unwrap().running
+    }
+
+    /// `(running, waiting, max concurrent)`.
+    pub fn load(&self) -> (usize, usize, usize) {
+        let state = self.inner.lock().unwrap();
+        (state.running, state.waiting.len(), state.max_concurrent)
+    }
+
+    /// Stops admitting jobs. Those already waiting stay queued until they are
+    /// cancelled.
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+    }
+
+    pub fn enqueue(&self, client: &str, priority: Priority) -> Ticket {
+        let (grant_tx, grant_rx) = oneshot::channel();
+        let (place_tx, place_rx) = watch::channel(Place {
+            position: usize::MAX,
+            overtaken_by: 0,
+        });
+
+        let mut state = self.inner.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.waiting.push(Waiter {
+            seq,
+            client: client.to_string(),
+            priority,
+            grant: grant_tx,
+            place: place_tx,
+        });
+        self.dispatch(&mut state);
+
+        Ticket {
+            queue: self.clone(),
+            seq,
+            grant: grant_rx,
+            place: place_rx,
+        }
+    }
+
+    /// Admits waiting jobs while slots are free, then republishes positions.
+    fn dispatch(&self, state: &mut State) {
+        while !state.closed && state.running < state.max_concurrent && !state.waiting.is_empty() {
+            sort_fairly(state);
+            let waiter = state.waiting.remove(0);
+
+            state.running += 1;
+            *state.running_per_client.entry(waiter.client.clone()).or_default() += 1;
+            let permit = Permit {
+                queue: self.clone(),
+                client: waiter.client.clone(),
+                armed: true,
+            };
+            let _ = waiter.place.send(Place {
+                position: 0,
+                overtaken_by: 0,
+            });
+
+            if let Err(mut permit) = waiter.grant.send(permit) {
+                // The job went away between enqueueing and admission. Undo by
+                // hand: letting the permit's Drop run would re-lock `state`.
+                permit.armed = false;
+                release(state, &waiter.client);
+            }
+        }
+
+        sort_fairly(state);
+        for (i, waiter) in state.waiting.iter().enumerate() {
+            let overtaken_by = state.waiting[..i]
+                .iter()
+                .filter(|w| w.seq > waiter.seq && rank(w.priority) > rank(waiter.priority))
+                .count();
+            let _ = waiter.place.send(Place {
+                position: i + 1,
+                overtaken_by,
+            });
+        }
+    }
+}
+
+fn sort_fairly(state: &mut State) {
+    let keys: HashMap<u64, (Reverse<u8>, usize, u64)> = state
+        .waiting
+        .iter()
+        .map(|w| (w.seq, fair_key(state, w)))
+        .collect();
+    state.waiting.sort_by_key(|w| keys[&w.seq]);
+}
+
+/// Orders waiters by priority, then by how many jobs their client already has
+/// running or ahead of them in line, then by arrival.
+fn fair_key(state: &State, waiter: &Waiter) -> (Reverse<u8>, usize, u64) {
+    let running = state.running_per_client.get(&waiter.client).copied().unwrap_or(0);
+    let ahead = state
+        .waiting
+        .iter()
+        .filter(|w| w.client == waiter.client && w.seq < waiter.seq)
+        .count();
+    (Reverse(rank(waiter.priority)), running + ahead, waiter.seq)
+}
+
+/// The enum's values aren't in priority order (normal is the default, 0).
+fn rank(priority: Priority) -> u8 {
+    match priority {
+        Priority::Low => 0,
+        Priority::Normal => 1,
+        Priority::High => 2,
+    }
+}
+
+fn release(state: &mut State, client: &str) {
+    state.running -= 1;
+    if let Some(n) = state.running_per_client.get_mut(client) {
+        *n -= 1;
+        if *n == 0 {
+            state.running_per_client.remove(client);
+        }
+    }
+}
+
+impl Ticket {
+    /// Waits for admission. Positions can be observed through `place` meanwhile.
+    pub async fn admitted(&mut self) -> Permit {
+        (&mut self.grant)
+            .await
+            .expect("the queue never drops a waiter's grant without dropping the waiter")
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let queue = self.queue.clone();
+        let mut state = queue.inner.lock().unwrap();
+        let before = state.waiting.len();
+        state.waiting.retain(|w| w.seq != self.seq);
+        if state.waiting.len() != before {
+            queue.dispatch(&mut state);
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let queue = self.queue.clone();
+        let mut state = queue.inner.lock().unwrap();
+        release(&mut state, &self.client);
+        queue.dispatch(&mut state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(ticket: &Ticket) -> usize {
+        ticket.place.borrow().position
+    }
+
+    #[tokio::test]
+    async fn a_client_with_one_job_goes_before_a_busier_one() {
+        let queue = JobQueue::new(2);
+        let first = queue.enqueue("alice", Priority::Normal).admitted().await;
+        let second = queue.enqueue("alice", Priority::Normal).admitted().await;
+        let mut alice = [
+            queue.enqueue("alice", Priority::Normal),
+            queue.enqueue("alice", Priority::Normal),
+        ];
+        let mut bob = queue.enqueue("bob", Priority::Normal);
+        assert_eq!([position(&bob), position(&alice[0]), position(&alice[1])], [1, 2, 3]);
+
+        // Alice still has a job running, so bob's goes first
+        drop(first);
+        let _bob = bob.admitted().await;
+        assert_eq!([position(&alice[0]), position(&alice[1])], [1, 2]);
+        drop(second);
+        let _alice = alice[0].admitted().await;
+        assert_eq!(position(&alice[1]), 1);
+        assert_eq!(queue.load(), (2, 1, 2));
+    }
+
+    #[tokio::test]
+    async fn higher_priorities_overtake_and_say_so() {
+        let queue = JobQueue::new(1);
+        let _running = queue.enqueue("alice", Priority::Normal).admitted().await;
+        let low = queue.enqueue("bob", Priority::Low);
+        let normal = queue.enqueue("carol", Priority::Normal);
+        let high = queue.enqueue("dave", Priority::High);
+
+        assert_eq!(*high.place.borrow(), Place { position: 1, overtaken_by: 0 });
+        assert_eq!(*normal.place.borrow(), Place { position: 2, overtaken_by: 1 });
+        assert_eq!(*low.place.borrow(), Place { position: 3, overtaken_by: 2 });
+    }
+
+    #[tokio::test]
+    async fn leaving_the_line_moves_everyone_up() {
+        let queue = JobQueue::new(1);
+        let _running = queue.enqueue("alice", Priority::Normal).admitted().await;
+        let bob = queue.enqueue("bob", Priority::Normal);
+        let carol = queue.enqueue("carol", Priority::Normal);
+        assert_eq!(position(&carol), 2);
+        drop(bob);
+        assert_eq!(position(&carol), 1);
+        assert_eq!(queue.load(), (1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn a_job_gone_before_admission_gives_its_slot_back() {
+        let queue = JobQueue::new(1);
+        // Admitted at once, but never collected
+        drop(queue.enqueue("alice", Priority::Normal));
+        assert_eq!(queue.running(), 0);
+        let mut bob = queue.enqueue("bob", Priority::Normal);
+        let _running = bob.admitted().await;
+        assert_eq!(queue.running(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_closed_queue_admits_nothing_more() {
+        let queue = JobQueue::new(1);
+        let running = queue.enqueue("alice", Priority::Normal).admitted().await;
+        let bob = queue.enqueue("bob", Priority::Normal);
+        queue.close();
+        drop(running);
+        assert_eq!(queue.load(), (0, 1, 1));
+        assert_eq!(position(&bob), 1);
+    }
+}
@@ -0,0 +1,155 @@
+/// Per-identity limits: how many jobs a user may have queued or running at
+/// once, how many they may submit per hour, and how many GPU-seconds their
+/// programs may use per day. The identity is the authenticated user (token or
+/// client certificate), or the remote address without auth.
+///
+/// A submission over a limit fails with RESOURCE_EXHAUSTED and a `retry-after`
+/// metadata entry (seconds). Usage is kept in memory, so it starts from zero
+/// when the host restarts.
+use common::compute::JobStatusResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Status};
+
+const HOUR: Duration = Duration::from_secs(3600);
+const DAY: Duration = Duration::from_secs(24 * 3600);
+/// Suggested wait when a user is at their concurrency limit; a slot frees up
+/// whenever one of their jobs ends, which we can't predict.
+const CONCURRENCY_RETRY: Duration = Duration::from_secs(30);
+
+/// Limits for one identity; 0 (or unset, in an override) means unlimited.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaLimits {
+    pub max_jobs: u32,
+    pub jobs_per_hour: u32,
+    pub gpu_seconds_per_day: u64,
+}
+
+#[derive(Default)]
+struct Usage {
+    active: u32,
+    submitted: VecDeque<Instant>,
+    /// `(when the job ended, GPU-seconds it used)`.
+    gpu_seconds: VecDeque<(Instant, f64)>,
+}
+
+#[derive(Clone)]
+pub struct Quotas {
+    default: QuotaLimits,
+    overrides: Arc<HashMap<String, QuotaLimits>>,
+    usage: Arc<Mutex<HashMap<String, Usage>>>,
+}
+
+/// One admitted job's hold on its owner's quota; dropping it ends the job.
+pub struct QuotaGuard {
+    quotas: Quotas,
+    identity: String,
+}
+
+impl Quotas {
+    pub fn new(default: QuotaLimits, overrides: HashMap<String, QuotaLimits>) -> Self {
+        Quotas {
+            default,
+            overrides: Arc::new(overrides),
+            usage: Arc::default(),
+        }
+    }
+
+    fn limits(&self, identity: &str) -> QuotaLimits {
+        self.overrides.get(identity).copied().unwrap_or(self.default)
+    }
+
+    /// Counts a new job against `identity`, or explains which limit it hit.
+    #[allow(clippy::result_large_err)]
+    pub fn admit(&self, identity: &str) -> Result<QuotaGuard, Status> {
+        let limits = self.limits(identity);
+        let now = Instant::now();
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(identity.to_string()).or_default();
+        while usage.submitted.front().is_some_and(|t| now - *t > HOUR) {
+            usage.submitted.pop_front();
+        }
+        while usage.gpu_seconds.front().is_some_and(|(t, _)| now - *t > DAY) {
+            usage.gpu_seconds.pop_front();
+        }
+
+        if limits.max_jobs > 0 && usage.active >= limits.max_jobs {
+            return Err(exhausted(
+                format!("{} already has {} jobs queued or running (limit {})", identity, usage.active, limits.max_jobs),
+                CONCURRENCY_RETRY,
+            ));
+        }
+        if limits.jobs_per_hour > 0 && usage.submitted.len() >= limits.jobs_per_hour as usize {
+            let oldest = usage.submitted[0];
+            return Err(exhausted(
+                format!("{} submitted {} jobs in the last hour (limit {})", identity, usage.submitted.len(), limits.jobs_per_hour),
+                HOUR.saturating_sub(now - oldest),
+            ));
+        }
+        let used: f64 = usage.gpu_seconds.iter().map(|(_, s)| s).sum();
+        if limits.gpu_seconds_per_day > 0 && used >= limits.gpu_seconds_per_day as f64 {
+            // Wait until enough of the day's usage has aged out
+            let mut over = used - limits.gpu_seconds_per_day as f64;
+            let mut retry = DAY;
+            for (ended, seconds) in &usage.gpu_seconds {
+                over -= seconds;
+                if over < 0.0 {
+                    retry = DAY.saturating_sub(now - *ended);
+                    break;
+                }
+            }
+            return Err(exhausted(
+                format!("{} used {:.0} GPU-seconds in the last day (limit {})", identity, used, limits.gpu_seconds_per_day),
+                retry,
+            ));
+        }
+
+        usage.active += 1;
+        usage.submitted.push_back(now);
+        Ok(QuotaGuard {
+            quotas: self.clone(),
+            identity: identity.to_string(),
+        })
+    }
+}
+
+impl QuotaGuard {
+    /// Charges the job's GPU time: run time multiplied by the GPUs it held.
+    pub fn finished(&self, status: &JobStatusResponse) {
+        let Some(result) = &status.result else {
+            return;
+        };
+        self.charge(result.run_ms, result.devices.len());
+    }
+
+    /// Charges GPU time used outside a job's result, e.g. by a kernel launch.
+    pub fn charge(&self, run_ms: u64, gpus: usize) {
+        let seconds = run_ms as f64 / 1000.0 * gpus as f64;
+        if seconds > 0.0 {
+            let mut usage = self.quotas.usage.lock().unwrap();
+            if let Some(usage) = usage.get_mut(&self.identity) {
+                usage.gpu_seconds.push_back((Instant::now(), seconds));
+            }
+        }
+    }
+}
+
+impl Drop for QuotaGuard {
+    fn drop(&mut self) {
+        if let Some(usage) = self.quotas.usage.lock().unwrap().get_mut(&self.identity) {
+            usage.active -= 1;
+        }
+    }
+}
+
+fn exhausted(message: String, retry_after: Duration) -> Status {
+    let mut metadata = MetadataMap::new();
+    // Round up so a client that waits exactly this long is let in
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    metadata.insert("retry-after", secs.max(1).to_string().parse().unwrap());
+    Status::with_metadata(Code::ResourceExhausted, message, metadata)
+}
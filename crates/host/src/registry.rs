@@ -0,0 +1,138 @@
+/// Jobs that are currently running, keyed by job id, so other RPCs can reach them.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// How many stdin chunks may be buffered before `WriteStdin` is back-pressured.
+const STDIN_BUFFER: usize = 16;
+
+/// The registry's side of a running job.
+struct RunningJob {
+    cancel: CancellationToken,
+    /// `None` once the client has signalled EOF.
+    stdin: Option<mpsc::Sender<Vec<u8>>>,
+    debug_port: Arc<OnceLock<u16>>,
+    submitter: String,
+    submitted: SystemTime,
+}
+
+/// A job running or queued, as the Admin service lists it.
+pub struct Registered {
+    pub job_id: String,
+    pub submitter: String,
+    pub submitted: SystemTime,
+}
+
+/// The job pipeline's side: what it must watch and where its stdin comes from.
+pub struct JobControl {
+    pub cancel: CancellationToken,
+    pub stdin: mpsc::Receiver<Vec<u8>>,
+    /// Set by a `debug` job once it knows its gdbserver's local port.
+    pub debug_port: Arc<OnceLock<u16>>,
+}
+
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<HashMap<String, RunningJob>>>,
+}
+
+impl JobRegistry {
+    /// Registers a job and returns the controls its pipeline should watch.
+    pub fn register(&self, job_id: &str, submitter: &str) -> JobControl {
+        let cancel = CancellationToken::new();
+        let (stdin_tx, stdin_rx) = mpsc::channel(STDIN_BUFFER);
+        let debug_port = Arc::new(OnceLock::new());
+        let job = RunningJob {
+            cancel: cancel.clone(),
+            stdin: Some(stdin_tx),
+            debug_port: debug_port.clone(),
+            submitter: submitter.to_string(),
+            submitted: SystemTime::now(),
+        };
+        self.jobs.lock().unwrap().insert(job_id.to_string(), job);
+        JobControl {
+            cancel,
+            stdin: stdin_rx,
+            debug_port,
+        }
+    }
+
+    pub fn unregister(&self, job_id: &str) {
+        self.jobs.lock().unwrap().remove(job_id);
+    }
+
+    pub fn is_running(&self, job_id: &str) -> bool {
+        self.jobs.lock().unwrap().contains_key(job_id)
+    }
+
+    /// Signals cancellation. Returns false if no such job is running.
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(job) => {
+                job.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every job running or queued.
+    pub fn ids(&self) -> Vec<String> {
+        self.jobs.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Every job running or queued, oldest first.
+    pub fn list(&self) -> Vec<Registered> {
+        let mut jobs: Vec<Registered> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, job)| Registered {
+                job_id: id.clone(),
+                submitter: job.submitter.clone(),
+                submitted: job.submitted,
+            })
+            .collect();
+        jobs.sort_by_key(|job| job.submitted);
+        jobs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.lock().unwrap().is_empty()
+    }
+
+    /// Signals cancellation to every job, running or queued. Returns how many.
+    pub fn cancel_all(&self) -> usize {
+        let jobs = self.jobs.lock().unwrap();
+        for job in jobs.values() {
+            job.cancel.cancel();
+        }
+        jobs.len()
+    }
+
+    /// Who submitted a running or queued job.
+    pub fn submitter(&self, job_id: &str) -> Option<String> {
+        Some(self.jobs.lock().unwrap().get(job_id)?.submitter.clone())
+    }
+
+    /// The sender feeding a job's stdin, if the job is running and stdin is still open.
+    pub fn stdin(&self, job_id: &str) -> Option<mpsc::Sender<Vec<u8>>> {
+        self.jobs.lock().unwrap().get(job_id)?.stdin.clone()
+    }
+
+    /// Drops the registry's stdin sender so the program sees EOF once the
+    /// buffered chunks are written.
+    pub fn close_stdin(&self, job_id: &str) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(job_id) {
+            job.stdin = None;
+        }
+    }
+
+    /// The port a running `debug` job's gdbserver listens on, once it has one.
+    pub fn debug_port(&self, job_id: &str) -> Option<u16> {
+        self.jobs.lock().unwrap().get(job_id)?.debug_port.get().copied()
+    }
+}
@@ -0,0 +1,134 @@
+/// Keeping job workspaces around after the job ends, so a crashed program's
+/// binary, inputs and core dump can be inspected.
+///
+/// A kept workspace is moved to `<dir>/<job id>` instead of being deleted. It
+/// is removed by `PurgeWorkspaces`, or by the periodic sweep once it is older
+/// than the configured age. The sweep works from directory mtimes, so it also
+/// covers workspaces kept before a restart.
+use crate::cleanup::CleanupQueue;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tracing::warn;
+
+/// How often expired workspaces are looked for.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetainPolicy {
+    /// Only jobs that ask for it (`keep_workspace`)
+    Requested,
+    /// Jobs that did not succeed, plus requested ones
+    Failed,
+    /// Every job
+    Always,
+}
+
+#[derive(Clone)]
+pub struct Retention {
+    dir: PathBuf,
+    policy: RetainPolicy,
+    /// `None` keeps workspaces until they are purged.
+    max_age: Option<Duration>,
+    cleanup: CleanupQueue,
+}
+
+impl Retention {
+    pub fn new(dir: PathBuf, policy: RetainPolicy, max_age: Option<Duration>, cleanup: CleanupQueue) -> Self {
+        Retention {
+            dir,
+            policy,
+            max_age,
+            cleanup,
+        }
+    }
+
+    pub fn wants(&self, succeeded: bool, requested: bool) -> bool {
+        requested
+            || match self.policy {
+                RetainPolicy::Requested => false,
+                RetainPolicy::Failed => !succeeded,
+                RetainPolicy::Always => true,
+            }
+    }
+
+    /// Moves a finished job's workspace into the retention directory and
+    /// returns its new path.
+    pub async fn keep(&self, job_id: &str, workspace: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir).await?;
+        let dest = self.dir.join(job_id);
+        fs::rename(workspace, &dest).await?;
+        // A rename keeps the old mtime; the age counts from now
+        std::fs::File::open(&dest)?.set_modified(SystemTime::now())?;
+        Ok(dest)
+    }
+
+    /// Deletes one kept workspace, or all of them. Returns how many.
+    pub async fn purge(&self, job_id: Option<&str>) -> io::Result<u32> {
+        let mut purged = 0;
+        match job_id {
+            Some(job_id) => {
+                let dir = crate::job::relative_path(job_id)
+                    .filter(|p| p.components().count() == 1)
+                    .map(|p| self.dir.join(p))
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid job id"))?;
+                if fs::metadata(&dir).await.is_ok() {
+                    self.cleanup.schedule(dir);
+                    purged += 1;
+                }
+            }
+            None => {
+                for dir in self.entries().await? {
+                    self.cleanup.schedule(dir);
+                    purged += 1;
+                }
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Periodically deletes workspaces older than the maximum age.
+    pub fn spawn_sweeper(&self) {
+        let Some(max_age) = self.max_age else {
+            return;
+        };
+        let retention = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = retention.sweep(max_age).await {
+                    warn!("⚠️ Could not sweep {}: {}", retention.dir.display(), e);
+                }
+                tokio::time::sleep(SWEEP_INTERVAL).await;
+            }
+        });
+    }
+
+    async fn sweep(&self, max_age: Duration) -> io::Result<()> {
+        for dir in self.entries().await? {
+            let expired = fs::metadata(&dir)
+                .await
+                .and_then(|m| m.modified())
+                .map(|modified| modified.elapsed().unwrap_or_default() > max_age)
+                .unwrap_or(false);
+            if expired {
+                self.cleanup.schedule(dir);
+            }
+        }
+        Ok(())
+    }
+
+    async fn entries(&self) -> io::Result<Vec<PathBuf>> {
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut dirs = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            dirs.push(entry.path());
+        }
+        Ok(dirs)
+    }
+}
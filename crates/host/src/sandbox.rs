@@ -0,0 +1,295 @@
+/// Where compiled programs run. `none` runs the binary directly as the host
+/// user; `bubblewrap` confines it in unprivileged Linux namespaces with no
+/// network and a read-only view of the system, minus the host's own data and
+/// secrets; `docker` runs it in a throwaway container. In every case the workspace is the only writable directory and
+/// output is streamed back the same way.
+///
+/// Under `bubblewrap` the compiler is confined the same way. A job may also
+/// bring its own image, in which case both nvcc and the program run inside
+/// it, so one host can serve several CUDA toolkits.
+///
+/// Every command is held to the host's per-job resource limits.
+use crate::limits::Limits;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxKind {
+    None,
+    Bubblewrap,
+    Docker,
+}
+
+#[derive(Clone)]
+pub struct Sandbox {
+    kind: SandboxKind,
+    /// Image for the `docker` backend.
+    image: Arc<str>,
+    /// Compile inside `image` too; only true for images that jobs brought.
+    compile_inside: bool,
+    /// Images jobs may ask for: exact names, prefixes ending in `*`, or `*`.
+    allowed_images: Arc<[String]>,
+    /// Absolute host paths hidden from `bubblewrap` jobs.
+    private_paths: Arc<[PathBuf]>,
+    limits: Limits,
+}
+
+impl Sandbox {
+    pub fn new(
+        kind: SandboxKind,
+        image: &str,
+        allowed_images: Vec<String>,
+        private_paths: Vec<PathBuf>,
+        limits: Limits,
+    ) -> Self {
+        Sandbox {
+            kind,
+            image: image.into(),
+            compile_inside: false,
+            allowed_images: allowed_images.into(),
+            private_paths: private_paths.iter().filter_map(|p| std::path::absolute(p).ok()).collect(),
+            limits,
+        }
+    }
+
+    /// The sandbox for a job that asked for `image`.
+    pub fn for_image(&self, image: &str) -> Result<Sandbox, String> {
+        let allowed = self.allowed_images.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => image.starts_with(prefix),
+            None => image == pattern,
+        });
+        if !allowed {
+            return Err(format!("Container image {} is not allowed on this host", image));
+        }
+        Ok(Sandbox {
+            kind: SandboxKind::Docker,
+            image: image.into(),
+            compile_inside: true,
+            allowed_images: self.allowed_images.clone(),
+            private_paths: self.private_paths.clone(),
+            limits: self.limits.clone(),
+        })
+    }
+
+    /// Builds the invocation of `nvcc`, or of a build system such as cmake,
+    /// for `workspace`; arguments are appended by the caller. Inside a job's
+    /// image the image's own tool of the same name is used. Under
+    /// `bubblewrap` the compile is confined like the program, since a
+    /// compiler can be talked into loading code; the `docker` backend only
+    /// builds inside a job's image, which `validate` insists on for build
+    /// systems.
+    pub fn compiler(&self, job_id: &str, workspace: &Path, tool: &Path) -> Command {
+        let mut cmd = if self.compile_inside {
+            let name = tool.file_name().unwrap_or(OsStr::new("nvcc"));
+            self.docker(&build_container_name(job_id), workspace, None, name)
+        } else {
+            let mut cmd = match self.kind {
+                SandboxKind::Bubblewrap => bubblewrap(workspace, &self.private_paths, tool),
+                _ => Command::new(tool),
+            };
+            self.limits.apply(&mut cmd, job_id);
+            cmd
+        };
+        cmd.current_dir(workspace);
+        cmd
+    }
+
+    /// Builds the command that runs `program` with `args` inside `workspace`,
+    /// restricted to the GPUs in `visible_devices`.
+    pub fn command(
+        &self,
+        job_id: &str,
+        workspace: &Path,
+        program: &Path,
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+        visible_devices: &str,
+    ) -> Command {
+        let mut cmd = match self.kind {
+            SandboxKind::None => Command::new(program),
+            SandboxKind::Bubblewrap => bubblewrap(workspace, &self.private_paths, program),
+            SandboxKind::Docker => {
+                self.docker(&container_name(job_id), workspace, Some(visible_devices), program)
+            }
+        };
+
+        if self.kind != SandboxKind::Docker {
+            self.limits.apply(&mut cmd, job_id);
+        }
+        cmd.args(args)
+            .env("CUDA_VISIBLE_DEVICES", visible_devices)
+            .current_dir(workspace);
+        cmd
+    }
+
+    /// `docker run` of `program` in `image` with only the workspace mounted
+    /// and no network. The GPUs in `visible_devices` are attached, if any.
+    fn docker(
+        &self,
+        name: &str,
+        workspace: &Path,
+        visible_devices: Option<&str>,
+        program: impl AsRef<OsStr>,
+    ) -> Command {
+        let mut volume = workspace.as_os_str().to_owned();
+        volume.push(":");
+        volume.push(workspace);
+
+        let mut cmd = Command::new("docker");
+        cmd.args(["run", "--rm", "-i", "--init", "--network", "none"])
+            .arg("--name")
+            .arg(name)
+            .arg("-v")
+            .arg(volume)
+            .arg("-w")
+            .arg(workspace);
+        // Only the leased GPUs are attached, numbered from 0 inside. The
+        // quotes keep docker from splitting a list of several at its commas.
+        if let Some(devices) = visible_devices.filter(|devices| !devices.is_empty()) {
+            cmd.arg("--gpus").arg(format!("\"device={}\"", devices));
+        }
+        // Files the container writes stay owned by the host user
+        #[cfg(unix)]
+        cmd.arg("--user").arg(unsafe { format!("{}:{}", libc::getuid(), libc::getgid()) });
+        cmd.args(self.limits.docker_args());
+        cmd.arg(&*self.image).arg(program);
+        cmd
+    }
+
+    /// Tears down whatever outlives a killed command. Killing the `docker`
+    /// CLI leaves its container running, so it is removed explicitly.
+    pub async fn release(&self, job_id: &str) {
+        if self.kind == SandboxKind::Docker {
+            let _ = Command::new("docker")
+                .args(["rm", "-f"])
+                .arg(container_name(job_id))
+                .arg(build_container_name(job_id))
+                .output()
+                .await;
+        }
+    }
+
+    /// Tears down what confined the job's processes, once it has ended.
+    pub async fn finish(&self, job_id: &str) {
+        self.limits.release(job_id).await;
+    }
+
+    /// Names the resource limit a program killed by `signal` ran into, if any.
+    pub fn limit_hit(&self, job_id: &str, signal: i32) -> Option<String> {
+        self.limits.explain(job_id, signal)
+    }
+
+    /// The per-job resource limits, for processes run outside any sandbox.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn kind(&self) -> SandboxKind {
+        self.kind
+    }
+
+    /// Identifies the toolchain for cache keys when compiling inside an image.
+    pub fn toolchain_image(&self) -> Option<&str> {
+        self.compile_inside.then_some(&*self.image)
+    }
+}
+
+/// `bwrap` running `program` with no network and a read-only view of the
+/// system with `private` paths emptied; only `workspace` is writable.
+fn bubblewrap(workspace: &Path, private: &[PathBuf], program: impl AsRef<OsStr>) -> Command {
+    let mut cmd = Command::new("bwrap");
+    cmd.args(["--unshare-all", "--die-with-parent", "--new-session"])
+        .args(["--ro-bind", "/", "/"])
+        // GPU device nodes must stay reachable
+        .args(["--dev-bind", "/dev", "/dev"])
+        .args(["--proc", "/proc"])
+        .args(["--tmpfs", "/tmp", "--tmpfs", "/home", "--tmpfs", "/root"]);
+    // Directories are covered with an empty tmpfs, files with /dev/null. The
+    // workspace, usually under the scratch directory, is bound back afterwards.
+    for path in private {
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.is_dir() => {
+                cmd.arg("--tmpfs").arg(path);
+            }
+            Ok(_) => {
+                cmd.args(["--ro-bind", "/dev/null"]).arg(path);
+            }
+            Err(_) => {}
+        }
+    }
+    cmd.arg("--bind")
+        .arg(workspace)
+        .arg(workspace)
+        .arg("--chdir")
+        .arg(workspace)
+        .args(["--cap-drop", "ALL", "--"])
+        .arg(program);
+    cmd
+}
+
+fn container_name(job_id: &str) -> String {
+    format!("ferris-{}", job_id)
+}
+
+fn build_container_name(job_id: &str) -> String {
+    format!("ferris-{}-build", job_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bubblewrap_hides_private_paths_before_binding_the_workspace() {
+        let scratch = std::env::temp_dir().join(format!("ferris-sandbox-test-{}", std::process::id()));
+        let workspace = scratch.join("job");
+        std::fs::create_dir_all(&workspace).unwrap();
+        let secret = scratch.join("tokens.txt");
+        std::fs::write(&secret, "alice:secret\n").unwrap();
+        let missing = scratch.join("missing");
+
+        let cmd = bubblewrap(&workspace, &[scratch.clone(), secret.clone(), missing.clone()], "prog");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        let at = |window: &[&OsStr]| args.windows(window.len()).position(|w| w == window);
+        let hidden_dir = at(&[OsStr::new("--tmpfs"), scratch.as_os_str()]).unwrap();
+        let hidden_file = at(&[OsStr::new("--ro-bind"), OsStr::new("/dev/null"), secret.as_os_str()]).unwrap();
+        let bound = at(&[OsStr::new("--bind"), workspace.as_os_str(), workspace.as_os_str()]).unwrap();
+        assert!(hidden_dir < bound && hidden_file < bound);
+        assert!(!args.contains(&missing.as_os_str()));
+
+        std::fs::remove_dir_all(&scratch).unwrap();
+    }
+
+    #[test]
+    fn bubblewrap_confines_the_compiler() {
+        let workspace = Path::new("/tmp/ferris-job");
+        let sandbox = Sandbox::new(SandboxKind::Bubblewrap, "", Vec::new(), Vec::new(), Limits::default());
+        let cmd = sandbox.compiler("job", workspace, Path::new("/usr/bin/nvcc"));
+        assert_eq!(cmd.as_std().get_program(), "bwrap");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.ends_with(&[OsStr::new("--"), OsStr::new("/usr/bin/nvcc")]));
+
+        let unconfined = Sandbox::new(SandboxKind::None, "", Vec::new(), Vec::new(), Limits::default());
+        let cmd = unconfined.compiler("job", workspace, Path::new("/usr/bin/nvcc"));
+        assert_eq!(cmd.as_std().get_program(), "/usr/bin/nvcc");
+    }
+
+    #[test]
+    fn docker_attaches_only_the_leased_gpus() {
+        let sandbox = Sandbox::new(SandboxKind::Docker, "cuda", Vec::new(), Vec::new(), Limits::default());
+        let workspace = Path::new("/tmp/ferris-job");
+        let program = Path::new("/tmp/ferris-job/app");
+        let args = |devices: &str| -> Vec<String> {
+            let cmd = sandbox.command("job", workspace, program, Vec::<String>::new(), devices);
+            cmd.as_std().get_args().map(|arg| arg.to_string_lossy().into_owned()).collect()
+        };
+
+        let leased = args("1,3");
+        let at = leased.iter().position(|arg| arg == "--gpus").unwrap();
+        assert_eq!(leased[at + 1], "\"device=1,3\"");
+        assert!(!leased.contains(&"all".to_string()));
+        assert!(!args("").contains(&"--gpus".to_string()));
+    }
+}
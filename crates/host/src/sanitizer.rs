@@ -0,0 +1,204 @@
+/// Jobs run under `compute-sanitizer`: the program is launched by the
+/// sanitizer with the requested tool, and the reports it interleaves with the
+/// program's output are picked out and sent as structured findings.
+///
+/// A report is a block of lines starting with `=========`: an unindented
+/// header naming the error, indented details (where, which address, the
+/// backtrace) and a bare `=========` closing it:
+///
+/// ```text
+/// ========= Invalid __global__ write of size 4 bytes
+/// =========     at 0x70 in /work/oob.cu:5:fill(int *, int)
+/// =========     by thread (32,0,0) in block (0,0,0)
+/// =========     Address 0x7f1e4a000080 is out of bounds
+/// =========     Saved host backtrace up to driver entry point at kernel launch time
+/// =========     Host Frame:main [0x7f4e]
+/// =========                in /work/app.out
+/// =========
+/// ```
+///
+/// The banner and the `ERROR SUMMARY` line are passed on as plain output.
+use common::compute::{OutputKind, Sanitizer, SanitizerFinding, Severity};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Every line compute-sanitizer writes starts with this.
+const PREFIX: &str = "=========";
+
+/// The sanitizer to launch and its arguments, ending with `program` and
+/// `args`; `None` when the job isn't sanitized. Any error makes the program
+/// exit 1, so the job fails even if the program itself wouldn't.
+pub fn wrap(sanitizer: &Path, tool: Sanitizer, program: &Path, args: &[String]) -> Option<(PathBuf, Vec<OsString>)> {
+    if tool == Sanitizer::None {
+        return None;
+    }
+    let mut wrapped: Vec<OsString> = ["--tool", name(tool), "--error-exitcode", "1"]
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+    wrapped.push(program.into());
+    wrapped.extend(args.iter().map(OsString::from));
+    Some((sanitizer.to_path_buf(), wrapped))
+}
+
+fn name(tool: Sanitizer) -> &'static str {
+    match tool {
+        Sanitizer::None => "none",
+        Sanitizer::Memcheck => "memcheck",
+        Sanitizer::Racecheck => "racecheck",
+        Sanitizer::Initcheck => "initcheck",
+        Sanitizer::Synccheck => "synccheck",
+    }
+}
+
+/// What to send for a line of the sanitized program's output.
+pub enum Report {
+    /// Anything that isn't part of a report, unchanged.
+    Line(Vec<u8>, OutputKind),
+    /// All the lines of one report, with what was parsed out of them.
+    Finding(Vec<u8>, OutputKind, SanitizerFinding),
+}
+
+/// Groups the lines of each report as they arrive.
+pub struct Findings {
+    tool: Sanitizer,
+    open: Option<Open>,
+    errors: u32,
+}
+
+struct Open {
+    lines: Vec<u8>,
+    kind: OutputKind,
+    finding: SanitizerFinding,
+}
+
+impl Findings {
+    pub fn new(tool: Sanitizer) -> Self {
+        Findings {
+            tool,
+            open: None,
+            errors: 0,
+        }
+    }
+
+    /// Error findings so far; warnings aren't counted.
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Takes the next line; returns what can be sent now, in order.
+    pub fn push(&mut self, line: Vec<u8>, kind: OutputKind) -> Vec<Report> {
+        let text = String::from_utf8_lossy(&line);
+        let Some(rest) = text.trim_end_matches(['\r', '\n']).strip_prefix(PREFIX) else {
+            return vec![Report::Line(line, kind)];
+        };
+        let rest = rest.to_string();
+        let body = rest.trim();
+
+        if body.is_empty() {
+            return match self.open.take() {
+                Some(mut open) => {
+                    open.lines.extend_from_slice(&line);
+                    vec![self.close(open)]
+                }
+                None => vec![Report::Line(line, kind)],
+            };
+        }
+        if rest.starts_with("  ") {
+            return match &mut self.open {
+                Some(open) => {
+                    open.lines.extend_from_slice(&line);
+                    detail(&mut open.finding, body);
+                    Vec::new()
+                }
+                None => vec![Report::Line(line, kind)],
+            };
+        }
+
+        // A header ends whatever report was open
+        let mut reports: Vec<Report> = self.open.take().map(|open| self.close(open)).into_iter().collect();
+        if body.starts_with("COMPUTE-SANITIZER") || body.contains("SUMMARY:") {
+            reports.push(Report::Line(line, kind));
+        } else {
+            self.open = Some(Open {
+                finding: header(self.tool, body),
+                lines: line,
+                kind,
+            });
+        }
+        reports
+    }
+
+    /// A report still open when the program exits.
+    pub fn finish(&mut self) -> Option<Report> {
+        let open = self.open.take()?;
+        Some(self.close(open))
+    }
+
+    fn close(&mut self, open: Open) -> Report {
+        if open.finding.severity() == Severity::Error {
+            self.errors += 1;
+        }
+        Report::Finding(open.lines, open.kind, open.finding)
+    }
+}
+
+/// A finding from its header, e.g. `Invalid __global__ read of size 4 bytes`
+/// or racecheck's `Warning: Race reported between Write access at ...`.
+fn header(tool: Sanitizer, text: &str) -> SanitizerFinding {
+    let (severity, error) = if let Some(error) = text.strip_prefix("Warning:") {
+        (Severity::Warning, error.trim())
+    } else {
+        (Severity::Error, text.strip_prefix("Error:").unwrap_or(text).trim())
+    };
+    let mut finding = SanitizerFinding {
+        error: error.to_string(),
+        ..Default::default()
+    };
+    finding.set_tool(tool);
+    finding.set_severity(severity);
+    if let Some((kernel, location)) = site(error) {
+        finding.kernel = kernel;
+        finding.location = location;
+    }
+    finding
+}
+
+/// Fills in the finding from one of its indented lines.
+fn detail(finding: &mut SanitizerFinding, text: &str) {
+    if let Some(frame) = text.strip_prefix("Host Frame:").or_else(|| text.strip_prefix("Device Frame:")) {
+        finding.backtrace.push(frame.trim().to_string());
+    } else if let Some(module) = text.strip_prefix("in ")
+        && let Some(frame) = finding.backtrace.last_mut()
+    {
+        // The module of the frame on the line above
+        frame.push_str(" in ");
+        frame.push_str(module);
+    } else if let Some(address) = text.strip_prefix("Address ") {
+        if finding.address.is_empty() {
+            finding.address = address.split_whitespace().next().unwrap_or_default().to_string();
+        }
+    } else if finding.kernel.is_empty()
+        && let Some((kernel, location)) = site(text)
+    {
+        finding.kernel = kernel;
+        finding.location = location;
+    }
+}
+
+/// Kernel and `file:line` from `at 0x70 in /work/oob.cu:5:fill(int *, int)`,
+/// or from newer releases' `at fill(int *, int)+0x70 in /work/oob.cu:5`.
+fn site(text: &str) -> Option<(String, String)> {
+    let text = format!(" {}", text);
+    let (_, at) = text.split_once(" at ")?;
+    let (pc, place) = at.split_once(" in ")?;
+    // racecheck appends a hazard count
+    let place = place.split(" [").next().unwrap_or(place).trim();
+    if pc.starts_with("0x") {
+        let mut parts = place.splitn(3, ':');
+        let (file, line, kernel) = (parts.next()?, parts.next()?, parts.next()?);
+        return Some((kernel.to_string(), format!("{}:{}", file, line)));
+    }
+    let kernel = pc.rsplit_once("+0x").map_or(pc, |(kernel, _)| kernel);
+    Some((kernel.to_string(), place.to_string()))
+}
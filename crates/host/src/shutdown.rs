@@ -0,0 +1,75 @@
+/// Graceful shutdown on SIGTERM or Ctrl-C.
+///
+/// The host stops taking new jobs and stops admitting queued ones, then gives
+/// running jobs a grace period to finish streaming. Whatever is still running
+/// or queued after that (or after a second signal) is cancelled, which kills
+/// its process tree. The server exits once those jobs have wound down and
+/// handed their workspaces to the cleanup queue.
+use crate::queue::JobQueue;
+use crate::registry::JobRegistry;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// How long cancelled jobs get to kill their processes and unregister.
+const STRAGGLER_WAIT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct Shutdown {
+    /// Cancelled once a signal arrives; the executor turns new jobs away.
+    pub draining: CancellationToken,
+    pub grace: Duration,
+    pub registry: JobRegistry,
+    pub queue: JobQueue,
+}
+
+impl Shutdown {
+    /// Resolves once the host is ready to stop serving.
+    pub async fn drain(self) {
+        signal().await;
+        self.draining.cancel();
+        self.queue.close();
+        info!(
+            "🛑 Shutting down: no new jobs; waiting up to {}s for {} running job(s)",
+            self.grace.as_secs(),
+            self.queue.running()
+        );
+
+        tokio::select! {
+            _ = tokio::time::timeout(self.grace, until(|| self.queue.running() == 0)) => {}
+            _ = signal() => warn!("⚠️ Second signal, not waiting any longer"),
+        }
+
+        let stragglers = self.registry.cancel_all();
+        if stragglers > 0 {
+            warn!("🔪 Cancelling {} job(s) still running or queued", stragglers);
+        }
+        if tokio::time::timeout(STRAGGLER_WAIT, until(|| self.registry.is_empty()))
+            .await
+            .is_err()
+        {
+            warn!("⚠️ Some jobs did not stop in time; their workspaces may be left behind");
+        }
+    }
+}
+
+async fn until(done: impl Fn() -> bool) {
+    while !done() {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(unix)]
+async fn signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut terminate = signal(SignalKind::terminate()).expect("can install a SIGTERM handler");
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
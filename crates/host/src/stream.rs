@@ -0,0 +1,399 @@
+/// Sender side of a job's response stream.
+///
+/// Every job must end with exactly one terminal message. `JobStream` enforces
+/// that: `finish` sends the terminal message and consumes the stream, and if a
+/// `JobStream` is dropped without being finished (an early return, a panic
+/// unwinding through the job body) the `Drop` impl emits a synthetic
+/// "ended unexpectedly" one so the client never hangs waiting for an end.
+/// Host-side failures end the same way, with an `ERROR` result rather than
+/// an error status, so every stream ends cleanly after a terminal message.
+///
+/// Ordering contract, relied on by clients:
+/// 1. Compiler status ("Compilation successful") precedes any program output.
+/// 2. Program output keeps the order in which it was sent.
+/// 3. The chunks of an artifact are consecutive, never interleaved with
+///    another artifact's.
+/// 4. The terminal message is always last.
+/// 5. Phases only move forward: compiling, then running, then done.
+///
+/// All messages for a job are produced by the single task that owns its
+/// `JobStream`; nothing else holds a sender. Within that task several
+/// producers run at once (the stdout and stderr readers, the GPU sampler), so
+/// they take turns: each message, or each artifact's whole run of chunks, is
+/// numbered (`seq`) and sent before the next producer gets its turn. A phase
+/// going backwards is a bug; it is logged, and fails debug builds.
+///
+/// Everything but artifact chunks is also recorded in the job's `JobLog`, so
+/// the same output can be polled without the stream.
+use crate::joblog::JobLog;
+use crate::metrics;
+use common::chunking;
+use common::encoding::FileEncoding;
+use common::compute::{
+    ArtifactChunk, ComputeResponse, DebugSession, Diagnostic, GpuSample, JobPhase, JobResult, JobStatus, OutputKind, SanitizerFinding, Severity,
+};
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use tonic::Status;
+use tracing::error;
+
+pub type ResponseSender = mpsc::Sender<Result<ComputeResponse, Status>>;
+
+pub struct JobStream {
+    tx: ResponseSender,
+    job_id: String,
+    log: JobLog,
+    file_encoding: FileEncoding,
+    finished: bool,
+    /// Held while a message, or an artifact's chunks, is numbered and sent.
+    turn: Mutex<()>,
+    next_seq: AtomicU64,
+    phase: AtomicI32,
+}
+
+impl JobStream {
+    pub fn new(tx: ResponseSender, job_id: impl Into<String>, log: JobLog) -> Self {
+        JobStream {
+            tx,
+            job_id: job_id.into(),
+            log,
+            file_encoding: FileEncoding::Identity,
+            finished: false,
+            turn: Mutex::new(()),
+            next_seq: AtomicU64::new(0),
+            phase: AtomicI32::new(JobPhase::Queued.into()),
+        }
+    }
+
+    /// Encodes artifacts the way the client negotiated for its uploads.
+    pub fn with_file_encoding(mut self, encoding: FileEncoding) -> Self {
+        self.file_encoding = encoding;
+        self
+    }
+
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    /// Reported to pollers, and to the stream as a message of its own.
+    pub async fn set_phase(&self, phase: JobPhase) {
+        let previous = self.phase.swap(phase.into(), Ordering::Relaxed);
+        if i32::from(phase) <= previous {
+            error!(?phase, "💥 Job phase went backwards");
+            debug_assert!(false, "job phase went backwards to {:?}", phase);
+        }
+        self.log.set_phase(phase);
+        self.emit(ComputeResponse {
+            job_id: self.job_id.clone(),
+            kind: OutputKind::System.into(),
+            phase: phase.into(),
+            ..Default::default()
+        })
+        .await;
+    }
+
+    pub fn set_queue_position(&self, position: usize) {
+        self.log.set_queue_position(position as u32);
+    }
+
+    /// Sends a non-terminal message from the host itself.
+    pub async fn send(&self, output: impl Into<String>, is_error: bool) {
+        self.emit(ComputeResponse {
+            output: output.into().into_bytes(),
+            is_error,
+            job_id: self.job_id.clone(),
+            kind: OutputKind::System.into(),
+            ..Default::default()
+        })
+        .await;
+    }
+
+    /// Sends a line of nvcc's or the program's output, bytes as written.
+    pub async fn send_output(&self, output: Vec<u8>, kind: OutputKind, diagnostic: Option<Diagnostic>) {
+        self.emit(ComputeResponse {
+            output,
+            is_error: kind == OutputKind::Stderr,
+            job_id: self.job_id.clone(),
+            diagnostic,
+            kind: kind.into(),
+            ..Default::default()
+        })
+        .await;
+    }
+
+    /// Sends the lines of one compute-sanitizer report together with what
+    /// was parsed out of them.
+    pub async fn send_finding(&self, output: Vec<u8>, kind: OutputKind, finding: SanitizerFinding) {
+        self.emit(ComputeResponse {
+            output,
+            is_error: kind == OutputKind::Stderr || finding.severity() == Severity::Error,
+            job_id: self.job_id.clone(),
+            finding: Some(finding),
+            kind: kind.into(),
+            ..Default::default()
+        })
+        .await;
+    }
+
+    /// Announces that the program is waiting under cuda-gdbserver, so the
+    /// client can open its end of the tunnel.
+    pub async fn send_debug_session(&self, binary: &str, time_limit: Duration) {
+        self.emit(ComputeResponse {
+            output: format!("🐞 cuda-gdbserver is waiting for a debugger; the session ends in {}s", time_limit.as_secs())
+                .into_bytes(),
+            job_id: self.job_id.clone(),
+            kind: OutputKind::System.into(),
+            debug_session: Some(DebugSession {
+                binary: binary.to_string(),
+                time_limit_secs: time_limit.as_secs() as u32,
+            }),
+            ..Default::default()
+        })
+        .await;
+    }
+
+    /// Sends one sample of the job's GPUs.
+    pub async fn send_telemetry(&self, samples: Vec<GpuSample>) {
+        self.emit(ComputeResponse {
+            job_id: self.job_id.clone(),
+            kind: OutputKind::System.into(),
+            telemetry: samples,
+            ..Default::default()
+        })
+        .await;
+    }
+
+    /// Records a message in the log, then sends it. A client that went away
+    /// doesn't stop the job; its output stays in the log.
+    ///
+    /// Cancel-safe: the channel slot is reserved before anything is numbered
+    /// or logged, so a message is either logged and sent or neither, and log
+    /// offsets keep matching what the client received.
+    async fn emit(&self, mut message: ComputeResponse) {
+        let _turn = self.turn.lock().await;
+        let permit = self.tx.reserve().await;
+        message.seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        self.log.push(&message);
+        metrics::streamed("output", message.output.len());
+        if let Ok(permit) = permit {
+            permit.send(Ok(message));
+        }
+    }
+
+    /// Sends a file as a run of artifact chunks, in the negotiated file
+    /// encoding; an empty file is a single empty `eof` chunk.
+    pub async fn send_artifact(&self, path: &str, data: &[u8]) {
+        let data = match self.file_encoding.encode(data) {
+            Ok(data) => data,
+            Err(e) => {
+                self.send(format!("⚠️ Could not compress {}: {}", path, e), true).await;
+                return;
+            }
+        };
+        let _turn = self.turn.lock().await;
+        let mut chunks = chunking::chunk_bytes(&data).peekable();
+        if chunks.peek().is_none() {
+            self.send_artifact_chunk(path, Vec::new(), true).await;
+        }
+        while let Some(chunk) = chunks.next() {
+            let eof = chunks.peek().is_none();
+            self.send_artifact_chunk(path, chunk.to_vec(), eof).await;
+        }
+    }
+
+    /// Only called with `turn` held.
+    async fn send_artifact_chunk(&self, path: &str, data: Vec<u8>, eof: bool) {
+        metrics::streamed("artifact", data.len());
+        let Ok(permit) = self.tx.reserve().await else {
+            return;
+        };
+        permit.send(Ok(ComputeResponse {
+            job_id: self.job_id.clone(),
+            artifact: Some(ArtifactChunk {
+                path: path.to_string(),
+                data,
+                eof,
+            }),
+            seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+            ..Default::default()
+        }));
+    }
+
+    /// Sends the terminal message with the job's structured result and closes
+    /// this side of the stream.
+    pub async fn finish(mut self, output: impl Into<String>, mut result: JobResult) {
+        self.finished = true;
+        result.job_id = self.job_id.clone();
+        self.emit(ComputeResponse {
+            output: output.into().into_bytes(),
+            is_error: result.status() != JobStatus::Succeeded,
+            job_id: self.job_id.clone(),
+            kind: OutputKind::System.into(),
+            result: Some(result),
+            ..Default::default()
+        })
+        .await;
+    }
+
+    /// Ends the job with a host-side `ERROR` result, for failures that leave
+    /// nothing else to report.
+    pub async fn fail(mut self, reason: impl Into<String>) {
+        self.finished = true;
+        let reason = reason.into();
+        self.log.set_error(&reason);
+        self.emit(self.error_message(&reason)).await;
+    }
+
+    fn error_message(&self, reason: &str) -> ComputeResponse {
+        let mut result = JobResult {
+            job_id: self.job_id.clone(),
+            ..Default::default()
+        };
+        result.set_status(JobStatus::Error);
+        ComputeResponse {
+            output: format!("❌ {}", reason).into_bytes(),
+            is_error: true,
+            job_id: self.job_id.clone(),
+            kind: OutputKind::System.into(),
+            result: Some(result),
+            ..Default::default()
+        }
+    }
+}
+
+impl Drop for JobStream {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        let reason = if std::thread::panicking() {
+            "💥 Job ended unexpectedly: the host task panicked"
+        } else {
+            "💥 Job ended unexpectedly without a final status"
+        };
+        error!("{}", reason);
+        // Nothing else can be sending any more, so no turn is needed
+        let message = ComputeResponse {
+            output: reason.as_bytes().to_vec(),
+            seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+            ..self.error_message(reason)
+        };
+        self.log.set_error(reason);
+        self.log.push(&message);
+
+        // Drop can't await, so hand the final send to the runtime.
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(Ok(message)).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joblog::JobLogs;
+    use std::collections::HashMap;
+
+    const LINES: usize = 300;
+    const SAMPLES: usize = 100;
+    const ARTIFACT_BYTES: usize = chunking::MAX_CHUNK_SIZE * 5 / 2;
+
+    /// Checks a received stream against the ordering contract.
+    fn check_contract(messages: &[ComputeResponse]) {
+        let mut phase = JobPhase::Queued;
+        let mut compiled_at = None;
+        let mut next_line: HashMap<i32, usize> = HashMap::new();
+        let mut open_artifact: Option<&str> = None;
+        for (i, message) in messages.iter().enumerate() {
+            assert_eq!(message.seq, i as u64, "numbered in the order received");
+            if let Some(path) = open_artifact {
+                let chunk = message.artifact.as_ref().expect("an artifact's chunks are consecutive");
+                assert_eq!(chunk.path, path);
+            }
+            if let Some(chunk) = &message.artifact {
+                open_artifact = (!chunk.eof).then_some(chunk.path.as_str());
+                continue;
+            }
+            if message.phase() != JobPhase::Unspecified {
+                assert!(message.phase() > phase, "phases only move forward");
+                phase = message.phase();
+            }
+            if message.output.starts_with("🚀 Compilation successful".as_bytes()) {
+                compiled_at = Some(i);
+            }
+            if matches!(message.kind(), OutputKind::Stdout | OutputKind::Stderr) {
+                assert!(compiled_at.is_some(), "program output comes after the compiler status");
+                let expected = next_line.entry(message.kind).or_default();
+                assert_eq!(message.output, format!("line {}\n", expected).into_bytes(), "each pipe keeps its order");
+                *expected += 1;
+            }
+            let last = i == messages.len() - 1;
+            assert_eq!(message.result.is_some(), last, "the terminal message is last, and only last");
+        }
+        assert_eq!(next_line.values().copied().collect::<Vec<_>>(), [LINES, LINES]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_producers_keep_the_ordering_contract() {
+        // A one-message buffer and a consumer that dawdles maximise interleaving
+        let (tx, mut rx) = mpsc::channel::<Result<ComputeResponse, Status>>(1);
+        let log = JobLogs::default().create("job", "tester");
+        let consumer = tokio::spawn(async move {
+            let mut received = Vec::new();
+            while let Some(message) = rx.recv().await {
+                received.push(message.expect("no error status"));
+                if received.len() % 7 == 0 {
+                    tokio::task::yield_now().await;
+                }
+            }
+            received
+        });
+
+        let stream = JobStream::new(tx, "job", log.clone());
+        stream.set_phase(JobPhase::Compiling).await;
+        stream.send("🚀 Compilation successful. Running...", false).await;
+        stream.set_phase(JobPhase::Running).await;
+        let pipe = |kind| {
+            let stream = &stream;
+            async move {
+                for line in 0..LINES {
+                    stream.send_output(format!("line {}\n", line).into_bytes(), kind, None).await;
+                }
+            }
+        };
+        let sampler = async {
+            for _ in 0..SAMPLES {
+                stream.send_telemetry(vec![GpuSample::default()]).await;
+                tokio::task::yield_now().await;
+            }
+        };
+        let artifacts = async {
+            for name in ["a.bin", "b.bin"] {
+                stream.send_artifact(name, &vec![7; ARTIFACT_BYTES]).await;
+                tokio::task::yield_now().await;
+            }
+        };
+        tokio::join!(pipe(OutputKind::Stdout), pipe(OutputKind::Stderr), sampler, artifacts);
+        stream.finish("🏁 Program exited.", JobResult::default()).await;
+
+        let received = consumer.await.unwrap();
+        check_contract(&received);
+
+        // The log holds the same messages, minus artifact chunks, in the same order
+        let logged = log.read(0, 0).entries;
+        let streamed: Vec<_> = received.into_iter().filter(|m| m.artifact.is_none()).collect();
+        assert_eq!(logged, streamed);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "went backwards")]
+    async fn a_phase_going_backwards_is_caught() {
+        let (tx, _rx) = mpsc::channel(10);
+        let stream = JobStream::new(tx, "job", JobLogs::default().create("job", "tester"));
+        stream.set_phase(JobPhase::Running).await;
+        stream.set_phase(JobPhase::Compiling).await;
+    }
+}
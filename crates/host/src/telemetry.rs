@@ -0,0 +1,36 @@
+/// Live GPU telemetry for jobs that ask for it. While the program runs, the
+/// GPUs it was given are sampled with `nvidia-smi` every second, and each
+/// sample goes to the job's stream as a message of its own.
+///
+/// The sampler runs inside the job's task, next to the program, so the
+/// stream keeps a single producer.
+use crate::probe;
+use crate::stream::JobStream;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs `work`, sending samples of `devices` to `stream` until it's done.
+/// Does nothing extra when `devices` is empty (a CPU job).
+pub async fn alongside<T>(stream: &JobStream, devices: &[u32], work: impl Future<Output = T>) -> T {
+    if devices.is_empty() {
+        return work.await;
+    }
+    let sampling = async {
+        let mut ticks = tokio::time::interval(INTERVAL);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let samples = probe::samples(devices).await;
+            if !samples.is_empty() {
+                stream.send_telemetry(samples).await;
+            }
+        }
+    };
+    tokio::select! {
+        done = work => done,
+        never = sampling => never,
+    }
+}
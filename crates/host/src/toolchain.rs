@@ -0,0 +1,85 @@
+/// Named CUDA toolkits installed side by side (e.g. /usr/local/cuda-11.8 and
+/// /usr/local/cuda-12.4). A job picks one by name; without a choice it gets
+/// whatever `nvcc` is on the host's PATH.
+use crate::probe;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+pub struct Toolchain {
+    pub cuda_home: PathBuf,
+    /// `nvcc --version` of this toolkit; part of the cache key.
+    pub version: String,
+}
+
+#[derive(Default)]
+pub struct Toolchains {
+    named: BTreeMap<String, Toolchain>,
+}
+
+impl Toolchains {
+    /// Probes every `(name, CUDA_HOME)` pair.
+    pub async fn probe(specs: impl IntoIterator<Item = (String, PathBuf)>) -> Self {
+        let mut named = BTreeMap::new();
+        for (name, cuda_home) in specs {
+            let version = probe::nvcc_version(&cuda_home.join("bin").join("nvcc")).await;
+            named.insert(name, Toolchain { cuda_home, version });
+        }
+        Toolchains { named }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Toolchain, String> {
+        self.named.get(name).ok_or_else(|| {
+            format!(
+                "Unknown toolchain {} (available: {})",
+                name,
+                self.names().collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.named.keys().map(String::as_str)
+    }
+}
+
+impl Toolchain {
+    pub fn nvcc(&self) -> PathBuf {
+        self.cuda_home.join("bin").join("nvcc")
+    }
+
+    pub fn compute_sanitizer(&self) -> PathBuf {
+        self.cuda_home.join("bin").join("compute-sanitizer")
+    }
+
+    pub fn cuda_gdbserver(&self) -> PathBuf {
+        self.cuda_home.join("bin").join("cuda-gdbserver")
+    }
+
+    /// Points `cmd` at this toolkit: `CUDA_HOME`, its `bin` first on `PATH`
+    /// and its libraries first on `LD_LIBRARY_PATH`.
+    pub fn apply(&self, cmd: &mut Command) {
+        cmd.env("CUDA_HOME", &self.cuda_home)
+            .env("PATH", prepend(&self.cuda_home.join("bin"), "PATH"))
+            .env("LD_LIBRARY_PATH", prepend(&self.cuda_home.join("lib64"), "LD_LIBRARY_PATH"));
+    }
+}
+
+fn prepend(dir: &Path, var: &str) -> OsString {
+    let mut paths = vec![dir.to_path_buf()];
+    if let Some(existing) = std::env::var_os(var) {
+        paths.extend(std::env::split_paths(&existing));
+    }
+    std::env::join_paths(paths).unwrap_or_else(|_| dir.as_os_str().to_owned())
+}
+
+/// Parses a `name=CUDA_HOME` command-line value.
+pub fn parse_spec(spec: &str) -> Result<(String, PathBuf), String> {
+    match spec.split_once('=') {
+        Some((name, home)) if !name.is_empty() && !home.is_empty() => {
+            Ok((name.to_string(), PathBuf::from(home)))
+        }
+        _ => Err(format!("expected name=CUDA_HOME, got {:?}", spec)),
+    }
+}
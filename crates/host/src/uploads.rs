@@ -0,0 +1,368 @@
+/// Files that reach the host in chunks: input data staged ahead of a job with
+/// `UploadInputs`, the sources and inputs streamed by `Submit`, and the
+/// prebuilt executable streamed by `RunBinary`, which is staged with the
+/// inputs.
+///
+/// Inputs are staged under `scratch/uploads/<id>`; the job that uses them
+/// takes the directory over as its workspace, so the program finds them in
+/// its working directory. Only the user who staged an upload can claim it;
+/// uploads no job claims expire.
+use crate::cleanup::CleanupQueue;
+use crate::job;
+use common::chunking::Reassembler;
+use common::encoding::FileEncoding;
+use common::compute::submit_message::Kind;
+use common::compute::{ComputeRequest, FileChunk, SourceFile, SubmitMessage, UploadInputsResponse};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs;
+use tonic::{Status, Streaming};
+use tracing::info;
+
+/// Largest single input file.
+pub const MAX_INPUT_FILE_BYTES: usize = 256 * 1024 * 1024;
+/// Largest upload, all files together.
+pub const MAX_UPLOAD_BYTES: u64 = 1024 * 1024 * 1024;
+/// How long an upload waits for the job that uses it.
+const UPLOAD_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone)]
+pub struct UploadStore {
+    root: PathBuf,
+    pending: Arc<Mutex<HashMap<String, Staged>>>,
+    cleanup: CleanupQueue,
+    /// Largest streamed source tree, all files together.
+    max_source_bytes: u64,
+}
+
+/// An upload waiting for its job.
+struct Staged {
+    dir: PathBuf,
+    owner: String,
+}
+
+/// A fully received `Submit` stream.
+pub struct Submission {
+    /// The start marker, with the streamed sources added to `files`.
+    pub request: ComputeRequest,
+    /// Staged input files, if any were sent.
+    pub inputs: Option<PathBuf>,
+}
+
+impl UploadStore {
+    pub fn new(root: impl Into<PathBuf>, cleanup: CleanupQueue, max_source_bytes: u64) -> Self {
+        UploadStore {
+            root: root.into(),
+            pending: Arc::default(),
+            cleanup,
+            max_source_bytes,
+        }
+    }
+
+    /// Stages every file of the stream. On any error the partial upload is
+    /// discarded.
+    pub async fn receive(
+        &self,
+        mut chunks: Streaming<FileChunk>,
+        encoding: FileEncoding,
+        owner: &str,
+    ) -> Result<UploadInputsResponse, Status> {
+        let upload_id = uuid::Uuid::new_v4().to_string();
+        let dir = self.root.join(&upload_id);
+        let mut files = FileReceiver::new(MAX_INPUT_FILE_BYTES, MAX_UPLOAD_BYTES, encoding);
+
+        let received = async {
+            fs::create_dir_all(&dir).await?;
+            while let Some(chunk) = chunks.message().await? {
+                if let Some((rel, data)) = files.push(chunk)? {
+                    write_file(&dir, &rel, data).await?;
+                }
+            }
+            files.finish()
+        }
+        .await;
+
+        match received {
+            Ok((count, bytes_received)) => {
+                let staged = Staged {
+                    dir,
+                    owner: owner.to_string(),
+                };
+                self.pending.lock().unwrap().insert(upload_id.clone(), staged);
+                self.expire_later(upload_id.clone());
+                Ok(UploadInputsResponse {
+                    upload_id,
+                    files: count,
+                    bytes_received,
+                })
+            }
+            Err(status) => {
+                self.cleanup.schedule(dir);
+                Err(status)
+            }
+        }
+    }
+
+    /// Reads a `Submit` stream up to its start marker. With `binary`, it's a
+    /// `RunBinary` stream, which must carry exactly one binary file.
+    pub async fn receive_submission(
+        &self,
+        mut messages: Streaming<SubmitMessage>,
+        encoding: FileEncoding,
+        binary: bool,
+    ) -> Result<Submission, Status> {
+        let mut sources = FileReceiver::new(self.max_source_bytes as usize, self.max_source_bytes, encoding);
+        let mut inputs = FileReceiver::new(MAX_INPUT_FILE_BYTES, MAX_UPLOAD_BYTES, encoding);
+        let mut source_files = Vec::new();
+        let mut staged: Option<PathBuf> = None;
+        let mut prebuilt: Option<String> = None;
+
+        let received = async {
+            while let Some(message) = messages.message().await? {
+                match message.kind {
+                    Some(Kind::Source(chunk)) => {
+                        if let Some((rel, data)) = sources.push(chunk)? {
+                            let contents = String::from_utf8(data).map_err(|_| {
+                                Status::invalid_argument(format!("{} is not valid UTF-8", rel.display()))
+                            })?;
+                            let path = rel.to_string_lossy().replace('\\', "/");
+                            source_files.push(SourceFile { path, contents });
+                        }
+                    }
+                    Some(Kind::Input(chunk)) => {
+                        if let Some((rel, data)) = inputs.push(chunk)? {
+                            let dir = staged.get_or_insert_with(|| {
+                                self.root.join(uuid::Uuid::new_v4().to_string())
+                            });
+                            write_file(dir, &rel, data).await?;
+                        }
+                    }
+                    Some(Kind::Binary(_)) if !binary => {
+                        return Err(Status::invalid_argument("Prebuilt binaries are sent with RunBinary"));
+                    }
+                    Some(Kind::Binary(chunk)) => {
+                        // Counts towards the inputs' limits, as it is staged with them
+                        if let Some((rel, data)) = inputs.push(chunk)? {
+                            if prebuilt.is_some() {
+                                return Err(Status::invalid_argument("RunBinary takes a single binary"));
+                            }
+                            let dir = staged.get_or_insert_with(|| {
+                                self.root.join(uuid::Uuid::new_v4().to_string())
+                            });
+                            write_file(dir, &rel, data).await?;
+                            prebuilt = Some(rel.to_string_lossy().replace('\\', "/"));
+                        }
+                    }
+                    Some(Kind::Start(mut request)) => {
+                        sources.finish()?;
+                        inputs.finish()?;
+                        if !request.input_upload_id.is_empty() {
+                            return Err(Status::invalid_argument(
+                                "Submit sends inputs in the stream, not by upload id",
+                            ));
+                        }
+                        if binary {
+                            request.prebuilt_binary = prebuilt
+                                .take()
+                                .ok_or_else(|| Status::invalid_argument("RunBinary needs a binary to run"))?;
+                        }
+                        request.files.append(&mut source_files);
+                        return Ok(request);
+                    }
+                    None => return Err(Status::invalid_argument("Empty submit message")),
+                }
+            }
+            Err(Status::invalid_argument("Submission ended before its start marker"))
+        }
+        .await;
+
+        match received {
+            Ok(request) => Ok(Submission {
+                request,
+                inputs: staged,
+            }),
+            Err(status) => {
+                if let Some(dir) = staged {
+                    self.cleanup.schedule(dir);
+                }
+                Err(status)
+            }
+        }
+    }
+
+    /// Claims a staged upload for a job of `owner`, who must have staged
+    /// it; each one can be used by a single job.
+    pub fn take(&self, upload_id: &str, owner: &str) -> Option<PathBuf> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.get(upload_id)?.owner != owner {
+            return None;
+        }
+        pending.remove(upload_id).map(|staged| staged.dir)
+    }
+
+    pub fn is_pending(&self, upload_id: &str) -> bool {
+        self.pending.lock().unwrap().contains_key(upload_id)
+    }
+
+    fn expire_later(&self, upload_id: String) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(UPLOAD_TTL).await;
+            let expired = store.pending.lock().unwrap().remove(&upload_id);
+            if let Some(Staged { dir, .. }) = expired {
+                info!("⌛ Upload {} was never used; discarding it", upload_id);
+                store.cleanup.schedule(dir);
+            }
+        });
+    }
+}
+
+/// Puts a stream of file chunks back together, one file at a time, enforcing
+/// the per-file and total limits and each file's checksum. With a file
+/// encoding the limits apply both to the bytes received and to the decoded
+/// files, so a small compressed upload can't fill the disk.
+struct FileReceiver {
+    current: Option<(String, Reassembler)>,
+    max_file: usize,
+    max_total: u64,
+    encoding: FileEncoding,
+    total: u64,
+    decoded: u64,
+    files: u32,
+}
+
+// Errors go straight back to the client, so they are built as gRPC statuses
+#[allow(clippy::result_large_err)]
+impl FileReceiver {
+    fn new(max_file: usize, max_total: u64, encoding: FileEncoding) -> Self {
+        FileReceiver {
+            current: None,
+            max_file,
+            max_total,
+            encoding,
+            total: 0,
+            decoded: 0,
+            files: 0,
+        }
+    }
+
+    /// Adds a chunk; returns the file's validated relative path and contents
+    /// once its last chunk is in.
+    fn push(&mut self, chunk: FileChunk) -> Result<Option<(PathBuf, Vec<u8>)>, Status> {
+        let data = match &mut self.current {
+            Some((path, data)) if *path == chunk.path => data,
+            Some((path, _)) => {
+                return Err(Status::invalid_argument(format!("{} ended without eof", path)));
+            }
+            None => {
+                let (_, data) = self
+                    .current
+                    .insert((chunk.path.clone(), Reassembler::new(self.max_file)));
+                data
+            }
+        };
+
+        self.total += chunk.data.len() as u64;
+        if self.total > self.max_total {
+            return Err(too_large(self.max_total));
+        }
+        data.push(&chunk.data)
+            .map_err(|e| Status::invalid_argument(format!("{}: {}", chunk.path, e)))?;
+
+        if !chunk.eof {
+            return Ok(None);
+        }
+        let (path, data) = self.current.take().expect("set above");
+        let data = data
+            .finish_verified(&chunk.sha256)
+            .map_err(|e| Status::data_loss(format!("{}: {}", path, e)))?;
+        let data = self
+            .encoding
+            .decode(data, self.max_file)
+            .map_err(|e| Status::invalid_argument(format!("{}: {}", path, e)))?;
+        self.decoded += data.len() as u64;
+        if self.decoded > self.max_total {
+            return Err(too_large(self.max_total));
+        }
+        let rel = job::relative_path(&path)
+            .ok_or_else(|| Status::invalid_argument(format!("Invalid file path: {}", path)))?;
+        self.files += 1;
+        Ok(Some((rel, data)))
+    }
+
+    /// Fails if a file was left incomplete. Returns (files, bytes).
+    fn finish(&self) -> Result<(u32, u64), Status> {
+        match &self.current {
+            Some((path, _)) => Err(Status::invalid_argument(format!("{} ended without eof", path))),
+            None => Ok((self.files, self.total)),
+        }
+    }
+}
+
+async fn write_file(dir: &Path, rel: &Path, data: Vec<u8>) -> Result<(), Status> {
+    let dest = dir.join(rel);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&dest, data).await?;
+    Ok(())
+}
+
+fn too_large(max: u64) -> Status {
+    if max.is_multiple_of(1 << 20) {
+        Status::resource_exhausted(format!("Uploads are limited to {} MiB", max >> 20))
+    } else {
+        Status::resource_exhausted(format!("Uploads are limited to {} bytes", max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::chunking::{chunk_bytes_with, digest};
+
+    fn chunk(path: &str, data: &[u8], eof: bool, sha256: &str) -> FileChunk {
+        FileChunk {
+            path: path.into(),
+            data: data.to_vec(),
+            eof,
+            sha256: sha256.into(),
+        }
+    }
+
+    #[test]
+    fn files_are_put_back_together() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut receiver = FileReceiver::new(1 << 20, 1 << 20, FileEncoding::Identity);
+        let mut chunks = chunk_bytes_with(&data, 64).peekable();
+        let mut received = None;
+        while let Some(piece) = chunks.next() {
+            let eof = chunks.peek().is_none();
+            received = receiver.push(chunk("data/in.bin", &piece, eof, &digest(&data))).unwrap();
+        }
+        assert_eq!(received, Some((PathBuf::from("data/in.bin"), data.clone())));
+        assert_eq!(receiver.finish().unwrap(), (1, 1000));
+    }
+
+    #[test]
+    fn interleaved_files_are_rejected() {
+        let mut receiver = FileReceiver::new(1 << 20, 1 << 20, FileEncoding::Identity);
+        receiver.push(chunk("a.bin", b"first half", false, "")).unwrap();
+        let err = receiver.push(chunk("b.bin", b"other file", true, "")).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(receiver.finish().is_err());
+    }
+
+    #[test]
+    fn oversized_files_are_rejected() {
+        let mut receiver = FileReceiver::new(16, 1 << 20, FileEncoding::Identity);
+        let err = receiver.push(chunk("big.bin", &[0; 17], true, "")).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        let mut receiver = FileReceiver::new(1 << 20, 16, FileEncoding::Identity);
+        let err = receiver.push(chunk("big.bin", &[0; 17], true, "")).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+    }
+}
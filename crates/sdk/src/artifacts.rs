@@ -0,0 +1,84 @@
+/// Writes artifact chunks streamed back by the host into a local directory.
+use common::compute::ArtifactChunk;
+use common::encoding::FileEncoding;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+
+/// Largest file accepted from the host once decoded; matches the host's own
+/// artifact limit.
+const MAX_ARTIFACT_BYTES: usize = 512 * 1024 * 1024;
+
+pub struct ArtifactWriter {
+    out_dir: PathBuf,
+    encoding: FileEncoding,
+    open: HashMap<String, Sink>,
+}
+
+/// Plain artifacts go straight to disk; encoded ones are held until complete
+/// and decoded as a whole.
+enum Sink {
+    File(File),
+    Encoded(Vec<u8>),
+}
+
+impl ArtifactWriter {
+    pub fn new(out_dir: PathBuf, encoding: FileEncoding) -> Self {
+        ArtifactWriter {
+            out_dir,
+            encoding,
+            open: HashMap::new(),
+        }
+    }
+
+    /// Appends a chunk to its file. Returns the local path once the file is
+    /// complete.
+    pub async fn write(&mut self, chunk: ArtifactChunk) -> io::Result<Option<PathBuf>> {
+        let dest = self.destination(&chunk.path)?;
+        if !self.open.contains_key(&chunk.path) {
+            let sink = match self.encoding {
+                FileEncoding::Identity => Sink::File(create(&dest).await?),
+                _ => Sink::Encoded(Vec::new()),
+            };
+            self.open.insert(chunk.path.clone(), sink);
+        }
+
+        match self.open.get_mut(&chunk.path).expect("opened above") {
+            Sink::File(file) => file.write_all(&chunk.data).await?,
+            Sink::Encoded(buf) => buf.extend_from_slice(&chunk.data),
+        }
+        if !chunk.eof {
+            return Ok(None);
+        }
+        match self.open.remove(&chunk.path).expect("opened above") {
+            Sink::File(mut file) => file.flush().await?,
+            Sink::Encoded(buf) => {
+                let data = self.encoding.decode(buf, MAX_ARTIFACT_BYTES)?;
+                create(&dest).await?.write_all(&data).await?;
+            }
+        }
+        Ok(Some(dest))
+    }
+
+    /// Maps a host-side relative path under `out_dir`, refusing anything that
+    /// would land outside it.
+    fn destination(&self, path: &str) -> io::Result<PathBuf> {
+        let rel = Path::new(path);
+        if rel.as_os_str().is_empty() || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("refusing artifact path {:?}", path),
+            ));
+        }
+        Ok(self.out_dir.join(rel))
+    }
+}
+
+async fn create(dest: &Path) -> io::Result<File> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    File::create(dest).await
+}
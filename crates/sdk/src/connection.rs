@@ -0,0 +1,321 @@
+/// How to reach a host: address, TLS settings (including a client certificate
+/// for mTLS) for `https://` servers, the bearer token sent with every call,
+/// compression, and the trace context of an enclosing pipeline.
+use crate::error::Error;
+use crate::job::Job;
+use crate::retry::RetryPolicy;
+use crate::submit;
+use common::compute::{AttachJobRequest, ComputeRequest, HostInfoRequest, LaunchKernelRequest, LaunchKernelResponse};
+use common::compute::admin_client;
+use common::compute::cuda_executor_client::CudaExecutorClient;
+use common::encoding::{FileEncoding, FILE_ENCODING_HEADER};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tonic::codec::CompressionEncoding;
+use tonic::metadata::MetadataValue;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Request, Status};
+
+/// The raw gRPC client, for calls without a wrapper here.
+pub type Client = CudaExecutorClient<InterceptedService<Channel, Credentials>>;
+
+/// The gRPC client of the host's Admin service, for the users it names as
+/// admins.
+pub type AdminClient = admin_client::AdminClient<InterceptedService<Channel, Credentials>>;
+
+/// W3C trace context variables a pipeline can set so the host's spans for
+/// this job join its trace.
+const TRACE_CONTEXT_ENV: [(&str, &str); 2] = [("TRACEPARENT", "traceparent"), ("TRACESTATE", "tracestate")];
+
+#[derive(Debug, Clone)]
+pub struct Connection {
+    server: String,
+    ca_cert: Option<PathBuf>,
+    tls_domain: Option<String>,
+    client_identity: Option<(PathBuf, PathBuf)>,
+    token: Option<String>,
+    compress: bool,
+    retry: RetryPolicy,
+}
+
+/// Attaches `authorization: Bearer <token>`, the requested file encoding and
+/// any trace context to outgoing calls.
+#[derive(Clone)]
+pub struct Credentials {
+    authorization: Option<MetadataValue<tonic::metadata::Ascii>>,
+    file_encoding: FileEncoding,
+    trace_context: Vec<(&'static str, MetadataValue<tonic::metadata::Ascii>)>,
+}
+
+impl Interceptor for Credentials {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.authorization {
+            request.metadata_mut().insert("authorization", value.clone());
+        }
+        if self.file_encoding != FileEncoding::Identity {
+            request.metadata_mut().insert(
+                FILE_ENCODING_HEADER,
+                MetadataValue::from_static(self.file_encoding.header_value()),
+            );
+        }
+        for (key, value) in &self.trace_context {
+            request.metadata_mut().insert(*key, value.clone());
+        }
+        Ok(request)
+    }
+}
+
+impl Connection {
+    /// `server` is e.g. `http://192.168.1.50:50051`, or `https://...` for TLS.
+    pub fn new(server: impl Into<String>) -> Self {
+        Connection {
+            server: server.into(),
+            ca_cert: None,
+            tls_domain: None,
+            client_identity: None,
+            token: None,
+            compress: false,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// PEM CA certificate to verify the host against, instead of the system roots.
+    pub fn with_ca_cert(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_cert = Some(path.into());
+        self
+    }
+
+    /// Name to expect in the host's certificate, if it differs from the server address.
+    pub fn with_tls_domain(mut self, domain: impl Into<String>) -> Self {
+        self.tls_domain = Some(domain.into());
+        self
+    }
+
+    /// PEM certificate and key, for hosts that require mutual TLS.
+    pub fn with_client_identity(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.client_identity = Some((cert.into(), key.into()));
+        self
+    }
+
+    /// Bearer token for hosts that require authentication.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Compress transfers: zstd for files in both directions, gzip for messages.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// How connecting and dropped job streams are retried; see [`RetryPolicy`].
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn file_encoding(&self) -> FileEncoding {
+        if self.compress {
+            FileEncoding::Zstd
+        } else {
+            FileEncoding::Identity
+        }
+    }
+
+    pub async fn connect(&self) -> Result<Host, Error> {
+        let authorization = match &self.token {
+            Some(token) => Some(
+                format!("Bearer {}", token)
+                    .parse()
+                    .map_err(|_| Error::Config("The token must be printable ASCII".into()))?,
+            ),
+            None => None,
+        };
+
+        let mut endpoint = Endpoint::from_shared(self.server.clone())?;
+
+        if endpoint.uri().scheme_str() == Some("https") {
+            // SNI and hostname verification both use this name
+            let domain = match &self.tls_domain {
+                Some(domain) => domain.clone(),
+                None => endpoint
+                    .uri()
+                    .host()
+                    .ok_or_else(|| Error::Config("The server address has no host name".into()))?
+                    .trim_matches(['[', ']'])
+                    .to_string(),
+            };
+            let mut tls = ClientTlsConfig::new().domain_name(domain);
+            tls = match &self.ca_cert {
+                Some(path) => tls.ca_certificate(Certificate::from_pem(tokio::fs::read(path).await?)),
+                None => tls.with_native_roots(),
+            };
+            if let Some((cert, key)) = &self.client_identity {
+                let identity =
+                    Identity::from_pem(tokio::fs::read(cert).await?, tokio::fs::read(key).await?);
+                tls = tls.identity(identity);
+            }
+            endpoint = endpoint.tls_config(tls)?;
+        } else if self.ca_cert.is_some() || self.tls_domain.is_some() || self.client_identity.is_some() {
+            return Err(Error::Config(
+                "A CA certificate, TLS domain or client certificate needs an https:// server address".into(),
+            ));
+        }
+
+        let mut attempt = 0;
+        let channel = loop {
+            match endpoint.connect().await {
+                Ok(channel) => break channel,
+                Err(e) => {
+                    let e = Error::from(e);
+                    attempt += 1;
+                    if !self.retry.wait(attempt, None, &e).await {
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        let credentials = Credentials {
+            authorization,
+            file_encoding: self.file_encoding(),
+            trace_context: TRACE_CONTEXT_ENV
+                .iter()
+                .filter_map(|(var, key)| Some((*key, std::env::var(var).ok()?.parse().ok()?)))
+                .collect(),
+        };
+        let admin = admin_client::AdminClient::with_interceptor(channel.clone(), credentials.clone());
+        let mut client = CudaExecutorClient::with_interceptor(channel, credentials);
+        if self.compress {
+            client = client
+                .send_compressed(CompressionEncoding::Gzip)
+                .accept_compressed(CompressionEncoding::Gzip);
+        }
+        Ok(Host {
+            client,
+            admin,
+            file_encoding: self.file_encoding(),
+            retry: self.retry.clone(),
+            chunk_size: Arc::new(OnceCell::new()),
+        })
+    }
+}
+
+/// A connected host.
+#[derive(Clone)]
+pub struct Host {
+    client: Client,
+    admin: AdminClient,
+    file_encoding: FileEncoding,
+    retry: RetryPolicy,
+    /// Upload chunk size, once the host has been asked.
+    chunk_size: Arc<OnceCell<usize>>,
+}
+
+impl Host {
+    pub fn grpc(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// The Admin service's client; its calls fail with `PermissionDenied`
+    /// unless the host lists the caller in `admin_users`.
+    pub fn admin(&self) -> AdminClient {
+        self.admin.clone()
+    }
+
+    pub fn file_encoding(&self) -> FileEncoding {
+        self.file_encoding
+    }
+
+    /// The size files are uploaded in: ours, or the host's `max_chunk_bytes`
+    /// if smaller. A host that can't be asked gets ours.
+    async fn chunk_size(&self) -> usize {
+        *self
+            .chunk_size
+            .get_or_init(|| async {
+                let info = self.client.clone().get_host_info(HostInfoRequest {}).await;
+                submit::chunk_size(info.map_or(0, |info| info.into_inner().max_chunk_bytes))
+            })
+            .await
+    }
+
+    /// Uploads the files and starts the job. Files are `(host path, local
+    /// path)` pairs as [`locate`](crate::locate) returns them; sources are
+    /// compiled, inputs land in the job's working directory.
+    pub async fn submit(
+        &self,
+        sources: Vec<(String, PathBuf)>,
+        inputs: Vec<(String, PathBuf)>,
+        request: ComputeRequest,
+    ) -> Result<Job, Error> {
+        let chunk_size = self.chunk_size().await;
+        let (outbound, reader) = submit::outbound(sources, None, inputs, request, self.file_encoding, chunk_size);
+        let mut client = self.client.clone();
+        let stream = match client.submit(outbound).await {
+            Ok(response) => response.into_inner(),
+            Err(status) => {
+                // A local read error ends the upload early; that is the real cause
+                if let Ok(Err(e)) = reader.await {
+                    return Err(e.into());
+                }
+                return Err(status.into());
+            }
+        };
+        Job::start(client, stream, self.retry.clone()).await
+    }
+
+    /// Uploads an executable built elsewhere and runs it, skipping the
+    /// build; `binary` is a `(host path, local path)` pair like the files of
+    /// [`submit`](Self::submit), and `request` mustn't ask for a build.
+    pub async fn run_binary(
+        &self,
+        binary: (String, PathBuf),
+        inputs: Vec<(String, PathBuf)>,
+        request: ComputeRequest,
+    ) -> Result<Job, Error> {
+        let chunk_size = self.chunk_size().await;
+        let (outbound, reader) =
+            submit::outbound(Vec::new(), Some(binary), inputs, request, self.file_encoding, chunk_size);
+        let mut client = self.client.clone();
+        let stream = match client.run_binary(outbound).await {
+            Ok(response) => response.into_inner(),
+            Err(status) => {
+                if let Ok(Err(e)) = reader.await {
+                    return Err(e.into());
+                }
+                return Err(status.into());
+            }
+        };
+        Job::start(client, stream, self.retry.clone()).await
+    }
+
+    /// Runs one kernel from a PTX module on the host's GPU and returns its
+    /// output buffers; no job, no nvcc. Waits for a free slot and GPU like a
+    /// job does.
+    pub async fn launch_kernel(&self, request: LaunchKernelRequest) -> Result<LaunchKernelResponse, Error> {
+        Ok(self.client.clone().launch_kernel(request).await?.into_inner())
+    }
+
+    /// Follows a job submitted earlier (or whose stream dropped), from
+    /// message `from` of its output on; artifacts are not replayed.
+    pub async fn attach(&self, job_id: &str, from: u64) -> Result<Job, Error> {
+        let stream = self
+            .client
+            .clone()
+            .attach_job(AttachJobRequest {
+                job_id: job_id.to_string(),
+                from_offset: from,
+            })
+            .await?
+            .into_inner();
+        Ok(Job::attached(self.client.clone(), job_id, stream, from, self.retry.clone()))
+    }
+}
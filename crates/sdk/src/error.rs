@@ -0,0 +1,58 @@
+/// Everything that can go wrong talking to a host.
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The host could not be reached.
+    Transport(tonic::transport::Error),
+    /// The host refused or failed a call.
+    Status(tonic::Status),
+    /// A local file could not be read or written.
+    Io(io::Error),
+    /// Unusable settings, e.g. a token that isn't printable ASCII.
+    Config(String),
+    /// The job's stream ended before its final message.
+    Ended,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "Could not reach the host: {}", e),
+            Error::Status(status) => write!(f, "{:?}: {}", status.code(), status.message()),
+            Error::Io(e) => e.fmt(f),
+            Error::Config(message) => f.write_str(message),
+            Error::Ended => f.write_str("The job's stream ended without a final status"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e),
+            Error::Status(status) => Some(status),
+            Error::Io(e) => Some(e),
+            Error::Config(_) | Error::Ended => None,
+        }
+    }
+}
+
+impl From<tonic::transport::Error> for Error {
+    fn from(e: tonic::transport::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        Error::Status(status)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
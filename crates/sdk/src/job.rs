@@ -0,0 +1,346 @@
+/// A submitted job: its output as it streams in, and a way to cancel it.
+///
+/// Messages arrive in the order described in the host's stream.rs: compiler
+/// output, then the program's, and the final message (carrying the
+/// `JobResult`) last. Artifacts come in two places: the compiled binary (or
+/// cubin) right after compiling, before the program runs, and the files the
+/// program wrote, profiler reports included, after it exits.
+use crate::connection::Client;
+use crate::error::Error;
+use crate::retry::{self, RetryPolicy};
+use common::compute::{
+    ArtifactChunk, AttachJobRequest, CancelJobRequest, ComputeResponse, DebugData, DebugSession, Diagnostic, GpuSample, JobPhase,
+    JobResult, OutputKind, SanitizerFinding, StdinChunk,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Streaming;
+
+pub struct Job {
+    id: String,
+    client: Client,
+    stream: Streaming<ComputeResponse>,
+    /// The first message, read to learn the job's id.
+    first: Option<ComputeResponse>,
+    /// Non-artifact messages received, i.e. where a reattach resumes.
+    offset: u64,
+    retry: RetryPolicy,
+}
+
+/// One message of a job's stream, by what it carries.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The job started compiling or running.
+    Phase(JobPhase),
+    /// A line nvcc or the program wrote, bytes as written, newline included.
+    /// Compiler messages come with their parsed form.
+    Output {
+        kind: OutputKind,
+        data: Vec<u8>,
+        diagnostic: Option<Diagnostic>,
+    },
+    /// The lines of one compute-sanitizer report, bytes as written, with what
+    /// was parsed out of them.
+    Finding {
+        kind: OutputKind,
+        data: Vec<u8>,
+        finding: SanitizerFinding,
+    },
+    /// A sample of the job's GPUs, about once a second while a `telemetry`
+    /// job's program runs.
+    Telemetry(Vec<GpuSample>),
+    /// A status message from the host.
+    Message { text: String, is_error: bool },
+    /// The program is waiting under cuda-gdbserver; connect a debugger
+    /// with [`Job::tunnel`].
+    Debugging { text: String, session: DebugSession },
+    /// A piece of the compiled binary or of a file the program wrote;
+    /// [`ArtifactWriter`](crate::ArtifactWriter) saves them.
+    Artifact(ArtifactChunk),
+    /// The job's last message.
+    Finished { text: String, result: JobResult },
+}
+
+impl From<ComputeResponse> for Event {
+    fn from(response: ComputeResponse) -> Self {
+        if let Some(chunk) = response.artifact {
+            return Event::Artifact(chunk);
+        }
+        let text = || String::from_utf8_lossy(&response.output).into_owned();
+        if let Some(result) = response.result.clone() {
+            return Event::Finished { text: text(), result };
+        }
+        if let Some(session) = response.debug_session.clone() {
+            return Event::Debugging { text: text(), session };
+        }
+        if !response.telemetry.is_empty() {
+            return Event::Telemetry(response.telemetry);
+        }
+        if let Some(finding) = response.finding.clone() {
+            return Event::Finding {
+                kind: response.kind(),
+                data: response.output,
+                finding,
+            };
+        }
+        match response.kind() {
+            OutputKind::Stdout | OutputKind::Stderr => Event::Output {
+                kind: response.kind(),
+                data: response.output,
+                diagnostic: response.diagnostic,
+            },
+            _ if response.phase() != JobPhase::Unspecified && response.output.is_empty() => {
+                Event::Phase(response.phase())
+            }
+            _ => Event::Message {
+                text: text(),
+                is_error: response.is_error,
+            },
+        }
+    }
+}
+
+impl Job {
+    /// Waits for the first message, which names the job.
+    pub(crate) async fn start(
+        client: Client,
+        mut stream: Streaming<ComputeResponse>,
+        retry: RetryPolicy,
+    ) -> Result<Self, Error> {
+        let first = stream.message().await?.ok_or(Error::Ended)?;
+        Ok(Job {
+            id: first.job_id.clone(),
+            client,
+            stream,
+            first: Some(first),
+            offset: 1,
+            retry,
+        })
+    }
+
+    pub(crate) fn attached(
+        client: Client,
+        job_id: &str,
+        stream: Streaming<ComputeResponse>,
+        from: u64,
+        retry: RetryPolicy,
+    ) -> Self {
+        Job {
+            id: job_id.to_string(),
+            client,
+            stream,
+            first: None,
+            offset: from,
+            retry,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Where the output received so far ends; `attach` resumes from here.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The next message as the host sent it; `None` once the stream is over.
+    /// A dropped stream is reattached as the retry policy allows.
+    pub async fn next_message(&mut self) -> Result<Option<ComputeResponse>, Error> {
+        if let Some(first) = self.first.take() {
+            return Ok(Some(first));
+        }
+        let mut attempt = 0;
+        loop {
+            let status = match self.stream.message().await {
+                Ok(message) => {
+                    if let Some(response) = &message
+                        && response.artifact.is_none()
+                    {
+                        self.offset += 1;
+                    }
+                    return Ok(message);
+                }
+                Err(status) if retry::dropped(&status) => status,
+                Err(status) => return Err(status.into()),
+            };
+            let mut error = Error::from(status);
+            loop {
+                attempt += 1;
+                if !self.retry.wait(attempt, Some(&self.id), &error).await {
+                    return Err(error);
+                }
+                match self.reattach().await {
+                    Ok(stream) => break self.stream = stream,
+                    Err(status) if retry::dropped(&status) => error = status.into(),
+                    // e.g. the host restarted and no longer knows the job
+                    Err(_) => return Err(error),
+                }
+            }
+        }
+    }
+
+    /// Borrows nothing across the await, so a `Job` can be driven from a
+    /// spawned task (`Streaming` isn't `Sync`).
+    fn reattach(&self) -> impl Future<Output = Result<Streaming<ComputeResponse>, tonic::Status>> + Send + 'static {
+        let request = AttachJobRequest {
+            job_id: self.id.clone(),
+            from_offset: self.offset,
+        };
+        let mut client = self.client.clone();
+        async move { Ok(client.attach_job(request).await?.into_inner()) }
+    }
+
+    pub async fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        Ok(self.next_message().await?.map(Event::from))
+    }
+
+    /// Asks the host to cancel the job. Its stream goes on to a final
+    /// "cancelled" message; false if the job had already finished.
+    pub async fn cancel(&self) -> Result<bool, Error> {
+        let response = self
+            .client
+            .clone()
+            .cancel_job(CancelJobRequest { job_id: self.id.clone() })
+            .await?;
+        Ok(response.into_inner().cancelled)
+    }
+
+    /// Streams `input` to the program's stdin until EOF; the job must have
+    /// been submitted with `forward_stdin`. The future doesn't borrow the
+    /// job, so it can be spawned while the output is read.
+    pub fn write_stdin(
+        &self,
+        mut input: impl AsyncRead + Unpin + Send + 'static,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let mut client = self.client.clone();
+        let job_id = self.id.clone();
+        async move {
+            let (tx, rx) = mpsc::channel(16);
+            let upload = tokio::spawn(async move { client.write_stdin(ReceiverStream::new(rx)).await });
+
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                let n = input.read(&mut buf).await.unwrap_or(0);
+                let chunk = StdinChunk {
+                    job_id: job_id.clone(),
+                    data: buf[..n].to_vec(),
+                    eof: n == 0,
+                };
+                if tx.send(chunk).await.is_err() || n == 0 {
+                    break;
+                }
+            }
+
+            drop(tx);
+            match upload.await {
+                Ok(result) => result.map(|_| ()).map_err(Error::from),
+                Err(_) => Err(Error::Ended),
+            }
+        }
+    }
+
+    /// Carries one debugger connection, e.g. accepted from a local listener,
+    /// to the job's cuda-gdbserver until either end closes it; the job must
+    /// have been submitted with `debug` and announced its session. Like
+    /// `write_stdin`, the future doesn't borrow the job.
+    pub fn tunnel(&self, socket: TcpStream) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let mut client = self.client.clone();
+        let job_id = self.id.clone();
+        async move {
+            let (mut reader, mut writer) = socket.into_split();
+            let (tx, rx) = mpsc::channel(16);
+            let _ = tx.send(DebugData { job_id, data: Vec::new() }).await;
+            let mut incoming = client.debug_tunnel(ReceiverStream::new(rx)).await?.into_inner();
+
+            let upstream = async move {
+                let mut buf = vec![0u8; 64 * 1024];
+                loop {
+                    let n = reader.read(&mut buf).await.unwrap_or(0);
+                    let data = DebugData {
+                        data: buf[..n].to_vec(),
+                        ..Default::default()
+                    };
+                    if n == 0 || tx.send(data).await.is_err() {
+                        break;
+                    }
+                }
+            };
+            // Ends when the gdbserver does; dropping the writer then tells
+            // the debugger, whose hanging up ends the upstream half
+            let downstream = async move {
+                while let Some(message) = incoming.message().await? {
+                    writer.write_all(&message.data).await?;
+                }
+                Ok(())
+            };
+            tokio::join!(upstream, downstream).1
+        }
+    }
+
+    /// Waits for the job to end, discarding its output.
+    pub async fn wait(mut self) -> Result<JobResult, Error> {
+        while let Some(message) = self.next_message().await? {
+            if let Some(result) = message.result {
+                return Ok(result);
+            }
+        }
+        Err(Error::Ended)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry_samples_are_their_own_event() {
+        let samples = vec![GpuSample {
+            index: 1,
+            utilization_percent: 87,
+            ..Default::default()
+        }];
+        let response = ComputeResponse {
+            kind: OutputKind::System.into(),
+            telemetry: samples.clone(),
+            ..Default::default()
+        };
+        assert!(matches!(Event::from(response), Event::Telemetry(got) if got == samples));
+    }
+
+    #[test]
+    fn sanitizer_reports_keep_their_finding() {
+        let finding = SanitizerFinding {
+            error: "Invalid __global__ write of size 4 bytes".into(),
+            kernel: "scale".into(),
+            ..Default::default()
+        };
+        let response = ComputeResponse {
+            output: b"========= Invalid __global__ write of size 4 bytes\n".to_vec(),
+            is_error: true,
+            kind: OutputKind::Stderr.into(),
+            finding: Some(finding.clone()),
+            ..Default::default()
+        };
+        match Event::from(response) {
+            Event::Finding { kind, data, finding: got } => {
+                assert_eq!(kind, OutputKind::Stderr);
+                assert!(data.starts_with(b"========= Invalid"));
+                assert_eq!(got, finding);
+            }
+            event => panic!("not a finding: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn status_messages_stay_messages() {
+        let response = ComputeResponse {
+            output: b"Compiling...".to_vec(),
+            kind: OutputKind::System.into(),
+            ..Default::default()
+        };
+        assert!(matches!(Event::from(response), Event::Message { text, is_error: false } if text == "Compiling..."));
+    }
+}
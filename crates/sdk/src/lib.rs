@@ -0,0 +1,38 @@
+//! Submit CUDA jobs to a Ferris-Compute-Cuda host and follow them from Rust.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), ferris_compute_client::Error> {
+//! use ferris_compute_client::{Connection, Event, compute::ComputeRequest, locate};
+//!
+//! let host = Connection::new("http://gpu-box:50051").with_token("s3cret").connect().await?;
+//! let request = ComputeRequest { compiler_flags: vec!["-O3".into()], ..Default::default() };
+//! let mut job = host.submit(locate(&["kernel.cu".into()])?, Vec::new(), request).await?;
+//! while let Some(event) = job.next_event().await? {
+//!     if let Event::Output { data, .. } = event {
+//!         print!("{}", String::from_utf8_lossy(&data));
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The `client` binary is built on this crate; anything it can do is one
+//! call away here too, and [`Host::grpc`] gives the raw gRPC client for the
+//! rest of the service (status, history, logs), [`Host::admin`] that of the
+//! host's Admin service.
+mod artifacts;
+mod connection;
+mod error;
+mod job;
+mod project;
+mod retry;
+mod submit;
+
+pub use artifacts::ArtifactWriter;
+pub use common::compute;
+pub use common::encoding::FileEncoding;
+pub use connection::{AdminClient, Client, Connection, Credentials, Host};
+pub use error::Error;
+pub use job::{Event, Job};
+pub use project::locate;
+pub use retry::{RetryPolicy, Retrying};
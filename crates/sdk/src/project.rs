@@ -0,0 +1,67 @@
+/// Collects the files of a submission from the paths given on the command line.
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// A directory argument contributes every file under it (hidden entries such as
+/// `.git` are skipped) with paths relative to that directory. A file argument
+/// keeps its relative path when it is a plain one (`src/kernel.cu`), otherwise
+/// it is submitted under its bare file name.
+///
+/// Returns each file's path on the host with its local path, without reading
+/// anything.
+pub fn locate(paths: &[PathBuf]) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut found = Vec::new();
+            walk(path, &mut found)?;
+            found.sort();
+            for file in found {
+                let rel = file.strip_prefix(path).unwrap_or(&file);
+                files.push((wire_path(rel), file.clone()));
+            }
+        } else {
+            files.push((wire_path(&submitted_path(path)), path.clone()));
+        }
+    }
+
+    Ok(files)
+}
+
+fn walk(dir: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, out)?;
+        } else {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn submitted_path(path: &Path) -> PathBuf {
+    let normal: PathBuf = path
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect();
+
+    if normal.components().all(|c| matches!(c, Component::Normal(_))) {
+        normal
+    } else {
+        PathBuf::from(path.file_name().unwrap_or_default())
+    }
+}
+
+/// The host expects forward slashes regardless of the client platform.
+fn wire_path(rel: &Path) -> String {
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,103 @@
+/// Riding out network blips: connecting is retried with exponential backoff,
+/// and a job whose stream drops is reattached (`AttachJob`) from the last
+/// message received, so the output carries on without gaps or repeats.
+///
+/// Artifact chunks are not kept in the host's job log, so a file the job was
+/// sending when the stream dropped is not resent.
+use crate::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Code;
+
+/// Longest wait between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+type RetryHook = Arc<dyn Fn(&Retrying) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct RetryPolicy {
+    retries: u32,
+    initial_delay: Duration,
+    on_retry: Option<RetryHook>,
+}
+
+/// Passed to the [`on_retry`](RetryPolicy::on_retry) hook before each wait.
+#[derive(Debug, Clone)]
+pub struct Retrying {
+    /// The job being reattached; `None` while connecting.
+    pub job_id: Option<String>,
+    /// 1 for the first retry.
+    pub attempt: u32,
+    pub retries: u32,
+    pub delay: Duration,
+    pub error: String,
+}
+
+impl Default for RetryPolicy {
+    /// Three retries, waiting 0.5s, 1s and 2s.
+    fn default() -> Self {
+        RetryPolicy::new(3, Duration::from_millis(500))
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("retries", &self.retries)
+            .field("initial_delay", &self.initial_delay)
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    /// Up to `retries` more attempts after a failure, the wait doubling from
+    /// `initial_delay` (to at most 30s).
+    pub fn new(retries: u32, initial_delay: Duration) -> Self {
+        RetryPolicy {
+            retries,
+            initial_delay,
+            on_retry: None,
+        }
+    }
+
+    /// Never retries.
+    pub fn none() -> Self {
+        RetryPolicy::new(0, Duration::ZERO)
+    }
+
+    /// Called before each wait, e.g. to tell the user what's going on.
+    pub fn on_retry(mut self, hook: impl Fn(&Retrying) + Send + Sync + 'static) -> Self {
+        self.on_retry = Some(Arc::new(hook));
+        self
+    }
+
+    /// Waits before retry number `attempt` (from 1), or returns false once
+    /// the retries are used up.
+    pub(crate) async fn wait(&self, attempt: u32, job_id: Option<&str>, error: &Error) -> bool {
+        if attempt > self.retries {
+            return false;
+        }
+        let delay = self
+            .initial_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(MAX_DELAY);
+        if let Some(hook) = &self.on_retry {
+            hook(&Retrying {
+                job_id: job_id.map(str::to_string),
+                attempt,
+                retries: self.retries,
+                delay,
+                error: error.to_string(),
+            });
+        }
+        tokio::time::sleep(delay).await;
+        true
+    }
+}
+
+/// Whether a job's stream ended because the connection dropped, rather than
+/// because the host failed the job.
+pub(crate) fn dropped(status: &tonic::Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::Unknown | Code::Cancelled)
+}
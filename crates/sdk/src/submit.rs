@@ -0,0 +1,135 @@
+/// The outbound half of a `Submit` or `RunBinary` call: every source (or the
+/// binary) and input file in chunks, then the start marker with the job
+/// settings. Files go in chunks of the size the host takes.
+use common::chunking::{self, MAX_CHUNK_SIZE};
+use common::compute::submit_message::Kind;
+use common::encoding::FileEncoding;
+use common::compute::{ComputeRequest, FileChunk, SubmitMessage};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// The chunk size to upload in, given the host's `max_chunk_bytes`: ours,
+/// unless the host takes smaller chunks. 0 means the host didn't say.
+pub fn chunk_size(advertised: u32) -> usize {
+    match advertised {
+        0 => MAX_CHUNK_SIZE,
+        advertised => (advertised as usize).min(MAX_CHUNK_SIZE),
+    }
+}
+
+/// Files are `(host path, local path)` pairs. They are read (and encoded) one
+/// at a time while earlier chunks are in flight; the task's result reports
+/// local read errors, which otherwise just look like the stream ending early.
+pub fn outbound(
+    sources: Vec<(String, PathBuf)>,
+    binary: Option<(String, PathBuf)>,
+    inputs: Vec<(String, PathBuf)>,
+    start: ComputeRequest,
+    encoding: FileEncoding,
+    chunk_size: usize,
+) -> (ReceiverStream<SubmitMessage>, JoinHandle<io::Result<()>>) {
+    let (tx, rx) = mpsc::channel(4);
+
+    let reader = tokio::spawn(async move {
+        for (path, local) in sources {
+            if !send_file(&tx, path, &local, encoding, chunk_size, Kind::Source).await? {
+                return Ok(());
+            }
+        }
+        if let Some((path, local)) = binary
+            && !send_file(&tx, path, &local, encoding, chunk_size, Kind::Binary).await?
+        {
+            return Ok(());
+        }
+        for (path, local) in inputs {
+            if !send_file(&tx, path, &local, encoding, chunk_size, Kind::Input).await? {
+                return Ok(());
+            }
+        }
+        let _ = tx.send(SubmitMessage { kind: Some(Kind::Start(start)) }).await;
+        Ok(())
+    });
+
+    (ReceiverStream::new(rx), reader)
+}
+
+/// Sends one file; an empty file is a single empty `eof` chunk. Returns false
+/// if the call has already ended.
+async fn send_file(
+    tx: &mpsc::Sender<SubmitMessage>,
+    path: String,
+    local: &Path,
+    encoding: FileEncoding,
+    chunk_size: usize,
+    kind: fn(FileChunk) -> Kind,
+) -> io::Result<bool> {
+    let data = tokio::fs::read(local)
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("Could not read file {}: {}", local.display(), e)))?;
+    let data = encoding.encode(&data)?;
+    let sha256 = chunking::digest(&data);
+
+    let mut chunks: Vec<Vec<u8>> = chunking::chunk_bytes_with(&data, chunk_size).map(|c| c.to_vec()).collect();
+    if chunks.is_empty() {
+        chunks.push(Vec::new());
+    }
+    let last = chunks.len() - 1;
+
+    for (i, data) in chunks.into_iter().enumerate() {
+        let eof = i == last;
+        let chunk = FileChunk {
+            path: path.clone(),
+            data,
+            eof,
+            sha256: if eof { sha256.clone() } else { String::new() },
+        };
+        if tx.send(SubmitMessage { kind: Some(kind(chunk)) }).await.is_err() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn takes_the_smaller_chunk_size() {
+        assert_eq!(chunk_size(0), MAX_CHUNK_SIZE);
+        assert_eq!(chunk_size(64 * 1024), 64 * 1024);
+        assert_eq!(chunk_size(u32::MAX), MAX_CHUNK_SIZE);
+    }
+
+    #[tokio::test]
+    async fn uploads_in_the_hosts_chunk_size() {
+        let local = std::env::temp_dir().join(format!("ferris-submit-{}.bin", std::process::id()));
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        std::fs::write(&local, &data).unwrap();
+
+        // A host that takes 64-byte chunks
+        let size = chunk_size(64);
+        let sources = vec![("main.cu".to_string(), local.clone())];
+        let start = ComputeRequest::default();
+        let (stream, reader) = outbound(sources, None, Vec::new(), start, FileEncoding::Identity, size);
+        let messages: Vec<_> = stream.collect().await;
+        reader.await.unwrap().unwrap();
+
+        let mut received = Vec::new();
+        for message in &messages[..messages.len() - 1] {
+            let Some(Kind::Source(chunk)) = &message.kind else {
+                panic!("expected a source chunk");
+            };
+            assert!(chunk.data.len() <= 64);
+            received.extend_from_slice(&chunk.data);
+        }
+        assert_eq!(received, data);
+        assert_eq!(messages.len(), 1000usize.div_ceil(64) + 1);
+        assert!(matches!(messages.last().unwrap().kind, Some(Kind::Start(_))));
+        std::fs::remove_file(&local).unwrap();
+    }
+}